@@ -26,6 +26,15 @@ pub enum HoldTapConfig {
     /// events than on timing. Be aware that doing the good succession
     /// of key might require some training.
     PermissiveHold,
+    /// Only the timeout will determine between hold and tap action, like
+    /// [HoldTapConfig::Default]. However, if the hold action was activated
+    /// and the key is released without any other key being pressed, the tap
+    /// action is also triggered after the hold action is released.
+    ///
+    /// This behavior is interesting for keys that you sometimes hold for
+    /// too long when you meant to tap them, like a space key that is also
+    /// a layer key.
+    RetroTap,
     /// A custom configuration. Allows the behavior to be controlled by a caller
     /// supplied handler function.
     ///
@@ -90,6 +99,64 @@ pub enum HoldTapConfig {
     /// });
     /// ```
     Custom(fn(StackedIter) -> Option<WaitingAction>),
+    /// If another key on the opposite hand is pressed, the hold action is
+    /// activated. If another key on the same hand is pressed, the tap action
+    /// is activated.
+    ///
+    /// The handler function returns the [Hand] of the key at the given
+    /// `(row, col)` coordinate. Keys that are assigned to [Hand::Any] (e.g.
+    /// thumb keys) are considered to be on the opposite hand of every other
+    /// key.
+    ///
+    /// This behavior is interesting for home row mods: rolling over keys on
+    /// the same hand will not trigger the modifier by accident.
+    ///
+    /// # Example:
+    /// ```
+    /// use keyberon::action::{Action, Hand, HoldTapConfig, HoldTapAction};
+    /// use keyberon::key_code::KeyCode;
+    ///
+    /// /// Columns 0 to 5 are on the left side of the keyboard, and the bottom
+    /// /// row contains thumb keys.
+    /// fn hand((i, j): (u8, u8)) -> Hand {
+    ///     match (i, j) {
+    ///         (4, _) => Hand::Any,
+    ///         (_, 0..=5) => Hand::Left,
+    ///         _ => Hand::Right,
+    ///     }
+    /// }
+    ///
+    /// const A_SHIFT: Action = Action::HoldTap(&HoldTapAction {
+    ///     timeout: 200,
+    ///     hold: Action::KeyCode(KeyCode::LShift),
+    ///     tap: Action::KeyCode(KeyCode::A),
+    ///     config: HoldTapConfig::ChordalHold(hand),
+    ///     tap_hold_interval: 0,
+    /// });
+    /// ```
+    ChordalHold(fn((u8, u8)) -> Hand),
+}
+
+/// The side of the keyboard that a key is on. Used by
+/// [HoldTapConfig::ChordalHold].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hand {
+    /// The key is on the left side of the keyboard.
+    Left,
+    /// The key is on the right side of the keyboard.
+    Right,
+    /// The key is considered to be on the opposite side of every other key.
+    Any,
+}
+
+impl Hand {
+    /// Returns `true` if both keys are on the same hand.
+    pub fn is_same_hand(self, other: Hand) -> bool {
+        matches!(
+            (self, other),
+            (Hand::Left, Hand::Left) | (Hand::Right, Hand::Right)
+        )
+    }
 }
 
 impl Debug for HoldTapConfig {
@@ -98,10 +165,15 @@ impl Debug for HoldTapConfig {
             HoldTapConfig::Default => f.write_str("Default"),
             HoldTapConfig::HoldOnOtherKeyPress => f.write_str("HoldOnOtherKeyPress"),
             HoldTapConfig::PermissiveHold => f.write_str("PermissiveHold"),
+            HoldTapConfig::RetroTap => f.write_str("RetroTap"),
             HoldTapConfig::Custom(func) => f
                 .debug_tuple("Custom")
                 .field(&(*func as fn(StackedIter<'static>) -> Option<WaitingAction>) as &dyn Debug)
                 .finish(),
+            HoldTapConfig::ChordalHold(func) => f
+                .debug_tuple("ChordalHold")
+                .field(&(*func as fn((u8, u8)) -> Hand) as &dyn Debug)
+                .finish(),
         }
     }
 }
@@ -111,10 +183,14 @@ impl PartialEq for HoldTapConfig {
         match (self, other) {
             (HoldTapConfig::Default, HoldTapConfig::Default)
             | (HoldTapConfig::HoldOnOtherKeyPress, HoldTapConfig::HoldOnOtherKeyPress)
-            | (HoldTapConfig::PermissiveHold, HoldTapConfig::PermissiveHold) => true,
+            | (HoldTapConfig::PermissiveHold, HoldTapConfig::PermissiveHold)
+            | (HoldTapConfig::RetroTap, HoldTapConfig::RetroTap) => true,
             (HoldTapConfig::Custom(self_func), HoldTapConfig::Custom(other_func)) => {
                 *self_func as fn(StackedIter<'static>) -> Option<WaitingAction> == *other_func
             }
+            (HoldTapConfig::ChordalHold(self_func), HoldTapConfig::ChordalHold(other_func)) => {
+                *self_func as fn((u8, u8)) -> Hand == *other_func
+            }
             _ => false,
         }
     }
@@ -201,6 +277,24 @@ where
     pub end_config: OneShotEndConfig,
 }
 
+/// Define sticky layer behaviour.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct StickyLayerAction {
+    /// The layer to activate.
+    pub layer: usize,
+    /// Timeout after which the layer will be deactivated if the key was tapped. Note: like
+    /// [`OneShotAction::timeout`], this will be overwritten if another one shot key is pressed.
+    pub timeout: u16,
+    /// If the key is held for this many ticks (usually milliseconds), the layer stops being
+    /// sticky, and will be deactivated as soon as the key is released.
+    ///
+    /// To deactivate the functionality, set this to 0.
+    pub hold_timeout: u16,
+    /// Configuration of the end behaviour when the key was tapped. See
+    /// [`OneShotAction::end_config`].
+    pub end_config: OneShotEndConfig,
+}
+
 /// Determines the behaviour for a [`TapDanceAction`]
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum TapDanceConfig {
@@ -256,6 +350,15 @@ where
     /// Fn key. If several layer actions are hold at the same time,
     /// the last pressed defines the current layer.
     Layer(usize),
+    /// While pressed, change the current layer, like [`Action::Layer`]. The layer is also
+    /// deactivated once `timeout` ticks (usually milliseconds) have elapsed since the press, even
+    /// if the key is still held. This prevents accidental long holds from locking you in a layer.
+    TimedMomentaryLayer {
+        /// The layer to activate.
+        layer: usize,
+        /// The duration, in ticks (usually milliseconds), after which the layer is deactivated.
+        timeout: u16,
+    },
     /// Switch the current layer until the layer gets toggled again.
     /// Make sure to also include a ToggleLayer(x) on layer x, otherwise
     /// you will be stuck on that layer. If multiple layers are toggled on,
@@ -276,6 +379,11 @@ where
     /// key will hold space until either another key is pressed or the timeout occurs, which will
     /// probably send many undesired space characters to your active application.
     OneShot(&'static OneShotAction<T, K>),
+    /// Sticky layer key. See [`StickyLayerAction`] for configuration info. If the key is tapped,
+    /// the layer is activated like a one shot layer key. If the key is held for longer than
+    /// `hold_timeout`, it behaves like [`Action::Layer`] instead, and the layer is deactivated
+    /// when the key is released.
+    StickyLayer(&'static StickyLayerAction),
     /// Tap-dance key. When tapping the key N times in quick succession, activates the N'th action
     /// in `actions`. The action will activate in the following conditions:
     ///
@@ -340,6 +448,37 @@ pub const fn t<T, K>(layer: usize) -> Action<T, K> {
     Action::ToggleLayer(layer)
 }
 
+/// A shortcut to create an [`Action::HoldTap`] that performs `tap` when tapped, and arms a one
+/// shot `hold` action (usually a modifier) when held for more than `timeout` ticks.
+///
+/// While held, the `hold` action is active like a normal held key. Once released, it stays
+/// active until the next non-one-shot key is released, or until `oneshot_timeout` ticks have
+/// elapsed since the hold was triggered.
+///
+/// ```
+/// use keyberon::action::{k, Action};
+/// use keyberon::key_code::KeyCode;
+/// use keyberon::tap_oneshot;
+///
+/// const SPACE_SHIFT: Action = tap_oneshot!(k(KeyCode::Space), k(KeyCode::LShift), 200, 1000);
+/// ```
+#[macro_export]
+macro_rules! tap_oneshot {
+    ($tap:expr, $hold:expr, $timeout:expr, $oneshot_timeout:expr) => {
+        $crate::action::Action::HoldTap(&$crate::action::HoldTapAction {
+            timeout: $timeout,
+            hold: $crate::action::Action::OneShot(&$crate::action::OneShotAction {
+                action: $hold,
+                timeout: $oneshot_timeout,
+                end_config: $crate::action::OneShotEndConfig::EndOnFirstRelease,
+            }),
+            tap: $tap,
+            config: $crate::action::HoldTapConfig::Default,
+            tap_hold_interval: 0,
+        })
+    };
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -5,6 +5,9 @@
 //! determine whether the user intends to release or press a given key. The actuator can be
 //! customized to change the acutation points for each switch, along with the mode that determines
 //! when it actuates.
+//!
+//! This module also provides a [`SocdResolver`], which can be used to resolve simultaneous
+//! opposing cardinal directions (SOCD) for pairs of keys, such as `A` and `D` in games.
 
 use crate::layout::Event;
 
@@ -33,13 +36,14 @@ pub enum AnalogAcutationMode {
 
 /// Analog matrix actuator
 pub struct AnalogActuator<const CS: usize, const RS: usize> {
-    press_threshold: u8,
-    release_threshold: u8,
+    press_thresholds: [[u8; CS]; RS],
+    release_thresholds: [[u8; CS]; RS],
     modes: [[AnalogAcutationMode; CS]; RS],
     actuation_points: [[u8; CS]; RS],
     cur_state: [[u8; CS]; RS],
     new_state: [[u8; CS]; RS],
     cur_actuated: [[bool; CS]; RS],
+    reported_state: [[u8; CS]; RS],
 }
 
 impl<const CS: usize, const RS: usize> AnalogActuator<CS, RS> {
@@ -53,11 +57,12 @@ impl<const CS: usize, const RS: usize> AnalogActuator<CS, RS> {
     ) -> Self {
         Self {
             modes,
-            press_threshold: 5,
-            release_threshold: 5,
+            press_thresholds: [[5; CS]; RS],
+            release_thresholds: [[5; CS]; RS],
             cur_state: [[0; CS]; RS],
             new_state: [[0; CS]; RS],
             cur_actuated: [[false; CS]; RS],
+            reported_state: [[0; CS]; RS],
             actuation_points,
         }
     }
@@ -97,14 +102,114 @@ impl<const CS: usize, const RS: usize> AnalogActuator<CS, RS> {
             .ok_or(AnalogActuatorError::InvalidLocation)
     }
 
-    /// Update the threshold to register a key press
+    /// Get the actuation point for a given key.
+    pub fn get_actuation_point(&self, row: usize, col: usize) -> Option<u8> {
+        self.actuation_points
+            .get(row)
+            .and_then(|row| row.get(col))
+            .copied()
+    }
+
+    /// Iterates on the analog values of keys that have changed since they were last returned
+    /// by this method, as `(row, col, value)`. Keys are only marked as reported once they are
+    /// consumed from the iterator, so the values can be retrieved in chunks.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use keyberon::analog::{AnalogAcutationMode, AnalogActuator};
+    /// let mut actuator = AnalogActuator::new(
+    ///     [[AnalogAcutationMode::Static; 2]; 2],
+    ///     [[127; 2]; 2],
+    /// );
+    ///
+    /// actuator.events([[0, 100], [50, 0]]).count();
+    /// assert_eq!(
+    ///     vec![(0, 1, 100)],
+    ///     actuator.changed_values().take(1).collect::<Vec<_>>(),
+    /// );
+    /// assert_eq!(
+    ///     vec![(1, 0, 50)],
+    ///     actuator.changed_values().collect::<Vec<_>>(),
+    /// );
+    /// assert_eq!(0, actuator.changed_values().count());
+    /// ```
+    pub fn changed_values(&mut self) -> impl Iterator<Item = (u8, u8, u8)> + '_ {
+        self.reported_state
+            .iter_mut()
+            .zip(self.new_state.iter())
+            .enumerate()
+            .flat_map(|(row, (reported, new))| {
+                reported.iter_mut().zip(new.iter()).enumerate().filter_map(
+                    move |(col, (reported, new))| {
+                        (*reported != *new).then(|| {
+                            *reported = *new;
+                            (row as u8, col as u8, *new)
+                        })
+                    },
+                )
+            })
+    }
+
+    /// Update the threshold to register a key press, for all keys
     pub fn set_press_threshold(&mut self, press_threshold: u8) {
-        self.press_threshold = press_threshold;
+        self.press_thresholds = [[press_threshold; CS]; RS];
     }
 
-    /// Update the threshold to register a key release
+    /// Update the threshold to register a key release, for all keys
     pub fn set_release_threshold(&mut self, release_threshold: u8) {
-        self.release_threshold = release_threshold;
+        self.release_thresholds = [[release_threshold; CS]; RS];
+    }
+
+    /// Update the thresholds to register a key press and a key release for a given key.
+    ///
+    /// In the rapid trigger modes, these determine how far a key needs to move down to register
+    /// an actuation, and how far it needs to move up to register a release. Lower values make the
+    /// key more sensitive.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use keyberon::analog::{AnalogAcutationMode, AnalogActuator};
+    /// use keyberon::layout::Event;
+    /// let mut actuator = AnalogActuator::new(
+    ///     [[AnalogAcutationMode::Rapid; 2]; 1],
+    ///     [[127; 2]; 1],
+    /// );
+    /// actuator.set_sensitivity(0, 1, 20, 20).ok();
+    ///
+    /// actuator.events([[200, 200]]).count();
+    ///
+    /// // Only `(0, 0)` has moved up enough to be released
+    /// assert_eq!(
+    ///     vec![Event::Release(0, 0)],
+    ///     actuator.events([[190, 190]]).collect::<Vec<_>>(),
+    /// );
+    /// ```
+    pub fn set_sensitivity(
+        &mut self,
+        row: usize,
+        col: usize,
+        press_threshold: u8,
+        release_threshold: u8,
+    ) -> Result<(), AnalogActuatorError> {
+        let press = self
+            .press_thresholds
+            .get_mut(row)
+            .and_then(|row| row.get_mut(col));
+        let release = self
+            .release_thresholds
+            .get_mut(row)
+            .and_then(|row| row.get_mut(col));
+
+        match (press, release) {
+            (Some(press), Some(release)) => {
+                *press = press_threshold;
+                *release = release_threshold;
+                Ok(())
+            }
+            _ => Err(AnalogActuatorError::InvalidLocation),
+        }
     }
 
     /// Iterates on the `Event`s generated by the update.
@@ -133,25 +238,34 @@ impl<const CS: usize, const RS: usize> AnalogActuator<CS, RS> {
     pub fn events(&mut self, new: [[u8; CS]; RS]) -> impl Iterator<Item = Event> + '_ {
         self.new_state = new;
 
-        let press_threshold = self.press_threshold;
-        let release_threshold = self.release_threshold;
-
         self.cur_state
             .iter_mut()
             .zip(self.cur_actuated.iter_mut())
             .zip(self.actuation_points.iter())
             .zip(self.modes.iter())
             .zip(self.new_state.iter())
+            .zip(
+                self.press_thresholds
+                    .iter()
+                    .zip(self.release_thresholds.iter()),
+            )
             .enumerate()
-            .flat_map(move |(row, ((((o, a), p), m), n))| {
+            .flat_map(move |(row, (((((o, a), p), m), n), (pt, rt)))| {
                 o.iter_mut()
                     .zip(a.iter_mut())
                     .zip(p.iter())
                     .zip(m.iter())
                     .zip(n.iter())
+                    .zip(pt.iter().zip(rt.iter()))
                     .enumerate()
                     .filter_map(
-                        move |(col, ((((cur, actuated), actuation_point), mode), new))| {
+                        move |(
+                            col,
+                            (
+                                ((((cur, actuated), actuation_point), mode), new),
+                                (&press_threshold, &release_threshold),
+                            ),
+                        )| {
                             let mut event = None;
 
                             match mode {
@@ -229,3 +343,135 @@ impl<const CS: usize, const RS: usize> AnalogActuator<CS, RS> {
             })
     }
 }
+
+/// Strategies to resolve simultaneous opposing cardinal directions (SOCD), or in other words,
+/// what to do when both keys in a [`SocdPair`] are held down.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocdMode {
+    /// The most recently pressed key is considered pressed, and the other key is released. If the
+    /// most recently pressed key is released while the other key is still held, the other key is
+    /// pressed again.
+    #[default]
+    LastInput,
+    /// Neither key is considered pressed while both keys are held.
+    Neutral,
+}
+
+/// A pair of opposing keys, identified by their matrix positions as `(row, col)`.
+#[derive(Clone, Copy)]
+pub struct SocdPair {
+    keys: [(u8, u8); 2],
+    mode: SocdMode,
+    held: [bool; 2],
+    active: [bool; 2],
+    last: usize,
+}
+
+impl SocdPair {
+    /// Create a new pair of opposing keys, using the given [`SocdMode`] to resolve presses when
+    /// both keys are held down.
+    pub const fn new(first: (u8, u8), second: (u8, u8), mode: SocdMode) -> Self {
+        Self {
+            keys: [first, second],
+            mode,
+            held: [false; 2],
+            active: [false; 2],
+            last: 0,
+        }
+    }
+
+    fn resolve(&mut self, idx: usize, pressed: bool) -> [Option<Event>; 2] {
+        self.held[idx] = pressed;
+        if pressed {
+            self.last = idx;
+        }
+
+        let desired = match (self.held, self.mode) {
+            ([true, true], SocdMode::LastInput) => {
+                let mut desired = [false; 2];
+                desired[self.last] = true;
+                desired
+            }
+            ([true, true], SocdMode::Neutral) => [false; 2],
+            (held, _) => held,
+        };
+
+        // Releases are reported before presses, so that both keys are never pressed at once
+        let mut events = [None; 2];
+        let mut slots = events.iter_mut();
+        for pressed in [false, true] {
+            for ((active, desired), (i, j)) in self.active.iter_mut().zip(desired).zip(self.keys) {
+                if *active != desired && desired == pressed {
+                    *active = pressed;
+                    *slots.next().unwrap() = Some(if pressed {
+                        Event::Press(i, j)
+                    } else {
+                        Event::Release(i, j)
+                    });
+                }
+            }
+        }
+
+        events
+    }
+}
+
+/// Resolves events for pairs of opposing keys, so that only one key in each pair is considered
+/// pressed at a time.
+///
+/// If a key belongs to more than one pair, only the first pair is used.
+pub struct SocdResolver<const N: usize> {
+    pairs: [SocdPair; N],
+}
+
+impl<const N: usize> SocdResolver<N> {
+    /// Create a new resolver for the given pairs of keys.
+    pub const fn new(pairs: [SocdPair; N]) -> Self {
+        Self { pairs }
+    }
+
+    /// Iterates on the resolved events for a given event. Events for keys that don't belong to
+    /// any pair are passed through unchanged.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use keyberon::analog::{SocdMode, SocdPair, SocdResolver};
+    /// use keyberon::layout::Event;
+    /// let mut socd = SocdResolver::new([SocdPair::new((0, 0), (0, 1), SocdMode::LastInput)]);
+    ///
+    /// socd.process(Event::Press(0, 0)).count();
+    ///
+    /// // `(0, 1)` was pressed last, so `(0, 0)` is released
+    /// assert_eq!(
+    ///     vec![Event::Release(0, 0), Event::Press(0, 1)],
+    ///     socd.process(Event::Press(0, 1)).collect::<Vec<_>>(),
+    /// );
+    ///
+    /// // `(0, 0)` is still held, so it gets pressed again
+    /// assert_eq!(
+    ///     vec![Event::Release(0, 1), Event::Press(0, 0)],
+    ///     socd.process(Event::Release(0, 1)).collect::<Vec<_>>(),
+    /// );
+    ///
+    /// // `(1, 0)` is not part of a pair
+    /// assert_eq!(
+    ///     vec![Event::Press(1, 0)],
+    ///     socd.process(Event::Press(1, 0)).collect::<Vec<_>>(),
+    /// );
+    /// ```
+    pub fn process(&mut self, event: Event) -> impl Iterator<Item = Event> {
+        let coord = event.coord();
+
+        let events = self
+            .pairs
+            .iter_mut()
+            .find_map(|pair| {
+                let idx = pair.keys.iter().position(|&key| key == coord)?;
+                Some(pair.resolve(idx, event.is_press()))
+            })
+            .unwrap_or([Some(event), None]);
+
+        IntoIterator::into_iter(events).flatten()
+    }
+}
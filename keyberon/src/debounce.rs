@@ -1,20 +1,38 @@
 //! Debouncer definition.
 //!
 //! When pressed, switches don't give a clear state change: they
-//! bounce. A debouncer filter these bounces. The current
+//! bounce. A debouncer filter these bounces. By default, the
 //! implementation validate the state change when the state is stable
 //! during a configurable number of update. 5 ms is the recommended
-//! duration for keyboard switches.
+//! duration for keyboard switches. See [`DebounceAlgorithm`] for the
+//! other available strategies.
 
 use crate::layout::Event;
 use either::Either::*;
 
+/// The strategy used by a [`Debouncer`] to filter bounces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DebounceAlgorithm {
+    /// Validate a state change once the state has been stable during
+    /// `nb_bounce` updates. This filters chatter well, but every
+    /// change is delayed by `nb_bounce` updates.
+    #[default]
+    Symmetric,
+    /// Validate a state change as soon as it is seen, then ignore any
+    /// changes during the next `nb_bounce` updates. This minimizes
+    /// latency, but a switch that bounces for longer than the lockout
+    /// period can generate spurious events. Changes to other keys that
+    /// happen during the lockout period are validated when it ends.
+    Eager,
+}
+
 /// The debouncer type.
 pub struct Debouncer<T> {
     cur: T,
     new: T,
     since: u16,
     nb_bounce: u16,
+    algorithm: DebounceAlgorithm,
 }
 
 impl<T> Debouncer<T> {
@@ -27,11 +45,27 @@ impl<T> Debouncer<T> {
     /// `nb_bounce` correspond to the number of update with same state
     /// needed to validate the new state.
     pub const fn new(cur: T, new: T, nb_bounce: u16) -> Self {
+        Self::new_with_algorithm(cur, new, nb_bounce, DebounceAlgorithm::Symmetric)
+    }
+
+    /// Create a new debouncer that uses the given
+    /// [`DebounceAlgorithm`]. The other parameters are the same as
+    /// [`Debouncer::new`].
+    ///
+    /// For [`DebounceAlgorithm::Eager`], `nb_bounce` correspond to
+    /// the number of updates that are ignored after a state change.
+    pub const fn new_with_algorithm(
+        cur: T,
+        new: T,
+        nb_bounce: u16,
+        algorithm: DebounceAlgorithm,
+    ) -> Self {
         Self {
             cur,
             new,
             since: 0,
             nb_bounce,
+            algorithm,
         }
     }
 }
@@ -44,6 +78,30 @@ impl<T: PartialEq> Debouncer<T> {
 
     /// Updates the current state.  Returns `true` if the state changes.
     pub fn update(&mut self, new: T) -> bool {
+        match self.algorithm {
+            DebounceAlgorithm::Symmetric => self.update_symmetric(new),
+            DebounceAlgorithm::Eager => self.update_eager(new),
+        }
+    }
+
+    fn update_eager(&mut self, new: T) -> bool {
+        // ignore any changes until the lockout period is over
+        if self.since > 0 {
+            self.since -= 1;
+            return false;
+        }
+
+        if self.cur == new {
+            return false;
+        }
+
+        self.new = new;
+        core::mem::swap(&mut self.cur, &mut self.new);
+        self.since = self.nb_bounce;
+        true
+    }
+
+    fn update_symmetric(&mut self, new: T) -> bool {
         if self.cur == new {
             self.since = 0;
             return false;
@@ -122,3 +180,76 @@ impl<T: PartialEq> Debouncer<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{DebounceAlgorithm, Debouncer};
+    use crate::layout::{Event, Event::*};
+    extern crate std;
+    use std::vec::Vec;
+
+    /// A key that bounces for 3 updates when pressed, stays pressed,
+    /// then bounces for 2 updates when released.
+    const BOUNCING_SIGNAL: [bool; 14] = [
+        false, true, false, true, true, true, true, true, true, false, true, false, false, false,
+    ];
+
+    fn run(signal: &[bool], algorithm: DebounceAlgorithm, nb_bounce: u16) -> Vec<(usize, Event)> {
+        let mut debouncer =
+            Debouncer::new_with_algorithm([[false]], [[false]], nb_bounce, algorithm);
+        signal
+            .iter()
+            .enumerate()
+            .flat_map(|(i, state)| {
+                debouncer
+                    .events([[*state]])
+                    .map(move |event| (i, event))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn symmetric_waits_for_stable_state() {
+        assert_eq!(
+            run(&BOUNCING_SIGNAL, DebounceAlgorithm::Symmetric, 2),
+            &[(5, Press(0, 0)), (13, Release(0, 0))]
+        );
+    }
+
+    #[test]
+    fn eager_reports_immediately() {
+        assert_eq!(
+            run(&BOUNCING_SIGNAL, DebounceAlgorithm::Eager, 3),
+            &[(1, Press(0, 0)), (9, Release(0, 0))]
+        );
+    }
+
+    #[test]
+    fn eager_lockout_too_short() {
+        // the key bounces for longer than the lockout period, so the
+        // bounce is reported
+        assert_eq!(
+            run(
+                &[false, true, false, false, true, true],
+                DebounceAlgorithm::Eager,
+                1
+            ),
+            &[(1, Press(0, 0)), (3, Release(0, 0)), (5, Press(0, 0))]
+        );
+    }
+
+    #[test]
+    fn eager_no_lockout() {
+        let mut debouncer =
+            Debouncer::new_with_algorithm([[false]], [[false]], 0, DebounceAlgorithm::Eager);
+        assert_eq!(
+            debouncer.events([[true]]).collect::<Vec<_>>(),
+            &[Press(0, 0)]
+        );
+        assert_eq!(
+            debouncer.events([[false]]).collect::<Vec<_>>(),
+            &[Release(0, 0)]
+        );
+    }
+}
@@ -48,8 +48,8 @@ pub use keyberon_macros::*;
 use num_traits::FromPrimitive;
 
 use crate::action::{
-    Action, HoldTapAction, HoldTapConfig, OneShotAction, OneShotEndConfig, TapDanceAction,
-    TapDanceConfig,
+    Action, HoldTapAction, HoldTapConfig, OneShotAction, OneShotEndConfig, StickyLayerAction,
+    TapDanceAction, TapDanceConfig,
 };
 use crate::key_code::KeyCode;
 use arraydeque::ArrayDeque;
@@ -77,6 +77,13 @@ pub type Layers<
 /// Events can be retrieved by iterating over this struct and calling [Stacked::event].
 type Stack = ArrayDeque<[Stacked; 16], arraydeque::behavior::Wrapping>;
 
+/// Coordinates of sticky layer keys that are still held, along with the remaining ticks before
+/// they stop being one shot keys.
+type HeldStickyKeys = ArrayDeque<[((u8, u8), u16); 16], arraydeque::behavior::Wrapping>;
+
+/// Coordinates of a hold-tap key that can still be retro-tapped, and its tap action.
+type RetroTap<T, K> = ((u8, u8), &'static Action<T, K>);
+
 /// The layout manager. It takes `Event`s and `tick`s as input, and
 /// generate keyboard reports.
 pub struct Layout<
@@ -98,6 +105,12 @@ pub struct Layout<
     active_sequences: ArrayDeque<[SequenceState; 4], arraydeque::behavior::Wrapping>,
     stacked: Stack,
     tap_hold_tracker: TapHoldTracker,
+    retro_tap: Option<RetroTap<T, K>>,
+    action_overrides: Vec<((u8, u8), Action<T, K>), 16>,
+    tapping_term: Option<u16>,
+    hold_tap_config: Option<HoldTapConfig>,
+    oneshot_timeout: Option<u16>,
+    tri_layer: Option<(usize, usize, usize)>,
 }
 
 /// An event on the key matrix.
@@ -186,11 +199,29 @@ impl<T: Copy> CustomEvent<T> {
 
 #[derive(Debug, Eq, PartialEq)]
 enum State<T: 'static + Copy, K: 'static + Copy> {
-    NormalKey { keycode: K, coord: (u8, u8) },
-    FakeKey { keycode: K },
-    MomentaryLayerModifier { value: usize, coord: (u8, u8) },
-    ToggleLayerModifier { value: usize },
-    Custom { value: T, coord: (u8, u8) },
+    NormalKey {
+        keycode: K,
+        coord: (u8, u8),
+    },
+    FakeKey {
+        keycode: K,
+    },
+    MomentaryLayerModifier {
+        value: usize,
+        coord: (u8, u8),
+    },
+    TimedMomentaryLayerModifier {
+        value: usize,
+        coord: (u8, u8),
+        remaining: u16,
+    },
+    ToggleLayerModifier {
+        value: usize,
+    },
+    Custom {
+        value: T,
+        coord: (u8, u8),
+    },
 }
 impl<T: 'static + Copy, K: 'static + Copy> Copy for State<T, K> {}
 impl<T: 'static + Copy, K: 'static + Copy> Clone for State<T, K> {
@@ -208,11 +239,28 @@ impl<T: 'static + Copy, K: 'static + Copy + PartialEq> State<T, K> {
         }
     }
     fn tick(&self) -> Option<Self> {
-        Some(*self)
+        match *self {
+            TimedMomentaryLayerModifier {
+                value,
+                coord,
+                remaining,
+            } => (remaining > 1).then_some(TimedMomentaryLayerModifier {
+                value,
+                coord,
+                remaining: remaining - 1,
+            }),
+            _ => Some(*self),
+        }
     }
     fn release(&self, c: (u8, u8), custom: &mut CustomEvent<T>) -> Option<Self> {
         match *self {
-            NormalKey { coord, .. } | MomentaryLayerModifier { coord, .. } if coord == c => None,
+            NormalKey { coord, .. }
+            | MomentaryLayerModifier { coord, .. }
+            | TimedMomentaryLayerModifier { coord, .. }
+                if coord == c =>
+            {
+                None
+            }
             Custom { value, coord } if coord == c => {
                 custom.update(CustomEvent::Release(value));
                 None
@@ -229,6 +277,7 @@ impl<T: 'static + Copy, K: 'static + Copy + PartialEq> State<T, K> {
     fn get_layer(&self) -> Option<usize> {
         match self {
             MomentaryLayerModifier { value, .. } => Some(*value),
+            TimedMomentaryLayerModifier { value, .. } => Some(*value),
             ToggleLayerModifier { value, .. } => Some(*value),
             _ => None,
         }
@@ -260,7 +309,7 @@ impl<T, K> WaitingState<T, K> {
     fn tick(&mut self, stacked: &Stack) -> Option<WaitingAction> {
         self.timeout = self.timeout.saturating_sub(1);
         match self.config {
-            HoldTapConfig::Default => (),
+            HoldTapConfig::Default | HoldTapConfig::RetroTap => (),
             HoldTapConfig::HoldOnOtherKeyPress => {
                 if stacked.iter().any(|s| s.event.is_press()) {
                     return Some(WaitingAction::Hold);
@@ -282,6 +331,21 @@ impl<T, K> WaitingState<T, K> {
                     return waiting_action;
                 }
             }
+            HoldTapConfig::ChordalHold(hand) => {
+                // Only presses that happened before the release of this key are considered.
+                for s in stacked.iter() {
+                    if self.is_corresponding_release(&s.event) {
+                        break;
+                    }
+                    if let Event::Press(i, j) = s.event {
+                        return if hand(self.coord).is_same_hand(hand((i, j))) {
+                            Some(WaitingAction::Tap)
+                        } else {
+                            Some(WaitingAction::Hold)
+                        };
+                    }
+                }
+            }
         }
         if let Some(&Stacked { since, .. }) = stacked
             .iter()
@@ -366,6 +430,9 @@ struct OneShotState {
     end_config: OneShotEndConfig,
     /// Marks if release of the one shot keys should be done on the next tick
     release_on_next_tick: bool,
+    /// KCoordinates of sticky layer keys that are still held, along with the remaining ticks
+    /// before they stop being one shot keys.
+    held_sticky_keys: HeldStickyKeys,
 }
 
 impl OneShotState {
@@ -376,12 +443,24 @@ impl OneShotState {
 
         self.timeout = self.timeout.saturating_sub(1);
 
-        if !self.release_on_next_tick && self.timeout > 0 {
+        // Sticky layer keys that are held for long enough are no longer treated as one shot keys,
+        // so their release will be processed normally.
+        for (coord, remaining) in self.held_sticky_keys.iter_mut() {
+            *remaining = remaining.saturating_sub(1);
+            if *remaining == 0 {
+                self.active_oneshot_keys.retain(|c| c != coord);
+            }
+        }
+        self.held_sticky_keys
+            .retain(|(_, remaining)| *remaining > 0);
+
+        if !self.release_on_next_tick && self.timeout > 0 && !self.active_oneshot_keys.is_empty() {
             return None;
         }
 
         self.active_oneshot_keys.clear();
         self.other_pressed_keys.clear();
+        self.held_sticky_keys.clear();
         Some(self.released_oneshot_keys.drain(..).collect())
     }
 
@@ -418,6 +497,8 @@ impl OneShotState {
     }
 
     fn handle_release(&mut self, coord: (u8, u8)) -> (bool, Option<Vec<(u8, u8), 16>>) {
+        self.held_sticky_keys.retain(|(c, _)| *c != coord);
+
         if matches!(
             self.end_config,
             OneShotEndConfig::EndOnFirstRelease | OneShotEndConfig::EndOnFirstReleaseOrRepress
@@ -595,6 +676,12 @@ impl<
             active_sequences: ArrayDeque::new(),
             stacked: ArrayDeque::new(),
             tap_hold_tracker: Default::default(),
+            retro_tap: None,
+            action_overrides: Vec::new(),
+            tapping_term: None,
+            hold_tap_config: None,
+            oneshot_timeout: None,
+            tri_layer: None,
         }
     }
     /// Check if the layout is in a state where it needs to be ticked repeatedly. This can be used
@@ -606,6 +693,10 @@ impl<
             || self.waiting.is_some()
             || self.oneshot.is_some()
             || self.tap_hold_tracker.timeout > 0
+            || self
+                .states
+                .iter()
+                .any(|s| matches!(s, TimedMomentaryLayerModifier { .. }))
     }
     /// Iterates on the key codes of the current state.
     pub fn keycodes(&self) -> impl Iterator<Item = K> + '_ {
@@ -615,6 +706,10 @@ impl<
         if let Some(w) = &self.waiting {
             let hold = w.hold;
             let coord = w.coord;
+            self.retro_tap = match w.config {
+                HoldTapConfig::RetroTap => Some((coord, w.tap)),
+                _ => None,
+            };
             self.waiting = None;
             if coord == self.tap_hold_tracker.coord {
                 self.tap_hold_tracker.timeout = 0;
@@ -906,10 +1001,32 @@ impl<
                         .retain(|s| s.release((i, j), &mut custom).is_some());
                 }
 
+                // Nothing interrupted the hold of a retro tap key, so tap it now. The tap is
+                // released on the next tick.
+                if let Some((coord, tap)) = self.retro_tap {
+                    if coord == (i, j) {
+                        self.retro_tap = None;
+                        custom.update(self.do_action(
+                            *tap,
+                            coord,
+                            0,
+                            &mut ActionContext::default(),
+                        ));
+                        self.stacked.push_front(Release(i, j).into());
+                    }
+                }
+
                 custom
             }
             Press(i, j) => {
-                let action = self.press_as_action((i, j), self.current_layer());
+                if self.retro_tap.is_some_and(|(coord, _)| coord != (i, j)) {
+                    self.retro_tap = None;
+                }
+
+                let action = match self.action_overrides.iter().position(|(c, _)| *c == (i, j)) {
+                    Some(idx) => self.action_overrides.swap_remove(idx).1,
+                    None => self.press_as_action((i, j), self.current_layer()),
+                };
                 self.do_action(action, (i, j), stacked.since, &mut ActionContext::default())
             }
         }
@@ -921,6 +1038,19 @@ impl<
             self.unstack(stacked);
         }
     }
+    /// Register a key event. If the event is a press, `action` is used instead of the action
+    /// defined in the layout for the given coordinates. The coordinates do not need to exist in
+    /// the layout, which makes it possible to press "virtual" keys (e.g. for combos).
+    ///
+    /// The corresponding release event should also be registered using [`Self::event`].
+    pub fn event_with_action(&mut self, event: Event, action: Action<T, K>) {
+        if let Event::Press(i, j) = event {
+            if self.action_overrides.push(((i, j), action)).is_err() {
+                return;
+            }
+        }
+        self.event(event);
+    }
     fn press_as_action(&self, coord: (u8, u8), layer: usize) -> Action<T, K> {
         use crate::action::Action::*;
         let action = self
@@ -954,6 +1084,37 @@ impl<
             .map(|a| *a = action)
             .ok_or(ChangeActionError::OutOfBounds)
     }
+    /// Changes the action for a given key on every layer. If the coordinates do not exist in the
+    /// layout, no layers are changed.
+    pub fn set_action_for_coord_on_all_layers(
+        &mut self,
+        coord: (u8, u8),
+        action: Action<T, K>,
+    ) -> Result<(), ChangeActionError> {
+        if coord.0 as usize >= R || coord.1 as usize >= C {
+            return Err(ChangeActionError::OutOfBounds);
+        }
+
+        for layer in self.layers.iter_mut() {
+            layer[coord.0 as usize][coord.1 as usize] = action;
+        }
+
+        Ok(())
+    }
+    /// Returns the number of layers that are in use, i.e. the index of the highest layer that
+    /// contains at least one action other than `NoOp` or `Trans`, plus one. Returns `0` if every
+    /// layer is empty.
+    pub fn live_layer_count(&self) -> usize {
+        self.layers
+            .iter()
+            .rposition(|layer| {
+                layer
+                    .iter()
+                    .flatten()
+                    .any(|action| !matches!(action, Action::NoOp | Action::Trans))
+            })
+            .map_or(0, |layer| layer + 1)
+    }
     /// Get a copy of the action for a given key
     pub fn get_action(&mut self, coord: (u8, u8), layer: usize) -> Option<Action<T, K>> {
         self.layers
@@ -986,13 +1147,17 @@ impl<
                     || coord != self.tap_hold_tracker.coord
                     || self.tap_hold_tracker.timeout == 0
                 {
+                    let config = match (config, self.hold_tap_config) {
+                        (HoldTapConfig::Default, Some(config)) => config,
+                        (config, _) => *config,
+                    };
                     let waiting: WaitingState<T, K> = WaitingState {
                         coord,
-                        timeout: *timeout,
+                        timeout: self.tapping_term.unwrap_or(*timeout),
                         delay,
                         hold,
                         tap,
-                        config: *config,
+                        config,
                     };
                     self.waiting = Some(waiting);
                     self.tap_hold_tracker.timeout = *tap_hold_interval;
@@ -1012,28 +1177,34 @@ impl<
                 context.inside_oneshot = true;
                 let custom = self.do_action(action, coord, delay, context);
                 context.inside_oneshot = false;
-                let overflow = if let Some(oneshot) = &mut self.oneshot {
-                    oneshot.end_config = end_config;
-                    oneshot.timeout = timeout;
-                    oneshot.handle_press(coord, true)
-                } else {
-                    let mut oneshot = OneShotState {
-                        active_oneshot_keys: ArrayDeque::new(),
-                        released_oneshot_keys: ArrayDeque::new(),
-                        other_pressed_keys: ArrayDeque::new(),
-                        timeout,
-                        end_config,
-                        release_on_next_tick: false,
-                    };
-                    let overflow = oneshot.handle_press(coord, true);
-                    self.oneshot = Some(oneshot);
-                    overflow
-                };
-                if let Some((i, j)) = overflow {
+                let timeout = self.oneshot_timeout.unwrap_or(timeout);
+                if let Some((i, j)) = self.activate_oneshot(coord, timeout, end_config) {
                     self.event(Event::Release(i, j))
                 }
                 return custom;
             }
+            StickyLayer(&StickyLayerAction {
+                layer,
+                timeout,
+                hold_timeout,
+                end_config,
+            }) => {
+                self.tap_hold_tracker.coord = coord;
+                context.inside_oneshot = true;
+                self.do_action(Layer(layer), coord, delay, context);
+                context.inside_oneshot = false;
+                let timeout = self.oneshot_timeout.unwrap_or(timeout);
+                let overflow = self.activate_oneshot(coord, timeout, end_config);
+                if let Some(oneshot) = &mut self.oneshot {
+                    oneshot.held_sticky_keys.retain(|(c, _)| *c != coord);
+                    if hold_timeout > 0 {
+                        oneshot.held_sticky_keys.push_back((coord, hold_timeout));
+                    }
+                }
+                if let Some((i, j)) = overflow {
+                    self.event(Event::Release(i, j))
+                }
+            }
             TapDance(tapdance_action) => {
                 self.tap_hold_tracker.coord = coord;
                 let mut custom = if let Some(tapdance_state) = &mut self.tapdance {
@@ -1105,6 +1276,17 @@ impl<
                 let _ = self.states.push(MomentaryLayerModifier { value, coord });
                 self.handle_terminal_action(coord, context);
             }
+            TimedMomentaryLayer { layer, timeout } => {
+                self.tap_hold_tracker.coord = coord;
+                if timeout > 0 {
+                    let _ = self.states.push(TimedMomentaryLayerModifier {
+                        value: layer,
+                        coord,
+                        remaining: timeout,
+                    });
+                }
+                self.handle_terminal_action(coord, context);
+            }
             ToggleLayer(value) => {
                 self.tap_hold_tracker.coord = coord;
                 let mut removed = false;
@@ -1137,6 +1319,32 @@ impl<
         CustomEvent::NoEvent
     }
 
+    fn activate_oneshot(
+        &mut self,
+        coord: (u8, u8),
+        timeout: u16,
+        end_config: OneShotEndConfig,
+    ) -> Option<(u8, u8)> {
+        if let Some(oneshot) = &mut self.oneshot {
+            oneshot.end_config = end_config;
+            oneshot.timeout = timeout;
+            oneshot.handle_press(coord, true)
+        } else {
+            let mut oneshot = OneShotState {
+                active_oneshot_keys: ArrayDeque::new(),
+                released_oneshot_keys: ArrayDeque::new(),
+                other_pressed_keys: ArrayDeque::new(),
+                timeout,
+                end_config,
+                release_on_next_tick: false,
+                held_sticky_keys: ArrayDeque::new(),
+            };
+            let overflow = oneshot.handle_press(coord, true);
+            self.oneshot = Some(oneshot);
+            overflow
+        }
+    }
+
     fn handle_terminal_action(&mut self, coord: (u8, u8), context: &mut ActionContext) {
         // ignore actions activated by a oneshot
         if !context.inside_oneshot {
@@ -1156,11 +1364,27 @@ impl<
 
     /// Obtain the index of the current active layer
     pub fn current_layer(&self) -> usize {
-        self.states
+        let layer = self
+            .states
             .iter()
             .rev()
             .find_map(State::get_layer)
-            .unwrap_or(self.default_layer)
+            .unwrap_or(self.default_layer);
+
+        match self.tri_layer {
+            Some((lower, upper, adjust))
+                if (layer == lower || layer == upper)
+                    && self.is_layer_active(lower)
+                    && self.is_layer_active(upper) =>
+            {
+                adjust
+            }
+            _ => layer,
+        }
+    }
+
+    fn is_layer_active(&self, layer: usize) -> bool {
+        self.states.iter().any(|s| s.get_layer() == Some(layer))
     }
 
     /// Sets the default layer for the layout
@@ -1169,6 +1393,80 @@ impl<
             self.default_layer = value
         }
     }
+
+    /// Deactivate every momentary and toggled layer, so that the default layer becomes the
+    /// current layer. Keys that are still held keep their state.
+    pub fn reset_to_default_layer(&mut self) {
+        self.states.retain(|s| s.get_layer().is_none());
+    }
+
+    /// Forget every held key, pending hold-tap, one shot, tap dance, sequence and stacked
+    /// event, and reset to the default layer. This can be used to recover from a layout that is
+    /// stuck in an unexpected state (e.g. after losing events from a split peripheral).
+    ///
+    /// No release events are generated, so any [`Action::Custom`] that is currently held will not
+    /// receive a [`CustomEvent::Release`].
+    pub fn clear_all_states(&mut self) {
+        self.states.clear();
+        self.waiting = None;
+        self.oneshot = None;
+        self.tapdance = None;
+        self.active_sequences.clear();
+        self.stacked.clear();
+        self.tap_hold_tracker = Default::default();
+        self.retro_tap = None;
+    }
+
+    /// Override the timeout of every [`HoldTapAction`] in the layout. Setting this to `None`
+    /// makes each hold-tap use its own timeout again.
+    ///
+    /// Hold-taps that are already waiting for a decision are not affected.
+    pub fn set_tapping_term(&mut self, tapping_term: Option<u16>) {
+        self.tapping_term = tapping_term
+    }
+
+    /// Obtain the timeout override set by [`Self::set_tapping_term`].
+    pub fn tapping_term(&self) -> Option<u16> {
+        self.tapping_term
+    }
+
+    /// Override the behavior of every [`HoldTapAction`] that uses [`HoldTapConfig::Default`].
+    /// Hold-taps with any other configuration keep their own behavior. Setting this to `None`
+    /// makes each hold-tap use its own configuration again.
+    pub fn set_hold_tap_config(&mut self, config: Option<HoldTapConfig>) {
+        self.hold_tap_config = config
+    }
+
+    /// Obtain the configuration override set by [`Self::set_hold_tap_config`].
+    pub fn hold_tap_config(&self) -> Option<HoldTapConfig> {
+        self.hold_tap_config
+    }
+
+    /// Override the timeout of every [`OneShotAction`] and [`StickyLayerAction`] in the layout.
+    /// Setting this to `None` makes each one shot key use its own timeout again.
+    pub fn set_oneshot_timeout(&mut self, timeout: Option<u16>) {
+        self.oneshot_timeout = timeout
+    }
+
+    /// Obtain the timeout override set by [`Self::set_oneshot_timeout`].
+    pub fn oneshot_timeout(&self) -> Option<u16> {
+        self.oneshot_timeout
+    }
+
+    /// Set up a tri-layer, as `(lower, upper, adjust)`. While both the `lower` and `upper` layers
+    /// are active, the `adjust` layer becomes the current layer. Setting this to `None` disables
+    /// the tri-layer.
+    ///
+    /// The `adjust` layer is only used if the layer that would otherwise be current is `lower` or
+    /// `upper`, so other layers activated on top of them still take priority.
+    pub fn set_tri_layer(&mut self, tri_layer: Option<(usize, usize, usize)>) {
+        self.tri_layer = tri_layer.filter(|(_, _, adjust)| *adjust < self.layers.len())
+    }
+
+    /// Obtain the tri-layer set by [`Self::set_tri_layer`].
+    pub fn tri_layer(&self) -> Option<(usize, usize, usize)> {
+        self.tri_layer
+    }
 }
 
 #[cfg(test)]
@@ -1176,10 +1474,10 @@ mod test {
     extern crate std;
     use super::{Event::*, Layout, *};
     use crate::action::Action::*;
-    use crate::action::HoldTapConfig;
     use crate::action::OneShotAction;
     use crate::action::TapDanceAction;
     use crate::action::{k, l, m, t};
+    use crate::action::{Hand, HoldTapConfig};
     use crate::key_code::KeyCode;
     use crate::key_code::KeyCode::*;
     use std::collections::BTreeSet;
@@ -1251,6 +1549,121 @@ mod test {
         assert_keys(&[], layout.keycodes());
     }
 
+    #[test]
+    fn retro_tap_hold_tap() {
+        static mut LAYERS: Layers<2, 1, 1> = [[[
+            HoldTap(&HoldTapAction {
+                timeout: 200,
+                hold: k(LCtrl),
+                tap: k(Space),
+                config: HoldTapConfig::RetroTap,
+                tap_hold_interval: 0,
+            }),
+            k(A),
+        ]]];
+        let mut layout = Layout::new(unsafe { &mut LAYERS });
+
+        // Released before the timeout, this is a regular tap
+        layout.event(Press(0, 0));
+        for _ in 0..10 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert_keys(&[], layout.keycodes());
+        }
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[Space], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(!layout.is_active());
+        assert_keys(&[], layout.keycodes());
+
+        // Held past the timeout without pressing another key, the tap is sent on release
+        layout.event(Press(0, 0));
+        for _ in 0..200 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert_keys(&[], layout.keycodes());
+        }
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[LCtrl], layout.keycodes());
+        for _ in 0..100 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert_keys(&[LCtrl], layout.keycodes());
+        }
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(layout.is_active());
+        assert_keys(&[Space], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(!layout.is_active());
+        assert_keys(&[], layout.keycodes());
+
+        // Pressing another key while holding cancels the retro tap
+        layout.event(Press(0, 0));
+        for _ in 0..201 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+        }
+        assert_keys(&[LCtrl], layout.keycodes());
+        layout.event(Press(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[LCtrl, A], layout.keycodes());
+        layout.event(Release(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[LCtrl], layout.keycodes());
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(!layout.is_active());
+        assert_keys(&[], layout.keycodes());
+    }
+
+    #[test]
+    fn hold_tap_overrides() {
+        static mut LAYERS: Layers<2, 1, 1> = [[[
+            HoldTap(&HoldTapAction {
+                timeout: 200,
+                hold: k(LCtrl),
+                tap: k(Space),
+                config: HoldTapConfig::Default,
+                tap_hold_interval: 0,
+            }),
+            k(A),
+        ]]];
+        let mut layout = Layout::new(unsafe { &mut LAYERS });
+
+        // The tapping term replaces the timeout of the hold-tap
+        layout.set_tapping_term(Some(50));
+        assert_eq!(Some(50), layout.tapping_term());
+        layout.event(Press(0, 0));
+        for _ in 0..50 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert_keys(&[], layout.keycodes());
+        }
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[LCtrl], layout.keycodes());
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(!layout.is_active());
+        assert_keys(&[], layout.keycodes());
+
+        // The hold-tap config applies to hold-taps that use the default config
+        layout.set_tapping_term(None);
+        layout.set_hold_tap_config(Some(HoldTapConfig::HoldOnOtherKeyPress));
+        layout.event(Press(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+        layout.event(Press(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[LCtrl], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[LCtrl, A], layout.keycodes());
+        layout.event(Release(0, 1));
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(!layout.is_active());
+        assert_keys(&[], layout.keycodes());
+    }
+
     #[test]
     fn hold_tap_interleaved_timeout() {
         static mut LAYERS: Layers<2, 1, 1> = [[[
@@ -1427,48 +1840,154 @@ mod test {
     }
 
     #[test]
-    fn multiple_actions() {
-        static mut LAYERS: Layers<2, 1, 2> = [
-            [[MultipleActions(&[l(1), k(LShift)].as_slice()), k(F)]],
-            [[Trans, k(E)]],
-        ];
+    fn chordal_hold() {
+        fn hand((_, j): (u8, u8)) -> Hand {
+            match j {
+                0..=1 => Hand::Left,
+                2 => Hand::Right,
+                _ => Hand::Any,
+            }
+        }
+        static mut LAYERS: Layers<4, 1, 1> = [[[
+            HoldTap(&HoldTapAction {
+                timeout: 200,
+                hold: k(LShift),
+                tap: k(A),
+                config: HoldTapConfig::ChordalHold(hand),
+                tap_hold_interval: 0,
+            }),
+            k(S),
+            k(J),
+            k(Space),
+        ]]];
         let mut layout = Layout::new(unsafe { &mut LAYERS });
         assert_eq!(CustomEvent::NoEvent, layout.tick());
         assert!(!layout.is_active());
         assert_keys(&[], layout.keycodes());
+
+        // Pressing a key on the same hand taps
         layout.event(Press(0, 0));
         assert_eq!(CustomEvent::NoEvent, layout.tick());
-        assert!(!layout.is_active());
-        assert_keys(&[LShift], layout.keycodes());
+        assert!(layout.is_active());
+        assert_keys(&[], layout.keycodes());
         layout.event(Press(0, 1));
         assert_eq!(CustomEvent::NoEvent, layout.tick());
-        assert!(!layout.is_active());
-        assert_keys(&[LShift, E], layout.keycodes());
-        layout.event(Release(0, 1));
+        assert_keys(&[A], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[A, S], layout.keycodes());
         layout.event(Release(0, 0));
+        layout.event(Release(0, 1));
         assert_eq!(CustomEvent::NoEvent, layout.tick());
-        assert!(layout.is_active());
-        assert_keys(&[LShift], layout.keycodes());
         assert_eq!(CustomEvent::NoEvent, layout.tick());
         assert!(!layout.is_active());
         assert_keys(&[], layout.keycodes());
-    }
 
-    #[test]
-    fn custom() {
-        static mut LAYERS: Layers<1, 1, 1, u8> = [[[Action::Custom(42)]]];
-        let mut layout = Layout::new(unsafe { &mut LAYERS });
+        // Pressing a key on the opposite hand holds
+        layout.event(Press(0, 0));
         assert_eq!(CustomEvent::NoEvent, layout.tick());
-        assert!(!layout.is_active());
+        assert!(layout.is_active());
         assert_keys(&[], layout.keycodes());
-
-        // Custom event
-        layout.event(Press(0, 0));
-        assert_eq!(CustomEvent::Press(42), layout.tick());
+        layout.event(Press(0, 2));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[LShift], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[LShift, J], layout.keycodes());
+        layout.event(Release(0, 2));
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
         assert!(!layout.is_active());
         assert_keys(&[], layout.keycodes());
 
-        // nothing more
+        // Keys that belong to any hand hold
+        layout.event(Press(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        layout.event(Press(0, 3));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[LShift], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[LShift, Space], layout.keycodes());
+        layout.event(Release(0, 3));
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(!layout.is_active());
+        assert_keys(&[], layout.keycodes());
+
+        // Presses after the release of the hold-tap key are ignored
+        layout.event(Press(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        layout.event(Release(0, 0));
+        layout.event(Press(0, 2));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[A], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[J], layout.keycodes());
+        layout.event(Release(0, 2));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(!layout.is_active());
+        assert_keys(&[], layout.keycodes());
+
+        // Without another key press, the timeout decides
+        layout.event(Press(0, 0));
+        for _ in 0..200 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert!(layout.is_active());
+            assert_keys(&[], layout.keycodes());
+        }
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[LShift], layout.keycodes());
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(!layout.is_active());
+        assert_keys(&[], layout.keycodes());
+    }
+
+    #[test]
+    fn multiple_actions() {
+        static mut LAYERS: Layers<2, 1, 2> = [
+            [[MultipleActions(&[l(1), k(LShift)].as_slice()), k(F)]],
+            [[Trans, k(E)]],
+        ];
+        let mut layout = Layout::new(unsafe { &mut LAYERS });
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(!layout.is_active());
+        assert_keys(&[], layout.keycodes());
+        layout.event(Press(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(!layout.is_active());
+        assert_keys(&[LShift], layout.keycodes());
+        layout.event(Press(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(!layout.is_active());
+        assert_keys(&[LShift, E], layout.keycodes());
+        layout.event(Release(0, 1));
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(layout.is_active());
+        assert_keys(&[LShift], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(!layout.is_active());
+        assert_keys(&[], layout.keycodes());
+    }
+
+    #[test]
+    fn custom() {
+        static mut LAYERS: Layers<1, 1, 1, u8> = [[[Action::Custom(42)]]];
+        let mut layout = Layout::new(unsafe { &mut LAYERS });
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(!layout.is_active());
+        assert_keys(&[], layout.keycodes());
+
+        // Custom event
+        layout.event(Press(0, 0));
+        assert_eq!(CustomEvent::Press(42), layout.tick());
+        assert!(!layout.is_active());
+        assert_keys(&[], layout.keycodes());
+
+        // nothing more
         assert_eq!(CustomEvent::NoEvent, layout.tick());
         assert!(!layout.is_active());
         assert_keys(&[], layout.keycodes());
@@ -2939,6 +3458,125 @@ mod test {
         assert_keys(&[], layout.keycodes());
     }
 
+    #[test]
+    fn sticky_layer() {
+        static mut LAYERS: Layers<3, 1, 2> = [
+            [[
+                StickyLayer(&StickyLayerAction {
+                    layer: 1,
+                    timeout: 100,
+                    hold_timeout: 50,
+                    end_config: OneShotEndConfig::EndOnFirstPress,
+                }),
+                k(A),
+                k(B),
+            ]],
+            [[Trans, k(C), k(D)]],
+        ];
+        let mut layout = Layout::new(unsafe { &mut LAYERS });
+
+        // Test:
+        // 1. press sticky layer
+        // 2. release sticky layer before hold timeout
+        // 3. press A within timeout
+        // 4. release A
+        layout.event(Press(0, 0));
+        for _ in 0..25 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert!(layout.is_active());
+            assert_eq!(1, layout.current_layer());
+        }
+        layout.event(Release(0, 0));
+        for _ in 0..25 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert!(layout.is_active());
+            assert_eq!(1, layout.current_layer());
+        }
+        layout.event(Press(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[C], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(0, layout.current_layer());
+        assert_keys(&[C], layout.keycodes());
+        layout.event(Release(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(!layout.is_active());
+        assert_keys(&[], layout.keycodes());
+
+        // Test:
+        // 1. press sticky layer
+        // 2. release sticky layer before hold timeout
+        // 3. press A after timeout
+        // 4. release A
+        layout.event(Press(0, 0));
+        layout.event(Release(0, 0));
+        for _ in 0..99 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert!(layout.is_active());
+            assert_eq!(1, layout.current_layer());
+        }
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(!layout.is_active());
+        assert_eq!(0, layout.current_layer());
+        layout.event(Press(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[A], layout.keycodes());
+        layout.event(Release(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+
+        // Test:
+        // 1. press sticky layer
+        // 2. hold sticky layer past hold timeout
+        // 3. release sticky layer
+        // 4. press A
+        // 5. release A
+        layout.event(Press(0, 0));
+        for _ in 0..75 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert_eq!(1, layout.current_layer());
+        }
+        assert!(!layout.is_active());
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(0, layout.current_layer());
+        layout.event(Press(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[A], layout.keycodes());
+        layout.event(Release(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(!layout.is_active());
+        assert_keys(&[], layout.keycodes());
+
+        // Test:
+        // 1. press sticky layer
+        // 2. press A and B before hold timeout
+        // 3. release A and B
+        // 4. release sticky layer
+        layout.event(Press(0, 0));
+        for _ in 0..25 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert_eq!(1, layout.current_layer());
+        }
+        layout.event(Press(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[C], layout.keycodes());
+        layout.event(Press(0, 2));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[C, D], layout.keycodes());
+        layout.event(Release(0, 1));
+        layout.event(Release(0, 2));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(1, layout.current_layer());
+        assert_keys(&[], layout.keycodes());
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(!layout.is_active());
+        assert_eq!(0, layout.current_layer());
+    }
+
     #[test]
     fn tap_dance() {
         static mut LAYERS: Layers<2, 2, 1> = [[
@@ -3878,4 +4516,461 @@ mod test {
         assert!(!layout.is_active());
         assert_keys(&[], layout.keycodes()); // Should still be empty
     }
+
+    #[test]
+    fn sequence_with_via_delay() {
+        // The delay token used by Via (`SS_DELAY(500)`), with the duration in ticks as ASCII
+        // digits. This taps A, waits for 500 ticks, then taps B.
+        static mut LAYERS: Layers<1, 1, 1> = [[[Sequence(
+            &[
+                1,
+                1,
+                crate::key_code::KeyCode::A as u8,
+                1,
+                4,
+                b'5',
+                b'0',
+                b'0',
+                b'|',
+                1,
+                1,
+                crate::key_code::KeyCode::B as u8,
+            ]
+            .as_slice(),
+        )]]];
+        let mut layout = Layout::new(unsafe { &mut LAYERS });
+
+        layout.event(Press(0, 0));
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick()); // Sequence detected & added
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[A], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+
+        // No keys are pressed for 500 ticks
+        for _ in 0..500 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert!(layout.is_active());
+            assert_keys(&[], layout.keycodes());
+        }
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[B], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(!layout.is_active());
+    }
+
+    #[test]
+    fn tap_oneshot() {
+        static mut LAYERS: Layers<3, 1, 1> = [[[
+            crate::tap_oneshot!(k(Space), k(LShift), 100, 200),
+            k(A),
+            k(B),
+        ]]];
+        let mut layout = Layout::new(unsafe { &mut LAYERS });
+
+        // Tapping within the timeout only sends the tap key
+        layout.event(Press(0, 0));
+        for _ in 0..98 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert!(layout.is_active());
+            assert_keys(&[], layout.keycodes());
+        }
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(layout.is_active());
+        assert_keys(&[Space], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(!layout.is_active());
+        assert_keys(&[], layout.keycodes());
+        layout.event(Press(0, 1));
+        layout.event(Release(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[A], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(!layout.is_active());
+        assert_keys(&[], layout.keycodes());
+
+        // Holding until the timeout, then releasing, arms the one shot modifier
+        layout.event(Press(0, 0));
+        for _ in 0..100 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert!(layout.is_active());
+            assert_keys(&[], layout.keycodes());
+        }
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(layout.is_active());
+        assert_keys(&[LShift], layout.keycodes());
+        layout.event(Release(0, 0));
+        for _ in 0..50 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert!(layout.is_active());
+            assert_keys(&[LShift], layout.keycodes());
+        }
+
+        // The one shot modifier applies to the next key, and ends when it is released
+        layout.event(Press(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(layout.is_active());
+        assert_keys(&[LShift, A], layout.keycodes());
+        layout.event(Release(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(!layout.is_active());
+        assert_keys(&[], layout.keycodes());
+        layout.event(Press(0, 2));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(!layout.is_active());
+        assert_keys(&[B], layout.keycodes());
+        layout.event(Release(0, 2));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(!layout.is_active());
+        assert_keys(&[], layout.keycodes());
+    }
+
+    #[test]
+    fn oneshot_timeout_override() {
+        static mut LAYERS: Layers<2, 1, 1> = [[[
+            OneShot(&OneShotAction {
+                action: k(LShift),
+                timeout: 100,
+                end_config: OneShotEndConfig::EndOnFirstPress,
+            }),
+            k(A),
+        ]]];
+        let mut layout = Layout::new(unsafe { &mut LAYERS });
+        layout.set_oneshot_timeout(Some(10));
+        assert_eq!(Some(10), layout.oneshot_timeout());
+
+        layout.event(Press(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[LShift], layout.keycodes());
+        layout.event(Release(0, 0));
+        for _ in 0..9 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert_keys(&[LShift], layout.keycodes());
+        }
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(!layout.is_active());
+        assert_keys(&[], layout.keycodes());
+    }
+
+    #[test]
+    fn tap_oneshot_timeout() {
+        static mut LAYERS: Layers<2, 1, 1> =
+            [[[crate::tap_oneshot!(k(Space), k(LShift), 100, 200), k(A)]]];
+        let mut layout = Layout::new(unsafe { &mut LAYERS });
+
+        // The one shot modifier expires if no other key is used in time
+        layout.event(Press(0, 0));
+        for _ in 0..101 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+        }
+        assert_keys(&[LShift], layout.keycodes());
+        layout.event(Release(0, 0));
+        for _ in 0..199 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert!(layout.is_active());
+            assert_keys(&[LShift], layout.keycodes());
+        }
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(!layout.is_active());
+        assert_keys(&[], layout.keycodes());
+        layout.event(Press(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[A], layout.keycodes());
+        layout.event(Release(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(!layout.is_active());
+        assert_keys(&[], layout.keycodes());
+    }
+
+    #[test]
+    fn timed_momentary_layer_release_before_timeout() {
+        static mut LAYERS: Layers<2, 1, 2> = [
+            [[
+                TimedMomentaryLayer {
+                    layer: 1,
+                    timeout: 100,
+                },
+                k(A),
+            ]],
+            [[Trans, k(B)]],
+        ];
+        let mut layout = Layout::new(unsafe { &mut LAYERS });
+
+        layout.event(Press(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(layout.is_active());
+        assert_eq!(1, layout.current_layer());
+        for _ in 0..50 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert_eq!(1, layout.current_layer());
+        }
+        layout.event(Press(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[B], layout.keycodes());
+        layout.event(Release(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+
+        // Releasing the key deactivates the layer before the timeout
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(!layout.is_active());
+        assert_eq!(0, layout.current_layer());
+        layout.event(Press(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[A], layout.keycodes());
+        layout.event(Release(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+    }
+
+    #[test]
+    fn timed_momentary_layer_timeout_before_release() {
+        static mut LAYERS: Layers<2, 1, 2> = [
+            [[
+                TimedMomentaryLayer {
+                    layer: 1,
+                    timeout: 100,
+                },
+                k(A),
+            ]],
+            [[Trans, k(B)]],
+        ];
+        let mut layout = Layout::new(unsafe { &mut LAYERS });
+
+        layout.event(Press(0, 0));
+        for _ in 0..100 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert!(layout.is_active());
+            assert_eq!(1, layout.current_layer());
+        }
+
+        // The layer is deactivated after the timeout, even though the key is still held
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(!layout.is_active());
+        assert_eq!(0, layout.current_layer());
+        layout.event(Press(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[A], layout.keycodes());
+        layout.event(Release(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+
+        // Releasing the key afterwards does nothing
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(!layout.is_active());
+        assert_eq!(0, layout.current_layer());
+    }
+
+    #[test]
+    fn set_action_for_coord_on_all_layers() {
+        static mut LAYERS: Layers<2, 1, 3> = [[[k(A), k(B)]], [[k(C), k(D)]], [[Trans, k(E)]]];
+        let mut layout = Layout::new(unsafe { &mut LAYERS });
+
+        layout
+            .set_action_for_coord_on_all_layers((0, 1), k(F))
+            .unwrap();
+        for layer in 0..3 {
+            assert_eq!(Some(k(F)), layout.get_action((0, 1), layer));
+        }
+        assert_eq!(Some(k(A)), layout.get_action((0, 0), 0));
+        assert_eq!(Some(k(C)), layout.get_action((0, 0), 1));
+        assert_eq!(Some(Trans), layout.get_action((0, 0), 2));
+
+        // Out of bounds coordinates leave every layer untouched
+        assert!(matches!(
+            layout.set_action_for_coord_on_all_layers((1, 0), k(G)),
+            Err(ChangeActionError::OutOfBounds)
+        ));
+        assert!(matches!(
+            layout.set_action_for_coord_on_all_layers((0, 2), k(G)),
+            Err(ChangeActionError::OutOfBounds)
+        ));
+        assert_eq!(Some(k(A)), layout.get_action((0, 0), 0));
+        assert_eq!(Some(k(F)), layout.get_action((0, 1), 0));
+    }
+
+    #[test]
+    fn event_with_action() {
+        static mut LAYERS: Layers<2, 1, 1> = [[[k(A), k(B)]]];
+        let mut layout = Layout::new(unsafe { &mut LAYERS });
+
+        // Existing keys can be pressed with a different action
+        layout.event_with_action(Press(0, 0), k(C));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[C], layout.keycodes());
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+
+        // The override only applies to a single press
+        layout.event(Press(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[A], layout.keycodes());
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+
+        // Coordinates outside of the layout can be used
+        layout.event_with_action(Press(255, 0), k(D));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[D], layout.keycodes());
+        layout.event(Press(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[D, B], layout.keycodes());
+        layout.event(Release(255, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[B], layout.keycodes());
+        layout.event(Release(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+    }
+
+    #[test]
+    fn live_layer_count() {
+        static mut LAYERS: Layers<2, 1, 4> = [
+            [[k(A), k(B)]],
+            [[Trans, NoOp]],
+            [[Trans, k(C)]],
+            [[NoOp, Trans]],
+        ];
+        let mut layout = Layout::new(unsafe { &mut LAYERS });
+        assert_eq!(3, layout.live_layer_count());
+
+        layout.change_action((0, 1), 2, Trans).unwrap();
+        assert_eq!(1, layout.live_layer_count());
+
+        layout.change_action((0, 0), 3, k(D)).unwrap();
+        assert_eq!(4, layout.live_layer_count());
+
+        layout
+            .set_action_for_coord_on_all_layers((0, 0), NoOp)
+            .unwrap();
+        layout
+            .set_action_for_coord_on_all_layers((0, 1), Trans)
+            .unwrap();
+        assert_eq!(0, layout.live_layer_count());
+    }
+
+    #[test]
+    fn clear_all_states() {
+        static mut LAYERS: Layers<3, 1, 3> = [
+            [[l(1), t(2), k(A)]],
+            [[Trans, Trans, k(B)]],
+            [[Trans, Trans, k(C)]],
+        ];
+        let mut layout = Layout::new(unsafe { &mut LAYERS });
+
+        // toggle L2, then hold L1 and B
+        layout.event(Press(0, 1));
+        layout.event(Release(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(2, layout.current_layer());
+        layout.event(Press(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        layout.event(Press(0, 2));
+        // leave an event stacked
+        layout.event(Press(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(1, layout.current_layer());
+        assert_keys(&[B], layout.keycodes());
+        assert!(layout.is_active());
+
+        layout.clear_all_states();
+        assert!(!layout.is_active());
+        assert_eq!(0, layout.current_layer());
+        assert_keys(&[], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+
+        // releasing keys that were forgotten does nothing
+        layout.event(Release(0, 0));
+        layout.event(Release(0, 2));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(0, layout.current_layer());
+        assert_keys(&[], layout.keycodes());
+
+        // the layout still works normally afterwards
+        layout.event(Press(0, 2));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[A], layout.keycodes());
+    }
+
+    #[test]
+    fn reset_to_default_layer() {
+        static mut LAYERS: Layers<3, 1, 3> = [
+            [[l(1), t(2), k(A)]],
+            [[Trans, Trans, k(B)]],
+            [[Trans, Trans, k(C)]],
+        ];
+        let mut layout = Layout::new(unsafe { &mut LAYERS });
+
+        // toggle L2, then hold L1 and B
+        layout.event(Press(0, 1));
+        layout.event(Release(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        layout.event(Press(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        layout.event(Press(0, 2));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(1, layout.current_layer());
+        assert_keys(&[B], layout.keycodes());
+
+        // held keys are kept, but layers are deactivated
+        layout.reset_to_default_layer();
+        assert_eq!(0, layout.current_layer());
+        assert_keys(&[B], layout.keycodes());
+        layout.event(Release(0, 2));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+        layout.event(Press(0, 2));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[A], layout.keycodes());
+    }
+
+    #[test]
+    fn tri_layer() {
+        static mut LAYERS: Layers<3, 1, 4> = [
+            [[l(1), l(2), k(A)]],
+            [[Trans, Trans, k(B)]],
+            [[Trans, Trans, k(C)]],
+            [[Trans, Trans, k(D)]],
+        ];
+        let mut layout = Layout::new(unsafe { &mut LAYERS });
+        layout.set_tri_layer(Some((1, 2, 3)));
+        assert_eq!(Some((1, 2, 3)), layout.tri_layer());
+
+        // holding L1 and L2 activates L3
+        layout.event(Press(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(1, layout.current_layer());
+        layout.event(Press(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(3, layout.current_layer());
+        layout.event(Press(0, 2));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[D], layout.keycodes());
+        layout.event(Release(0, 2));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+
+        // releasing one of them goes back to the other layer
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(2, layout.current_layer());
+        layout.event(Release(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(0, layout.current_layer());
+
+        // invalid adjust layers are ignored
+        layout.set_tri_layer(Some((1, 2, 4)));
+        assert_eq!(None, layout.tri_layer());
+    }
 }
@@ -101,6 +101,7 @@ where
     P: InputPin,
 {
     pins: [[Option<P>; CS]; RS],
+    active_high: [[bool; CS]; RS],
 }
 
 impl<P, const CS: usize, const RS: usize> DirectPinMatrix<P, CS, RS>
@@ -115,11 +116,27 @@ where
     where
         P: InputPin<Error = E>,
     {
-        let res = Self { pins };
+        Self::new_with_active_high(pins, [[false; CS]; RS])
+    }
+
+    /// Creates a new DirectPinMatrix, where some pins are active-high.
+    ///
+    /// Pins marked with `true` in `active_high` are considered pressed
+    /// when their state is "high" (e.g. pull-down inputs), while the
+    /// other pins are considered pressed when their state is "low".
+    pub fn new_with_active_high<E>(
+        pins: [[Option<P>; CS]; RS],
+        active_high: [[bool; CS]; RS],
+    ) -> Result<Self, E>
+    where
+        P: InputPin<Error = E>,
+    {
+        let res = Self { pins, active_high };
         Ok(res)
     }
 
-    /// Scans the pins and checks which keys are pressed (state is "low").
+    /// Scans the pins and checks which keys are pressed (state is "low",
+    /// or "high" for active-high pins).
     pub fn get<E>(&mut self) -> Result<[[bool; CS]; RS], E>
     where
         P: InputPin<Error = E>,
@@ -129,7 +146,12 @@ where
         for (ri, row) in self.pins.iter_mut().enumerate() {
             for (ci, col_option) in row.iter().enumerate() {
                 if let Some(col) = col_option {
-                    if col.is_low()? {
+                    let pressed = if self.active_high[ri][ci] {
+                        col.is_high()?
+                    } else {
+                        col.is_low()?
+                    };
+                    if pressed {
                         keys[ri][ci] = true;
                     }
                 }
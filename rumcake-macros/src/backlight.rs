@@ -1,5 +1,5 @@
 use proc_macro2::TokenStream;
-use proc_macro_error::OptionExt;
+use proc_macro_error::{abort, emit_error, OptionExt};
 use quote::{quote, ToTokens};
 use syn::parse::Parse;
 use syn::punctuated::Punctuated;
@@ -8,11 +8,12 @@ use syn::{Ident, Token};
 use crate::common::{Layer, MatrixLike, OptionalItem};
 use crate::TuplePair;
 
-pub fn led_layout(input: MatrixLike<OptionalItem<TuplePair>>) -> TokenStream {
+pub fn led_layout(input: MatrixLike<OptionalItem<TuplePair>>, col_count: usize) -> TokenStream {
     let coordinates = input.rows.iter().map(|row| {
         let items = &row.items;
+        let padding = (items.len()..col_count).map(|_| quote! { None });
 
-        quote! { #(#items),* }
+        quote! { #(#items,)* #(#padding),* }
     });
 
     quote! {
@@ -43,7 +44,7 @@ impl ToTokens for LEDFlags {
     }
 }
 
-pub fn led_flags(input: MatrixLike<OptionalItem<LEDFlags>>) -> TokenStream {
+pub fn led_flags(input: MatrixLike<OptionalItem<LEDFlags>>, col_count: usize) -> TokenStream {
     let flags = input.rows.iter().map(|row| {
         let items = row.items.iter().map(|col| match col {
             OptionalItem::None => quote! {
@@ -53,8 +54,13 @@ pub fn led_flags(input: MatrixLike<OptionalItem<LEDFlags>>) -> TokenStream {
                 #ident
             },
         });
+        let padding = (row.items.len()..col_count).map(|_| {
+            quote! {
+                ::rumcake::lighting::LEDFlags::NONE
+            }
+        });
 
-        quote! { #(#items),* }
+        quote! { #(#items,)* #(#padding),* }
     });
 
     quote! {
@@ -77,17 +83,46 @@ pub fn setup_backlight_matrix(
         led_flags,
     }: BacklightMatrixMacroInput,
 ) -> TokenStream {
-    let first_row = led_layout
+    // Rows that are shorter than the longest row are padded with `None`, so that the dimensions
+    // of the matrix can be inferred from `led_layout` alone.
+    let col_count = led_layout
         .layer
         .rows
-        .first()
+        .iter()
+        .map(|row| row.items.len())
+        .max()
         .expect_or_abort("Expected at least one row to be defined.");
-
     let row_count = led_layout.layer.rows.len();
-    let col_count = first_row.items.len();
 
-    let led_layout = self::led_layout(led_layout.layer);
-    let led_flags = self::led_flags(led_flags.layer);
+    if led_flags.layer.rows.len() != row_count {
+        abort!(
+            led_flags.layer_brace.span.join(),
+            "`led_flags` must have the same number of rows as `led_layout` (expected {}, found {}).",
+            row_count,
+            led_flags.layer.rows.len()
+        );
+    }
+
+    for (row, (layout_row, flags_row)) in led_layout
+        .layer
+        .rows
+        .iter()
+        .zip(led_flags.layer.rows.iter())
+        .enumerate()
+    {
+        if layout_row.items.len() != flags_row.items.len() {
+            emit_error!(
+                flags_row.row_bracket.span.join(),
+                "Row {} of `led_flags` must have the same number of columns as the same row in `led_layout` (expected {}, found {}).",
+                row,
+                layout_row.items.len(),
+                flags_row.items.len()
+            );
+        }
+    }
+
+    let led_layout = self::led_layout(led_layout.layer, col_count);
+    let led_flags = self::led_flags(led_flags.layer, col_count);
 
     quote! {
         const LIGHTING_COLS: usize = #col_count;
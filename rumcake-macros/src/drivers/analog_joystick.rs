@@ -0,0 +1,16 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::Expr;
+
+crate::parse_as_custom_fields! {
+    pub struct AnalogJoystickArgsBuilder for AnalogJoystickArgs {
+        x: Expr,
+        y: Expr,
+    }
+}
+
+pub fn setup_analog_joystick(AnalogJoystickArgs { x, y }: AnalogJoystickArgs) -> TokenStream {
+    quote! {
+        ::rumcake::drivers::analog_joystick::AnalogJoystick::new(setup_adc_sampler(), #x, #y)
+    }
+}
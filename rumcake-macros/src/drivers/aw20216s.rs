@@ -0,0 +1,80 @@
+use crate::common::{Layer, MatrixLike, OptionalItem};
+use proc_macro2::{Ident, TokenStream};
+use quote::quote;
+use syn::Expr;
+
+fn render_optional_item_to_led(item: &OptionalItem<Ident>) -> TokenStream {
+    match item {
+        OptionalItem::None => quote! { 255 },
+        OptionalItem::Some(ident) => {
+            quote! { ::rumcake::drivers::aw20216s::Position::#ident as u8 }
+        }
+    }
+}
+
+pub fn get_led_from_matrix_coordinates(input: MatrixLike<OptionalItem<Ident>>) -> TokenStream {
+    let values = input.rows.iter().map(|row| {
+        let items = row.items.iter().map(render_optional_item_to_led);
+        quote! { #(#items),* }
+    });
+
+    quote! {
+        fn get_led_from_matrix_coordinates(x: u8, y: u8) -> u8 {
+            let lookup: [[u8; Self::LIGHTING_COLS]; Self::LIGHTING_ROWS] = [
+                #([ #values ]),*
+            ];
+
+            lookup[y as usize][x as usize]
+        }
+    }
+}
+
+crate::parse_as_custom_fields! {
+    pub struct AW20216SRgbMatrixLedArgsBuilder for AW20216SRgbMatrixLedArgs {
+        red: Layer<OptionalItem<Ident>>,
+        green: Layer<OptionalItem<Ident>>,
+        blue: Layer<OptionalItem<Ident>>,
+    }
+}
+
+pub fn get_led_from_rgb_matrix_coordinates(
+    AW20216SRgbMatrixLedArgs { red, green, blue }: AW20216SRgbMatrixLedArgs,
+) -> TokenStream {
+    let red_values = red.layer.rows.iter();
+    let green_values = green.layer.rows.iter();
+    let blue_values = blue.layer.rows.iter();
+
+    let rows =
+        red_values
+            .zip(green_values)
+            .zip(blue_values)
+            .map(|((red_row, green_row), blue_row)| {
+                let red_leds = red_row.items.iter().map(render_optional_item_to_led);
+                let green_leds = green_row.items.iter().map(render_optional_item_to_led);
+                let blue_leds = blue_row.items.iter().map(render_optional_item_to_led);
+                quote! { #(#red_leds),*, #(#green_leds),*, #(#blue_leds),* }
+            });
+
+    quote! {
+        fn get_led_from_matrix_coordinates(x: u8, y: u8) -> u8 {
+            let lookup: [[u8; { Self::LIGHTING_COLS * 3 }]; Self::LIGHTING_ROWS] = [
+                #([ #rows ]),*
+            ];
+
+            lookup[y as usize][x as usize]
+        }
+    }
+}
+
+crate::parse_as_custom_fields! {
+    pub struct AW20216SArgsBuilder for AW20216SArgs {
+        device: Ident,
+        spi: Expr,
+    }
+}
+
+pub fn setup_aw20216s(AW20216SArgs { device, spi }: AW20216SArgs) -> TokenStream {
+    quote! {
+        ::rumcake::drivers::aw20216s::setup_driver::<#device, _>(#spi).await
+    }
+}
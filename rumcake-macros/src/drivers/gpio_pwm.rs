@@ -0,0 +1,47 @@
+use proc_macro2::{Ident, TokenStream};
+use proc_macro_error::abort;
+use quote::quote;
+use syn::{LitInt, PathSegment};
+
+crate::parse_as_custom_fields! {
+    pub struct GpioPwmArgsBuilder for GpioPwmArgs {
+        pin: Ident,
+        frequency: LitInt
+    }
+}
+
+pub fn setup_gpio_pwm(GpioPwmArgs { pin, frequency }: GpioPwmArgs) -> TokenStream {
+    let frequency = frequency.base10_parse::<u32>().unwrap_or_else(|_| {
+        abort!(
+            frequency,
+            "The provided frequency could not be parsed as a u32 value."
+        )
+    });
+
+    if frequency == 0 {
+        abort!(pin, "The PWM frequency must be greater than 0.")
+    }
+
+    let hal_name: PathSegment = syn::parse_str(crate::hw::HAL_CRATE).unwrap();
+
+    quote! {
+        {
+            static SOFT_PWM_STATE: ::rumcake::drivers::gpio_pwm::SoftPwmState =
+                ::rumcake::drivers::gpio_pwm::SoftPwmState::new();
+
+            #[::embassy_executor::task]
+            async fn __gpio_pwm_task(
+                pin: ::rumcake::hw::platform::#hal_name::gpio::Output<'static>,
+            ) {
+                ::rumcake::drivers::gpio_pwm::soft_pwm_task(pin, &SOFT_PWM_STATE, #frequency).await;
+            }
+
+            ::embassy_executor::Spawner::for_current_executor()
+                .await
+                .spawn(__gpio_pwm_task(::rumcake::hw::platform::output_pin!(#pin)))
+                .unwrap();
+
+            ::rumcake::drivers::gpio_pwm::setup_soft_pwm_driver(&SOFT_PWM_STATE)
+        }
+    }
+}
@@ -1,4 +1,10 @@
+pub mod analog_joystick;
+pub mod aw20216s;
+pub mod gpio_pwm;
 pub mod is31fl3731;
 pub mod nrf_ble;
+pub mod sh1106;
+pub mod sharp_memory_lcd;
 pub mod ssd1306;
+pub mod trackpoint;
 pub mod ws2812;
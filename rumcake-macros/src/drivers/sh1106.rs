@@ -0,0 +1,38 @@
+use proc_macro2::{Ident, TokenStream};
+use proc_macro_error::abort_call_site;
+use quote::quote;
+use syn::Expr;
+
+crate::parse_as_custom_fields! {
+    pub struct Sh1106ArgsBuilder for Sh1106Args {
+        i2c: Option<Expr>,
+        i2c_bus: Option<Expr>,
+        size: Ident,
+        rotation: Ident,
+    }
+}
+
+pub fn setup_sh1106(
+    Sh1106Args {
+        i2c,
+        i2c_bus,
+        size,
+        rotation,
+    }: Sh1106Args,
+) -> TokenStream {
+    let i2c = match (i2c, i2c_bus) {
+        (Some(i2c), None) => quote! { #i2c },
+        (None, Some(i2c_bus)) => quote! {
+            ::rumcake::hw::shared_i2c::shared_i2c_device(#i2c_bus)
+        },
+        _ => abort_call_site!("exactly one of `i2c` or `i2c_bus` must be specified"),
+    };
+
+    quote! {
+        ::rumcake::drivers::sh1106::setup_driver(
+            #i2c,
+            ::rumcake::drivers::sh1106::#size,
+            ::rumcake::drivers::sh1106::DisplayRotation::#rotation
+        )
+    }
+}
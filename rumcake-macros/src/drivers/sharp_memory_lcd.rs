@@ -0,0 +1,30 @@
+use proc_macro2::{Ident, TokenStream};
+use quote::quote;
+use syn::Expr;
+
+crate::parse_as_custom_fields! {
+    pub struct SharpMemoryLcdArgsBuilder for SharpMemoryLcdArgs {
+        spi: Expr,
+        cs: Expr,
+        size: Ident,
+        rotation: Ident,
+    }
+}
+
+pub fn setup_sharp_lcd(
+    SharpMemoryLcdArgs {
+        spi,
+        cs,
+        size,
+        rotation,
+    }: SharpMemoryLcdArgs,
+) -> TokenStream {
+    quote! {
+        ::rumcake::drivers::sharp_memory_lcd::setup_driver(
+            #spi,
+            #cs,
+            ::rumcake::drivers::sharp_memory_lcd::#size,
+            ::rumcake::drivers::sharp_memory_lcd::DisplayRotation::#rotation
+        ).await
+    }
+}
@@ -1,10 +1,12 @@
 use proc_macro2::{Ident, TokenStream};
+use proc_macro_error::abort_call_site;
 use quote::quote;
 use syn::Expr;
 
 crate::parse_as_custom_fields! {
     pub struct Ssd1306ArgsBuilder for Ssd1306Args {
-        i2c: Expr,
+        i2c: Option<Expr>,
+        i2c_bus: Option<Expr>,
         size: Ident,
         rotation: Ident,
     }
@@ -13,10 +15,19 @@ crate::parse_as_custom_fields! {
 pub fn setup_ssd1306(
     Ssd1306Args {
         i2c,
+        i2c_bus,
         size,
         rotation,
     }: Ssd1306Args,
 ) -> TokenStream {
+    let i2c = match (i2c, i2c_bus) {
+        (Some(i2c), None) => quote! { #i2c },
+        (None, Some(i2c_bus)) => quote! {
+            ::rumcake::hw::shared_i2c::shared_i2c_device(#i2c_bus)
+        },
+        _ => abort_call_site!("exactly one of `i2c` or `i2c_bus` must be specified"),
+    };
+
     quote! {
         ::rumcake::drivers::ssd1306::setup_driver(
             #i2c,
@@ -0,0 +1,16 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::Expr;
+
+crate::parse_as_custom_fields! {
+    pub struct TrackpointArgsBuilder for TrackpointArgs {
+        x: Expr,
+        y: Expr,
+    }
+}
+
+pub fn setup_trackpoint(TrackpointArgs { x, y }: TrackpointArgs) -> TokenStream {
+    quote! {
+        ::rumcake::drivers::trackpoint::Trackpoint::new(setup_adc_sampler(), #x, #y)
+    }
+}
@@ -21,7 +21,7 @@ pub fn get_led_from_matrix_coordinates(input: MatrixLike<OptionalItem<Literal>>)
 
 pub mod bitbang {
     use proc_macro2::{Ident, TokenStream};
-    use proc_macro_error::abort;
+    use proc_macro_error::{abort, abort_call_site};
     use quote::quote;
     use syn::LitInt;
 
@@ -51,3 +51,63 @@ pub mod bitbang {
         }
     }
 }
+
+pub mod spi {
+    use proc_macro2::{Ident, TokenStream};
+    use proc_macro_error::abort;
+    use quote::quote;
+    use syn::LitInt;
+
+    crate::parse_as_custom_fields! {
+        pub struct WS2812SpiArgsBuilder for WS2812SpiArgs {
+            spi: Ident,
+            mosi: Ident,
+            dma: Ident,
+            bits: Option<LitInt>
+        }
+    }
+
+    pub fn setup_ws2812_spi(
+        WS2812SpiArgs {
+            spi,
+            mosi,
+            dma,
+            bits,
+        }: WS2812SpiArgs,
+    ) -> TokenStream {
+        if !cfg!(feature = "rp") {
+            abort_call_site!(
+                "`setup_ws2812_spi!` is only supported on RP2040-based keyboards. On other platforms, create an SPI bus yourself, and pass it to `rumcake::drivers::ws2812_spi::setup_driver`."
+            );
+        }
+
+        let bits = if let Some(lit) = bits {
+            match lit.base10_parse::<u8>() {
+                Ok(value) if value == 3 || value == 4 => value,
+                _ => abort!(
+                    lit,
+                    "The number of SPI bits used to encode each bit must be 3 or 4."
+                ),
+            }
+        } else {
+            4
+        };
+
+        let encoding = if bits == 3 {
+            quote! { ::rumcake::drivers::ws2812_spi::ThreeBitEncoding }
+        } else {
+            quote! { ::rumcake::drivers::ws2812_spi::FourBitEncoding }
+        };
+
+        quote! {
+            ::rumcake::drivers::ws2812_spi::setup_driver::<#encoding, _>(
+                ::rumcake::hw::platform::setup_spi_txonly! {
+                    spi: #spi,
+                    mosi: #mosi,
+                    dma: #dma,
+                    frequency: <#encoding as ::rumcake::drivers::ws2812_spi::Ws2812SpiEncoding>::FREQUENCY,
+                }
+            )
+        }
+    }
+}
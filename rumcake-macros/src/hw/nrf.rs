@@ -4,12 +4,9 @@ use proc_macro_error::{abort, OptionExt};
 use quote::quote;
 use syn::parse::Parse;
 use syn::punctuated::Punctuated;
-use syn::{braced, parenthesized, LitInt, Token};
+use syn::{braced, parenthesized, LitBool, LitInt, Token};
 
-use crate::common::{
-    AnalogPinType, DirectPinArgs, DirectPinDefinition, MultiplexerArgs, MultiplexerDefinition,
-    OptionalItem,
-};
+use crate::common::{AnalogPinType, DirectPinArgs, DirectPinDefinition, MultiplexerDefinition};
 
 pub const HAL_CRATE: &str = "embassy_nrf";
 
@@ -26,6 +23,19 @@ pub fn input_pin(ident: Ident) -> TokenStream {
     }
 }
 
+pub fn input_pin_pull_down(ident: Ident) -> TokenStream {
+    quote! {
+        unsafe {
+            ::rumcake::hw::platform::embassy_nrf::gpio::Input::new(
+                ::rumcake::hw::platform::embassy_nrf::gpio::Pin::degrade(
+                    ::rumcake::hw::platform::embassy_nrf::peripherals::#ident::steal(),
+                ),
+                ::rumcake::hw::platform::embassy_nrf::gpio::Pull::Down,
+            )
+        }
+    }
+}
+
 pub fn output_pin(ident: Ident) -> TokenStream {
     quote! {
         unsafe {
@@ -46,6 +56,8 @@ crate::parse_as_custom_fields! {
         i2c: Ident,
         sda: Ident,
         scl: Ident,
+        frequency: Option<LitInt>,
+        pullups: Option<LitBool>,
     }
 }
 
@@ -55,8 +67,23 @@ pub fn setup_i2c(
         i2c,
         sda,
         scl,
+        frequency,
+        pullups,
     }: I2cArgs,
 ) -> TokenStream {
+    let frequency = frequency.map_or(quote! { K100 }, |lit| {
+        match lit.base10_parse::<u32>() {
+            Ok(100_000) => quote! { K100 },
+            Ok(250_000) => quote! { K250 },
+            Ok(400_000) => quote! { K400 },
+            _ => abort!(
+                lit,
+                "The provided frequency is not supported. Valid frequencies are 100000, 250000 and 400000."
+            ),
+        }
+    });
+    let pullups = pullups.map_or(false, |lit| lit.value);
+
     quote! {
         unsafe {
             use ::rumcake::hw::platform::embassy_nrf::interrupt::InterruptExt;
@@ -69,7 +96,92 @@ pub fn setup_i2c(
             let i2c = ::rumcake::hw::platform::embassy_nrf::peripherals::#i2c::steal();
             let sda = ::rumcake::hw::platform::embassy_nrf::peripherals::#sda::steal();
             let scl = ::rumcake::hw::platform::embassy_nrf::peripherals::#scl::steal();
-            ::rumcake::hw::platform::embassy_nrf::twim::Twim::new(i2c, Irqs, sda, scl, Default::default())
+            let mut config = ::rumcake::hw::platform::embassy_nrf::twim::Config::default();
+            config.frequency = ::rumcake::hw::platform::embassy_nrf::twim::Frequency::#frequency;
+            config.scl_pullup = #pullups;
+            config.sda_pullup = #pullups;
+            ::rumcake::hw::platform::embassy_nrf::twim::Twim::new(i2c, Irqs, sda, scl, config)
+        }
+    }
+}
+
+crate::parse_as_custom_fields! {
+    pub struct I2sArgsBuilder for I2sArgs {
+        interrupt: Ident,
+        i2s: Ident,
+        mck: Ident,
+        sck: Ident,
+        lrck: Ident,
+        sdout: Ident,
+        buffer_size: Option<LitInt>,
+        sample_rate: Option<LitInt>,
+    }
+}
+
+pub fn setup_i2s(
+    I2sArgs {
+        interrupt,
+        i2s,
+        mck,
+        sck,
+        lrck,
+        sdout,
+        buffer_size,
+        sample_rate,
+    }: I2sArgs,
+) -> TokenStream {
+    let buf_size = buffer_size.map_or(256, |lit| {
+        lit.base10_parse::<usize>().unwrap_or_else(|_| {
+            abort!(
+                lit,
+                "The provided buffer size could not be parsed as a usize value."
+            )
+        })
+    });
+    let (sample_rate, sample_rate_variant) = sample_rate.map_or(
+        (16000, quote! { _16000 }),
+        |lit| match lit.base10_parse::<u32>() {
+            Ok(11025) => (11025, quote! { _11025 }),
+            Ok(16000) => (16000, quote! { _16000 }),
+            Ok(22050) => (22050, quote! { _22050 }),
+            Ok(32000) => (32000, quote! { _32000 }),
+            Ok(44100) => (44100, quote! { _44100 }),
+            Ok(48000) => (48000, quote! { _48000 }),
+            _ => abort!(
+                lit,
+                "The provided sample rate is not supported. Valid sample rates are 11025, 16000, 22050, 32000, 44100 and 48000."
+            ),
+        },
+    );
+
+    quote! {
+        unsafe {
+            ::rumcake::hw::platform::embassy_nrf::bind_interrupts! {
+                struct Irqs {
+                    #interrupt => ::rumcake::hw::platform::embassy_nrf::i2s::InterruptHandler<::rumcake::hw::platform::embassy_nrf::peripherals::#i2s>;
+                }
+            };
+            let i2s = ::rumcake::hw::platform::embassy_nrf::peripherals::#i2s::steal();
+            let mck = ::rumcake::hw::platform::embassy_nrf::peripherals::#mck::steal();
+            let sck = ::rumcake::hw::platform::embassy_nrf::peripherals::#sck::steal();
+            let lrck = ::rumcake::hw::platform::embassy_nrf::peripherals::#lrck::steal();
+            let sdout = ::rumcake::hw::platform::embassy_nrf::peripherals::#sdout::steal();
+            let mut config = ::rumcake::hw::platform::embassy_nrf::i2s::Config::default();
+            config.channels = ::rumcake::hw::platform::embassy_nrf::i2s::Channels::MonoLeft;
+            let stream = ::rumcake::hw::platform::embassy_nrf::i2s::I2S::new_master(
+                i2s,
+                Irqs,
+                mck,
+                sck,
+                lrck,
+                ::rumcake::hw::platform::embassy_nrf::i2s::ApproxSampleRate::#sample_rate_variant.into(),
+                config,
+            )
+            .output(
+                sdout,
+                ::rumcake::hw::platform::embassy_nrf::i2s::MultiBuffering::<i16, 2, #buf_size>::new(),
+            );
+            ::rumcake::hw::platform::I2SAudioDriver::<_, #buf_size, #sample_rate>::new(stream)
         }
     }
 }
@@ -192,7 +304,7 @@ pub fn setup_adc_sampler(
             multiplexer_args, ..
         }) = ch
         {
-            acc.max(multiplexer_args.select_pins.items.len())
+            acc.max(multiplexer_args.address_bits())
         } else {
             acc
         }
@@ -205,22 +317,14 @@ pub fn setup_adc_sampler(
             AnalogPinType::Multiplexed(MultiplexerDefinition {
                 multiplexer_args, ..
             }) => {
-                let MultiplexerArgs { pin, select_pins } = multiplexer_args;
-                let select_pins = select_pins.items.iter().map(|select_pin| match select_pin {
-                    OptionalItem::None => quote! { None },
-                    OptionalItem::Some(pin_ident) => {
-                        quote! { Some(::rumcake::hw::platform::output_pin!(#pin_ident)) }
-                    }
-                });
+                let pin = &multiplexer_args.pin;
+                let multiplexer = multiplexer_args.to_multiplexer(select_pin_count);
 
                 (
                     quote! {
                         ::rumcake::hw::platform::AnalogPinType::Multiplexed(
                             [0; #buf_size],
-                            ::rumcake::hw::Multiplexer::new(
-                                [ #(#select_pins),* ],
-                                None
-                            )
+                            #multiplexer
                         )
                     },
                     quote! {
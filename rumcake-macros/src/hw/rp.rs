@@ -2,12 +2,9 @@ use proc_macro2::{Ident, TokenStream};
 use proc_macro_error::abort;
 use quote::quote;
 use syn::punctuated::Punctuated;
-use syn::{LitInt, Token};
+use syn::{Expr, LitInt, Token};
 
-use crate::common::{
-    AnalogPinType, DirectPinArgs, DirectPinDefinition, MultiplexerArgs, MultiplexerDefinition,
-    OptionalItem,
-};
+use crate::common::{AnalogPinType, DirectPinArgs, DirectPinDefinition, MultiplexerDefinition};
 
 pub const HAL_CRATE: &str = "embassy_rp";
 
@@ -24,6 +21,19 @@ pub fn input_pin(ident: Ident) -> TokenStream {
     }
 }
 
+pub fn input_pin_pull_down(ident: Ident) -> TokenStream {
+    quote! {
+        unsafe {
+            ::rumcake::hw::platform::embassy_rp::gpio::Input::new(
+                ::rumcake::hw::platform::embassy_rp::gpio::Pin::degrade(
+                    ::rumcake::hw::platform::embassy_rp::peripherals::#ident::steal(),
+                ),
+                ::rumcake::hw::platform::embassy_rp::gpio::Pull::Down,
+            )
+        }
+    }
+}
+
 pub fn output_pin(ident: Ident) -> TokenStream {
     quote! {
         unsafe {
@@ -43,6 +53,7 @@ crate::parse_as_custom_fields! {
         i2c: Ident,
         scl: Ident,
         sda: Ident,
+        frequency: Option<LitInt>,
     }
 }
 
@@ -52,8 +63,18 @@ pub fn setup_i2c(
         i2c,
         scl,
         sda,
+        frequency,
     }: I2cArgs,
 ) -> TokenStream {
+    let frequency = frequency.map_or(100_000, |lit| {
+        lit.base10_parse::<u32>().unwrap_or_else(|_| {
+            abort!(
+                lit,
+                "The provided frequency could not be parsed as a u32 value."
+            )
+        })
+    });
+
     quote! {
         unsafe {
             ::rumcake::hw::platform::embassy_rp::bind_interrupts! {
@@ -64,7 +85,38 @@ pub fn setup_i2c(
             let i2c = ::rumcake::hw::platform::embassy_rp::peripherals::#i2c::steal();
             let scl = ::rumcake::hw::platform::embassy_rp::peripherals::#scl::steal();
             let sda = ::rumcake::hw::platform::embassy_rp::peripherals::#sda::steal();
-            ::rumcake::hw::platform::embassy_rp::i2c::I2c::new_async(i2c, scl, sda, Irqs, Default::default())
+            let mut config = ::rumcake::hw::platform::embassy_rp::i2c::Config::default();
+            config.frequency = #frequency;
+            ::rumcake::hw::platform::embassy_rp::i2c::I2c::new_async(i2c, scl, sda, Irqs, config)
+        }
+    }
+}
+
+crate::parse_as_custom_fields! {
+    pub struct SpiTxOnlyArgsBuilder for SpiTxOnlyArgs {
+        spi: Ident,
+        mosi: Ident,
+        dma: Ident,
+        frequency: Expr,
+    }
+}
+
+pub fn setup_spi_txonly(
+    SpiTxOnlyArgs {
+        spi,
+        mosi,
+        dma,
+        frequency,
+    }: SpiTxOnlyArgs,
+) -> TokenStream {
+    quote! {
+        unsafe {
+            let spi = ::rumcake::hw::platform::embassy_rp::peripherals::#spi::steal();
+            let mosi = ::rumcake::hw::platform::embassy_rp::peripherals::#mosi::steal();
+            let dma = ::rumcake::hw::platform::embassy_rp::peripherals::#dma::steal();
+            let mut config = ::rumcake::hw::platform::embassy_rp::spi::Config::default();
+            config.frequency = #frequency;
+            ::rumcake::hw::platform::embassy_rp::spi::Spi::new_txonly_nosck(spi, mosi, dma, config)
         }
     }
 }
@@ -129,7 +181,7 @@ pub fn setup_adc_sampler(channels: Punctuated<AnalogPinType, Token![,]>) -> Toke
             multiplexer_args, ..
         }) = ch
         {
-            acc.max(multiplexer_args.select_pins.items.len())
+            acc.max(multiplexer_args.address_bits())
         } else {
             acc
         }
@@ -141,21 +193,13 @@ pub fn setup_adc_sampler(channels: Punctuated<AnalogPinType, Token![,]>) -> Toke
             AnalogPinType::Multiplexed(MultiplexerDefinition {
                  multiplexer_args, ..
             }) => {
-                let MultiplexerArgs {pin, select_pins} = multiplexer_args;
-                let select_pins = select_pins.items.iter().map(|select_pin| match select_pin {
-                    OptionalItem::None => quote! { None },
-                    OptionalItem::Some(pin_ident) => {
-                        quote! { Some(::rumcake::hw::platform::output_pin!(#pin_ident)) }
-                    }
-                });
+                let pin = &multiplexer_args.pin;
+                let multiplexer = multiplexer_args.to_multiplexer(select_pin_count);
 
                 (
                     quote! {
                         ::rumcake::hw::platform::AnalogPinType::Multiplexed(
-                            ::rumcake::hw::Multiplexer::new(
-                                [ #(#select_pins),* ],
-                                None
-                            )
+                            #multiplexer
                         )
                     },
                     quote! {
@@ -3,12 +3,9 @@ use proc_macro_error::{abort, OptionExt};
 use quote::quote;
 use syn::parse::Parse;
 use syn::punctuated::Punctuated;
-use syn::{braced, parenthesized, LitInt, Token};
+use syn::{braced, parenthesized, LitBool, LitInt, Token};
 
-use crate::common::{
-    AnalogPinType, DirectPinArgs, DirectPinDefinition, MultiplexerArgs, MultiplexerDefinition,
-    OptionalItem,
-};
+use crate::common::{AnalogPinType, DirectPinArgs, DirectPinDefinition, MultiplexerDefinition};
 
 pub const HAL_CRATE: &str = "embassy_stm32";
 
@@ -45,6 +42,19 @@ pub fn input_pin(args: Punctuated<Ident, Token![,]>) -> TokenStream {
     }
 }
 
+pub fn input_pin_pull_down(ident: Ident) -> TokenStream {
+    quote! {
+        unsafe {
+            ::rumcake::hw::platform::embassy_stm32::gpio::Input::new(
+                ::rumcake::hw::platform::embassy_stm32::gpio::Pin::degrade(
+                    ::rumcake::hw::platform::embassy_stm32::peripherals::#ident::steal(),
+                ),
+                ::rumcake::hw::platform::embassy_stm32::gpio::Pull::Down,
+            )
+        }
+    }
+}
+
 pub fn output_pin(ident: Ident) -> TokenStream {
     quote! {
         unsafe {
@@ -67,7 +77,9 @@ crate::parse_as_custom_fields! {
         scl: Ident,
         sda: Ident,
         rx_dma: Ident,
-        tx_dma: Ident
+        tx_dma: Ident,
+        frequency: Option<LitInt>,
+        pullups: Option<LitBool>,
     }
 }
 
@@ -80,8 +92,20 @@ pub fn setup_i2c(
         sda,
         rx_dma,
         tx_dma,
+        frequency,
+        pullups,
     }: I2cArgs,
 ) -> TokenStream {
+    let frequency = frequency.map_or(100_000, |lit| {
+        lit.base10_parse::<u32>().unwrap_or_else(|_| {
+            abort!(
+                lit,
+                "The provided frequency could not be parsed as a u32 value."
+            )
+        })
+    });
+    let pullups = pullups.map_or(false, |lit| lit.value);
+
     let interrupt_setup = if event_interrupt == error_interrupt {
         quote! {
             #event_interrupt => ::rumcake::hw::platform::embassy_stm32::i2c::EventInterruptHandler<::rumcake::hw::platform::embassy_stm32::peripherals::#i2c>, ::rumcake::hw::platform::embassy_stm32::i2c::ErrorInterruptHandler<::rumcake::hw::platform::embassy_stm32::peripherals::#i2c>;
@@ -105,8 +129,11 @@ pub fn setup_i2c(
             let sda = ::rumcake::hw::platform::embassy_stm32::peripherals::#sda::steal();
             let rx_dma = ::rumcake::hw::platform::embassy_stm32::peripherals::#rx_dma::steal();
             let tx_dma = ::rumcake::hw::platform::embassy_stm32::peripherals::#tx_dma::steal();
-            let time = ::rumcake::hw::platform::embassy_stm32::time::Hertz(100_000);
-            ::rumcake::hw::platform::embassy_stm32::i2c::I2c::new(i2c, scl, sda, Irqs, tx_dma, rx_dma, time, Default::default())
+            let time = ::rumcake::hw::platform::embassy_stm32::time::Hertz(#frequency);
+            let mut config = ::rumcake::hw::platform::embassy_stm32::i2c::Config::default();
+            config.scl_pullup = #pullups;
+            config.sda_pullup = #pullups;
+            ::rumcake::hw::platform::embassy_stm32::i2c::I2c::new(i2c, scl, sda, Irqs, tx_dma, rx_dma, time, config)
         }
     }
 }
@@ -208,7 +235,7 @@ fn setup_adc_inner(adc_definition: &STM32AdcSamplerDefinition) -> (TokenStream,
             multiplexer_args, ..
         }) = ch
         {
-            acc.max(multiplexer_args.select_pins.items.len())
+            acc.max(multiplexer_args.address_bits())
         } else {
             acc
         }
@@ -220,21 +247,13 @@ fn setup_adc_inner(adc_definition: &STM32AdcSamplerDefinition) -> (TokenStream,
             AnalogPinType::Multiplexed(MultiplexerDefinition {
                 multiplexer_args, ..
             }) => {
-                let MultiplexerArgs {pin, select_pins} = multiplexer_args;
-                let select_pins = select_pins.items.iter().map(|select_pin| match select_pin {
-                    OptionalItem::None => quote! { None },
-                    OptionalItem::Some(pin_ident) => {
-                        quote! { Some(::rumcake::hw::platform::output_pin!(#pin_ident)) }
-                    }
-                });
+                let pin = &multiplexer_args.pin;
+                let multiplexer = multiplexer_args.to_multiplexer(select_pin_count);
 
                 (
                     quote! {
                         ::rumcake::hw::platform::AnalogPinType::Multiplexed(
-                            ::rumcake::hw::Multiplexer::new(
-                                [ #(#select_pins),* ],
-                                None
-                            )
+                            #multiplexer
                         )
                     },
                     quote! {
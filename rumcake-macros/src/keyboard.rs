@@ -1,12 +1,13 @@
 use darling::util::{Override, SpannedValue};
 use darling::FromMeta;
-use proc_macro2::{Ident, TokenStream, TokenTree};
+use proc_macro2::{Delimiter, Group, Ident, TokenStream, TokenTree};
 use proc_macro_error::{abort, emit_error, OptionExt};
-use quote::quote;
-use syn::parse::Parse;
+use quote::{format_ident, quote, ToTokens};
+use syn::parse::{Parse, Parser};
 use syn::punctuated::Punctuated;
 use syn::{
-    braced, custom_keyword, Expr, ExprRange, ItemStruct, LitInt, LitStr, Path, PathSegment, Token,
+    braced, bracketed, custom_keyword, parenthesized, Expr, ExprRange, ItemStruct, LitInt, LitStr,
+    Path, PathSegment, Token,
 };
 
 use crate::common::{Layer, LayoutLike, MatrixLike, OptionalItem, Row};
@@ -25,10 +26,14 @@ pub(crate) struct KeyboardSettings {
     rgb_backlight_matrix: Option<LightingSettings>,
     underglow: Option<LightingSettings>,
     display: Option<DisplaySettings>,
+    audio: Option<AudioSettings>,
+    status_led: Option<StatusLedSettings>,
+    pointer: Option<PointerSettings>,
     split_peripheral: Option<SplitPeripheralSettings>,
     split_central: Option<SplitCentralSettings>,
     via: Option<ViaSettings>,
     vial: Option<ViaSettings>,
+    raw_hid: bool,
     bootloader_double_tap_reset: Option<Override<LitInt>>,
 }
 
@@ -44,6 +49,23 @@ pub(crate) struct DisplaySettings {
     driver_setup_fn: Path,
 }
 
+#[derive(Debug, FromMeta)]
+pub(crate) struct AudioSettings {
+    driver_setup_fn: Path,
+}
+
+#[derive(Debug, FromMeta)]
+pub(crate) struct StatusLedSettings {
+    pin: Ident,
+}
+
+#[derive(Debug, FromMeta)]
+pub(crate) struct PointerSettings {
+    driver_setup_fn: Option<Path>,
+    #[darling(default)]
+    mouse_keys: bool,
+}
+
 #[derive(Debug, FromMeta)]
 pub(crate) struct SplitCentralSettings {
     driver_type: Option<LitStr>,
@@ -69,6 +91,9 @@ pub(crate) struct StorageSettings {
     buffer_size: Option<LitInt>,
     flash_size: Option<LitInt>,
     dma: Option<Ident>,
+    driver_setup_fn: Option<Path>,
+    config_start: Option<LitInt>,
+    config_end: Option<LitInt>,
 }
 
 fn setup_storage_driver(
@@ -236,6 +261,45 @@ fn setup_storage_driver(
 
             return true;
         }
+        "external" => {
+            if config.buffer_size.is_some() {
+                emit_error!(
+                    config.driver,
+                    "`buffer_size` is not used by the external storage driver. Implement `get_storage_buffer` in your `StorageDevice` implementation instead."
+                );
+
+                return true;
+            }
+
+            let Some(driver_setup_fn) = &config.driver_setup_fn else {
+                emit_error!(
+                    config.driver,
+                    "You must specify a `driver_setup_fn` that returns your flash driver."
+                );
+
+                return true;
+            };
+
+            let (Some(config_start), Some(config_end)) = (&config.config_start, &config.config_end)
+            else {
+                emit_error!(
+                    config.driver,
+                    "You must specify the `config_start` and `config_end` addresses of the storage partition."
+                );
+
+                return true;
+            };
+
+            initialization.extend(quote! {
+                use ::rumcake::storage::FlashStorage;
+                let flash = #driver_setup_fn().await;
+                static mut READ_BUF: [u8; <<#kb_name as ::rumcake::storage::StorageDevice>::FlashStorageType as ::rumcake::storage::FlashStorage>::ERASE_SIZE] = [0; <<#kb_name as ::rumcake::storage::StorageDevice>::FlashStorageType as ::rumcake::storage::FlashStorage>::ERASE_SIZE];
+                static mut OP_BUF: [u8; <<#kb_name as ::rumcake::storage::StorageDevice>::FlashStorageType as ::rumcake::storage::FlashStorage>::ERASE_SIZE] = [0; <<#kb_name as ::rumcake::storage::StorageDevice>::FlashStorageType as ::rumcake::storage::FlashStorage>::ERASE_SIZE];
+                unsafe { <#kb_name as ::rumcake::storage::StorageDevice>::get_storage_service().setup(flash, #config_start, #config_end, &mut READ_BUF, &mut OP_BUF).await; }
+            });
+
+            return false;
+        }
         _ => (),
     };
 
@@ -276,7 +340,7 @@ pub(crate) fn keyboard_main(
         tasks.extend(quote! {
             #[::embassy_executor::task]
             async fn __adc_task(sampler: &'static AdcSamplerType) {
-                ::rumcake::tasks::adc_task(sampler).await;
+                ::rumcake::tasks::adc_task(#kb_name, sampler).await;
             }
         });
         spawning.extend(quote! {
@@ -359,16 +423,40 @@ pub(crate) fn keyboard_main(
         spawning.extend(quote! {
             spawner.spawn(__layout_collect(#kb_name)).unwrap();
         });
+
+        if cfg!(feature = "wpm") {
+            tasks.extend(quote! {
+                #[::embassy_executor::task]
+                async fn __wpm_task(k: #kb_name) {
+                    ::rumcake::tasks::wpm_task(k).await;
+                }
+            });
+            spawning.extend(quote! {
+                spawner.spawn(__wpm_task(#kb_name)).unwrap();
+            });
+        }
+
+        if cfg!(feature = "idle") {
+            tasks.extend(quote! {
+                #[::embassy_executor::task]
+                async fn __idle_task(k: #kb_name) {
+                    ::rumcake::tasks::idle_task(k).await;
+                }
+            });
+            spawning.extend(quote! {
+                spawner.spawn(__idle_task(#kb_name)).unwrap();
+            });
+        }
     }
 
     tasks.extend(quote! {
         #[::embassy_executor::task]
-        async fn __output_switcher() {
-            ::rumcake::tasks::output_switcher().await;
+        async fn __output_switcher(k: #kb_name) {
+            ::rumcake::tasks::output_switcher(k).await;
         }
     });
     spawning.extend(quote! {
-        spawner.spawn(__output_switcher()).unwrap();
+        spawner.spawn(__output_switcher(#kb_name)).unwrap();
     });
 
     if cfg!(feature = "nrf") && keyboard.bluetooth {
@@ -384,6 +472,29 @@ pub(crate) fn keyboard_main(
         spawning.extend(quote! {
             spawner.spawn(__nrf_ble_task(#kb_name, sd, hid_server)).unwrap();
         });
+
+        if keyboard.storage.is_some() {
+            tasks.extend(quote! {
+                #[::embassy_executor::task]
+                async fn __bluetooth_profile_storage_task() {
+                    ::rumcake::tasks::bluetooth_profile_storage_task(&DATABASE).await;
+                }
+            });
+            spawning.extend(quote! {
+                ::rumcake::bluetooth::initialize_bluetooth_profile_data::<#kb_name, _, _>(&DATABASE).await;
+                spawner.spawn(__bluetooth_profile_storage_task()).unwrap();
+            });
+        }
+
+        tasks.extend(quote! {
+            #[::embassy_executor::task]
+            async fn __bluetooth_profile_task() {
+                ::rumcake::tasks::bluetooth_profile_task::<#kb_name>().await;
+            }
+        });
+        spawning.extend(quote! {
+            spawner.spawn(__bluetooth_profile_task()).unwrap();
+        });
     }
 
     // USB Configuration
@@ -417,7 +528,11 @@ pub(crate) fn keyboard_main(
             // HID Class setup
             static KB_STATE: ::static_cell::StaticCell<::rumcake::usb::UsbState> = ::static_cell::StaticCell::new();
             let kb_state = KB_STATE.init(::rumcake::usb::UsbState::new());
-            let kb_class = ::rumcake::usb::setup_usb_hid_nkro_writer(&mut builder, kb_state);
+            let kb_class = ::rumcake::usb::setup_usb_hid_nkro_writer_with_poll_interval(
+                &mut builder,
+                kb_state,
+                <#kb_name as ::rumcake::usb::USBKeyboard>::USB_POLL_INTERVAL_MS,
+            );
         });
         tasks.extend(quote! {
             #[::embassy_executor::task]
@@ -441,6 +556,19 @@ pub(crate) fn keyboard_main(
             spawner.spawn(__usb_hid_kb_write_task(#kb_name, kb_class)).unwrap();
         });
 
+        if keyboard.storage.is_some() {
+            tasks.extend(quote! {
+                #[::embassy_executor::task]
+                async fn __nkro_storage_task() {
+                    ::rumcake::tasks::nkro_storage_task(&DATABASE).await;
+                }
+            });
+            spawning.extend(quote! {
+                ::rumcake::usb::initialize_nkro_data(&DATABASE).await;
+                spawner.spawn(__nkro_storage_task()).unwrap();
+            });
+        }
+
         if cfg!(feature = "media-keycodes") {
             initialization.extend(quote! {
                 // HID consumer
@@ -459,6 +587,25 @@ pub(crate) fn keyboard_main(
                 spawner.spawn(__usb_hid_consumer_write_task(#kb_name, consumer_class)).unwrap();
             });
         }
+
+        if keyboard.pointer.is_some() {
+            initialization.extend(quote! {
+                // HID mouse
+                static MOUSE_STATE: ::static_cell::StaticCell<::rumcake::usb::UsbState> = ::static_cell::StaticCell::new();
+                let mouse_state = MOUSE_STATE.init(::rumcake::usb::UsbState::new());
+                let mouse_class = ::rumcake::usb::setup_usb_hid_mouse_writer(&mut builder, mouse_state);
+            });
+            tasks.extend(quote! {
+                #[::embassy_executor::task]
+                async fn __usb_hid_mouse_write_task(k: #kb_name, mouse_class: ::rumcake::usb::WheelMouseReportWriter<'static, __usb_driver::UsbDriver>) {
+                    ::rumcake::tasks::usb_hid_mouse_write_task(k, mouse_class).await;
+                }
+            });
+            spawning.extend(quote! {
+                // HID Mouse Report sending
+                spawner.spawn(__usb_hid_mouse_write_task(#kb_name, mouse_class)).unwrap();
+            });
+        }
     }
 
     if keyboard.usb && (keyboard.via.is_some() || keyboard.vial.is_some()) {
@@ -492,6 +639,48 @@ pub(crate) fn keyboard_main(
         });
     }
 
+    if keyboard.raw_hid && !keyboard.usb {
+        emit_error!(
+            str,
+            "Raw HID is currently only supported over USB. Please add `usb` to use `raw_hid`."
+        );
+        error = true;
+    } else if keyboard.raw_hid {
+        initialization.extend(quote! {
+            // Raw HID setup
+            static RAW_HID_STATE: ::static_cell::StaticCell<::rumcake::usb::UsbState> = ::static_cell::StaticCell::new();
+            let raw_hid_state = RAW_HID_STATE.init(::rumcake::usb::UsbState::new());
+            let (raw_hid_reader, raw_hid_writer) =
+                ::rumcake::usb::setup_usb_raw_hid_reader_writer(&mut builder, raw_hid_state);
+        });
+        tasks.extend(quote! {
+            #[::embassy_executor::task]
+            async fn __usb_hid_raw_hid_read_task(k: #kb_name, raw_hid_reader: ::rumcake::usb::RawHidReportReader<'static, __usb_driver::UsbDriver>) {
+                ::rumcake::tasks::usb_hid_raw_hid_read_task(k, raw_hid_reader).await;
+            }
+
+            #[::embassy_executor::task]
+            async fn __usb_hid_raw_hid_write_task(k: #kb_name, raw_hid_writer: ::rumcake::usb::RawHidReportWriter<'static, __usb_driver::UsbDriver>) {
+                ::rumcake::tasks::usb_hid_raw_hid_write_task(k, raw_hid_writer).await;
+            }
+
+            #[::embassy_executor::task]
+            async fn __raw_hid_process_task(k: #kb_name) {
+                ::rumcake::tasks::raw_hid_process_task(k).await;
+            }
+        });
+        spawning.extend(quote! {
+            // Raw HID report reading, writing and processing
+            spawner
+                .spawn(__usb_hid_raw_hid_read_task(#kb_name, raw_hid_reader))
+                .unwrap();
+            spawner
+                .spawn(__usb_hid_raw_hid_write_task(#kb_name, raw_hid_writer))
+                .unwrap();
+            spawner.spawn(__raw_hid_process_task(#kb_name)).unwrap();
+        });
+    }
+
     if keyboard.via.is_some() && keyboard.vial.is_some() {
         emit_error!(
             str,
@@ -707,10 +896,17 @@ pub(crate) fn keyboard_main(
                     async fn __underglow_lighting_storage_task(underglow_animator_storage: ::rumcake::lighting::underglow::storage::UnderglowStorage::<#id, __underglow_driver::UnderglowDriver>) {
                         ::rumcake::tasks::lighting_storage_task(underglow_animator_storage, &DATABASE).await;
                     }
+
+                    #[::embassy_executor::task]
+                    async fn __underglow_zone_storage_task() {
+                        ::rumcake::tasks::underglow_zone_storage_task(&DATABASE).await;
+                    }
                 });
                 spawning.extend(quote! {
                     ::rumcake::lighting::initialize_lighting_data(&underglow_animator_storage, &DATABASE).await;
+                    ::rumcake::lighting::underglow::initialize_underglow_zone_data(&DATABASE).await;
                     spawner.spawn(__underglow_lighting_storage_task(underglow_animator_storage)).unwrap();
+                    spawner.spawn(__underglow_zone_storage_task()).unwrap();
                 });
             }
 
@@ -728,9 +924,15 @@ pub(crate) fn keyboard_main(
                 async fn __underglow_lighting_task(underglow_animator: ::rumcake::lighting::underglow::UnderglowAnimator::<#id, __underglow_driver::UnderglowDriver>) {
                     ::rumcake::tasks::lighting_task(underglow_animator, None).await;
                 }
+
+                #[::embassy_executor::task]
+                async fn __underglow_layer_task(id: #id) {
+                    ::rumcake::tasks::underglow_layer_task(id).await;
+                }
             });
             spawning.extend(quote! {
                 spawner.spawn(__underglow_lighting_task(underglow_animator)).unwrap();
+                spawner.spawn(__underglow_layer_task(#id)).unwrap();
             });
         }
     }
@@ -909,6 +1111,101 @@ pub(crate) fn keyboard_main(
         });
     }
 
+    // Audio setup
+    if let Some(args) = keyboard.audio {
+        let setup_fn = args.driver_setup_fn;
+        outer.extend(quote! {
+            mod __audio_driver {
+                use super::*;
+                pub type AudioDriver = impl ::rumcake::audio::AudioDriver<super::#kb_name>;
+                pub async fn __setup_audio_driver() -> AudioDriver {
+                    #setup_fn().await
+                }
+            }
+        });
+        tasks.extend(quote! {
+            #[::embassy_executor::task]
+            async fn __audio_task(k: #kb_name, audio_driver: __audio_driver::AudioDriver) {
+                ::rumcake::tasks::audio_task(k, audio_driver).await;
+            }
+        });
+        spawning.extend(quote! {
+            spawner.spawn(__audio_task(#kb_name, __audio_driver::__setup_audio_driver().await)).unwrap();
+        });
+    }
+
+    // Status LED setup
+    if let Some(args) = keyboard.status_led {
+        let pin = args.pin;
+        let hal_name: PathSegment = syn::parse_str(crate::hw::HAL_CRATE).unwrap();
+        tasks.extend(quote! {
+            #[::embassy_executor::task]
+            async fn __status_led_task(
+                k: #kb_name,
+                pin: ::rumcake::hw::platform::#hal_name::gpio::Output<'static>,
+            ) {
+                ::rumcake::tasks::status_led_task(k, pin).await;
+            }
+        });
+        spawning.extend(quote! {
+            spawner.spawn(__status_led_task(#kb_name, ::rumcake::hw::platform::output_pin!(#pin))).unwrap();
+        });
+    }
+
+    // Pointing device setup
+    if let Some(args) = keyboard.pointer {
+        if args.driver_setup_fn.is_none() && !args.mouse_keys {
+            emit_error!(
+                str,
+                "Pointer settings must specify a `driver_setup_fn`, or enable `mouse_keys`."
+            );
+            error = true;
+        }
+
+        tasks.extend(quote! {
+            #[::embassy_executor::task]
+            async fn __pointer_task(k: #kb_name) {
+                ::rumcake::tasks::pointer_task(k).await;
+            }
+        });
+        spawning.extend(quote! {
+            spawner.spawn(__pointer_task(#kb_name)).unwrap();
+        });
+
+        if let Some(setup_fn) = args.driver_setup_fn {
+            outer.extend(quote! {
+                mod __pointer_driver {
+                    use super::*;
+                    pub type PointerDriver = impl ::rumcake::pointer::PointerDriver<super::#kb_name>;
+                    pub async fn __setup_pointer_driver() -> PointerDriver {
+                        #setup_fn().await
+                    }
+                }
+            });
+            tasks.extend(quote! {
+                #[::embassy_executor::task]
+                async fn __pointer_driver_task(k: #kb_name, pointer_driver: __pointer_driver::PointerDriver) {
+                    ::rumcake::tasks::pointer_driver_task(k, pointer_driver).await;
+                }
+            });
+            spawning.extend(quote! {
+                spawner.spawn(__pointer_driver_task(#kb_name, __pointer_driver::__setup_pointer_driver().await)).unwrap();
+            });
+        }
+
+        if args.mouse_keys {
+            tasks.extend(quote! {
+                #[::embassy_executor::task]
+                async fn __mouse_keys_task(k: #kb_name) {
+                    ::rumcake::tasks::mouse_keys_task(k).await;
+                }
+            });
+            spawning.extend(quote! {
+                spawner.spawn(__mouse_keys_task(#kb_name)).unwrap();
+            });
+        }
+    }
+
     if let Some(arg) = keyboard.bootloader_double_tap_reset {
         let timeout: u64 = match arg {
             Override::Inherit => 200,
@@ -934,7 +1231,7 @@ pub(crate) fn keyboard_main(
 
         spawning.extend(quote! {
             unsafe {
-                ::rumcake::hw::check_double_tap_bootloader(#timeout).await;
+                ::rumcake::hw::check_double_tap_bootloader(#kb_name, #timeout).await;
             }
         });
     }
@@ -1005,7 +1302,8 @@ pub fn build_standard_matrix(input: StandardMatrixDefinition) -> TokenStream {
                                 ::rumcake::hw::platform::output_pin!(#rows)
                             ),*
                         ],
-                        Self::DEBOUNCE_MS
+                        Self::DEBOUNCE_MS,
+                        Self::DEBOUNCE_ALGORITHM
                     ).unwrap()
                 )
             })
@@ -1013,17 +1311,59 @@ pub fn build_standard_matrix(input: StandardMatrixDefinition) -> TokenStream {
     }
 }
 
-pub fn build_direct_pin_matrix(input: MatrixLike<OptionalItem<Ident>>) -> TokenStream {
+custom_keyword!(High);
+
+/// A pin in a direct pin matrix. Pins are active-low (pull-up) by default, and can be marked as
+/// active-high (pull-down) using `High(<pin>)`.
+#[derive(Debug)]
+pub struct DirectPin {
+    active_high: bool,
+    pin: Ident,
+}
+
+impl Parse for DirectPin {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        if input.peek(High) {
+            input.parse::<High>()?;
+            let content;
+            parenthesized!(content in input);
+            Ok(Self {
+                active_high: true,
+                pin: content.parse()?,
+            })
+        } else {
+            Ok(Self {
+                active_high: false,
+                pin: input.parse()?,
+            })
+        }
+    }
+}
+
+pub fn build_direct_pin_matrix(input: MatrixLike<OptionalItem<DirectPin>>) -> TokenStream {
     let values = input.rows.iter().map(|row| {
         let items = row.items.iter().map(|item| match item {
             OptionalItem::None => quote! { None },
-            OptionalItem::Some(pin_ident) => {
-                quote! { Some(::rumcake::hw::platform::input_pin!(#pin_ident)) }
+            OptionalItem::Some(DirectPin { active_high, pin }) => {
+                let pin = if *active_high {
+                    crate::hw::input_pin_pull_down(pin.clone())
+                } else {
+                    quote! { ::rumcake::hw::platform::input_pin!(#pin) }
+                };
+                quote! { Some(#pin) }
             }
         });
         quote! { #(#items),* }
     });
 
+    let active_high = input.rows.iter().map(|row| {
+        let items = row.items.iter().map(|item| match item {
+            OptionalItem::None => false,
+            OptionalItem::Some(DirectPin { active_high, .. }) => *active_high,
+        });
+        quote! { #(#items),* }
+    });
+
     let row_count = input.rows.len();
     let col_count = input
         .rows
@@ -1054,7 +1394,11 @@ pub fn build_direct_pin_matrix(input: MatrixLike<OptionalItem<Ident>>) -> TokenS
                         [
                             #([ #values ]),*
                         ],
-                        Self::DEBOUNCE_MS
+                        [
+                            #([ #active_high ]),*
+                        ],
+                        Self::DEBOUNCE_MS,
+                        Self::DEBOUNCE_ALGORITHM
                     ).unwrap()
                 )
             })
@@ -1066,6 +1410,46 @@ crate::parse_as_custom_fields! {
     pub struct AnalogMatrixDefinitionBuilder for AnalogMatrixDefinition {
         pub channels: Layer<OptionalItem<TuplePair>>,
         pub ranges: Layer<OptionalItem<ExprRange>>,
+        pub actuation_points: Option<Layer<OptionalItem<LitInt>>>,
+        pub modes: Option<Layer<OptionalItem<Ident>>>,
+        pub sensitivity: Option<Layer<OptionalItem<TuplePair>>>,
+        pub socd: Option<SocdPairs>,
+    }
+}
+
+pub struct SocdPairs {
+    bracket_token: syn::token::Bracket,
+    pairs: Punctuated<SocdPairDefinition, Token![,]>,
+}
+
+impl Parse for SocdPairs {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let content;
+        Ok(Self {
+            bracket_token: bracketed!(content in input),
+            pairs: Punctuated::parse_terminated(&content)?,
+        })
+    }
+}
+
+pub struct SocdPairDefinition {
+    mode: Ident,
+    parenthesis_token: syn::token::Paren,
+    first: TuplePair,
+    comma_token: Token![,],
+    second: TuplePair,
+}
+
+impl Parse for SocdPairDefinition {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let content;
+        Ok(Self {
+            mode: input.parse()?,
+            parenthesis_token: parenthesized!(content in input),
+            first: content.parse()?,
+            comma_token: content.parse()?,
+            second: content.parse()?,
+        })
     }
 }
 
@@ -1096,6 +1480,86 @@ pub fn build_analog_matrix(input: AnalogMatrixDefinition) -> TokenStream {
         .items
         .len();
 
+    let actuation_points = match input.actuation_points {
+        Some(actuation_points) => {
+            let rows = actuation_points.layer.rows.iter().map(|row| {
+                let items = row.items.iter().map(|item| match item {
+                    OptionalItem::None => quote! { 127 },
+                    OptionalItem::Some(percentage) => {
+                        let value = match percentage.base10_parse::<u16>() {
+                            Ok(percentage) if percentage <= 100 => (percentage * 255 / 100) as u8,
+                            _ => abort!(percentage, "Actuation points must be between 0 and 100."),
+                        };
+                        quote! { #value }
+                    }
+                });
+                quote! { [ #(#items),* ] }
+            });
+            quote! { [ #(#rows),* ] }
+        }
+        None => quote! { [[127; #col_count]; #row_count] },
+    };
+
+    let modes = match input.modes {
+        Some(modes) => {
+            let rows = modes.layer.rows.iter().map(|row| {
+                let items = row.items.iter().map(|item| match item {
+                    OptionalItem::None => quote! {
+                        ::rumcake::keyberon::analog::AnalogAcutationMode::Static
+                    },
+                    OptionalItem::Some(mode) => quote! {
+                        ::rumcake::keyberon::analog::AnalogAcutationMode::#mode
+                    },
+                });
+                quote! { [ #(#items),* ] }
+            });
+            quote! { [ #(#rows),* ] }
+        }
+        None => quote! {
+            [[::rumcake::keyberon::analog::AnalogAcutationMode::Static; #col_count]; #row_count]
+        },
+    };
+
+    let sensitivity = match input.sensitivity {
+        Some(sensitivity) => {
+            let rows = sensitivity.layer.rows.iter().map(|row| {
+                let items = row.items.iter().map(|item| match item {
+                    OptionalItem::None => quote! { (5, 5) },
+                    OptionalItem::Some(thresholds) => quote! { #thresholds },
+                });
+                quote! { [ #(#items),* ] }
+            });
+            quote! { [ #(#rows),* ] }
+        }
+        None => quote! { [[(5, 5); #col_count]; #row_count] },
+    };
+
+    let socd_pairs = input
+        .socd
+        .map(|socd| {
+            socd.pairs
+                .into_iter()
+                .map(
+                    |SocdPairDefinition {
+                         mode,
+                         first,
+                         second,
+                         ..
+                     }| {
+                        quote! {
+                            ::rumcake::keyberon::analog::SocdPair::new(
+                                #first,
+                                #second,
+                                ::rumcake::keyberon::analog::SocdMode::#mode
+                            )
+                        }
+                    },
+                )
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    let socd_count = socd_pairs.len();
+
     quote! {
         const MATRIX_ROWS: usize = #row_count;
         const MATRIX_COLS: usize = #col_count;
@@ -1106,7 +1570,8 @@ pub fn build_analog_matrix(input: AnalogMatrixDefinition) -> TokenStream {
                     ::rumcake::keyboard::PollableAnalogMatrix<
                         AdcSamplerType,
                         #col_count,
-                        #row_count
+                        #row_count,
+                        #socd_count
                     >
                 >
             > = ::rumcake::once_cell::sync::OnceCell::new();
@@ -1120,6 +1585,10 @@ pub fn build_analog_matrix(input: AnalogMatrixDefinition) -> TokenStream {
                         [
                             #([ #ranges ]),*
                         ],
+                        #actuation_points,
+                        #modes,
+                        #sensitivity,
+                        [ #(#socd_pairs),* ],
                     )
                 )
             })
@@ -1127,7 +1596,117 @@ pub fn build_analog_matrix(input: AnalogMatrixDefinition) -> TokenStream {
     }
 }
 
-pub fn build_layout(raw: TokenStream, layers: LayoutLike<TokenTree>) -> TokenStream {
+pub struct LayerAlias {
+    name: Ident,
+    eq_token: Token![=],
+    index: LitInt,
+}
+
+impl Parse for LayerAlias {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        Ok(Self {
+            name: input.parse()?,
+            eq_token: input.parse()?,
+            index: input.parse()?,
+        })
+    }
+}
+
+/// Strip the optional `layers { NAME = index, ... }` preamble from a `build_layout!` invocation,
+/// and replace any `(NAME)` layer switches in the layout with the corresponding layer index.
+pub fn resolve_layer_aliases(input: TokenStream) -> (TokenStream, Vec<LayerAlias>) {
+    let mut tokens = input.into_iter().peekable();
+
+    let aliases = match tokens.peek() {
+        Some(TokenTree::Ident(ident)) if ident == "layers" => {
+            let ident = ident.clone();
+            tokens.next();
+            let Some(TokenTree::Group(group)) = tokens.next() else {
+                abort!(ident, "Expected layer aliases: layers {{ NAME = 0, ... }}");
+            };
+            if group.delimiter() != Delimiter::Brace {
+                abort!(group, "Expected layer aliases: layers {{ NAME = 0, ... }}");
+            }
+
+            let aliases = match Punctuated::<LayerAlias, Token![,]>::parse_terminated
+                .parse2(group.stream())
+            {
+                Ok(aliases) => aliases.into_iter().collect::<Vec<_>>(),
+                Err(err) => abort!(err.span(), "{}", err),
+            };
+
+            for (i, alias) in aliases.iter().enumerate() {
+                if aliases[..i].iter().any(|other| other.name == alias.name) {
+                    emit_error!(
+                        alias.name,
+                        "Layer alias `{}` is defined more than once",
+                        alias.name
+                    );
+                }
+            }
+
+            aliases
+        }
+        _ => Vec::new(),
+    };
+
+    // Each layer is a brace group at the top level
+    let resolved = tokens
+        .map(|token| match token {
+            TokenTree::Group(group) if group.delimiter() == Delimiter::Brace => {
+                replace_group_stream(&group, replace_layer_aliases(group.stream(), &aliases))
+            }
+            token => token,
+        })
+        .collect();
+
+    (resolved, aliases)
+}
+
+fn replace_layer_aliases(stream: TokenStream, aliases: &[LayerAlias]) -> TokenStream {
+    stream
+        .into_iter()
+        .map(|token| {
+            let TokenTree::Group(group) = token else {
+                return token;
+            };
+
+            match group.delimiter() {
+                // Layer switch, e.g. `(NAV)`
+                Delimiter::Parenthesis => {
+                    let mut inner = group.stream().into_iter();
+                    match (inner.next(), inner.next()) {
+                        (Some(TokenTree::Ident(ident)), None) => aliases
+                            .iter()
+                            .find(|alias| alias.name == ident)
+                            .map_or(TokenTree::Group(group.clone()), |alias| {
+                                replace_group_stream(&group, alias.index.to_token_stream())
+                            }),
+                        _ => TokenTree::Group(group),
+                    }
+                }
+                // Rows and keycode groups
+                Delimiter::Bracket => {
+                    replace_group_stream(&group, replace_layer_aliases(group.stream(), aliases))
+                }
+                // Actions inside of braces are Rust expressions, so they are left untouched.
+                _ => TokenTree::Group(group),
+            }
+        })
+        .collect()
+}
+
+fn replace_group_stream(group: &Group, stream: TokenStream) -> TokenTree {
+    let mut new_group = Group::new(group.delimiter(), stream);
+    new_group.set_span(group.span());
+    TokenTree::Group(new_group)
+}
+
+pub fn build_layout(
+    raw: TokenStream,
+    layers: LayoutLike<TokenTree>,
+    aliases: Vec<LayerAlias>,
+) -> TokenStream {
     let rows = &layers
         .layers
         .first()
@@ -1143,6 +1722,19 @@ pub fn build_layout(raw: TokenStream, layers: LayoutLike<TokenTree>) -> TokenStr
     let row_count = rows.len();
     let col_count = first_row.items.len();
 
+    for alias in aliases {
+        match alias.index.base10_parse::<usize>() {
+            Ok(index) if index < layer_count => {}
+            Ok(_) => emit_error!(
+                alias.index,
+                "Layer alias `{}` refers to a layer that doesn't exist. There are only {} layers.",
+                alias.name,
+                layer_count
+            ),
+            Err(err) => emit_error!(alias.index, "{}", err),
+        }
+    }
+
     quote! {
         const LAYOUT_COLS: usize = #col_count;
         const LAYOUT_ROWS: usize = #row_count;
@@ -1184,53 +1776,151 @@ crate::parse_as_custom_fields! {
         sw_pos: TuplePair,
         cw_pos: TuplePair,
         ccw_pos: TuplePair,
+        acceleration: Option<AccelerationCurve>,
     }
 }
 
-custom_keyword!(Encoder);
-
-pub struct EncoderDefinition {
-    encoder_keyword: Encoder,
-    brace_token: syn::token::Brace,
-    encoder_args: SetupEncoderArgs,
+pub struct AccelerationCurve {
+    bracket_token: syn::token::Bracket,
+    points: Punctuated<TuplePair, Token![,]>,
 }
 
-impl Parse for EncoderDefinition {
+impl Parse for AccelerationCurve {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
         let content;
         Ok(Self {
-            encoder_keyword: input.parse()?,
-            brace_token: braced!(content in input),
-            encoder_args: content.parse()?,
+            bracket_token: bracketed!(content in input),
+            points: Punctuated::parse_terminated(&content)?,
         })
     }
 }
 
+crate::parse_as_custom_fields! {
+    pub struct SetupPolledEncoderArgsBuilder for SetupPolledEncoderArgs {
+        sw: Expr,
+        output_a: Expr,
+        output_b: Expr,
+        sw_pos: TuplePair,
+        cw_pos: TuplePair,
+        ccw_pos: TuplePair,
+        acceleration: Option<AccelerationCurve>,
+    }
+}
+
+custom_keyword!(Encoder);
+custom_keyword!(PolledEncoder);
+
+pub enum EncoderDefinition {
+    Interrupt {
+        encoder_keyword: Encoder,
+        brace_token: syn::token::Brace,
+        encoder_args: SetupEncoderArgs,
+    },
+    Polled {
+        encoder_keyword: PolledEncoder,
+        brace_token: syn::token::Brace,
+        encoder_args: SetupPolledEncoderArgs,
+    },
+}
+
+impl Parse for EncoderDefinition {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let content;
+        let lookahead = input.lookahead1();
+        if lookahead.peek(Encoder) {
+            Ok(Self::Interrupt {
+                encoder_keyword: input.parse()?,
+                brace_token: braced!(content in input),
+                encoder_args: content.parse()?,
+            })
+        } else if lookahead.peek(PolledEncoder) {
+            Ok(Self::Polled {
+                encoder_keyword: input.parse()?,
+                brace_token: braced!(content in input),
+                encoder_args: content.parse()?,
+            })
+        } else {
+            Err(lookahead.error())
+        }
+    }
+}
+
 pub fn setup_encoders(encoders: Punctuated<EncoderDefinition, Token![,]>) -> TokenStream {
     let count = encoders.len();
 
-    let (positions, definitions): (Vec<TokenStream>, Vec<TokenStream>) = encoders
+    let mut positions = Vec::new();
+    let mut definitions = Vec::new();
+    let mut curves = Vec::new();
+
+    // Encoders are returned in an array, so if both kinds of encoders are used, they need to be
+    // wrapped in the same type.
+    let mixed = encoders
         .iter()
-        .map(|EncoderDefinition { encoder_args, .. }| {
-            let SetupEncoderArgs {
-                sw_pin,
-                output_a_pin,
-                output_b_pin,
-                sw_pos,
-                cw_pos,
-                ccw_pos,
-            } = encoder_args;
-
-            (
-                quote! {
-                    [#sw_pos, #cw_pos, #ccw_pos]
-                },
-                quote! {
+        .any(|encoder| matches!(encoder, EncoderDefinition::Interrupt { .. }))
+        && encoders
+            .iter()
+            .any(|encoder| matches!(encoder, EncoderDefinition::Polled { .. }));
+
+    for encoder in encoders.iter() {
+        let (definition, sw_pos, cw_pos, ccw_pos, acceleration) = match encoder {
+            EncoderDefinition::Interrupt {
+                encoder_args:
+                    SetupEncoderArgs {
+                        sw_pin,
+                        output_a_pin,
+                        output_b_pin,
+                        sw_pos,
+                        cw_pos,
+                        ccw_pos,
+                        acceleration,
+                    },
+                ..
+            } => {
+                let definition = quote! {
                     ::rumcake::keyboard::EC11Encoder::new(#sw_pin, #output_a_pin, #output_b_pin)
-                },
-            )
-        })
-        .unzip();
+                };
+                let definition = if mixed {
+                    quote! { ::rumcake::keyboard::AnyEncoder::Interrupt(#definition) }
+                } else {
+                    definition
+                };
+                (definition, sw_pos, cw_pos, ccw_pos, acceleration)
+            }
+            EncoderDefinition::Polled {
+                encoder_args:
+                    SetupPolledEncoderArgs {
+                        sw,
+                        output_a,
+                        output_b,
+                        sw_pos,
+                        cw_pos,
+                        ccw_pos,
+                        acceleration,
+                    },
+                ..
+            } => {
+                let definition = quote! {
+                    ::rumcake::keyboard::PolledEncoder::new(#sw, #output_a, #output_b)
+                };
+                let definition = if mixed {
+                    quote! { ::rumcake::keyboard::AnyEncoder::Polled(#definition) }
+                } else {
+                    definition
+                };
+                (definition, sw_pos, cw_pos, ccw_pos, acceleration)
+            }
+        };
+
+        positions.push(quote! {
+            [#sw_pos, #cw_pos, #ccw_pos]
+        });
+        definitions.push(definition);
+
+        let points = acceleration.iter().flat_map(|curve| curve.points.iter());
+        curves.push(quote! {
+            &[#(#points),*]
+        });
+    }
 
     quote! {
         const ENCODER_COUNT: usize = #count;
@@ -1242,6 +1932,49 @@ pub fn setup_encoders(encoders: Punctuated<EncoderDefinition, Token![,]>) -> Tok
         fn get_layout_mappings() -> [[(u8, u8); 3]; Self::ENCODER_COUNT] {
             [#(#positions),*]
         }
+
+        fn get_acceleration_curves() -> [&'static [(u16, u8)]; Self::ENCODER_COUNT] {
+            [#(#curves),*]
+        }
+    }
+}
+
+crate::parse_as_custom_fields! {
+    pub struct ComboBufferArgsBuilder for ComboBufferArgs {
+        combo_count: LitInt,
+    }
+}
+
+pub fn setup_combo_buffer(ComboBufferArgs { combo_count }: ComboBufferArgs) -> TokenStream {
+    quote! {
+        const COMBO_COUNT: usize = #combo_count;
+
+        fn get_combo_buffer(
+        ) -> Option<&'static mut ::rumcake::keyboard::ComboBuffer<{ Self::COMBO_COUNT }>> {
+            static mut COMBO_BUFFER: ::rumcake::keyboard::ComboBuffer<#combo_count> =
+                ::rumcake::keyboard::ComboBuffer::new();
+            Some(unsafe { &mut COMBO_BUFFER })
+        }
+    }
+}
+
+crate::parse_as_custom_fields! {
+    pub struct TapDanceBufferArgsBuilder for TapDanceBufferArgs {
+        tap_dance_count: LitInt,
+    }
+}
+
+pub fn setup_tap_dance_buffer(
+    TapDanceBufferArgs { tap_dance_count }: TapDanceBufferArgs,
+) -> TokenStream {
+    quote! {
+        const TAP_DANCE_COUNT: usize = #tap_dance_count;
+
+        fn get_tap_dance_buffer() -> Option<&'static mut [::rumcake::keyboard::TapDanceEntry]> {
+            static mut TAP_DANCE_BUFFER: [::rumcake::keyboard::TapDanceEntry; #tap_dance_count] =
+                [const { ::rumcake::keyboard::TapDanceEntry::new() }; #tap_dance_count];
+            Some(unsafe { &mut TAP_DANCE_BUFFER })
+        }
     }
 }
 
@@ -1331,3 +2064,133 @@ pub fn remap_matrix(input: RemapMacroInput) -> TokenStream {
         }
     }
 }
+
+custom_keyword!(tap);
+custom_keyword!(press);
+custom_keyword!(release);
+custom_keyword!(delay);
+custom_keyword!(unicode);
+
+pub enum SequenceItem {
+    Text(LitStr),
+    Tap(Ident),
+    Press(Ident),
+    Release(Ident),
+    Delay(LitInt),
+    Unicode {
+        mode: Expr,
+        layout: Expr,
+        text: LitStr,
+    },
+}
+
+impl Parse for SequenceItem {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let content;
+        let lookahead = input.lookahead1();
+        if lookahead.peek(LitStr) {
+            Ok(Self::Text(input.parse()?))
+        } else if lookahead.peek(tap) {
+            input.parse::<tap>()?;
+            parenthesized!(content in input);
+            Ok(Self::Tap(content.parse()?))
+        } else if lookahead.peek(press) {
+            input.parse::<press>()?;
+            parenthesized!(content in input);
+            Ok(Self::Press(content.parse()?))
+        } else if lookahead.peek(release) {
+            input.parse::<release>()?;
+            parenthesized!(content in input);
+            Ok(Self::Release(content.parse()?))
+        } else if lookahead.peek(delay) {
+            input.parse::<delay>()?;
+            parenthesized!(content in input);
+            Ok(Self::Delay(content.parse()?))
+        } else if lookahead.peek(unicode) {
+            input.parse::<unicode>()?;
+            parenthesized!(content in input);
+            let mode = content.parse()?;
+            content.parse::<Token![,]>()?;
+            let layout = content.parse()?;
+            content.parse::<Token![,]>()?;
+            let text = content.parse()?;
+            Ok(Self::Unicode { mode, layout, text })
+        } else {
+            Err(lookahead.error())
+        }
+    }
+}
+
+pub fn sequence(items: Punctuated<SequenceItem, Token![,]>) -> TokenStream {
+    // Sequence events use the same encoding as keyberon's `Action::Sequence`
+    let event = |event: u8, key: &Ident| {
+        quote! { 1, #event, ::rumcake::keyberon::key_code::KeyCode::#key as u8 }
+    };
+
+    // Consecutive bytes are grouped into one part. Unicode characters are created by
+    // `unicode_sequence`, so each one gets its own part, and the parts are joined at compile time.
+    let mut parts = Vec::new();
+    let mut unicode_parts = Vec::new();
+    let mut bytes: Vec<TokenStream> = Vec::new();
+
+    for item in items.iter() {
+        match item {
+            SequenceItem::Text(text) => {
+                for character in text.value().bytes() {
+                    if !character.is_ascii() {
+                        abort!(
+                            text,
+                            "Strings can only contain ASCII characters. Use `unicode(mode, layout, \"...\")` to type other characters."
+                        );
+                    }
+                    if character == 1 {
+                        abort!(text, "Strings can not contain the character `\\x01`.");
+                    }
+                    bytes.push(quote! { #character });
+                }
+            }
+            SequenceItem::Tap(key) => bytes.push(event(1, key)),
+            SequenceItem::Press(key) => bytes.push(event(2, key)),
+            SequenceItem::Release(key) => bytes.push(event(3, key)),
+            SequenceItem::Delay(ms) => {
+                let value = match ms.base10_parse::<u32>() {
+                    Ok(0) => abort!(ms, "Delays must be greater than 0."),
+                    Ok(value) => value,
+                    Err(err) => abort!(ms, "{}", err),
+                };
+                let digits = value.to_string().into_bytes();
+                bytes.push(quote! { 1, 4, #(#digits,)* b'|' });
+            }
+            SequenceItem::Unicode { mode, layout, text } => {
+                for character in text.value().chars() {
+                    if !bytes.is_empty() {
+                        parts.push(quote! { &[#(#bytes),*] });
+                        bytes.clear();
+                    }
+
+                    let name = format_ident!("UNICODE_{}", unicode_parts.len());
+                    unicode_parts.push(quote! {
+                        const #name: [u8; ::rumcake::unicode::unicode_sequence_len(#mode, #layout, #character)] =
+                            ::rumcake::unicode::unicode_sequence(#mode, #layout, #character);
+                    });
+                    parts.push(quote! { &#name });
+                }
+            }
+        }
+    }
+
+    if !bytes.is_empty() {
+        parts.push(quote! { &[#(#bytes),*] });
+    }
+
+    quote! {
+        {
+            #(#unicode_parts)*
+            const PARTS: &[&[u8]] = &[#(#parts),*];
+            const BYTES: [u8; ::rumcake::keyboard::sequence_len(PARTS)] =
+                ::rumcake::keyboard::concat_sequences(PARTS);
+            const SEQUENCE: &[u8] = &BYTES;
+            &SEQUENCE
+        }
+    }
+}
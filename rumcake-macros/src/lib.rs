@@ -171,7 +171,8 @@ pub fn build_standard_matrix(input: proc_macro::TokenStream) -> proc_macro::Toke
 #[proc_macro]
 #[proc_macro_error]
 pub fn build_direct_pin_matrix(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    let matrix = parse_macro_input!(input as common::MatrixLike<common::OptionalItem<Ident>>);
+    let matrix =
+        parse_macro_input!(input as common::MatrixLike<common::OptionalItem<keyboard::DirectPin>>);
     keyboard::build_direct_pin_matrix(matrix).into()
 }
 
@@ -185,9 +186,10 @@ pub fn build_analog_matrix(input: proc_macro::TokenStream) -> proc_macro::TokenS
 #[proc_macro]
 #[proc_macro_error]
 pub fn build_layout(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    let raw = input.clone();
+    let (raw, aliases) = keyboard::resolve_layer_aliases(input.into());
+    let input = raw.clone().into();
     let layers = parse_macro_input!(input as common::LayoutLike<TokenTree>);
-    keyboard::build_layout(raw.into(), layers).into()
+    keyboard::build_layout(raw, layers, aliases).into()
 }
 
 #[proc_macro]
@@ -197,6 +199,27 @@ pub fn setup_encoders(input: proc_macro::TokenStream) -> proc_macro::TokenStream
     keyboard::setup_encoders(args).into()
 }
 
+#[proc_macro]
+#[proc_macro_error]
+pub fn setup_combo_buffer(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let args = parse_macro_input!(input as keyboard::ComboBufferArgs);
+    keyboard::setup_combo_buffer(args).into()
+}
+
+#[proc_macro]
+#[proc_macro_error]
+pub fn setup_tap_dance_buffer(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let args = parse_macro_input!(input as keyboard::TapDanceBufferArgs);
+    keyboard::setup_tap_dance_buffer(args).into()
+}
+
+#[proc_macro]
+#[proc_macro_error]
+pub fn sequence(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let items = parse_macro_input!(input with Punctuated<keyboard::SequenceItem, Token![,]>::parse_terminated);
+    keyboard::sequence(items).into()
+}
+
 #[proc_macro]
 #[proc_macro_error]
 pub fn remap_matrix(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
@@ -216,14 +239,26 @@ pub fn setup_backlight_matrix(input: proc_macro::TokenStream) -> proc_macro::Tok
 #[proc_macro]
 pub fn led_layout(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let matrix = parse_macro_input!(input as common::MatrixLike<common::OptionalItem<TuplePair>>);
-    backlight::led_layout(matrix).into()
+    let col_count = matrix
+        .rows
+        .iter()
+        .map(|row| row.items.len())
+        .max()
+        .unwrap_or(0);
+    backlight::led_layout(matrix, col_count).into()
 }
 
 #[proc_macro]
 pub fn led_flags(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let matrix =
         parse_macro_input!(input as common::MatrixLike<common::OptionalItem<backlight::LEDFlags>>);
-    backlight::led_flags(matrix).into()
+    let col_count = matrix
+        .rows
+        .iter()
+        .map(|row| row.items.len())
+        .max()
+        .unwrap_or(0);
+    backlight::led_flags(matrix, col_count).into()
 }
 
 mod drivers;
@@ -235,6 +270,13 @@ pub fn setup_ws2812_bitbang(input: proc_macro::TokenStream) -> proc_macro::Token
     drivers::ws2812::bitbang::setup_ws2812_bitbang(matrix).into()
 }
 
+#[proc_macro]
+#[proc_macro_error]
+pub fn setup_ws2812_spi(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let args = parse_macro_input!(input as drivers::ws2812::spi::WS2812SpiArgs);
+    drivers::ws2812::spi::setup_ws2812_spi(args).into()
+}
+
 #[proc_macro]
 pub fn ws2812_get_led_from_matrix_coordinates(
     input: proc_macro::TokenStream,
@@ -267,6 +309,37 @@ pub fn is31fl3731_get_led_from_rgb_matrix_coordinates(
     drivers::is31fl3731::get_led_from_rgb_matrix_coordinates(layout).into()
 }
 
+#[proc_macro]
+#[proc_macro_error]
+pub fn setup_aw20216s(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let args = parse_macro_input!(input as drivers::aw20216s::AW20216SArgs);
+    drivers::aw20216s::setup_aw20216s(args).into()
+}
+
+#[proc_macro]
+pub fn aw20216s_get_led_from_matrix_coordinates(
+    input: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    let layout = parse_macro_input!(input as common::MatrixLike<common::OptionalItem<Ident>>);
+    drivers::aw20216s::get_led_from_matrix_coordinates(layout).into()
+}
+
+#[proc_macro]
+#[proc_macro_error]
+pub fn aw20216s_get_led_from_rgb_matrix_coordinates(
+    input: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    let layout = parse_macro_input!(input as drivers::aw20216s::AW20216SRgbMatrixLedArgs);
+    drivers::aw20216s::get_led_from_rgb_matrix_coordinates(layout).into()
+}
+
+#[proc_macro]
+#[proc_macro_error]
+pub fn setup_gpio_pwm(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let args = parse_macro_input!(input as drivers::gpio_pwm::GpioPwmArgs);
+    drivers::gpio_pwm::setup_gpio_pwm(args).into()
+}
+
 #[proc_macro]
 #[proc_macro_error]
 pub fn setup_ssd1306(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
@@ -274,6 +347,34 @@ pub fn setup_ssd1306(input: proc_macro::TokenStream) -> proc_macro::TokenStream
     drivers::ssd1306::setup_ssd1306(args).into()
 }
 
+#[proc_macro]
+#[proc_macro_error]
+pub fn setup_sh1106(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let args = parse_macro_input!(input as drivers::sh1106::Sh1106Args);
+    drivers::sh1106::setup_sh1106(args).into()
+}
+
+#[proc_macro]
+#[proc_macro_error]
+pub fn setup_sharp_lcd(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let args = parse_macro_input!(input as drivers::sharp_memory_lcd::SharpMemoryLcdArgs);
+    drivers::sharp_memory_lcd::setup_sharp_lcd(args).into()
+}
+
+#[proc_macro]
+#[proc_macro_error]
+pub fn setup_analog_joystick(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let args = parse_macro_input!(input as drivers::analog_joystick::AnalogJoystickArgs);
+    drivers::analog_joystick::setup_analog_joystick(args).into()
+}
+
+#[proc_macro]
+#[proc_macro_error]
+pub fn setup_trackpoint(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let args = parse_macro_input!(input as drivers::trackpoint::TrackpointArgs);
+    drivers::trackpoint::setup_trackpoint(args).into()
+}
+
 #[proc_macro]
 #[proc_macro_error]
 pub fn setup_nrf_ble_split_central(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
@@ -370,6 +471,14 @@ pub fn rp_setup_i2c(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     hw::setup_i2c(args).into()
 }
 
+#[cfg(feature = "rp")]
+#[proc_macro]
+#[proc_macro_error]
+pub fn rp_setup_spi_txonly(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let args = parse_macro_input!(input as hw::SpiTxOnlyArgs);
+    hw::setup_spi_txonly(args).into()
+}
+
 #[cfg(feature = "nrf")]
 #[proc_macro]
 pub fn nrf_input_pin(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
@@ -408,6 +517,14 @@ pub fn nrf_setup_i2c(input: proc_macro::TokenStream) -> proc_macro::TokenStream
     hw::setup_i2c(args).into()
 }
 
+#[cfg(feature = "nrf")]
+#[proc_macro]
+#[proc_macro_error]
+pub fn nrf_setup_i2s(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let args = parse_macro_input!(input as hw::I2sArgs);
+    hw::setup_i2s(args).into()
+}
+
 mod via;
 
 #[proc_macro]
@@ -428,12 +545,12 @@ macro_rules! parse_as_custom_fields {
         $crate::parse_as_custom_fields!($str_vis struct $builder_name for $str_name [$($all)*] -> []);
     };
     ($str_vis:vis struct $builder_name:ident for $str_name:ident [$vis:vis $field_name:ident: Option<$type:ty> $(, $($rest:tt)*)? ] -> [$($processed:tt)*]) => {
-        $crate::parse_as_custom_fields!($str_vis struct $builder_name for $str_name [$($($rest)*)?] -> [$($processed)* $vis $field_name: (Some(None), Option<$type>),]);
+        $crate::parse_as_custom_fields!($str_vis struct $builder_name for $str_name [$($($rest)*)?] -> [$($processed)* $vis $field_name: (Some(None), $crate::common::parse_optional::<$type>, Option<$type>),]);
     };
     ($str_vis:vis struct $builder_name:ident for $str_name:ident [$vis:vis $field_name:ident: $type:ty $(, $($rest:tt)*)? ] -> [$($processed:tt)*]) => {
-        $crate::parse_as_custom_fields!($str_vis struct $builder_name for $str_name [$($($rest)*)?] -> [$($processed)* $vis $field_name: (None, $type),]);
+        $crate::parse_as_custom_fields!($str_vis struct $builder_name for $str_name [$($($rest)*)?] -> [$($processed)* $vis $field_name: (None, syn::parse::ParseBuffer::parse::<$type>, $type),]);
     };
-    ($str_vis:vis struct $builder_name:ident for $str_name:ident [] -> [$($vis:vis $field_name:ident: ($default:expr, $($type:tt)*)),*,]) => {
+    ($str_vis:vis struct $builder_name:ident for $str_name:ident [] -> [$($vis:vis $field_name:ident: ($default:expr, $parse:expr, $($type:tt)*)),*,]) => {
         $str_vis struct $str_name {
             $($vis $field_name: $($type)*),*
         }
@@ -471,7 +588,7 @@ macro_rules! parse_as_custom_fields {
                     let ident: Ident = input.parse()?;
                     let _colon: syn::Token![:] = input.parse()?;
                     match ident.to_string().as_str() {
-                        $(stringify!($field_name) => args.$field_name = Some(input.parse()?)),*,
+                        $(stringify!($field_name) => args.$field_name = Some($parse(input)?)),*,
                         _ => return Err(syn::Error::new(input.span(), "unknown field encountered."))
                     }
                     if input.is_empty() {
@@ -494,15 +611,96 @@ pub(crate) mod common {
     use syn::parse::Parse;
     use syn::{braced, bracketed, custom_keyword, Token};
 
+    /// Parse a value for an optional field in [`parse_as_custom_fields`]. Unlike the [`Parse`]
+    /// implementation for [`Option`], this doesn't require the value to be a [`syn::token::Token`].
+    pub fn parse_optional<T: Parse>(input: syn::parse::ParseStream) -> syn::Result<Option<T>> {
+        input.parse().map(Some)
+    }
+
     custom_keyword!(Multiplexer);
     custom_keyword!(Direct);
     custom_keyword!(pin);
     custom_keyword!(select_pins);
+    custom_keyword!(enable_pins);
+
+    /// Maximum number of enable pins, matching `rumcake::hw::MAX_CASCADED_MULTIPLEXERS`.
+    const MAX_CASCADED_MULTIPLEXERS: usize = 8;
 
     crate::parse_as_custom_fields! {
         pub struct MultiplexerArgsBuilder for MultiplexerArgs {
             pub pin: Ident,
-            pub select_pins: Row<OptionalItem<Ident>>
+            pub select_pins: Row<OptionalItem<Ident>>,
+            pub enable_pins: Option<Row<Ident>>
+        }
+    }
+
+    impl MultiplexerArgs {
+        /// Number of bits needed to select a channel, including the bits used to choose one of the
+        /// cascaded multiplexers (if `enable_pins` is specified).
+        pub fn address_bits(&self) -> usize {
+            let cascade_bits = self.enable_pins.as_ref().map_or(0, |enable_pins| {
+                enable_pins.items.len().next_power_of_two().trailing_zeros() as usize
+            });
+
+            self.select_pins.items.len() + cascade_bits
+        }
+
+        /// Create a `Multiplexer` that uses `address_bits` bits to select a channel. This must be
+        /// at least [`MultiplexerArgs::address_bits`].
+        pub fn to_multiplexer(&self, address_bits: usize) -> TokenStream {
+            let select_pins = self
+                .select_pins
+                .items
+                .iter()
+                .map(|select_pin| match select_pin {
+                    OptionalItem::None => quote! { None },
+                    OptionalItem::Some(pin_ident) => {
+                        quote! { Some(::rumcake::hw::platform::output_pin!(#pin_ident)) }
+                    }
+                });
+
+            match &self.enable_pins {
+                Some(enable_pins) => {
+                    if enable_pins.items.is_empty() {
+                        proc_macro_error::abort!(
+                            enable_pins.row_bracket.span.join(),
+                            "At least one enable pin must be specified."
+                        );
+                    }
+
+                    if enable_pins.items.len() > MAX_CASCADED_MULTIPLEXERS {
+                        proc_macro_error::abort!(
+                            enable_pins.row_bracket.span.join(),
+                            "At most {} multiplexers can be cascaded.",
+                            MAX_CASCADED_MULTIPLEXERS
+                        );
+                    }
+
+                    let enable_pins = enable_pins.items.iter().map(|pin_ident| {
+                        quote! { ::rumcake::hw::platform::output_pin!(#pin_ident) }
+                    });
+
+                    quote! {
+                        ::rumcake::hw::Multiplexer::new_cascaded(
+                            [ #(#select_pins),* ],
+                            [ #(#enable_pins),* ]
+                        )
+                    }
+                }
+                None => {
+                    // Pad the select pins, so that all multiplexers have the same number of pins
+                    let padding =
+                        (self.select_pins.items.len()..address_bits).map(|_| quote! { None });
+                    let select_pins = select_pins.chain(padding);
+
+                    quote! {
+                        ::rumcake::hw::Multiplexer::new(
+                            [ #(#select_pins),* ],
+                            None
+                        )
+                    }
+                }
+            }
         }
     }
 
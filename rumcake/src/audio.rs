@@ -0,0 +1,271 @@
+//! Audio feature.
+//!
+//! Plays short sample-based sounds when keys are pressed and released, using a DAC (usually
+//! connected over I2S). To use the audio feature, keyboards must implement [`AudioDevice`], and
+//! provide a driver that implements [`AudioDriver`].
+//!
+//! Sounds are grouped into [`KeySoundTheme`]s, which can be switched at runtime using
+//! [`AudioCommand`]s, or [`crate::keyboard::Keycode::Audio`] in your layout.
+
+use core::fmt::Debug;
+
+use defmt::{error, warn, Debug2Format};
+use embassy_futures::select::{select, Either};
+use embassy_sync::channel::Channel;
+use heapless::Vec;
+use keyberon::layout::Event;
+use serde::{Deserialize, Serialize};
+
+use crate::hw::platform::RawMutex;
+use crate::keyboard::MATRIX_EVENTS;
+use crate::State;
+
+/// A sound sample. Samples are mono, signed 16-bit PCM, played back at
+/// [`AudioDevice::SAMPLE_RATE`].
+#[derive(Debug, Clone, Copy)]
+pub struct AudioSample(pub &'static [i16]);
+
+/// A set of sounds to play on key events.
+///
+/// If there is more than one sample for an event, the sample is chosen based on the position of
+/// the key (`(row + col) % len`), so that neighbouring keys sound slightly different.
+#[derive(Debug, Clone, Copy)]
+pub struct KeySoundTheme {
+    /// Samples to play when a key is pressed.
+    pub press: &'static [AudioSample],
+    /// Samples to play when a key is released. This can be empty.
+    pub release: &'static [AudioSample],
+}
+
+/// Commands that can be sent to the audio task.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+#[repr(u8)]
+pub enum AudioCommand {
+    /// Turn key sounds on.
+    TurnOn = 0,
+    /// Turn key sounds off.
+    TurnOff = 1,
+    /// Toggle key sounds.
+    Toggle = 2,
+    /// Switch to the next theme in [`AudioDevice::get_themes`].
+    NextTheme = 3,
+    /// Switch to the previous theme in [`AudioDevice::get_themes`].
+    PrevTheme = 4,
+    /// Switch to the theme with the given index in [`AudioDevice::get_themes`].
+    SetTheme(u8) = 5,
+    /// Set the volume (`0` to `255`).
+    SetVolume(u8) = 6,
+    /// Increase the volume by the given amount.
+    IncreaseVolume(u8) = 7,
+    /// Decrease the volume by the given amount.
+    DecreaseVolume(u8) = 8,
+}
+
+/// Configuration for key sounds.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioConfig {
+    /// Whether key sounds are enabled.
+    pub enabled: bool,
+    /// Index of the current theme in [`AudioDevice::get_themes`].
+    pub theme: u8,
+    /// Volume (`0` to `255`).
+    pub volume: u8,
+}
+
+impl AudioConfig {
+    pub const fn default() -> Self {
+        AudioConfig {
+            enabled: true,
+            theme: 0,
+            volume: 128,
+        }
+    }
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self::default()
+    }
+}
+
+pub(crate) static AUDIO_COMMAND_CHANNEL: Channel<RawMutex, AudioCommand, 2> = Channel::new();
+
+/// State that contains the current audio configuration. Note that updating the state object will
+/// not control the output of the audio task. Use [`AudioCommand`]s instead.
+pub static AUDIO_CONFIG_STATE: State<AudioConfig> = State::new(AudioConfig::default(), &[]);
+
+/// A trait that keyboards must implement to use the audio feature.
+pub trait AudioDevice {
+    /// Sample rate of all [`AudioSample`]s, in Hz. This should match the sample rate that your
+    /// driver is configured with.
+    const SAMPLE_RATE: u32 = 16000;
+
+    /// Maximum number of sounds that can be played at the same time. If a key event occurs while
+    /// this many sounds are playing, the oldest sound is stopped to make room for the new one.
+    const MAX_VOICES: usize = 4;
+
+    /// Number of samples that get mixed and sent to the driver at a time. Larger values use more
+    /// memory, but reduce the chance of the DAC running out of samples.
+    const BUFFER_SIZE: usize = 256;
+
+    /// The themes that can be selected. The first theme is used by default.
+    fn get_themes() -> &'static [KeySoundTheme];
+}
+
+/// A trait that a driver must implement in order to play sounds.
+pub trait AudioDriver<K: AudioDevice> {
+    /// The type of error that the driver will return if [`AudioDriver::write`] fails.
+    type DriverWriteError: Debug;
+
+    /// Play a buffer of mixed samples. This should wait until the driver is ready to receive more
+    /// samples, so that the audio task is paced by the DAC.
+    async fn write(&mut self, buf: &[i16]) -> Result<(), Self::DriverWriteError>;
+
+    /// Called when there are no more sounds to play. You can use this to stop the DAC.
+    async fn stop(&mut self) {}
+}
+
+struct Voice {
+    sample: AudioSample,
+    position: usize,
+}
+
+fn select_sample(samples: &[AudioSample], row: u8, col: u8) -> Option<AudioSample> {
+    (!samples.is_empty()).then(|| samples[(row as usize + col as usize) % samples.len()])
+}
+
+fn process_command<K: AudioDevice>(config: &mut AudioConfig, command: AudioCommand) {
+    let theme_count = K::get_themes().len() as u8;
+
+    match command {
+        AudioCommand::TurnOn => config.enabled = true,
+        AudioCommand::TurnOff => config.enabled = false,
+        AudioCommand::Toggle => config.enabled = !config.enabled,
+        AudioCommand::NextTheme => {
+            if theme_count > 0 {
+                config.theme = (config.theme + 1) % theme_count;
+            }
+        }
+        AudioCommand::PrevTheme => {
+            if theme_count > 0 {
+                config.theme = (config.theme + theme_count - 1) % theme_count;
+            }
+        }
+        AudioCommand::SetTheme(theme) => {
+            if theme < theme_count {
+                config.theme = theme;
+            } else {
+                warn!("[AUDIO] Ignoring invalid theme: {}", theme);
+            }
+        }
+        AudioCommand::SetVolume(volume) => config.volume = volume,
+        AudioCommand::IncreaseVolume(amount) => {
+            config.volume = config.volume.saturating_add(amount)
+        }
+        AudioCommand::DecreaseVolume(amount) => {
+            config.volume = config.volume.saturating_sub(amount)
+        }
+    }
+}
+
+fn register_event<K: AudioDevice>(
+    voices: &mut Vec<Voice, { K::MAX_VOICES }>,
+    config: &AudioConfig,
+    event: Event,
+) where
+    [(); K::MAX_VOICES]:,
+{
+    if !config.enabled || K::MAX_VOICES == 0 {
+        return;
+    }
+
+    let Some(theme) = K::get_themes().get(config.theme as usize) else {
+        return;
+    };
+
+    let sample = match event {
+        Event::Press(row, col) => select_sample(theme.press, row, col),
+        Event::Release(row, col) => select_sample(theme.release, row, col),
+    };
+
+    if let Some(sample) = sample {
+        // Polyphony limit reached, so we stop the oldest sound
+        if voices.is_full() {
+            voices.remove(0);
+        }
+
+        voices
+            .push(Voice {
+                sample,
+                position: 0,
+            })
+            .ok();
+    }
+}
+
+pub async fn audio_task<K: AudioDevice>(_k: K, mut driver: impl AudioDriver<K>)
+where
+    [(); K::MAX_VOICES]:,
+    [(); K::BUFFER_SIZE]:,
+{
+    let Ok(mut subscriber) = MATRIX_EVENTS.subscriber() else {
+        warn!("[AUDIO] Could not subscribe to matrix events. The audio task will not run.");
+        return;
+    };
+    let mut config = AUDIO_CONFIG_STATE.get().await;
+    let mut voices: Vec<Voice, { K::MAX_VOICES }> = Vec::new();
+    let mut buf = [0i16; K::BUFFER_SIZE];
+
+    loop {
+        if voices.is_empty() {
+            // Sleep until there is something to do
+            match select(
+                subscriber.next_message_pure(),
+                AUDIO_COMMAND_CHANNEL.receive(),
+            )
+            .await
+            {
+                Either::First(event) => register_event::<K>(&mut voices, &config, event),
+                Either::Second(command) => {
+                    process_command::<K>(&mut config, command);
+                    AUDIO_CONFIG_STATE.set(config).await;
+                    continue;
+                }
+            }
+        }
+
+        while let Ok(command) = AUDIO_COMMAND_CHANNEL.try_receive() {
+            process_command::<K>(&mut config, command);
+            AUDIO_CONFIG_STATE.set(config).await;
+        }
+
+        while let Some(event) = subscriber.try_next_message_pure() {
+            register_event::<K>(&mut voices, &config, event);
+        }
+
+        // Mix all of the active voices into the buffer
+        let volume = config.volume as i32;
+        for (i, out) in buf.iter_mut().enumerate() {
+            let mixed: i32 = voices
+                .iter()
+                .filter_map(|voice| voice.sample.0.get(voice.position + i))
+                .map(|sample| *sample as i32)
+                .sum();
+            *out = (mixed * volume / 255).clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+        }
+
+        for voice in voices.iter_mut() {
+            voice.position += K::BUFFER_SIZE;
+        }
+        voices.retain(|voice| voice.position < voice.sample.0.len());
+
+        if let Err(err) = driver.write(&buf).await {
+            error!("[AUDIO] Couldn't play samples: {}", Debug2Format(&err));
+        }
+
+        if voices.is_empty() {
+            driver.stop().await;
+        }
+    }
+}
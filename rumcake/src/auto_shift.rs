@@ -0,0 +1,201 @@
+//! Auto shift feature.
+//!
+//! With auto shift, tapping a key sends it normally, and holding it for at least
+//! [`AutoShiftDevice::AUTO_SHIFT_TIMEOUT`] milliseconds sends it with `LShift` held. Only keys
+//! whose action on the current layer is a single keycode in [`AutoShiftDevice::AUTO_SHIFT_KEYS`]
+//! are affected. Other keys, including hold-tap keys, are sent to the layout without any delay.
+//!
+//! When an auto shift key is pressed, its press is delayed until the key is released or the
+//! timeout passes. If a different key is pressed in the meantime, the auto shift key is sent
+//! without shift first. Pressing a different key while a shifted key is held stops shifting.
+//!
+//! Delayed presses and releases are processed by key pairs and combos when they are finally sent
+//! to the layout, just like any other matrix event.
+//!
+//! To change how auto shift behaves, implement [`AutoShiftDevice`], and set
+//! [`crate::keyboard::KeyboardLayout::AutoShiftDeviceType`].
+
+use defmt::debug;
+use embassy_time::{Duration, Instant};
+use heapless::Vec;
+use keyberon::action::Action;
+use keyberon::key_code::KeyCode;
+use keyberon::layout::{Event, Layout as KeyberonLayout};
+
+use crate::keyboard::Keycode;
+
+/// A trait that keyboards can implement to change the behaviour of auto shift.
+pub trait AutoShiftDevice {
+    /// Time in milliseconds that a key has to be held for before it gets sent with shift.
+    const AUTO_SHIFT_TIMEOUT: u64 = 175;
+
+    /// Keycodes that get shifted when they are held. By default, this includes letters, numbers,
+    /// and symbols.
+    const AUTO_SHIFT_KEYS: &'static [KeyCode] = DEFAULT_AUTO_SHIFT_KEYS;
+}
+
+/// Keycodes that are used by [`AutoShiftDevice::AUTO_SHIFT_KEYS`] by default.
+pub const DEFAULT_AUTO_SHIFT_KEYS: &[KeyCode] = &[
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+    KeyCode::Kb1,
+    KeyCode::Kb2,
+    KeyCode::Kb3,
+    KeyCode::Kb4,
+    KeyCode::Kb5,
+    KeyCode::Kb6,
+    KeyCode::Kb7,
+    KeyCode::Kb8,
+    KeyCode::Kb9,
+    KeyCode::Kb0,
+    KeyCode::Minus,
+    KeyCode::Equal,
+    KeyCode::LBracket,
+    KeyCode::RBracket,
+    KeyCode::Bslash,
+    KeyCode::SColon,
+    KeyCode::Quote,
+    KeyCode::Grave,
+    KeyCode::Comma,
+    KeyCode::Dot,
+    KeyCode::Slash,
+];
+
+/// Default auto shift behaviour, used if
+/// [`crate::keyboard::KeyboardLayout::AutoShiftDeviceType`] is not set.
+pub struct DefaultAutoShiftDevice;
+impl AutoShiftDevice for DefaultAutoShiftDevice {}
+
+#[derive(Default)]
+pub(crate) struct AutoShiftState {
+    /// Auto shift key that is being held, and when it was pressed. Its press hasn't been sent to
+    /// the layout yet.
+    pending: Option<((u8, u8), Instant)>,
+    /// Auto shift key that was held past the timeout, and is being sent with shift.
+    shifted: Option<(u8, u8)>,
+    /// Auto shift key that was tapped, whose release still needs to be sent to the layout.
+    release_pending: Option<(u8, u8)>,
+}
+
+impl AutoShiftState {
+    /// Returns `true` if an auto shift key is waiting to be resolved, or if a release still needs
+    /// to be sent to the layout.
+    pub(crate) fn is_pending(&self) -> bool {
+        self.pending.is_some() || self.release_pending.is_some()
+    }
+
+    /// Returns `true` if `LShift` should be added to the report.
+    pub(crate) fn should_shift(&self) -> bool {
+        self.shifted.is_some()
+    }
+
+    fn is_auto_shift_key<D: AutoShiftDevice, const C: usize, const R: usize, const L: usize>(
+        layout: &mut KeyberonLayout<C, R, L, Keycode>,
+        (row, col): (u8, u8),
+    ) -> bool {
+        // Shift is already being held, so there's no need to delay the key
+        if layout
+            .keycodes()
+            .any(|k| matches!(k, KeyCode::LShift | KeyCode::RShift))
+        {
+            return false;
+        }
+
+        let layer = layout.current_layer();
+        matches!(
+            layout.get_action((row, col), layer),
+            Some(Action::KeyCode(key)) if D::AUTO_SHIFT_KEYS.contains(&key)
+        )
+    }
+
+    /// Resolve the press of the pending key without shift, adding it to `events`.
+    fn resolve_unshifted(&mut self, events: &mut Vec<Event, 2>) {
+        if let Some(((row, col), _)) = self.pending.take() {
+            let _ = events.push(Event::Press(row, col));
+        }
+    }
+
+    /// Process a matrix event. Returns the events that should be sent to the layout, in order.
+    pub(crate) fn event<D: AutoShiftDevice, const C: usize, const R: usize, const L: usize>(
+        &mut self,
+        layout: &mut KeyberonLayout<C, R, L, Keycode>,
+        event: Event,
+    ) -> Vec<Event, 2> {
+        let mut events = Vec::new();
+
+        match event {
+            Event::Press(row, col) => {
+                // Another key interrupts the held key. A pending key is sent without shift, and
+                // a shifted key stops being shifted, so that the new key isn't shifted too.
+                self.resolve_unshifted(&mut events);
+                self.shifted = None;
+
+                if Self::is_auto_shift_key::<D, C, R, L>(layout, (row, col)) {
+                    self.pending = Some(((row, col), Instant::now()));
+                    return events;
+                }
+            }
+            Event::Release(row, col) => {
+                if self.pending.is_some_and(|(coord, _)| coord == (row, col)) {
+                    debug!("[AUTO_SHIFT] Key tapped, sending without shift");
+                    self.resolve_unshifted(&mut events);
+                    self.release_pending = Some((row, col));
+                    return events;
+                }
+
+                if self.shifted == Some((row, col)) {
+                    self.shifted = None;
+                }
+            }
+        }
+
+        let _ = events.push(event);
+        events
+    }
+
+    /// Release tapped keys, and shift the pending key if the auto shift timeout has passed.
+    /// Returns the events that should be sent to the layout, in order. This should be called
+    /// before processing new events.
+    pub(crate) fn tick<D: AutoShiftDevice>(&mut self) -> Vec<Event, 2> {
+        let mut events = Vec::new();
+
+        if let Some((row, col)) = self.release_pending.take() {
+            let _ = events.push(Event::Release(row, col));
+        }
+
+        if let Some(((row, col), pressed_at)) = self.pending {
+            if pressed_at.elapsed() >= Duration::from_millis(D::AUTO_SHIFT_TIMEOUT) {
+                debug!("[AUTO_SHIFT] Key held, sending with shift");
+                self.pending = None;
+                self.shifted = Some((row, col));
+                let _ = events.push(Event::Press(row, col));
+            }
+        }
+
+        events
+    }
+}
@@ -6,13 +6,21 @@
 #[cfg(any(all(feature = "nrf", feature = "bluetooth"), doc))]
 pub mod nrf_ble;
 
+use defmt::{info, Debug2Format};
+use embassy_sync::channel::Channel;
+use embassy_sync::mutex::Mutex;
 use embassy_sync::signal::Signal;
+use postcard::experimental::max_size::MaxSize;
+use serde::{Deserialize, Serialize};
 
 use crate::hw::platform::RawMutex;
 use crate::hw::HIDDevice;
-use crate::keyboard::Keyboard;
+use crate::keyboard::{Keyboard, KeyboardLayout};
 use crate::State;
 
+/// The maximum number of host profiles that can carry their own [`BluetoothProfilePreferences`].
+pub const MAX_BLUETOOTH_PROFILES: usize = 4;
+
 /// A trait that keyboards must implement to communicate with host devices over Bluetooth (LE).
 pub trait BluetoothKeyboard: Keyboard + HIDDevice {
     /// Vendor ID for the keyboard.
@@ -23,10 +31,228 @@ pub trait BluetoothKeyboard: Keyboard + HIDDevice {
 
     /// Product version for the keyboard.
     const BLE_PRODUCT_VERSION: &'static str = Self::HARDWARE_REVISION;
+
+    /// Default preferences for each host profile, indexed by profile number. Profiles set to
+    /// `None` share the same configuration as every other profile, which is the default behaviour.
+    const BLE_PROFILE_PREFERENCES: [Option<BluetoothProfilePreferences>; MAX_BLUETOOTH_PROFILES] =
+        [None; MAX_BLUETOOTH_PROFILES];
+}
+
+/// Preferences that are applied when switching to a host profile. This can be used to make each
+/// host more recognizable, e.g. by using a different lighting color and default layer per host.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, MaxSize)]
+pub struct BluetoothProfilePreferences {
+    /// The layer to use as the default layer. If `None`, the default layer will not be changed.
+    pub default_layer: Option<u8>,
+    /// The hue and saturation to use for underglow and RGB backlighting. If `None`, the lighting
+    /// color will not be changed.
+    pub lighting_color: Option<(u8, u8)>,
+}
+
+/// An enumeration of possible commands that will be processed by the bluetooth task, used to
+/// manage the bonds of each host profile.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+#[repr(u8)]
+pub enum BluetoothCommand {
+    /// Switch to the given host profile, from `0` to [`MAX_BLUETOOTH_PROFILES`] - 1. The current
+    /// connection is dropped, and the keyboard will try to reconnect to the host that is bonded
+    /// to the new profile. If the profile has no bond, any host can pair with it.
+    SelectProfile(u8) = 0,
+    /// Clear the bond of the current host profile, and disconnect from its host. Another host can
+    /// then pair with the profile.
+    ClearProfile = 1,
+    /// Clear the bonds of every host profile, and disconnect from the current host.
+    ClearAllProfiles = 2,
+}
+
+pub(crate) static BLUETOOTH_COMMAND_CHANNEL: Channel<RawMutex, BluetoothCommand, 2> =
+    Channel::new();
+
+/// State that contains the currently selected host profile. Note that updating the state object
+/// will not change the host that the keyboard connects to. Use [`BluetoothCommand`]s instead.
+pub static BLUETOOTH_PROFILE_STATE: State<u8> = State::new(0, &[&BLUETOOTH_PROFILE_STATE_LISTENER]);
+
+static BLUETOOTH_PROFILE_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
+
+static BLUETOOTH_PROFILE_PREFERENCES: Mutex<
+    RawMutex,
+    Option<[Option<BluetoothProfilePreferences>; MAX_BLUETOOTH_PROFILES]>,
+> = Mutex::new(None);
+
+async fn get_profile_preferences<K: BluetoothKeyboard>(
+    profile: u8,
+) -> Option<BluetoothProfilePreferences> {
+    BLUETOOTH_PROFILE_PREFERENCES
+        .lock()
+        .await
+        .unwrap_or(K::BLE_PROFILE_PREFERENCES)
+        .get(profile as usize)
+        .copied()
+        .flatten()
+}
+
+/// Change the preferences for the given host profile. If `preferences` is `None`, the profile will
+/// share the same configuration as every other profile.
+pub async fn set_profile_preferences<K: BluetoothKeyboard>(
+    profile: u8,
+    preferences: Option<BluetoothProfilePreferences>,
+) {
+    let mut stored = BLUETOOTH_PROFILE_PREFERENCES.lock().await;
+    let profiles = stored.get_or_insert(K::BLE_PROFILE_PREFERENCES);
+    if let Some(entry) = profiles.get_mut(profile as usize) {
+        *entry = preferences;
+        BLUETOOTH_PROFILE_PREFERENCES_SAVE_SIGNAL.signal(());
+    }
+}
+
+/// Apply the preferences for the given host profile, if there are any. This is called by
+/// [`bluetooth_profile_task`] when switching to a different host profile. Changes are applied
+/// using the existing lighting command channels, and by changing the default layer of the layout.
+pub async fn apply_profile_preferences<K: BluetoothKeyboard + KeyboardLayout>(profile: u8)
+where
+    [(); K::LAYERS]:,
+    [(); K::LAYOUT_COLS]:,
+    [(); K::LAYOUT_ROWS]:,
+{
+    let Some(preferences) = get_profile_preferences::<K>(profile).await else {
+        return;
+    };
+
+    info!(
+        "[BT_HID] Applying preferences for profile {}: {}",
+        profile,
+        Debug2Format(&preferences)
+    );
+
+    if let Some(layer) = preferences.default_layer {
+        if (layer as usize) < K::LAYERS {
+            K::get_layout()
+                .layout
+                .lock()
+                .await
+                .set_default_layer(layer as usize);
+        }
+    }
+
+    #[cfg(any(feature = "underglow", feature = "rgb-backlight-matrix"))]
+    if let Some((hue, sat)) = preferences.lighting_color {
+        #[cfg(feature = "underglow")]
+        if let Some(channel) = <K::UnderglowDeviceType as crate::lighting::underglow::private::MaybeUnderglowDevice>::get_command_channel() {
+            channel
+                .send(crate::lighting::underglow::UnderglowCommand::SetHue(hue))
+                .await;
+            channel
+                .send(crate::lighting::underglow::UnderglowCommand::SetSaturation(sat))
+                .await;
+        }
+
+        #[cfg(feature = "rgb-backlight-matrix")]
+        if let Some(channel) = <K::RGBBacklightMatrixDeviceType as crate::lighting::rgb_backlight_matrix::private::MaybeRGBBacklightMatrixDevice>::get_command_channel() {
+            channel
+                .send(crate::lighting::rgb_backlight_matrix::RGBBacklightMatrixCommand::SetHue(hue))
+                .await;
+            channel
+                .send(crate::lighting::rgb_backlight_matrix::RGBBacklightMatrixCommand::SetSaturation(sat))
+                .await;
+        }
+    }
+}
+
+/// Task that applies the preferences of the current host profile when the keyboard starts, and
+/// every time the host profile is changed.
+pub async fn bluetooth_profile_task<K: BluetoothKeyboard + KeyboardLayout>()
+where
+    [(); K::LAYERS]:,
+    [(); K::LAYOUT_COLS]:,
+    [(); K::LAYOUT_ROWS]:,
+{
+    loop {
+        BLUETOOTH_PROFILE_STATE_LISTENER.reset();
+        apply_profile_preferences::<K>(BLUETOOTH_PROFILE_STATE.get().await).await;
+        BLUETOOTH_PROFILE_STATE_LISTENER.wait().await;
+    }
+}
+
+static BLUETOOTH_PROFILE_PREFERENCES_SAVE_SIGNAL: Signal<RawMutex, ()> = Signal::new();
+
+#[cfg(feature = "storage")]
+pub use storage::*;
+
+#[cfg(feature = "storage")]
+mod storage {
+    use core::any::TypeId;
+
+    use defmt::{info, warn, Debug2Format};
+
+    use crate::storage::{FlashStorage, StorageDevice, StorageKey, StorageService};
+
+    use super::{
+        BluetoothKeyboard, BluetoothProfilePreferences, BLUETOOTH_PROFILE_PREFERENCES,
+        BLUETOOTH_PROFILE_PREFERENCES_SAVE_SIGNAL, MAX_BLUETOOTH_PROFILES,
+    };
+
+    /// Obtains the host profile preferences from storage. If it fails to get data, the defaults
+    /// defined by [`BluetoothKeyboard::BLE_PROFILE_PREFERENCES`] will be used.
+    pub async fn initialize_bluetooth_profile_data<
+        K: BluetoothKeyboard,
+        S: StorageDevice + 'static,
+        F: FlashStorage,
+    >(
+        database: &StorageService<'_, F, S>,
+    ) where
+        [(); F::ERASE_SIZE]:,
+    {
+        let metadata: [u8; core::mem::size_of::<TypeId>()] = unsafe {
+            core::mem::transmute(TypeId::of::<
+                [Option<BluetoothProfilePreferences>; MAX_BLUETOOTH_PROFILES],
+            >())
+        };
+        let _ = database
+            .check_metadata(StorageKey::BluetoothProfiles, &metadata)
+            .await;
+
+        let profiles = if let Ok(profiles) = database.read(StorageKey::BluetoothProfiles).await {
+            info!(
+                "[BT_HID] Obtained profile preferences from storage: {}",
+                Debug2Format(&profiles)
+            );
+            profiles
+        } else {
+            warn!("[BT_HID] Could not get profile preferences from storage, using defaults.");
+            K::BLE_PROFILE_PREFERENCES
+        };
+
+        BLUETOOTH_PROFILE_PREFERENCES.lock().await.replace(profiles);
+    }
+
+    /// Task that saves the host profile preferences to storage whenever they are changed using
+    /// [`super::set_profile_preferences`].
+    pub async fn bluetooth_profile_storage_task<S: StorageDevice, F: FlashStorage>(
+        database: &StorageService<'_, F, S>,
+    ) where
+        [(); F::ERASE_SIZE]:,
+    {
+        loop {
+            BLUETOOTH_PROFILE_PREFERENCES_SAVE_SIGNAL.wait().await;
+            let profiles = *BLUETOOTH_PROFILE_PREFERENCES.lock().await;
+            if let Some(profiles) = profiles {
+                let _ = database
+                    .write(StorageKey::BluetoothProfiles, profiles)
+                    .await;
+            }
+        }
+    }
 }
 
-pub(crate) static BLUETOOTH_CONNECTED_STATE: State<bool> =
-    State::new(false, &[&crate::hw::BLUETOOTH_CONNECTED_STATE_LISTENER]);
+pub(crate) static BLUETOOTH_CONNECTED_STATE: State<bool> = State::new(
+    false,
+    &[
+        &crate::hw::BLUETOOTH_CONNECTED_STATE_LISTENER,
+        #[cfg(feature = "status-led")]
+        &crate::status_led::STATUS_LED_BLUETOOTH_CONNECTED_STATE_LISTENER,
+    ],
+);
 
 pub(crate) static CURRENT_OUTPUT_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
 pub(crate) static BATTERY_LEVEL_LISTENER: Signal<RawMutex, ()> = Signal::new();
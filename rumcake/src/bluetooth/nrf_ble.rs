@@ -1,7 +1,7 @@
 use core::cell::{Cell, RefCell};
 
 use defmt::{debug, error, info, warn, Debug2Format};
-use embassy_futures::select::{self, select3, select4};
+use embassy_futures::select::{self, select, select3, select4};
 use heapless::Vec;
 use nrf_softdevice::ble::gatt_server::builder::ServiceBuilder;
 use nrf_softdevice::ble::gatt_server::characteristic::{Attribute, Metadata, Properties};
@@ -21,11 +21,12 @@ use usbd_human_interface_device::device::consumer::MultipleConsumerReport;
 use usbd_human_interface_device::device::keyboard::NKROBootKeyboardReport;
 
 use crate::hw::platform::BLUETOOTH_ADVERTISING_MUTEX;
-use crate::hw::{HIDOutput, BATTERY_LEVEL_STATE, CURRENT_OUTPUT_STATE};
+use crate::hw::{get_lowest_battery_level, HIDOutput, CURRENT_OUTPUT_STATE};
 
 use crate::bluetooth::{
-    BluetoothKeyboard, BATTERY_LEVEL_LISTENER, BLUETOOTH_CONNECTED_STATE,
-    CURRENT_OUTPUT_STATE_LISTENER,
+    BluetoothCommand, BluetoothKeyboard, BATTERY_LEVEL_LISTENER, BLUETOOTH_COMMAND_CHANNEL,
+    BLUETOOTH_CONNECTED_STATE, BLUETOOTH_PROFILE_STATE, CURRENT_OUTPUT_STATE_LISTENER,
+    MAX_BLUETOOTH_PROFILES,
 };
 
 #[derive(Clone, Copy)]
@@ -35,27 +36,52 @@ struct Peer {
     peer_id: IdentityKey,
 }
 
+/// Bond information for a single host profile.
+#[derive(Default)]
+struct Bond {
+    peer: Option<Peer>,
+    sys_attrs: Vec<u8, 62>,
+}
+
 pub struct Bonder {
-    peer: Cell<Option<Peer>>,
-    sys_attrs: RefCell<Vec<u8, 62>>,
+    profile: Cell<u8>,
+    bonds: RefCell<[Bond; MAX_BLUETOOTH_PROFILES]>,
 }
 
 impl Default for Bonder {
     fn default() -> Self {
         Bonder {
-            peer: Cell::new(None),
-            sys_attrs: Default::default(),
+            profile: Cell::new(0),
+            bonds: Default::default(),
         }
     }
 }
 
+impl Bonder {
+    fn current_peer(&self) -> Option<Peer> {
+        self.bonds.borrow()[self.profile.get() as usize].peer
+    }
+
+    /// Check if the host of the given connection can use the current profile. This is the case if
+    /// the profile has no bond yet, or if it is bonded to the same host.
+    fn is_allowed(&self, conn: &Connection) -> bool {
+        self.current_peer()
+            .map(|peer| peer.peer_id.is_match(conn.peer_address()))
+            .unwrap_or(true)
+    }
+
+    fn clear(&self, profile: u8) {
+        self.bonds.borrow_mut()[profile as usize] = Bond::default();
+    }
+}
+
 impl SecurityHandler for Bonder {
     fn io_capabilities(&self) -> IoCapabilities {
         IoCapabilities::None
     }
 
-    fn can_bond(&self, _conn: &Connection) -> bool {
-        true
+    fn can_bond(&self, conn: &Connection) -> bool {
+        self.is_allowed(conn)
     }
 
     // fn display_passkey(&self, passkey: &[u8; 6]) {
@@ -79,23 +105,29 @@ impl SecurityHandler for Bonder {
         peer_id: IdentityKey,
     ) {
         // First time
-        debug!("[BT_HID] storing bond for: id: {}, key: {}", master_id, key);
+        debug!(
+            "[BT_HID] storing bond for profile {}: id: {}, key: {}",
+            self.profile.get(),
+            master_id,
+            key
+        );
 
         // TODO: save keys
-        self.sys_attrs.borrow_mut().clear();
-        self.peer.set(Some(Peer {
+        let mut bonds = self.bonds.borrow_mut();
+        let bond = &mut bonds[self.profile.get() as usize];
+        bond.sys_attrs.clear();
+        bond.peer = Some(Peer {
             master_id,
             key,
             peer_id,
-        }))
+        })
     }
 
     fn get_key(&self, _conn: &Connection, master_id: MasterId) -> Option<EncryptionInfo> {
         // Reconnecting with an existing bond
         debug!("[BT_HID] getting bond for: id: {}", master_id);
 
-        self.peer
-            .get()
+        self.current_peer()
             .and_then(|peer| (master_id == peer.master_id).then_some(peer.key))
     }
 
@@ -106,12 +138,14 @@ impl SecurityHandler for Bonder {
             conn.peer_address()
         );
 
-        if let Some(peer) = self.peer.get() {
+        let mut bonds = self.bonds.borrow_mut();
+        let bond = &mut bonds[self.profile.get() as usize];
+        if let Some(peer) = bond.peer {
             if peer.peer_id.is_match(conn.peer_address()) {
-                let mut sys_attrs = self.sys_attrs.borrow_mut();
+                let sys_attrs = &mut bond.sys_attrs;
                 let capacity = sys_attrs.capacity();
                 sys_attrs.resize(capacity, 0).unwrap();
-                let len = get_sys_attrs(conn, &mut sys_attrs).unwrap() as u16;
+                let len = get_sys_attrs(conn, sys_attrs).unwrap() as u16;
                 sys_attrs.truncate(len as usize);
                 // TODO: save sys_attrs for peer
             }
@@ -122,16 +156,15 @@ impl SecurityHandler for Bonder {
         let addr = conn.peer_address();
         debug!("[BT_HID] loading system attributes for: {}", addr);
 
-        let attrs = self.sys_attrs.borrow();
+        let bonds = self.bonds.borrow();
+        let bond = &bonds[self.profile.get() as usize];
 
-        // TODO: search stored peers
-        let attrs = if self
+        let attrs = if bond
             .peer
-            .get()
             .map(|peer| peer.peer_id.is_match(addr))
             .unwrap_or(false)
         {
-            (!attrs.is_empty()).then_some(attrs.as_slice())
+            (!bond.sys_attrs.is_empty()).then_some(bond.sys_attrs.as_slice())
         } else {
             None
         };
@@ -145,6 +178,41 @@ impl SecurityHandler for Bonder {
     }
 }
 
+/// Process a [`BluetoothCommand`]. Returns `true` if the current connection should be dropped.
+async fn process_command(bonder: &Bonder, command: BluetoothCommand) -> bool {
+    match command {
+        BluetoothCommand::SelectProfile(profile) => {
+            if profile as usize >= MAX_BLUETOOTH_PROFILES {
+                warn!("[BT_HID] Ignoring invalid profile: {}", profile);
+                return false;
+            }
+
+            if profile == bonder.profile.get() {
+                return false;
+            }
+
+            info!("[BT_HID] Switching to profile {}", profile);
+            bonder.profile.set(profile);
+            BLUETOOTH_PROFILE_STATE.set(profile).await;
+        }
+        BluetoothCommand::ClearProfile => {
+            info!(
+                "[BT_HID] Clearing bond for profile {}",
+                bonder.profile.get()
+            );
+            bonder.clear(bonder.profile.get());
+        }
+        BluetoothCommand::ClearAllProfiles => {
+            info!("[BT_HID] Clearing bonds for all profiles");
+            for profile in 0..MAX_BLUETOOTH_PROFILES {
+                bonder.clear(profile as u8);
+            }
+        }
+    }
+
+    true
+}
+
 #[repr(u8)]
 #[derive(Clone, Copy, PrimitiveEnum, Default)]
 pub enum VidSource {
@@ -617,7 +685,7 @@ impl Service for HIDService {
                 _ => {}
             }
         }
-        if handle == self.consumer_report_cccd_handle {
+        if handle == self.consumer_report_cccd_handle && !data.is_empty() {
             match data[0] & 0x01 {
                 0x00 => {
                     return Some(HIDServiceEvent::ConsumerReportCccdWrite {
@@ -632,7 +700,7 @@ impl Service for HIDService {
                 _ => {}
             }
         }
-        if handle == self.via_input_report_cccd_handle {
+        if handle == self.via_input_report_cccd_handle && !data.is_empty() {
             match data[0] & 0x01 {
                 0x00 => {
                     return Some(HIDServiceEvent::ViaReportCccdWrite {
@@ -742,16 +810,35 @@ where
 
         let connection = {
             let _lock = BLUETOOTH_ADVERTISING_MUTEX.lock().await;
-            match advertise_pairable(sd, advertisement, &Default::default(), bonder).await {
-                Ok(connection) => {
+            match select(
+                advertise_pairable(sd, advertisement, &Default::default(), bonder),
+                BLUETOOTH_COMMAND_CHANNEL.receive(),
+            )
+            .await
+            {
+                select::Either::First(Ok(connection)) => {
+                    // Only the host bonded to the current profile can connect to it
+                    if !bonder.is_allowed(&connection) {
+                        info!(
+                            "[BT_HID] Rejecting host device that is not bonded to profile {}",
+                            bonder.profile.get()
+                        );
+                        let _ = connection.disconnect();
+                        continue;
+                    }
+
                     info!("[BT_HID] Connection established with host device");
                     BLUETOOTH_CONNECTED_STATE.set(true).await;
                     connection
                 }
-                Err(error) => {
+                select::Either::First(Err(error)) => {
                     warn!("[BT_HID] BLE advertising error: {}", Debug2Format(&error));
                     continue;
                 }
+                select::Either::Second(command) => {
+                    process_command(bonder, command).await;
+                    continue;
+                }
             }
         };
 
@@ -802,7 +889,7 @@ where
         let adc_fut = async {
             loop {
                 BATTERY_LEVEL_LISTENER.wait().await;
-                let pct = BATTERY_LEVEL_STATE.get().await;
+                let pct = get_lowest_battery_level().await;
 
                 match server.bas.battery_level_notify(&connection, &pct) {
                     Ok(_) => {
@@ -934,25 +1021,41 @@ where
                         }
                     };
                 } else {
-                    CURRENT_OUTPUT_STATE_LISTENER.wait().await;
+                    CURRENT_OUTPUT_STATE
+                        .wait_until(|output| matches!(output, Some(HIDOutput::Bluetooth)))
+                        .await;
                 }
             }
         };
 
-        match select3(conn_fut, adc_fut, hid_fut).await {
-            select::Either3::First(error) => {
+        let command_fut = async {
+            loop {
+                let command = BLUETOOTH_COMMAND_CHANNEL.receive().await;
+                if process_command(bonder, command).await {
+                    break;
+                }
+            }
+        };
+
+        match select4(conn_fut, adc_fut, hid_fut, command_fut).await {
+            select::Either4::First(error) => {
                 warn!(
                     "[BT_HID] Connection has been lost: {}",
                     Debug2Format(&error)
                 );
                 BLUETOOTH_CONNECTED_STATE.set(false).await;
             }
-            select::Either3::Second(_) => {
+            select::Either4::Second(_) => {
                 error!("[BT_HID] Battery task failed. This should not happen.");
             }
-            select::Either3::Third(_) => {
+            select::Either4::Third(_) => {
                 error!("[BT_HID] HID task failed. This should not happen.");
             }
+            select::Either4::Fourth(()) => {
+                info!("[BT_HID] Disconnecting from host device");
+                let _ = connection.disconnect();
+                BLUETOOTH_CONNECTED_STATE.set(false).await;
+            }
         };
     }
 }
@@ -0,0 +1,124 @@
+//! Caps word feature.
+//!
+//! While caps word is active, letters are sent with `LShift` held, so that a single word can be
+//! typed in capital letters without having to hold shift or toggle caps lock. Caps word turns
+//! itself off when a key that doesn't belong in a word is pressed (like `Space` or `Enter`), or
+//! if no keys are pressed for [`CapsWordDevice::CAPS_WORD_TIMEOUT`] milliseconds.
+//!
+//! Caps word can be controlled with [`crate::keyboard::Keycode::CapsWord`] in your layout. To
+//! change how caps word behaves, implement [`CapsWordDevice`], and set
+//! [`crate::keyboard::KeyboardLayout::CapsWordDeviceType`].
+
+use defmt::debug;
+use embassy_time::{Duration, Instant};
+use heapless::Vec;
+use keyberon::key_code::KeyCode;
+
+/// A trait that keyboards can implement to change the behaviour of caps word.
+pub trait CapsWordDevice {
+    /// Time in milliseconds that caps word can be idle (no keys pressed) before it turns off. Set
+    /// this to `0` to disable the timeout.
+    const CAPS_WORD_TIMEOUT: u64 = 5000;
+
+    /// Determine whether pressing `key` continues the current word. If this returns `false`, caps
+    /// word is turned off, and `key` will be sent without shift.
+    ///
+    /// By default, letters, numbers, `Minus`, `BSpace`, `Delete` and the shift keys continue the
+    /// word.
+    fn caps_word_continues(key: KeyCode) -> bool {
+        Self::caps_word_should_shift(key)
+            || (KeyCode::Kb1 as u8..=KeyCode::Kb0 as u8).contains(&(key as u8))
+            || matches!(
+                key,
+                KeyCode::BSpace | KeyCode::Delete | KeyCode::LShift | KeyCode::RShift
+            )
+    }
+
+    /// Determine whether `key` should be sent with shift while caps word is active.
+    ///
+    /// By default, letters and `Minus` (which results in an underscore) are shifted.
+    fn caps_word_should_shift(key: KeyCode) -> bool {
+        (KeyCode::A as u8..=KeyCode::Z as u8).contains(&(key as u8)) || key == KeyCode::Minus
+    }
+}
+
+/// Default caps word behaviour, used if [`crate::keyboard::KeyboardLayout::CapsWordDeviceType`]
+/// is not set.
+pub struct DefaultCapsWordDevice;
+impl CapsWordDevice for DefaultCapsWordDevice {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+#[repr(u8)]
+/// An enumeration of possible commands that will be processed by caps word.
+pub enum CapsWordCommand {
+    /// Turn caps word on.
+    TurnOn = 0,
+    /// Turn caps word off.
+    TurnOff = 1,
+    /// Toggle caps word.
+    Toggle = 2,
+}
+
+pub(crate) struct CapsWordState {
+    active: bool,
+    last_activity: Instant,
+    held: Vec<KeyCode, 24>,
+}
+
+impl Default for CapsWordState {
+    fn default() -> Self {
+        Self {
+            active: false,
+            last_activity: Instant::MIN,
+            held: Vec::new(),
+        }
+    }
+}
+
+impl CapsWordState {
+    pub(crate) fn command(&mut self, command: CapsWordCommand) {
+        self.active = match command {
+            CapsWordCommand::TurnOn => true,
+            CapsWordCommand::TurnOff => false,
+            CapsWordCommand::Toggle => !self.active,
+        };
+        self.last_activity = Instant::now();
+
+        debug!("[CAPS_WORD] Caps word active: {}", self.active);
+    }
+
+    /// Update the caps word state using the keycodes that are currently held down. Returns `true`
+    /// if `LShift` should be added to the report.
+    pub(crate) fn update<D: CapsWordDevice>(
+        &mut self,
+        keycodes: impl Iterator<Item = KeyCode>,
+    ) -> bool {
+        let keycodes = keycodes.collect::<Vec<KeyCode, 24>>();
+
+        if self.active
+            && D::CAPS_WORD_TIMEOUT > 0
+            && self.last_activity.elapsed() >= Duration::from_millis(D::CAPS_WORD_TIMEOUT)
+        {
+            self.active = false;
+            debug!("[CAPS_WORD] Caps word timed out");
+        }
+
+        for key in keycodes.iter().filter(|k| !self.held.contains(k)) {
+            if !self.active {
+                break;
+            }
+
+            if D::caps_word_continues(*key) {
+                self.last_activity = Instant::now();
+            } else {
+                self.active = false;
+                debug!("[CAPS_WORD] Caps word ended by {}", *key as u8);
+            }
+        }
+
+        self.held = keycodes;
+
+        self.active && self.held.iter().any(|k| D::caps_word_should_shift(*k))
+    }
+}
@@ -0,0 +1,137 @@
+//! Utilities for converting between HSV and RGB colors.
+//!
+//! These are the same conversions that rumcake's underglow and RGB backlight matrix animators
+//! use, so custom effects can use them to produce colors that match the built-in effects.
+//!
+//! Hue, saturation and value are all stored as a [`u8`]. A hue of `0` is red, `85` is green, and
+//! `170` is blue.
+
+use smart_leds::RGB8;
+
+/// A color in the HSV color space.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Hsv {
+    /// Hue of the color.
+    pub h: u8,
+    /// Saturation of the color.
+    pub s: u8,
+    /// Value (brightness) of the color.
+    pub v: u8,
+}
+
+impl Hsv {
+    /// Create a new HSV color from its hue, saturation and value.
+    pub const fn new(h: u8, s: u8, v: u8) -> Self {
+        Self { h, s, v }
+    }
+}
+
+impl From<smart_leds::hsv::Hsv> for Hsv {
+    fn from(value: smart_leds::hsv::Hsv) -> Self {
+        Self::new(value.hue, value.sat, value.val)
+    }
+}
+
+impl From<Hsv> for smart_leds::hsv::Hsv {
+    fn from(value: Hsv) -> Self {
+        Self {
+            hue: value.h,
+            sat: value.s,
+            val: value.v,
+        }
+    }
+}
+
+/// Convert an HSV color to RGB.
+pub const fn hsv_to_rgb(hsv: Hsv) -> RGB8 {
+    let v = hsv.v as u16;
+    let s = hsv.s as u16;
+    let f = (hsv.h as u16 * 2 % 85) * 3; // position of the hue in its sixth of the hue circle
+
+    let p = (v * (255 - s) / 255) as u8;
+    let q = (v * (255 - (s * f) / 255) / 255) as u8;
+    let t = (v * (255 - (s * (255 - f)) / 255) / 255) as u8;
+    let v = v as u8;
+
+    let (r, g, b) = match hsv.h {
+        0..=42 | 255 => (v, t, p),
+        43..=84 => (q, v, p),
+        85..=127 => (p, v, t),
+        128..=169 => (p, q, v),
+        170..=212 => (t, p, v),
+        213..=254 => (v, p, q),
+    };
+
+    RGB8 { r, g, b }
+}
+
+/// Convert an RGB color to HSV. Converting the result back with [`hsv_to_rgb`] gives a color that
+/// is close to the original, but may not be identical due to rounding.
+pub const fn rgb_to_hsv(rgb: RGB8) -> Hsv {
+    let max = max(max(rgb.r, rgb.g), rgb.b);
+    let min = min(min(rgb.r, rgb.g), rgb.b);
+    let delta = (max - min) as i16;
+
+    if delta == 0 {
+        return Hsv::new(0, 0, max);
+    }
+
+    let s = (255 * delta as u16 / max as u16) as u8;
+
+    let (r, g, b) = (rgb.r as i16, rgb.g as i16, rgb.b as i16);
+    let h = if max == rgb.r {
+        43 * (g - b) / delta
+    } else if max == rgb.g {
+        85 + 43 * (b - r) / delta
+    } else {
+        170 + 43 * (r - g) / delta
+    };
+
+    Hsv::new(h.rem_euclid(256) as u8, s, max)
+}
+
+const fn max(a: u8, b: u8) -> u8 {
+    if a > b {
+        a
+    } else {
+        b
+    }
+}
+
+const fn min(a: u8, b: u8) -> u8 {
+    if a < b {
+        a
+    } else {
+        b
+    }
+}
+
+const _: () = {
+    const fn rgb(r: u8, g: u8, b: u8) -> RGB8 {
+        RGB8 { r, g, b }
+    }
+
+    const fn rgb_eq(a: RGB8, b: RGB8) -> bool {
+        a.r == b.r && a.g == b.g && a.b == b.b
+    }
+
+    const fn hsv_eq(a: Hsv, b: Hsv) -> bool {
+        a.h == b.h && a.s == b.s && a.v == b.v
+    }
+
+    assert!(rgb_eq(hsv_to_rgb(Hsv::new(0, 255, 255)), rgb(255, 0, 0)));
+    assert!(rgb_eq(hsv_to_rgb(Hsv::new(85, 255, 255)), rgb(0, 255, 0)));
+    assert!(rgb_eq(hsv_to_rgb(Hsv::new(170, 255, 255)), rgb(0, 0, 255)));
+    assert!(rgb_eq(hsv_to_rgb(Hsv::new(0, 0, 128)), rgb(128, 128, 128)));
+
+    assert!(hsv_eq(
+        rgb_to_hsv(rgb(0, 255, 255)),
+        Hsv::new(128, 255, 255)
+    ));
+    assert!(hsv_eq(rgb_to_hsv(rgb(255, 255, 0)), Hsv::new(43, 255, 255)));
+    assert!(hsv_eq(
+        rgb_to_hsv(rgb(255, 0, 255)),
+        Hsv::new(213, 255, 255)
+    ));
+    assert!(hsv_eq(rgb_to_hsv(rgb(0, 0, 0)), Hsv::new(0, 0, 0)));
+};
@@ -2,24 +2,46 @@
 //!
 //! To use the display feature, keyboards must implement [`DisplayDevice`], along
 //! with the trait corresponding to the chosen driver (which should implement
-//! [`drivers::DisplayDriver`]).
+//! [`DisplayDriver`]).
+//!
+//! The contents of the display are made up of [`Widget`]s, which are drawn one after another
+//! by the driver. To change what is shown, set [`DisplayDevice::WIDGETS`].
 
-use core::fmt::Debug;
+use core::convert::Infallible;
+use core::fmt::{Debug, Write};
+use core::marker::PhantomData;
+use core::ops::Range;
 
 use embassy_futures::select::{select, select_array, Either};
 use embassy_sync::signal::Signal;
 use embassy_time::{Duration, Ticker, Timer};
-use embedded_graphics::mono_font::ascii::FONT_6X10;
+use embedded_graphics::mono_font::ascii::{FONT_4X6, FONT_6X10};
 use embedded_graphics::mono_font::{MonoTextStyle, MonoTextStyleBuilder};
 use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::{Dimensions, DrawTarget, OriginDimensions, Point, Size};
+use embedded_graphics::primitives::Rectangle;
+use embedded_graphics::text::{Baseline, Text};
+use embedded_graphics::{Drawable, Pixel};
 use embedded_text::alignment::HorizontalAlignment;
 use embedded_text::style::{HeightMode, TextBoxStyle, TextBoxStyleBuilder};
+use embedded_text::TextBox;
 use heapless::String;
+use keyberon::action::Action;
+use keyberon::key_code::KeyCode;
 
 use crate::hw::platform::RawMutex;
+use crate::hw::PowerManaged;
+use crate::keyboard::{KeyboardLayout, Keycode};
 
 pub(crate) static OUTPUT_MODE_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
 pub(crate) static BATTERY_LEVEL_LISTENER: Signal<RawMutex, ()> = Signal::new();
+pub(crate) static CURRENT_LAYER_LISTENER: Signal<RawMutex, ()> = Signal::new();
+#[cfg(feature = "wpm")]
+pub(crate) static WPM_LISTENER: Signal<RawMutex, ()> = Signal::new();
+#[cfg(feature = "scan-metrics")]
+pub(crate) static SCAN_RATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
+#[cfg(feature = "idle")]
+pub(crate) static IDLE_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
 
 /// A trait that keyboards must implement to use a display.
 pub trait DisplayDevice {
@@ -32,6 +54,10 @@ pub trait DisplayDevice {
     ///
     /// If set to 0, the screen will always stay on.
     const TIMEOUT: usize = 30;
+
+    /// Widgets to draw on the display, in order. Set this to an empty slice if you want to draw
+    /// everything yourself.
+    const WIDGETS: &'static [&'static dyn Widget] = DEFAULT_WIDGETS;
 }
 
 /// Default style for text. The default style uses [`BinaryColor`], and [`FONT_6X10`].
@@ -46,119 +72,439 @@ pub static DEFAULT_TEXTBOX_STYLE: TextBoxStyle = TextBoxStyleBuilder::new()
     .alignment(HorizontalAlignment::Left)
     .build();
 
-macro_rules! text_box {
-    ($box:expr, "textbox", $text:expr) => {
-        embedded_text::TextBox::with_textbox_style(
-            $text,
-            $box,
-            DEFAULT_STYLE,
-            DEFAULT_TEXTBOX_STYLE,
-        )
-    };
-    ($box:expr, "text", $text:expr) => {
-        embedded_graphics::text::Text::new(
-            $text,
-            embedded_graphics::prelude::Point::zero(),
-            DEFAULT_STYLE,
-        )
-    };
+/// Possible orientations for a display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    /// Vertical/portrait orientation.
+    Vertical,
+    /// Horizontal/landscape orientation.
+    Horizontal,
 }
 
-// TODO: fix default horizontal layout overflow if "FlowLayout" ever gets implemented: https://github.com/bugadani/embedded-layout/issues/8
-macro_rules! on_update_default {
-    ($display:ident, "HORIZONTAL", $margin:expr) => {
-        on_update_default!($display, horizontal, $margin, "text")
-    };
-    ($display:ident, "VERTICAL", $margin:expr) => {
-        on_update_default!($display, vertical, $margin, "textbox")
-    };
-    ($display:ident, $direction:ident, $margin:expr, $text_type:tt) => {
-        use embedded_graphics::Drawable;
-        use embedded_layout::prelude::Align;
-
-        let bounding_box = $display.bounding_box();
-
-        // Empty chain
-        let contents = embedded_layout::prelude::Chain::new(embedded_graphics::text::Text::new(
-            "",
-            embedded_graphics::prelude::Point::zero(),
-            DEFAULT_STYLE,
-        ));
-
-        // Battery level
-        #[cfg(feature = "nrf-ble")]
-        let battery_level = {
-            let mut string: String<8> = String::from("BAT: ");
-            string
-                .push_str(&String::<3>::from(
-                    crate::hw::BATTERY_LEVEL_STATE.get().await,
-                ))
-                .unwrap();
-            string
-        };
+/// Information that [`Widget`]s can show. This is collected once before each frame is drawn.
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct DisplayData {
+    /// Battery level, obtained from [`crate::hw::get_lowest_battery_level`].
+    #[cfg(feature = "nrf-ble")]
+    pub battery_level: u8,
+
+    /// The current output mode. On split keyboard peripherals, this is received from the central
+    /// device (see [`crate::split::MessageToPeripheral::DisplayState`]).
+    #[cfg(any(
+        all(feature = "usb", feature = "bluetooth"),
+        feature = "split-peripheral"
+    ))]
+    pub output_mode: crate::hw::OutputMode,
+
+    /// The currently active layer, obtained from [`crate::keyboard::CURRENT_LAYER_STATE`].
+    pub current_layer: usize,
+
+    /// The current typing speed, obtained from [`crate::wpm::WPM_STATE`].
+    #[cfg(feature = "wpm")]
+    pub wpm: u16,
+
+    /// The current matrix scan rate, obtained from [`crate::scan_metrics::SCAN_RATE_STATE`].
+    #[cfg(feature = "scan-metrics")]
+    pub scan_rate: u32,
+}
+
+impl DisplayData {
+    async fn collect() -> Self {
+        Self {
+            #[cfg(feature = "nrf-ble")]
+            battery_level: crate::hw::get_lowest_battery_level().await,
+            #[cfg(any(
+                all(feature = "usb", feature = "bluetooth"),
+                feature = "split-peripheral"
+            ))]
+            output_mode: crate::hw::OUTPUT_MODE_STATE.get().await,
+            current_layer: crate::keyboard::CURRENT_LAYER_STATE.get().await,
+            #[cfg(feature = "wpm")]
+            wpm: crate::wpm::WPM_STATE.get().await,
+            #[cfg(feature = "scan-metrics")]
+            scan_rate: crate::scan_metrics::SCAN_RATE_STATE.get().await,
+        }
+    }
+}
+
+/// The area of the display that a [`Widget`] can draw to. The origin is the top left corner of
+/// the widget, and pixels outside of [`WidgetTarget::size`] are ignored.
+pub struct WidgetTarget<'a> {
+    draw: &'a mut dyn FnMut(Pixel<BinaryColor>),
+    size: Size,
+    orientation: Orientation,
+}
+
+impl<'a> WidgetTarget<'a> {
+    /// Orientation of the display. In [`Orientation::Horizontal`], widgets are placed next to each
+    /// other. In [`Orientation::Vertical`], widgets are placed below each other.
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    /// Draw a line of text using [`DEFAULT_STYLE`], returning the size of the area that was drawn
+    /// to. In [`Orientation::Vertical`], the text will wrap to fit the width of the widget.
+    pub fn draw_text(&mut self, text: &str) -> Size {
+        match self.orientation {
+            Orientation::Horizontal => {
+                let text = Text::with_baseline(text, Point::zero(), DEFAULT_STYLE, Baseline::Top);
+                let _ = text.draw(self);
+                text.bounding_box().size
+            }
+            Orientation::Vertical => {
+                let text_box = TextBox::with_textbox_style(
+                    text,
+                    Rectangle::new(Point::zero(), Size::new(self.size.width, 0)),
+                    DEFAULT_STYLE,
+                    DEFAULT_TEXTBOX_STYLE,
+                );
+                let _ = text_box.draw(self);
+                text_box.bounds.size
+            }
+        }
+    }
+}
+
+impl<'a> OriginDimensions for WidgetTarget<'a> {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
 
-        #[cfg(feature = "nrf-ble")]
-        let contents = contents.append(text_box!(bounding_box, $text_type, &battery_level));
-
-        // Mode
-        #[cfg(all(feature = "usb", feature = "bluetooth"))]
-        let contents = contents.append(text_box!(
-            bounding_box,
-            $text_type,
-            match crate::hw::OUTPUT_MODE_STATE.get().await {
-                crate::hw::OutputMode::Usb => "MODE: USB",
-                crate::hw::OutputMode::Bluetooth => "MODE: BT",
+impl<'a> DrawTarget for WidgetTarget<'a> {
+    type Color = BinaryColor;
+
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let bounds = Rectangle::new(Point::zero(), self.size);
+
+        for pixel in pixels {
+            if bounds.contains(pixel.0) {
+                (self.draw)(pixel);
             }
-        ));
-
-        embedded_layout::layout::linear::LinearLayout::$direction(contents)
-            .with_spacing(embedded_layout::layout::linear::FixedMargin($margin))
-            .align_to(
-                &bounding_box,
-                embedded_layout::prelude::horizontal::Left,
-                embedded_layout::prelude::vertical::Top,
-            )
-            .arrange()
-            .draw($display)
+        }
+
+        Ok(())
+    }
+}
+
+/// A piece of information that can be shown on the display.
+pub trait Widget {
+    /// Draw the widget to `target`, using the information in `data`. Returns the size of the area
+    /// that was drawn to, which is used to position the next widget. A widget that has nothing to
+    /// show can return [`Size::zero`].
+    fn draw(&self, data: &DisplayData, target: &mut WidgetTarget<'_>) -> Size;
+}
+
+/// Shows the battery level (BAT). Requires the `nrf-ble` feature.
+#[cfg(feature = "nrf-ble")]
+pub struct BatteryWidget;
+
+#[cfg(feature = "nrf-ble")]
+impl Widget for BatteryWidget {
+    fn draw(&self, data: &DisplayData, target: &mut WidgetTarget<'_>) -> Size {
+        let mut string: String<8> = String::from("BAT: ");
+        string
+            .push_str(&String::<3>::from(data.battery_level))
             .unwrap();
-    };
+        target.draw_text(&string)
+    }
 }
 
-use embedded_graphics::prelude::DrawTarget;
+/// Shows the current output mode. Requires the `usb` and `bluetooth` features, or the
+/// `split-peripheral` feature. See [`crate::hw::HardwareCommand::ToggleOutput`].
+#[cfg(any(
+    all(feature = "usb", feature = "bluetooth"),
+    feature = "split-peripheral"
+))]
+pub struct OutputModeWidget;
 
-/// Possible orientations for a display.
-pub enum Orientation {
-    /// Vertical/portrait orientation.
-    Vertical,
-    /// Horizontal/landscape orientation.
-    Horizontal,
+#[cfg(any(
+    all(feature = "usb", feature = "bluetooth"),
+    feature = "split-peripheral"
+))]
+impl Widget for OutputModeWidget {
+    fn draw(&self, data: &DisplayData, target: &mut WidgetTarget<'_>) -> Size {
+        target.draw_text(match data.output_mode {
+            crate::hw::OutputMode::Usb => "MODE: USB",
+            crate::hw::OutputMode::Bluetooth => "MODE: BT",
+            crate::hw::OutputMode::Auto => "MODE: AUTO",
+        })
+    }
+}
+
+/// Shows the index of the currently active layer (LAYER). This is not included in
+/// [`DEFAULT_WIDGETS`].
+pub struct LayerWidget;
+
+impl Widget for LayerWidget {
+    fn draw(&self, data: &DisplayData, target: &mut WidgetTarget<'_>) -> Size {
+        let mut string: String<17> = String::from("LAYER: ");
+        string
+            .push_str(&String::<10>::from(data.current_layer as u32))
+            .unwrap();
+        target.draw_text(&string)
+    }
+}
+
+/// Shows the current typing speed (WPM). Requires the `wpm` feature.
+#[cfg(feature = "wpm")]
+pub struct WpmWidget;
+
+#[cfg(feature = "wpm")]
+impl Widget for WpmWidget {
+    fn draw(&self, data: &DisplayData, target: &mut WidgetTarget<'_>) -> Size {
+        let mut string: String<10> = String::from("WPM: ");
+        string.push_str(&String::<5>::from(data.wpm)).unwrap();
+        target.draw_text(&string)
+    }
 }
 
-/// Default implementation for a display.
+/// Shows the number of matrix scans per second (SCAN). Requires the `scan-metrics` feature.
+/// This is not included in [`DEFAULT_WIDGETS`].
+#[cfg(feature = "scan-metrics")]
+pub struct ScanRateWidget;
+
+#[cfg(feature = "scan-metrics")]
+impl Widget for ScanRateWidget {
+    fn draw(&self, data: &DisplayData, target: &mut WidgetTarget<'_>) -> Size {
+        let mut string: String<16> = String::from("SCAN: ");
+        string
+            .push_str(&String::<10>::from(data.scan_rate))
+            .unwrap();
+        target.draw_text(&string)
+    }
+}
+
+/// Style used by [`KeymapWidget`] for its labels, so that a full row of keys can fit on small
+/// displays.
+static KEYMAP_STYLE: MonoTextStyle<'_, BinaryColor> = MonoTextStyleBuilder::new()
+    .font(&FONT_4X6)
+    .text_color(BinaryColor::On)
+    .build();
+
+/// Shows a grid of the keys on the currently active layer, using abbreviated key names. Each key
+/// gets an equal share of the widget's width, and labels that don't fit are cut off. This is not
+/// included in [`DEFAULT_WIDGETS`].
 ///
-/// The default contents of the display will depend on what feature flags are
-/// enabled. A list of possible data that may be shown includes:
-/// - Battery level (BAT): `nrf-ble` must be enabled.
-/// - Mode: `usb` and `bluetooth` enabled at the same time. See
-/// [`rumcake::bluetooth::BluetoothCommand::ToggleOutput`]
-pub async fn on_update_default(
+/// The layout is read every time the widget is drawn, so changes made to the layout (e.g. using
+/// Via) will also be shown.
+pub struct KeymapWidget<K>(PhantomData<K>);
+
+impl<K> KeymapWidget<K> {
+    /// Create a widget that shows the layout of `K`.
+    pub const fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<K: KeyboardLayout> Widget for KeymapWidget<K>
+where
+    [(); K::LAYERS]:,
+    [(); K::LAYOUT_ROWS]:,
+    [(); K::LAYOUT_COLS]:,
+{
+    fn draw(&self, data: &DisplayData, target: &mut WidgetTarget<'_>) -> Size {
+        // The layout task only holds the lock while processing events, so instead of waiting,
+        // we skip this frame if it is busy.
+        let Ok(mut layout) = K::get_layout().layout.try_lock() else {
+            return Size::zero();
+        };
+
+        let char_size = FONT_4X6.character_size;
+        let cols = K::LAYOUT_COLS.max(1) as u32;
+        let cell_width = (target.size().width / cols).max(char_size.width);
+        let max_chars = (cell_width / char_size.width) as usize;
+
+        for row in 0..K::LAYOUT_ROWS {
+            for col in 0..K::LAYOUT_COLS {
+                let Some(action) = layout.get_action((row as u8, col as u8), data.current_layer)
+                else {
+                    continue;
+                };
+
+                let label = action_label(&action);
+                let position = Point::new(
+                    (col as u32 * cell_width) as i32,
+                    (row as u32 * char_size.height) as i32,
+                );
+                let _ = Text::with_baseline(
+                    &label[..label.len().min(max_chars)],
+                    position,
+                    KEYMAP_STYLE,
+                    Baseline::Top,
+                )
+                .draw(target);
+            }
+        }
+
+        Size::new(cell_width * cols, char_size.height * K::LAYOUT_ROWS as u32)
+    }
+}
+
+fn action_label(action: &Action<Keycode, KeyCode>) -> String<8> {
+    let mut label = String::new();
+
+    match action {
+        Action::NoOp => {}
+        Action::Trans => label.push('_').unwrap(),
+        Action::KeyCode(key) => label = keycode_label(*key),
+        // Usually a key with some modifiers, so the last keycode is the most useful one to show
+        Action::MultipleKeyCodes(keys) => {
+            if let Some(key) = keys.last() {
+                label = keycode_label(*key);
+            }
+        }
+        Action::Layer(layer) | Action::TimedMomentaryLayer { layer, .. } => {
+            let _ = write!(label, "L{}", layer);
+        }
+        Action::ToggleLayer(layer) => {
+            let _ = write!(label, "T{}", layer);
+        }
+        Action::DefaultLayer(layer) => {
+            let _ = write!(label, "D{}", layer);
+        }
+        Action::HoldTap(hold_tap) => label = action_label(&hold_tap.tap),
+        Action::OneShot(one_shot) => label = action_label(&one_shot.action),
+        _ => label.push('*').unwrap(),
+    }
+
+    label
+}
+
+fn keycode_label(key: KeyCode) -> String<8> {
+    let short = match key {
+        KeyCode::Escape => "Esc",
+        KeyCode::BSpace => "Bspc",
+        KeyCode::Enter => "Ent",
+        KeyCode::Space => "Spc",
+        KeyCode::CapsLock => "Caps",
+        KeyCode::Delete => "Del",
+        KeyCode::Insert => "Ins",
+        KeyCode::PgDown => "PgDn",
+        KeyCode::Left => "Lft",
+        KeyCode::Right => "Rgt",
+        KeyCode::Down => "Dn",
+        KeyCode::LShift | KeyCode::RShift => "Sft",
+        KeyCode::LCtrl | KeyCode::RCtrl => "Ctl",
+        KeyCode::LAlt | KeyCode::RAlt => "Alt",
+        KeyCode::LGui | KeyCode::RGui => "Gui",
+        KeyCode::Minus => "-",
+        KeyCode::Equal => "=",
+        KeyCode::LBracket => "[",
+        KeyCode::RBracket => "]",
+        KeyCode::Bslash => "\\",
+        KeyCode::SColon => ";",
+        KeyCode::Quote => "'",
+        KeyCode::Grave => "`",
+        KeyCode::Comma => ",",
+        KeyCode::Dot => ".",
+        KeyCode::Slash => "/",
+        _ => {
+            // Use the name of the keycode, with the prefix of the number keys removed
+            let mut name: String<24> = String::new();
+            let _ = write!(name, "{:?}", key);
+            return name
+                .strip_prefix("Kb")
+                .unwrap_or(&name)
+                .chars()
+                .take(8)
+                .collect();
+        }
+    };
+
+    String::from(short)
+}
+
+/// Widgets that are shown if [`DisplayDevice::WIDGETS`] is not set.
+///
+/// The default widgets will depend on what feature flags are enabled. A list of possible widgets
+/// includes:
+/// - [`BatteryWidget`]: `nrf-ble` must be enabled.
+/// - [`OutputModeWidget`]: `usb` and `bluetooth` enabled at the same time.
+/// - [`WpmWidget`]: `wpm` must be enabled.
+pub const DEFAULT_WIDGETS: &[&dyn Widget] = &[
+    #[cfg(feature = "nrf-ble")]
+    &BatteryWidget,
+    #[cfg(all(feature = "usb", feature = "bluetooth"))]
+    &OutputModeWidget,
+    #[cfg(feature = "wpm")]
+    &WpmWidget,
+];
+
+/// Draw `widgets` to the display, starting from the top left corner. Widgets are separated by
+/// `margin` pixels.
+pub async fn draw_widgets(
     display: &mut impl DrawTarget<Color = BinaryColor, Error = impl Debug>,
+    widgets: &[&dyn Widget],
     orientation: Orientation,
     margin: i32,
 ) {
-    match orientation {
-        Orientation::Vertical => {
-            on_update_default!(display, "VERTICAL", margin);
+    let data = DisplayData::collect().await;
+    let bounds = display.bounding_box();
+    let mut offset = bounds.top_left;
+
+    for widget in widgets {
+        let used = (offset - bounds.top_left).component_max(Point::zero());
+        let size = match orientation {
+            Orientation::Horizontal => Size::new(
+                bounds.size.width.saturating_sub(used.x as u32),
+                bounds.size.height,
+            ),
+            Orientation::Vertical => Size::new(
+                bounds.size.width,
+                bounds.size.height.saturating_sub(used.y as u32),
+            ),
+        };
+
+        if size.width == 0 || size.height == 0 {
+            break;
         }
-        Orientation::Horizontal => {
-            on_update_default!(display, "HORIZONTAL", margin);
+
+        let mut draw = |Pixel(point, color): Pixel<BinaryColor>| {
+            // Pixels are already clipped by the widget target, so any errors are ignored
+            let _ = display.draw_iter(core::iter::once(Pixel(point + offset, color)));
+        };
+        let mut target = WidgetTarget {
+            draw: &mut draw,
+            size,
+            orientation,
+        };
+
+        let drawn = widget.draw(&data, &mut target);
+        if drawn == Size::zero() {
+            continue;
+        }
+
+        match orientation {
+            Orientation::Horizontal => offset.x += drawn.width as i32 + margin,
+            Orientation::Vertical => offset.y += drawn.height as i32 + margin,
         }
     }
 }
 
+/// Default implementation for a display, which draws [`DEFAULT_WIDGETS`].
+pub async fn on_update_default(
+    display: &mut impl DrawTarget<Color = BinaryColor, Error = impl Debug>,
+    orientation: Orientation,
+    margin: i32,
+) {
+    draw_widgets(display, DEFAULT_WIDGETS, orientation, margin).await;
+}
+
 /// Trait that drivers must implement to work with the display task.
-pub trait DisplayDriver<K: DisplayDevice> {
-    /// Use the driver to update the display with new information.
+///
+/// If the `idle` feature is enabled, the display task calls
+/// [`PowerManaged::enter_low_power`] when the keyboard goes idle, and doesn't update the display
+/// until the keyboard wakes up.
+pub trait DisplayDriver<K: DisplayDevice>: PowerManaged {
+    /// Use the driver to update the display with new information. Drivers should draw
+    /// [`DisplayDevice::WIDGETS`] using [`draw_widgets`].
     ///
     /// Called every time a data source updates, or every frame if [`DisplayDevice::FPS`] is non-zero.
     async fn on_update(&mut self);
@@ -172,9 +518,52 @@ pub trait DisplayDriver<K: DisplayDevice> {
     ///
     /// Called when the screen is being turned back on after being turned off.
     async fn turn_on(&mut self);
+
+    /// Make the next call to [`DisplayDriver::on_update`] send the entire frame to the display.
+    ///
+    /// Drivers that support partial updates should only send the parts of the frame that changed
+    /// since the last update (see [`changed_range`]). This is called after the display is turned
+    /// on, in case the display lost its contents. By default, this does nothing, which is fine
+    /// for drivers that always send the entire frame.
+    fn invalidate(&mut self) {}
+
+    /// Interval in milliseconds at which [`DisplayDriver::maintain`] gets called, if the display
+    /// hasn't been updated in that time. Set this to `0` if the driver doesn't need maintenance.
+    const MAINTENANCE_INTERVAL: u64 = 0;
+
+    /// Use the driver to perform periodic maintenance on the display, without changing its
+    /// contents (e.g. toggling VCOM on Sharp memory LCDs). This is also called while the display
+    /// is turned off, or while the keyboard is idle.
+    async fn maintain(&mut self) {}
 }
 
-pub async fn display_task<K: DisplayDevice>(_k: K, mut display: impl DisplayDriver<K>) {
+/// Wait until [`DisplayDriver::maintain`] should be called. This never completes if the driver
+/// doesn't need maintenance.
+async fn maintenance_timer<K: DisplayDevice, D: DisplayDriver<K>>() {
+    if D::MAINTENANCE_INTERVAL > 0 {
+        Timer::after(Duration::from_millis(D::MAINTENANCE_INTERVAL)).await
+    } else {
+        core::future::pending().await
+    }
+}
+
+/// Find the range of bytes that are different in `previous` and `current`. Returns [`None`] if
+/// they are the same.
+///
+/// This can be used by [`DisplayDriver`]s that keep a copy of the last frame that was sent to the
+/// display, to only send the parts of the frame buffer that changed.
+pub fn changed_range(previous: &[u8], current: &[u8]) -> Option<Range<usize>> {
+    let mut changed = previous
+        .iter()
+        .zip(current)
+        .enumerate()
+        .filter(|(_, (a, b))| a != b);
+    let (start, _) = changed.next()?;
+    let end = changed.last().map_or(start, |(i, _)| i) + 1;
+    Some(start..end)
+}
+
+pub async fn display_task<K: DisplayDevice, D: DisplayDriver<K>>(_k: K, mut display: D) {
     let mut ticker = if K::FPS > 0 {
         Some(Ticker::every(Duration::from_millis(1000 / K::FPS as u64)))
     } else {
@@ -186,21 +575,61 @@ pub async fn display_task<K: DisplayDevice>(_k: K, mut display: impl DisplayDriv
     let mut display_on = true;
 
     // Render a frame after turning on
+    display.invalidate();
     display.on_update().await;
 
     loop {
+        #[cfg(feature = "idle")]
+        if crate::idle::IDLE_STATE.get().await {
+            display.enter_low_power().await;
+            while crate::idle::IDLE_STATE.get().await {
+                if let Either::Second(()) =
+                    select(IDLE_STATE_LISTENER.wait(), maintenance_timer::<K, D>()).await
+                {
+                    display.maintain().await;
+                }
+            }
+            display.exit_low_power().await;
+
+            // Waking up is caused by activity, so we turn the display back on if it timed out
+            if !display_on {
+                display.turn_on().await;
+                display_on = true;
+            }
+
+            display.invalidate();
+            display.on_update().await;
+        }
+
         let update_fut = async {
-            if let Some(ref mut ticker) = ticker {
-                ticker.next().await;
-                ((), 0)
-            } else {
-                let mut result = select_array([
-                    OUTPUT_MODE_STATE_LISTENER.wait(),
-                    BATTERY_LEVEL_LISTENER.wait(),
-                ])
-                .await;
-                result.1 += 1;
-                result
+            loop {
+                let wait_fut = async {
+                    if let Some(ref mut ticker) = ticker {
+                        ticker.next().await;
+                        ((), 0)
+                    } else {
+                        let mut result = select_array([
+                            OUTPUT_MODE_STATE_LISTENER.wait(),
+                            BATTERY_LEVEL_LISTENER.wait(),
+                            #[cfg(feature = "wpm")]
+                            WPM_LISTENER.wait(),
+                            #[cfg(feature = "scan-metrics")]
+                            SCAN_RATE_LISTENER.wait(),
+                            CURRENT_LAYER_LISTENER.wait(),
+                            #[cfg(feature = "idle")]
+                            IDLE_STATE_LISTENER.wait(),
+                        ])
+                        .await;
+                        result.1 += 1;
+                        result
+                    }
+                };
+
+                // Maintenance doesn't count as an update, so it doesn't reset the display timeout
+                match select(wait_fut, maintenance_timer::<K, D>()).await {
+                    Either::First(result) => break result,
+                    Either::Second(()) => display.maintain().await,
+                }
             }
         };
 
@@ -218,6 +647,7 @@ pub async fn display_task<K: DisplayDevice>(_k: K, mut display: impl DisplayDriv
                             // Turn the display on in the event of a tick, or change in USB state.
                             if !display_on {
                                 display.turn_on().await;
+                                display.invalidate();
                                 display_on = true;
                             }
                         }
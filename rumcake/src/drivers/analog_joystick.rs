@@ -0,0 +1,170 @@
+//! Rumcake driver implementation for analog joysticks (thumbsticks).
+//!
+//! This driver provides an implementation for [`PointerDriver`]. Both axes of the joystick are
+//! read using the ADC sampler, so you must use `setup_adc_sampler!` to configure the analog pins
+//! that the joystick is connected to. The further the joystick is pushed from its center, the
+//! faster the cursor moves.
+//!
+//! To use this driver, keyboards must implement [`AnalogJoystickDevice`]. The result of
+//! [`setup_analog_joystick!`] should be returned by the `driver_setup_fn` used for the pointer
+//! task.
+
+use defmt::debug;
+use embassy_time::{Duration, Timer};
+
+use crate::keyboard::MatrixSampler;
+use crate::pointer::{PointerDevice, PointerDriver};
+
+pub use rumcake_macros::setup_analog_joystick;
+
+/// A trait that keyboards must implement to use an analog joystick.
+pub trait AnalogJoystickDevice: PointerDevice {
+    /// How often the joystick should be sampled, in milliseconds.
+    const JOYSTICK_POLL_INTERVAL: u64 = 10;
+
+    /// Number of samples that get averaged on startup to find the center position of the
+    /// joystick.
+    const JOYSTICK_CALIBRATION_SAMPLES: u16 = 16;
+
+    /// Readings that are within this distance from the center position are ignored. Increase this
+    /// if the cursor moves when the joystick is not being touched.
+    const JOYSTICK_DEADZONE: u16 = 64;
+
+    /// Speed of the cursor, in fixed point where `256` represents a multiplier of `1`. With a
+    /// value of `256`, each ADC unit of deflection outside of the deadzone moves the cursor by 1
+    /// count every poll.
+    const JOYSTICK_SENSITIVITY: u16 = 2;
+
+    /// Whether to invert the X axis.
+    const JOYSTICK_INVERT_X: bool = false;
+
+    /// Whether to invert the Y axis.
+    const JOYSTICK_INVERT_Y: bool = false;
+}
+
+/// Errors that can be returned by [`AnalogJoystick`].
+#[derive(Debug)]
+pub enum AnalogJoystickError {
+    /// A sample could not be obtained for one of the axes. Make sure that the channels passed to
+    /// [`AnalogJoystick::new`] are configured in `setup_adc_sampler!`.
+    MissingSample,
+}
+
+struct Axis {
+    ch: usize,
+    sub_ch: usize,
+    center: i32,
+    /// Sub-count movement that hasn't been sent yet, with 8 fractional bits.
+    remainder: i32,
+}
+
+impl Axis {
+    fn new((ch, sub_ch): (usize, usize)) -> Self {
+        Self {
+            ch,
+            sub_ch,
+            center: 0,
+            remainder: 0,
+        }
+    }
+
+    fn process<K: AnalogJoystickDevice>(&mut self, reading: i32, invert: bool) -> i16 {
+        let deflection = reading - self.center;
+        let magnitude = (deflection.abs() - K::JOYSTICK_DEADZONE as i32).max(0);
+
+        if magnitude == 0 {
+            self.remainder = 0;
+            return 0;
+        }
+
+        self.remainder += magnitude * deflection.signum() * K::JOYSTICK_SENSITIVITY as i32;
+        let movement = self.remainder / 256;
+        self.remainder -= movement * 256;
+
+        let movement = movement.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+        if invert {
+            -movement
+        } else {
+            movement
+        }
+    }
+}
+
+/// Driver for an analog joystick. Create this using [`setup_analog_joystick!`].
+pub struct AnalogJoystick<'a, S: MatrixSampler> {
+    sampler: &'a S,
+    x: Axis,
+    y: Axis,
+}
+
+impl<'a, S: MatrixSampler> AnalogJoystick<'a, S>
+where
+    i32: From<S::SampleType>,
+{
+    /// Create a new analog joystick driver. `x` and `y` are the `(ch, sub_ch)` pairs passed to
+    /// [`MatrixSampler::get_sample`] to read each axis.
+    pub fn new(sampler: &'a S, x: (usize, usize), y: (usize, usize)) -> Self {
+        Self {
+            sampler,
+            x: Axis::new(x),
+            y: Axis::new(y),
+        }
+    }
+
+    fn read(&self) -> Result<(i32, i32), AnalogJoystickError> {
+        let x = self.sampler.get_sample(self.x.ch, self.x.sub_ch);
+        let y = self.sampler.get_sample(self.y.ch, self.y.sub_ch);
+
+        match (x, y) {
+            (Some(x), Some(y)) => Ok((i32::from(x), i32::from(y))),
+            _ => Err(AnalogJoystickError::MissingSample),
+        }
+    }
+}
+
+impl<'a, K: AnalogJoystickDevice, S: MatrixSampler> PointerDriver<K> for AnalogJoystick<'a, S>
+where
+    i32: From<S::SampleType>,
+{
+    type DriverError = AnalogJoystickError;
+
+    const POLL_INTERVAL: u64 = K::JOYSTICK_POLL_INTERVAL;
+
+    async fn init(&mut self) -> Result<(), Self::DriverError> {
+        let (mut sum_x, mut sum_y, mut count) = (0, 0, 0);
+
+        for _ in 0..K::JOYSTICK_CALIBRATION_SAMPLES {
+            // Samples may not be available yet if the ADC sampler hasn't started
+            if let Ok((x, y)) = self.read() {
+                sum_x += x;
+                sum_y += y;
+                count += 1;
+            }
+
+            Timer::after(Duration::from_millis(K::JOYSTICK_POLL_INTERVAL)).await;
+        }
+
+        if count == 0 {
+            return Err(AnalogJoystickError::MissingSample);
+        }
+
+        self.x.center = sum_x / count;
+        self.y.center = sum_y / count;
+
+        debug!(
+            "[JOYSTICK] Calibrated center position: ({}, {})",
+            self.x.center, self.y.center
+        );
+
+        Ok(())
+    }
+
+    async fn read_movement(&mut self) -> Result<(i16, i16), Self::DriverError> {
+        let (x, y) = self.read()?;
+
+        Ok((
+            self.x.process::<K>(x, K::JOYSTICK_INVERT_X),
+            self.y.process::<K>(y, K::JOYSTICK_INVERT_Y),
+        ))
+    }
+}
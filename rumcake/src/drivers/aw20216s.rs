@@ -0,0 +1,445 @@
+//! Rumcake driver implementations for the AW20216S SPI LED matrix driver.
+//!
+//! The AW20216S can drive an 18x12 matrix of LEDs (72 RGB LEDs). Each LED has its own 8-bit PWM
+//! value, and its own 8-bit current scaling value, which is multiplied with the global current.
+//!
+//! This driver provides implementations for
+//! [`SimpleBacklightMatrixDriver`](`crate::lighting::simple_backlight_matrix::SimpleBacklightMatrixDriver`),
+//! and
+//! [`RGBBacklightMatrixDriver`](`crate::lighting::rgb_backlight_matrix::RGBBacklightMatrixDriver`)
+//!
+//! To use this driver for backlighting, keyboards must implement
+//! [`AW20216SBacklightDriver`](AW20216SBacklightDriver). The result of [`setup_driver`] should be
+//! passed to a backlight task.
+//!
+//! The driver expects an [`SpiDevice`], which handles the chip select pin. Note that the `EN` pin
+//! of the AW20216S must be driven high before the driver is set up.
+
+#[repr(u8)]
+#[allow(missing_docs)]
+/// Possible positions on an AW20216S LED matrix, in the form of `SW{x}_CS{y}`. Consult the
+/// datasheet for more details.
+pub enum Position {
+    SW1_CS1 = 0x00,
+    SW1_CS2,
+    SW1_CS3,
+    SW1_CS4,
+    SW1_CS5,
+    SW1_CS6,
+    SW1_CS7,
+    SW1_CS8,
+    SW1_CS9,
+    SW1_CS10,
+    SW1_CS11,
+    SW1_CS12,
+    SW1_CS13,
+    SW1_CS14,
+    SW1_CS15,
+    SW1_CS16,
+    SW1_CS17,
+    SW1_CS18,
+
+    SW2_CS1,
+    SW2_CS2,
+    SW2_CS3,
+    SW2_CS4,
+    SW2_CS5,
+    SW2_CS6,
+    SW2_CS7,
+    SW2_CS8,
+    SW2_CS9,
+    SW2_CS10,
+    SW2_CS11,
+    SW2_CS12,
+    SW2_CS13,
+    SW2_CS14,
+    SW2_CS15,
+    SW2_CS16,
+    SW2_CS17,
+    SW2_CS18,
+
+    SW3_CS1,
+    SW3_CS2,
+    SW3_CS3,
+    SW3_CS4,
+    SW3_CS5,
+    SW3_CS6,
+    SW3_CS7,
+    SW3_CS8,
+    SW3_CS9,
+    SW3_CS10,
+    SW3_CS11,
+    SW3_CS12,
+    SW3_CS13,
+    SW3_CS14,
+    SW3_CS15,
+    SW3_CS16,
+    SW3_CS17,
+    SW3_CS18,
+
+    SW4_CS1,
+    SW4_CS2,
+    SW4_CS3,
+    SW4_CS4,
+    SW4_CS5,
+    SW4_CS6,
+    SW4_CS7,
+    SW4_CS8,
+    SW4_CS9,
+    SW4_CS10,
+    SW4_CS11,
+    SW4_CS12,
+    SW4_CS13,
+    SW4_CS14,
+    SW4_CS15,
+    SW4_CS16,
+    SW4_CS17,
+    SW4_CS18,
+
+    SW5_CS1,
+    SW5_CS2,
+    SW5_CS3,
+    SW5_CS4,
+    SW5_CS5,
+    SW5_CS6,
+    SW5_CS7,
+    SW5_CS8,
+    SW5_CS9,
+    SW5_CS10,
+    SW5_CS11,
+    SW5_CS12,
+    SW5_CS13,
+    SW5_CS14,
+    SW5_CS15,
+    SW5_CS16,
+    SW5_CS17,
+    SW5_CS18,
+
+    SW6_CS1,
+    SW6_CS2,
+    SW6_CS3,
+    SW6_CS4,
+    SW6_CS5,
+    SW6_CS6,
+    SW6_CS7,
+    SW6_CS8,
+    SW6_CS9,
+    SW6_CS10,
+    SW6_CS11,
+    SW6_CS12,
+    SW6_CS13,
+    SW6_CS14,
+    SW6_CS15,
+    SW6_CS16,
+    SW6_CS17,
+    SW6_CS18,
+
+    SW7_CS1,
+    SW7_CS2,
+    SW7_CS3,
+    SW7_CS4,
+    SW7_CS5,
+    SW7_CS6,
+    SW7_CS7,
+    SW7_CS8,
+    SW7_CS9,
+    SW7_CS10,
+    SW7_CS11,
+    SW7_CS12,
+    SW7_CS13,
+    SW7_CS14,
+    SW7_CS15,
+    SW7_CS16,
+    SW7_CS17,
+    SW7_CS18,
+
+    SW8_CS1,
+    SW8_CS2,
+    SW8_CS3,
+    SW8_CS4,
+    SW8_CS5,
+    SW8_CS6,
+    SW8_CS7,
+    SW8_CS8,
+    SW8_CS9,
+    SW8_CS10,
+    SW8_CS11,
+    SW8_CS12,
+    SW8_CS13,
+    SW8_CS14,
+    SW8_CS15,
+    SW8_CS16,
+    SW8_CS17,
+    SW8_CS18,
+
+    SW9_CS1,
+    SW9_CS2,
+    SW9_CS3,
+    SW9_CS4,
+    SW9_CS5,
+    SW9_CS6,
+    SW9_CS7,
+    SW9_CS8,
+    SW9_CS9,
+    SW9_CS10,
+    SW9_CS11,
+    SW9_CS12,
+    SW9_CS13,
+    SW9_CS14,
+    SW9_CS15,
+    SW9_CS16,
+    SW9_CS17,
+    SW9_CS18,
+
+    SW10_CS1,
+    SW10_CS2,
+    SW10_CS3,
+    SW10_CS4,
+    SW10_CS5,
+    SW10_CS6,
+    SW10_CS7,
+    SW10_CS8,
+    SW10_CS9,
+    SW10_CS10,
+    SW10_CS11,
+    SW10_CS12,
+    SW10_CS13,
+    SW10_CS14,
+    SW10_CS15,
+    SW10_CS16,
+    SW10_CS17,
+    SW10_CS18,
+
+    SW11_CS1,
+    SW11_CS2,
+    SW11_CS3,
+    SW11_CS4,
+    SW11_CS5,
+    SW11_CS6,
+    SW11_CS7,
+    SW11_CS8,
+    SW11_CS9,
+    SW11_CS10,
+    SW11_CS11,
+    SW11_CS12,
+    SW11_CS13,
+    SW11_CS14,
+    SW11_CS15,
+    SW11_CS16,
+    SW11_CS17,
+    SW11_CS18,
+
+    SW12_CS1,
+    SW12_CS2,
+    SW12_CS3,
+    SW12_CS4,
+    SW12_CS5,
+    SW12_CS6,
+    SW12_CS7,
+    SW12_CS8,
+    SW12_CS9,
+    SW12_CS10,
+    SW12_CS11,
+    SW12_CS12,
+    SW12_CS13,
+    SW12_CS14,
+    SW12_CS15,
+    SW12_CS16,
+    SW12_CS17,
+    SW12_CS18,
+}
+
+use core::fmt::Debug;
+
+use embassy_time::Timer;
+use embedded_hal_async::spi::{Operation, SpiDevice};
+use smart_leds::RGB8;
+
+pub use rumcake_macros::{
+    aw20216s_get_led_from_matrix_coordinates as get_led_from_matrix_coordinates,
+    aw20216s_get_led_from_rgb_matrix_coordinates as get_led_from_rgb_matrix_coordinates,
+    setup_aw20216s,
+};
+
+/// Number of LEDs that the AW20216S can drive.
+pub const NUM_LEDS: usize = 216;
+
+const CHIP_ID: u8 = 0b1010 << 4;
+
+const PAGE_FUNCTION: u8 = 0x00;
+const PAGE_PWM: u8 = 0x01;
+const PAGE_SCALING: u8 = 0x02;
+
+const REG_GLOBAL_CONFIG: u8 = 0x00;
+const REG_GLOBAL_CURRENT: u8 = 0x01;
+const REG_RESET: u8 = 0x2F;
+const REG_MIX_FUNCTION: u8 = 0x46;
+
+const RESET_MAGIC: u8 = 0xAE;
+const GLOBAL_CONFIG_SWSEL_1_12: u8 = 0xB0;
+const GLOBAL_CONFIG_CHIPEN: u8 = 0x01;
+const MIX_FUNCTION_LPEN: u8 = 0x01 << 1;
+
+/// A trait that keyboards must implement to use the AW20216S driver for backlighting.
+pub trait AW20216SBacklightDriver {
+    /// Global current control value, from 0 to 255. The current of each LED is determined by this
+    /// value, multiplied by the LED's current scaling value.
+    const GLOBAL_CURRENT: u8 = 150;
+
+    /// Current scaling value for each LED, indexed by [`Position`]. Defaults to 150 for all LEDs.
+    const CURRENT_SCALING: [u8; NUM_LEDS] = [150; NUM_LEDS];
+
+    /// Convert matrix coordinates in the form of (col, row) to an AW20216S [`Position`].
+    ///
+    /// It is recommended to use [`get_led_from_matrix_coordinates`] or
+    /// [`get_led_from_rgb_matrix_coordinates`] to implement this function.
+    fn get_led_from_matrix_coordinates(x: u8, y: u8) -> u8;
+}
+
+/// AW20216S driver, which communicates using an SPI device.
+pub struct AW20216S<S: SpiDevice> {
+    spi: S,
+}
+
+impl<S: SpiDevice> AW20216S<S> {
+    async fn write_registers(&mut self, page: u8, reg: u8, data: &[u8]) -> Result<(), S::Error> {
+        self.spi
+            .transaction(&mut [
+                Operation::Write(&[CHIP_ID | page << 1, reg]),
+                Operation::Write(data),
+            ])
+            .await
+    }
+
+    /// Set the PWM values of all of the LEDs, indexed by [`Position`].
+    pub async fn set_pwm(&mut self, pwm: &[u8; NUM_LEDS]) -> Result<(), S::Error> {
+        self.write_registers(PAGE_PWM, 0x00, pwm).await
+    }
+
+    /// Reset the chip, and initialize it using the current settings in the
+    /// [`AW20216SBacklightDriver`] implementation.
+    pub async fn setup<K: AW20216SBacklightDriver>(&mut self) -> Result<(), S::Error> {
+        self.write_registers(PAGE_FUNCTION, REG_RESET, &[RESET_MAGIC])
+            .await?;
+        Timer::after_millis(2).await;
+
+        self.set_enabled(true).await?;
+        self.write_registers(PAGE_FUNCTION, REG_GLOBAL_CURRENT, &[K::GLOBAL_CURRENT])
+            .await?;
+        self.write_registers(PAGE_FUNCTION, REG_MIX_FUNCTION, &[MIX_FUNCTION_LPEN])
+            .await?;
+        self.write_registers(PAGE_SCALING, 0x00, &K::CURRENT_SCALING)
+            .await?;
+        self.set_pwm(&[0; NUM_LEDS]).await
+    }
+
+    /// Enable or disable the chip. The LEDs turn off while the chip is disabled.
+    pub async fn set_enabled(&mut self, enabled: bool) -> Result<(), S::Error> {
+        let config = if enabled {
+            GLOBAL_CONFIG_SWSEL_1_12 | GLOBAL_CONFIG_CHIPEN
+        } else {
+            GLOBAL_CONFIG_SWSEL_1_12
+        };
+
+        self.write_registers(PAGE_FUNCTION, REG_GLOBAL_CONFIG, &[config])
+            .await
+    }
+}
+
+/// Create an instance of the AW20216S driver with the provided SPI device, and initialize it using
+/// the current settings in the [`AW20216SBacklightDriver`] implementation.
+pub async fn setup_driver<K: AW20216SBacklightDriver, S: SpiDevice>(spi: S) -> AW20216S<S> {
+    let mut driver = AW20216S { spi };
+
+    driver.setup::<K>().await.unwrap();
+
+    driver
+}
+
+#[cfg(feature = "simple-backlight-matrix")]
+impl<
+        SpiError: Debug + 'static,
+        S: SpiDevice<Error = SpiError>,
+        K: AW20216SBacklightDriver
+            + crate::lighting::simple_backlight_matrix::SimpleBacklightMatrixDevice,
+    > crate::lighting::simple_backlight_matrix::SimpleBacklightMatrixDriver<K> for AW20216S<S>
+{
+    type DriverWriteError = S::Error;
+
+    async fn write(
+        &mut self,
+        buf: &[[u8; K::LIGHTING_COLS]; K::LIGHTING_ROWS],
+    ) -> Result<(), Self::DriverWriteError> {
+        let mut payload = [0; NUM_LEDS];
+
+        // Map the frame data to LED offsets and set the brightness of the LED in the payload
+        for (row_num, row) in buf.iter().enumerate() {
+            for (col_num, val) in row.iter().enumerate() {
+                let offset = K::get_led_from_matrix_coordinates(col_num as u8, row_num as u8);
+                if offset != 255 {
+                    payload[offset as usize] = *val;
+                }
+            }
+        }
+
+        self.set_pwm(&payload).await
+    }
+
+    type DriverEnableError = S::Error;
+
+    async fn turn_on(&mut self) -> Result<(), Self::DriverEnableError> {
+        self.set_enabled(true).await
+    }
+
+    type DriverDisableError = S::Error;
+
+    async fn turn_off(&mut self) -> Result<(), Self::DriverDisableError> {
+        self.set_enabled(false).await
+    }
+}
+
+#[cfg(feature = "rgb-backlight-matrix")]
+impl<
+        SpiError: Debug + 'static,
+        S: SpiDevice<Error = SpiError>,
+        K: AW20216SBacklightDriver + crate::lighting::rgb_backlight_matrix::RGBBacklightMatrixDevice,
+    > crate::lighting::rgb_backlight_matrix::RGBBacklightMatrixDriver<K> for AW20216S<S>
+{
+    type DriverWriteError = S::Error;
+
+    async fn write(
+        &mut self,
+        buf: &[[RGB8; K::LIGHTING_COLS]; K::LIGHTING_ROWS],
+    ) -> Result<(), Self::DriverWriteError> {
+        let mut payload = [0; NUM_LEDS];
+
+        // Map the frame data to LED offsets and set the brightness of the LED in the payload
+        for (row_num, row) in buf.iter().enumerate() {
+            for (col_num, color) in row.iter().enumerate() {
+                for (component, val) in color.iter().enumerate() {
+                    let offset = K::get_led_from_matrix_coordinates(
+                        col_num as u8 + (component * K::LIGHTING_COLS) as u8,
+                        row_num as u8,
+                    );
+                    if offset != 255 {
+                        payload[offset as usize] = val;
+                    }
+                }
+            }
+        }
+
+        self.set_pwm(&payload).await
+    }
+
+    type DriverEnableError = S::Error;
+
+    async fn turn_on(&mut self) -> Result<(), Self::DriverEnableError> {
+        self.set_enabled(true).await
+    }
+
+    type DriverDisableError = S::Error;
+
+    async fn turn_off(&mut self) -> Result<(), Self::DriverDisableError> {
+        self.set_enabled(false).await
+    }
+}
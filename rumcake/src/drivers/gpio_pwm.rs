@@ -0,0 +1,203 @@
+//! Rumcake driver implementations for single-color backlights that are driven by one GPIO pin
+//! (e.g. a pin connected to the gate of a MOSFET).
+//!
+//! This driver provides implementations for
+//! [`SimpleBacklightDriver`](`crate::lighting::simple_backlight::SimpleBacklightDriver`).
+//!
+//! There are two versions of this driver:
+//! - [`GpioPwm`], which uses a hardware PWM channel of your MCU. To use it, implement
+//!   [`PwmOutput`] for your HAL's PWM type, and pass it to [`setup_driver`].
+//! - [`SoftPwm`], which toggles a normal output pin in a task to generate the PWM signal. This
+//!   can be used on any pin, but the PWM resolution is limited by the tick rate of
+//!   `embassy-time`. You can use [`setup_gpio_pwm`] to create this driver.
+//!
+//! The result of either setup function should be passed to a backlight task.
+
+use core::cell::Cell;
+use core::convert::Infallible;
+
+use embassy_futures::select::{select, Either};
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Timer};
+use embedded_hal::digital::v2::OutputPin;
+
+use crate::hw::platform::{BlockingMutex, RawMutex};
+
+pub use rumcake_macros::setup_gpio_pwm;
+
+/// A trait that hardware PWM channels must implement to be used with [`GpioPwm`].
+pub trait PwmOutput {
+    /// The maximum duty cycle value, which corresponds to the output being high all the time.
+    fn max_duty(&self) -> u32;
+
+    /// Set the duty cycle of the PWM channel, from `0` to [`PwmOutput::max_duty`].
+    fn set_duty(&mut self, duty: u32);
+}
+
+/// Backlight driver that uses a hardware PWM channel.
+pub struct GpioPwm<P: PwmOutput> {
+    pwm: P,
+    brightness: u8,
+}
+
+/// Create an instance of the hardware PWM backlight driver with the provided PWM channel.
+pub fn setup_driver<P: PwmOutput>(mut pwm: P) -> GpioPwm<P> {
+    pwm.set_duty(0);
+
+    GpioPwm { pwm, brightness: 0 }
+}
+
+impl<P: PwmOutput> GpioPwm<P> {
+    fn set_brightness(&mut self, brightness: u8) {
+        let max_duty = self.pwm.max_duty() as u64;
+        self.pwm
+            .set_duty((max_duty * brightness as u64 / u8::MAX as u64) as u32);
+    }
+}
+
+#[cfg(feature = "simple-backlight")]
+impl<P: PwmOutput, K: crate::lighting::simple_backlight::SimpleBacklightDevice>
+    crate::lighting::simple_backlight::SimpleBacklightDriver<K> for GpioPwm<P>
+{
+    type DriverWriteError = Infallible;
+
+    async fn write(&mut self, brightness: u8) -> Result<(), Self::DriverWriteError> {
+        self.brightness = brightness;
+        self.set_brightness(brightness);
+
+        Ok(())
+    }
+
+    type DriverEnableError = Infallible;
+
+    async fn turn_on(&mut self) -> Result<(), Self::DriverEnableError> {
+        self.set_brightness(self.brightness);
+
+        Ok(())
+    }
+
+    type DriverDisableError = Infallible;
+
+    async fn turn_off(&mut self) -> Result<(), Self::DriverDisableError> {
+        self.set_brightness(0);
+
+        Ok(())
+    }
+}
+
+/// State shared between a [`SoftPwm`] driver, and the task that generates its PWM signal.
+pub struct SoftPwmState {
+    brightness: BlockingMutex<Cell<u8>>,
+    changed: Signal<RawMutex, ()>,
+}
+
+impl SoftPwmState {
+    /// Create new software PWM state. The output starts off.
+    pub const fn new() -> Self {
+        Self {
+            brightness: BlockingMutex::new(Cell::new(0)),
+            changed: Signal::new(),
+        }
+    }
+
+    fn set(&self, brightness: u8) {
+        self.brightness.lock(|b| b.set(brightness));
+        self.changed.signal(());
+    }
+
+    fn get(&self) -> u8 {
+        self.brightness.lock(|b| b.get())
+    }
+}
+
+impl Default for SoftPwmState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Backlight driver that sends brightness values to a task running [`soft_pwm_task`].
+pub struct SoftPwm {
+    state: &'static SoftPwmState,
+    brightness: u8,
+}
+
+/// Create an instance of the software PWM backlight driver. [`soft_pwm_task`] must be running
+/// with the same `state` for the output to change.
+///
+/// It is recommended to use [`setup_gpio_pwm`] instead, which also spawns the task.
+pub fn setup_soft_pwm_driver(state: &'static SoftPwmState) -> SoftPwm {
+    SoftPwm {
+        state,
+        brightness: 0,
+    }
+}
+
+/// Generate a PWM signal with the given frequency (in Hz) on `pin`, using the brightness values
+/// sent to the [`SoftPwm`] driver that uses the same `state`.
+///
+/// If the brightness is `0` or `255`, the pin is held low or high, and this task waits for the
+/// brightness to change without waking up.
+pub async fn soft_pwm_task(mut pin: impl OutputPin, state: &'static SoftPwmState, frequency: u32) {
+    let period = Duration::from_hz(frequency as u64);
+
+    loop {
+        let brightness = state.get();
+
+        match brightness {
+            0 => {
+                let _ = pin.set_low();
+                state.changed.wait().await;
+            }
+            u8::MAX => {
+                let _ = pin.set_high();
+                state.changed.wait().await;
+            }
+            _ => {
+                let high_time = period * brightness as u32 / u8::MAX as u32;
+
+                // Keep generating the signal until the brightness changes
+                loop {
+                    let _ = pin.set_high();
+                    Timer::after(high_time).await;
+                    let _ = pin.set_low();
+                    if let Either::First(()) =
+                        select(state.changed.wait(), Timer::after(period - high_time)).await
+                    {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "simple-backlight")]
+impl<K: crate::lighting::simple_backlight::SimpleBacklightDevice>
+    crate::lighting::simple_backlight::SimpleBacklightDriver<K> for SoftPwm
+{
+    type DriverWriteError = Infallible;
+
+    async fn write(&mut self, brightness: u8) -> Result<(), Self::DriverWriteError> {
+        self.brightness = brightness;
+        self.state.set(brightness);
+
+        Ok(())
+    }
+
+    type DriverEnableError = Infallible;
+
+    async fn turn_on(&mut self) -> Result<(), Self::DriverEnableError> {
+        self.state.set(self.brightness);
+
+        Ok(())
+    }
+
+    type DriverDisableError = Infallible;
+
+    async fn turn_off(&mut self) -> Result<(), Self::DriverDisableError> {
+        self.state.set(0);
+
+        Ok(())
+    }
+}
@@ -9,6 +9,9 @@
 //! To use this driver for backlighting, keyboards must implement
 //! [`IS31FL3731BacklightDriver`](IS31FL3731BacklightDriver). The result of [`setup_driver`] should
 //! be passed to a backlight task.
+//!
+//! Note that the IS31FL3731 does not have open/short LED detection or thermal status registers,
+//! so this driver does not implement `read_status` for any of the backlight driver traits.
 
 pub use is31fl3731 as driver;
 
@@ -173,7 +176,7 @@ pub enum Position {
 use core::fmt::Debug;
 use embassy_time::Delay;
 use embedded_hal_async::i2c::I2c;
-use is31fl3731::{gamma, Error, IS31FL3731};
+use is31fl3731::{Error, IS31FL3731};
 use smart_leds::RGB8;
 
 pub use rumcake_macros::{
@@ -215,7 +218,7 @@ impl<
     type DriverWriteError = Error<I2CError>;
 
     async fn write(&mut self, brightness: u8) -> Result<(), Self::DriverWriteError> {
-        let payload = [gamma(brightness); 144];
+        let payload = [brightness; 144];
 
         self.all_pixels(&payload).await?;
 
@@ -260,7 +263,7 @@ impl<
             for (col_num, val) in row.iter().enumerate() {
                 let offset = K::get_led_from_matrix_coordinates(col_num as u8, row_num as u8);
                 if offset != 255 {
-                    payload[offset as usize] = gamma(*val);
+                    payload[offset as usize] = *val;
                 }
             }
         }
@@ -311,7 +314,7 @@ impl<
                         row_num as u8,
                     );
                     if offset != 255 {
-                        payload[offset as usize] = gamma(val);
+                        payload[offset as usize] = val;
                     }
                 }
             }
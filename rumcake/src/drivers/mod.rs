@@ -2,20 +2,102 @@
 
 use embedded_io_async::{Read, Write};
 
+#[cfg(feature = "analog-joystick")]
+pub mod analog_joystick;
+
+#[cfg(feature = "aw20216s")]
+pub mod aw20216s;
+
+#[cfg(feature = "gpio-pwm")]
+pub mod gpio_pwm;
+
 #[cfg(feature = "is31fl3731")]
 pub mod is31fl3731;
 
 #[cfg(feature = "nrf-ble")]
 pub mod nrf_ble;
 
+#[cfg(feature = "pmw3360")]
+pub mod pmw3360;
+
+#[cfg(feature = "sh1106")]
+pub mod sh1106;
+
+#[cfg(feature = "sharp-memory-lcd")]
+pub mod sharp_memory_lcd;
+
 #[cfg(feature = "ssd1306")]
 pub mod ssd1306;
 
+#[cfg(feature = "trackpoint")]
+pub mod trackpoint;
+
 #[cfg(feature = "ws2812-bitbang")]
 pub mod ws2812_bitbang;
 
+#[cfg(feature = "ws2812-spi")]
+pub mod ws2812_spi;
+
+/// Serialize a split message into a COBS frame. If the `split-crc` feature is enabled, a CRC
+/// of the serialized message is appended before encoding the frame.
+#[cfg(any(feature = "split-central", feature = "split-peripheral"))]
+fn serialize_split_frame<T: serde::Serialize, const N: usize>(
+    message: &T,
+    buffer: &mut [u8; N],
+) -> Result<(), postcard::Error> {
+    #[cfg(feature = "split-crc")]
+    {
+        let mut data = [0; N];
+        let len = postcard::to_slice(message, &mut data)?.len();
+        let crc = crate::split::crc16(&data[..len]);
+        data[len..len + crate::split::CRC_LENGTH].copy_from_slice(&crc.to_le_bytes());
+
+        if cobs::max_encoding_length(len + crate::split::CRC_LENGTH) >= buffer.len() {
+            return Err(postcard::Error::SerializeBufferFull);
+        }
+        cobs::encode(&data[..len + crate::split::CRC_LENGTH], buffer);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "split-crc"))]
+    postcard::to_slice_cobs(message, buffer).map(|_| ())
+}
+
+/// Deserialize a COBS frame created by [`serialize_split_frame`]. If the `split-crc` feature is
+/// enabled, frames with a CRC that does not match their contents are rejected without being
+/// deserialized.
+#[cfg(any(feature = "split-central", feature = "split-peripheral"))]
+fn deserialize_split_frame<'a, T: serde::Deserialize<'a>>(
+    buffer: &'a mut [u8],
+) -> Result<T, postcard::Error> {
+    #[cfg(feature = "split-crc")]
+    {
+        let len =
+            cobs::decode_in_place(buffer).map_err(|_| postcard::Error::DeserializeBadEncoding)?;
+        let Some(data_len) = len.checked_sub(crate::split::CRC_LENGTH) else {
+            return Err(postcard::Error::DeserializeUnexpectedEnd);
+        };
+        let (data, crc) = buffer[..len].split_at(data_len);
+        if crate::split::crc16(data).to_le_bytes() != crc {
+            return Err(postcard::Error::DeserializeBadCrc);
+        }
+        postcard::from_bytes(data)
+    }
+
+    #[cfg(not(feature = "split-crc"))]
+    postcard::from_bytes_cobs(buffer)
+}
+
 /// Struct that allows you to use a serial driver (implementor of both [`embedded_io_async::Read`]
 /// and [`embedded_io_async::Write`]) with rumcake. This can be used for split keyboards.
+///
+/// A serial connection is only connected to one peripheral, so when it is used as a central
+/// device driver, that peripheral has an index of `0`. Messages targeting other peripherals are
+/// ignored.
+///
+/// If the `split-crc` feature is enabled, a CRC is appended to each message, and messages that
+/// were corrupted during transmission are dropped. Both halves of your keyboard must be built
+/// with the same setting.
 pub struct SerialSplitDriver<D: Write + Read> {
     /// A serial driver that implements the [`embedded_io_async::Read`] and
     /// [`embedded_io_async::Write`] traits.
@@ -32,9 +114,10 @@ impl<D: Write + Read> crate::split::central::CentralDeviceDriver for SerialSplit
         crate::split::MessageToCentral,
         crate::split::central::CentralDeviceError<Self::DriverError>,
     > {
-        let mut buffer = [0; crate::split::MESSAGE_TO_CENTRAL_BUFFER_SIZE];
+        let mut buffer =
+            [0; crate::split::MESSAGE_TO_CENTRAL_BUFFER_SIZE + crate::split::CRC_LENGTH];
         self.serial.read_exact(&mut buffer).await?;
-        postcard::from_bytes_cobs(&mut buffer)
+        deserialize_split_frame(&mut buffer)
             .map_err(crate::split::central::CentralDeviceError::DeserializationError)
     }
 
@@ -42,14 +125,27 @@ impl<D: Write + Read> crate::split::central::CentralDeviceDriver for SerialSplit
         &mut self,
         message: crate::split::MessageToPeripheral,
     ) -> Result<(), crate::split::central::CentralDeviceError<Self::DriverError>> {
-        let mut buffer = [0; crate::split::MESSAGE_TO_PERIPHERAL_BUFFER_SIZE];
-        postcard::to_slice_cobs(&message, &mut buffer)
+        let mut buffer =
+            [0; crate::split::MESSAGE_TO_PERIPHERAL_BUFFER_SIZE + crate::split::CRC_LENGTH];
+        serialize_split_frame(&message, &mut buffer)
             .map_err(crate::split::central::CentralDeviceError::SerializationError)?;
         self.serial
             .write_all(&buffer)
             .await
             .map_err(crate::split::central::CentralDeviceError::DriverError)
     }
+
+    async fn send_message_to_peripheral(
+        &mut self,
+        index: usize,
+        message: crate::split::MessageToPeripheral,
+    ) -> Result<(), crate::split::central::CentralDeviceError<Self::DriverError>> {
+        if index != 0 {
+            return Ok(());
+        }
+
+        self.broadcast_message_to_peripherals(message).await
+    }
 }
 
 #[cfg(feature = "split-peripheral")]
@@ -60,8 +156,9 @@ impl<D: Write + Read> crate::split::peripheral::PeripheralDeviceDriver for Seria
         &mut self,
         event: crate::split::MessageToCentral,
     ) -> Result<(), crate::split::peripheral::PeripheralDeviceError<Self::DriverError>> {
-        let mut buffer = [0; crate::split::MESSAGE_TO_CENTRAL_BUFFER_SIZE];
-        postcard::to_slice_cobs(&event, &mut buffer)
+        let mut buffer =
+            [0; crate::split::MESSAGE_TO_CENTRAL_BUFFER_SIZE + crate::split::CRC_LENGTH];
+        serialize_split_frame(&event, &mut buffer)
             .map_err(crate::split::peripheral::PeripheralDeviceError::SerializationError)?;
         self.serial
             .write_all(&buffer)
@@ -75,9 +172,10 @@ impl<D: Write + Read> crate::split::peripheral::PeripheralDeviceDriver for Seria
         crate::split::MessageToPeripheral,
         crate::split::peripheral::PeripheralDeviceError<Self::DriverError>,
     > {
-        let mut buffer = [0; crate::split::MESSAGE_TO_PERIPHERAL_BUFFER_SIZE];
+        let mut buffer =
+            [0; crate::split::MESSAGE_TO_PERIPHERAL_BUFFER_SIZE + crate::split::CRC_LENGTH];
         self.serial.read_exact(&mut buffer).await?;
-        postcard::from_bytes_cobs(&mut buffer)
+        deserialize_split_frame(&mut buffer)
             .map_err(crate::split::peripheral::PeripheralDeviceError::DeserializationError)
     }
 }
@@ -9,6 +9,9 @@
 //! Bluetooth address of the central device to [`nrf_ble_peripheral_task`].
 //! [`central::NRFBLECentralDriver`] and [`peripheral::NRFBLEPeripheralDriver`] then need to be
 //! passed to the [`central_task`] and [`peripheral_task`] respectively.
+//!
+//! When sending messages to a specific peripheral, the index of a peripheral is the position of
+//! its address in the list of addresses passed to [`nrf_ble_central_task`].
 
 #[cfg(feature = "split-central")]
 /// nrf-softdevice central device driver implementations
@@ -27,21 +30,26 @@ pub mod central {
     use crate::hw::platform::RawMutex;
     use crate::split::central::{CentralDeviceDriver, CentralDeviceError};
     use crate::split::{
-        MessageToCentral, MessageToPeripheral, MESSAGE_TO_CENTRAL_BUFFER_SIZE,
-        MESSAGE_TO_PERIPHERAL_BUFFER_SIZE,
+        MessageToCentral, MessageToPeripheral, TargetedMessageToPeripheral,
+        MESSAGE_TO_CENTRAL_BUFFER_SIZE, MESSAGE_TO_PERIPHERAL_BUFFER_SIZE,
     };
 
     pub use rumcake_macros::setup_nrf_ble_split_central;
 
     pub struct NRFBLECentralDriver<'a> {
-        publisher: Publisher<'a, RawMutex, MessageToPeripheral, 4, 4, 1>,
+        publisher: Publisher<'a, RawMutex, TargetedMessageToPeripheral, 4, 4, 1>,
     }
 
     pub static BLE_MESSAGES_FROM_PERIPHERALS: Channel<RawMutex, MessageToCentral, 4> =
         Channel::new();
 
-    pub static BLE_MESSAGES_TO_PERIPHERALS: PubSubChannel<RawMutex, MessageToPeripheral, 4, 4, 1> =
-        PubSubChannel::new();
+    pub static BLE_MESSAGES_TO_PERIPHERALS: PubSubChannel<
+        RawMutex,
+        TargetedMessageToPeripheral,
+        4,
+        4,
+        1,
+    > = PubSubChannel::new();
 
     pub static BLUETOOTH_CONNECTION_MUTEX: Mutex<RawMutex, ()> = Mutex::new(());
 
@@ -67,7 +75,22 @@ pub mod central {
             &mut self,
             message: MessageToPeripheral,
         ) -> Result<(), CentralDeviceError<Self::DriverError>> {
-            self.publisher.publish(message).await;
+            self.publisher.publish(message.into()).await;
+
+            Ok(())
+        }
+
+        async fn send_message_to_peripheral(
+            &mut self,
+            index: usize,
+            message: MessageToPeripheral,
+        ) -> Result<(), CentralDeviceError<Self::DriverError>> {
+            self.publisher
+                .publish(TargetedMessageToPeripheral {
+                    peripheral: Some(index),
+                    message,
+                })
+                .await;
 
             Ok(())
         }
@@ -93,7 +116,7 @@ pub mod central {
 
         info!("[SPLIT_BT_DRIVER] Bluetooth services started");
 
-        let peripheral_fut = |peripheral_addr: [u8; 6]| {
+        let peripheral_fut = |index: usize, peripheral_addr: [u8; 6]| {
             async move {
                 loop {
                     let whitelist = [&Address::new(AddressType::RandomStatic, peripheral_addr)];
@@ -170,7 +193,14 @@ pub mod central {
                         while subscriber.try_next_message_pure().is_some() {}
 
                         loop {
-                            let message = subscriber.next_message_pure().await;
+                            let TargetedMessageToPeripheral {
+                                peripheral,
+                                message,
+                            } = subscriber.next_message_pure().await;
+
+                            if peripheral.is_some_and(|peripheral| peripheral != index) {
+                                continue;
+                            }
 
                             let mut buf = [0; MESSAGE_TO_PERIPHERAL_BUFFER_SIZE];
                             postcard::to_slice_cobs(&message, &mut buf).unwrap();
@@ -211,7 +241,8 @@ pub mod central {
 
         let futures = if let Ok(futures) = peripheral_addresses
             .iter()
-            .map(|addr| peripheral_fut(*addr))
+            .enumerate()
+            .map(|(index, addr)| peripheral_fut(index, *addr))
             .collect::<Vec<_, P>>()
             .into_array::<P>()
         {
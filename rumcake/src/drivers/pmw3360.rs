@@ -0,0 +1,306 @@
+//! Rumcake driver implementation for PMW3360 optical sensors.
+//!
+//! This driver provides an implementation for [`PointerDriver`]. The sensor is connected using an
+//! SPI peripheral, which must be configured in SPI mode 3, with a frequency of 2MHz or lower. The
+//! NCS (chip select) pin is controlled by the driver, so it must not be managed by the SPI
+//! peripheral.
+//!
+//! The PMW3360 requires firmware (SROM) to be uploaded to it every time it is powered on. The
+//! SROM is not distributed with rumcake, so you must provide it using
+//! [`Pmw3360Device::PMW3360_SROM`].
+//!
+//! To use this driver, keyboards must implement [`Pmw3360Device`]. The result of [`setup_driver`]
+//! should be returned by the `driver_setup_fn` used for the pointer task.
+
+use defmt::{debug, warn};
+use embassy_time::{Duration, Timer};
+use embedded_hal::digital::v2::OutputPin;
+use embedded_hal_async::spi::SpiBus;
+
+use crate::pointer::{PointerDevice, PointerDriver};
+
+/// Value of the `Product_ID` register for a PMW3360.
+const PRODUCT_ID: u8 = 0x42;
+
+mod registers {
+    pub const PRODUCT_ID: u8 = 0x00;
+    pub const MOTION: u8 = 0x02;
+    pub const DELTA_X_L: u8 = 0x03;
+    pub const DELTA_X_H: u8 = 0x04;
+    pub const DELTA_Y_L: u8 = 0x05;
+    pub const DELTA_Y_H: u8 = 0x06;
+    pub const CONFIG1: u8 = 0x0F;
+    pub const CONFIG2: u8 = 0x10;
+    pub const SROM_ENABLE: u8 = 0x13;
+    pub const SROM_ID: u8 = 0x2A;
+    pub const POWER_UP_RESET: u8 = 0x3A;
+    pub const MOTION_BURST: u8 = 0x50;
+    pub const SROM_LOAD_BURST: u8 = 0x62;
+}
+
+/// Bit in the `Motion` register that is set if motion has occurred since the last read.
+const MOTION_MOT: u8 = 1 << 7;
+
+/// Bit in the `Motion` register that is set if the sensor has been lifted off the surface.
+const MOTION_LIFT_STAT: u8 = 1 << 3;
+
+/// `OP_Mode` bits of the `Motion` register. Rest modes are disabled by the driver, so these should
+/// always be `0`. If they aren't, the motion burst data is invalid.
+const MOTION_OP_MODE: u8 = 0b111;
+
+// in microseconds, taken from PMW3360 datasheet
+const T_SRAD: u64 = 160;
+const T_SRAD_MOTBR: u64 = 35;
+const T_SCLK_NCS_WRITE: u64 = 35;
+const T_SWW: u64 = 180;
+const T_SRR: u64 = 20;
+const T_BEXIT: u64 = 1;
+const T_SROM_BYTE: u64 = 15;
+
+/// A trait that keyboards must implement to use a PMW3360 sensor.
+pub trait Pmw3360Device: PointerDevice {
+    /// The SROM firmware to upload to the sensor when it starts. This can be obtained from the
+    /// sensor's manufacturer, and included with [`include_bytes!`].
+    const PMW3360_SROM: &'static [u8];
+
+    /// How often the sensor should be polled, in milliseconds.
+    const PMW3360_POLL_INTERVAL: u64 = 10;
+
+    /// Resolution of the sensor, in counts per inch. This must be between `100` and `12000`, and
+    /// is rounded down to a multiple of `100`.
+    const PMW3360_CPI: u16 = 1600;
+
+    /// Whether to invert the X axis.
+    const PMW3360_INVERT_X: bool = false;
+
+    /// Whether to invert the Y axis.
+    const PMW3360_INVERT_Y: bool = false;
+}
+
+/// Errors that can be returned by [`Pmw3360`].
+#[derive(Debug)]
+pub enum Pmw3360Error<E> {
+    /// An error occurred while communicating with the sensor over SPI.
+    Spi(E),
+    /// The NCS pin could not be set.
+    Pin,
+    /// The `Product_ID` register did not contain the expected value. Contains the value that was
+    /// read.
+    InvalidProductId(u8),
+    /// The sensor did not report an SROM ID after uploading the SROM firmware.
+    SromUploadFailed,
+}
+
+impl<E> From<E> for Pmw3360Error<E> {
+    fn from(value: E) -> Self {
+        Self::Spi(value)
+    }
+}
+
+/// Driver for a PMW3360 sensor. Create this using [`setup_driver`].
+pub struct Pmw3360<S: SpiBus, P: OutputPin> {
+    spi: S,
+    ncs: P,
+    in_burst: bool,
+}
+
+impl<S: SpiBus, P: OutputPin> Pmw3360<S, P> {
+    /// Create a new PMW3360 driver. The sensor is not initialized until [`PointerDriver::init`]
+    /// is called.
+    pub fn new(spi: S, ncs: P) -> Self {
+        Self {
+            spi,
+            ncs,
+            in_burst: false,
+        }
+    }
+
+    fn select(&mut self) -> Result<(), Pmw3360Error<S::Error>> {
+        self.ncs.set_low().map_err(|_| Pmw3360Error::Pin)
+    }
+
+    fn deselect(&mut self) -> Result<(), Pmw3360Error<S::Error>> {
+        self.ncs.set_high().map_err(|_| Pmw3360Error::Pin)
+    }
+
+    /// Read a register from the sensor. Accessing any register exits burst mode.
+    pub async fn read_register(&mut self, address: u8) -> Result<u8, Pmw3360Error<S::Error>> {
+        self.in_burst = false;
+
+        let mut buf = [0];
+        self.select()?;
+        self.spi.write(&[address & 0x7F]).await?;
+        self.spi.flush().await?;
+        Timer::after(Duration::from_micros(T_SRAD)).await;
+        self.spi.read(&mut buf).await?;
+        self.spi.flush().await?;
+        self.deselect()?;
+        Timer::after(Duration::from_micros(T_SRR)).await;
+
+        Ok(buf[0])
+    }
+
+    /// Write to a register on the sensor. Accessing any register exits burst mode.
+    pub async fn write_register(
+        &mut self,
+        address: u8,
+        value: u8,
+    ) -> Result<(), Pmw3360Error<S::Error>> {
+        self.in_burst = false;
+
+        self.select()?;
+        self.spi.write(&[address | 0x80, value]).await?;
+        self.spi.flush().await?;
+        Timer::after(Duration::from_micros(T_SCLK_NCS_WRITE)).await;
+        self.deselect()?;
+        Timer::after(Duration::from_micros(T_SWW)).await;
+
+        Ok(())
+    }
+
+    /// Change the resolution of the sensor. `cpi` is clamped to `100..=12000`, and rounded down to
+    /// a multiple of `100`.
+    pub async fn set_cpi(&mut self, cpi: u16) -> Result<(), Pmw3360Error<S::Error>> {
+        let value = (cpi.clamp(100, 12000) / 100 - 1) as u8;
+        self.write_register(registers::CONFIG1, value).await
+    }
+
+    /// Upload SROM firmware to the sensor. Returns the SROM ID reported by the sensor.
+    async fn upload_srom(&mut self, srom: &[u8]) -> Result<u8, Pmw3360Error<S::Error>> {
+        // Rest mode must be disabled during the upload
+        self.write_register(registers::CONFIG2, 0x00).await?;
+        self.write_register(registers::SROM_ENABLE, 0x1D).await?;
+        Timer::after(Duration::from_millis(10)).await;
+        self.write_register(registers::SROM_ENABLE, 0x18).await?;
+
+        self.select()?;
+        self.spi.write(&[registers::SROM_LOAD_BURST | 0x80]).await?;
+        self.spi.flush().await?;
+        Timer::after(Duration::from_micros(T_SROM_BYTE)).await;
+
+        // This takes ~60ms. Awaiting a timer between each byte lets other tasks run in the
+        // meantime.
+        for byte in srom {
+            self.spi.write(&[*byte]).await?;
+            self.spi.flush().await?;
+            Timer::after(Duration::from_micros(T_SROM_BYTE)).await;
+        }
+
+        self.deselect()?;
+        Timer::after(Duration::from_micros(200)).await;
+
+        self.read_register(registers::SROM_ID).await
+    }
+
+    /// Read the `Motion`, `Observation`, `Delta_X_L`, `Delta_X_H`, `Delta_Y_L` and `Delta_Y_H`
+    /// registers with a single motion burst.
+    async fn read_burst(&mut self) -> Result<[u8; 6], Pmw3360Error<S::Error>> {
+        if !self.in_burst {
+            // Writing any value to the Motion_Burst register starts burst mode
+            self.write_register(registers::MOTION_BURST, 0x00).await?;
+            self.in_burst = true;
+        }
+
+        let mut buf = [0; 6];
+        self.select()?;
+        self.spi.write(&[registers::MOTION_BURST]).await?;
+        self.spi.flush().await?;
+        Timer::after(Duration::from_micros(T_SRAD_MOTBR)).await;
+        self.spi.read(&mut buf).await?;
+        self.spi.flush().await?;
+        self.deselect()?;
+        Timer::after(Duration::from_micros(T_BEXIT)).await;
+
+        Ok(buf)
+    }
+}
+
+/// Create an instance of the PMW3360 driver with the provided SPI bus and NCS pin. The SPI bus
+/// must be configured in SPI mode 3, with a frequency of 2MHz or lower.
+pub fn setup_driver<S: SpiBus, P: OutputPin>(spi: S, ncs: P) -> Pmw3360<S, P> {
+    Pmw3360::new(spi, ncs)
+}
+
+impl<K: Pmw3360Device, S: SpiBus, P: OutputPin> PointerDriver<K> for Pmw3360<S, P> {
+    type DriverError = Pmw3360Error<S::Error>;
+
+    const POLL_INTERVAL: u64 = K::PMW3360_POLL_INTERVAL;
+
+    async fn init(&mut self) -> Result<(), Self::DriverError> {
+        // Reset the SPI port
+        self.deselect()?;
+        self.select()?;
+        self.deselect()?;
+
+        self.write_register(registers::POWER_UP_RESET, 0x5A).await?;
+        Timer::after(Duration::from_millis(50)).await;
+
+        // Motion registers must be read once after a reset
+        for register in [
+            registers::MOTION,
+            registers::DELTA_X_L,
+            registers::DELTA_X_H,
+            registers::DELTA_Y_L,
+            registers::DELTA_Y_H,
+        ] {
+            self.read_register(register).await?;
+        }
+
+        let product_id = self.read_register(registers::PRODUCT_ID).await?;
+        if product_id != PRODUCT_ID {
+            return Err(Pmw3360Error::InvalidProductId(product_id));
+        }
+
+        let srom_id = self.upload_srom(K::PMW3360_SROM).await?;
+        if srom_id == 0 {
+            return Err(Pmw3360Error::SromUploadFailed);
+        }
+
+        debug!(
+            "[PMW3360] Uploaded SROM, reported SROM ID: {=u8:#X}",
+            srom_id
+        );
+
+        // Keep rest modes disabled, so that the OP_Mode bits can be used to validate burst data
+        self.write_register(registers::CONFIG2, 0x00).await?;
+        self.set_cpi(K::PMW3360_CPI).await?;
+
+        Ok(())
+    }
+
+    async fn read_movement(&mut self) -> Result<(i16, i16), Self::DriverError> {
+        let burst = self.read_burst().await?;
+        let motion = burst[0];
+
+        if motion & MOTION_OP_MODE != 0 {
+            // Burst mode was exited (e.g. the sensor was reset), so the data can't be trusted.
+            // Start burst mode again on the next poll.
+            warn!(
+                "[PMW3360] Received invalid motion burst data, Motion register: {=u8:#X}",
+                motion
+            );
+            self.in_burst = false;
+            return Ok((0, 0));
+        }
+
+        if motion & MOTION_MOT == 0 || motion & MOTION_LIFT_STAT != 0 {
+            return Ok((0, 0));
+        }
+
+        let x = i16::from_le_bytes([burst[2], burst[3]]);
+        let y = i16::from_le_bytes([burst[4], burst[5]]);
+
+        Ok((
+            if K::PMW3360_INVERT_X {
+                x.saturating_neg()
+            } else {
+                x
+            },
+            if K::PMW3360_INVERT_Y {
+                y.saturating_neg()
+            } else {
+                y
+            },
+        ))
+    }
+}
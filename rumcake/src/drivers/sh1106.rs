@@ -0,0 +1,428 @@
+//! Rumcake driver implementation for SH1106 OLED displays.
+//!
+//! This driver provides implementations for
+//! [`DisplayDriver`](`crate::display::DisplayDriver`).
+//!
+//! The SH1106 has 132 columns of display RAM, but most 128 pixel wide modules only have columns
+//! `2..130` connected to the panel. The SH1106 also doesn't support horizontal addressing, so the
+//! frame buffer is flushed one page (8 rows) at a time, setting the start column before each page.
+//! The column offset that gets applied is determined by [`Sh1106Size::COLUMN_OFFSET`]. SSD1306
+//! panels also support this page addressing scheme, so they can be driven by this driver too by
+//! using a size with a column offset of `0`.
+//!
+//! The driver keeps a copy of the last frame that was sent to the display. When flushing, only the
+//! columns that changed in each page are sent, and pages that didn't change are skipped entirely.
+//!
+//! To use this driver for the display feature, keyboards must implement
+//! [`Sh1106I2cDisplayDriver`]. The result of [`setup_driver`] should be passed to a display task.
+
+use core::convert::Infallible;
+use core::fmt::Debug;
+use core::marker::PhantomData;
+use core::ops::Range;
+
+use defmt::{error, Debug2Format};
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::{DrawTarget, OriginDimensions, Size};
+use embedded_graphics::Pixel;
+use embedded_hal::blocking::i2c::Write;
+
+pub use rumcake_macros::setup_sh1106;
+
+/// I2C address used by most SH1106 modules.
+pub const I2C_ADDRESS: u8 = 0x3C;
+
+/// Number of columns in the SH1106's display RAM.
+const RAM_COLUMNS: usize = 132;
+
+/// Largest frame buffer needed by an SH1106 (132x64 pixels).
+const MAX_BUFFER_SIZE: usize = RAM_COLUMNS * 64 / 8;
+
+/// Control byte sent before a stream of commands.
+const COMMAND_MODE: u8 = 0x00;
+
+/// Control byte sent before a stream of display data.
+const DATA_MODE: u8 = 0x40;
+
+/// Describes the dimensions of the display, and how its columns map to the controller's RAM.
+pub trait Sh1106Size {
+    /// Width of the display in pixels.
+    const WIDTH: u8;
+
+    /// Height of the display in pixels. This must be a multiple of 8.
+    const HEIGHT: u8;
+
+    /// The RAM column that corresponds to the first column of pixels on the display.
+    const COLUMN_OFFSET: u8;
+
+    /// Value sent with the "set COM pins hardware configuration" command (`0xDA`).
+    const COM_PINS: u8 = 0x12;
+}
+
+/// 128x64 pixel display. This is the size used by most 1.3" SH1106 modules.
+pub struct DisplaySize128x64;
+impl Sh1106Size for DisplaySize128x64 {
+    const WIDTH: u8 = 128;
+    const HEIGHT: u8 = 64;
+    const COLUMN_OFFSET: u8 = 2;
+}
+
+/// 132x64 pixel display, which uses the full display RAM of the SH1106.
+pub struct DisplaySize132x64;
+impl Sh1106Size for DisplaySize132x64 {
+    const WIDTH: u8 = 132;
+    const HEIGHT: u8 = 64;
+    const COLUMN_OFFSET: u8 = 0;
+}
+
+/// Rotation of the display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayRotation {
+    /// No rotation.
+    Rotate0,
+    /// Rotate the display 90 degrees clockwise.
+    Rotate90,
+    /// Rotate the display 180 degrees.
+    Rotate180,
+    /// Rotate the display 270 degrees clockwise.
+    Rotate270,
+}
+
+/// Display RAM columns that get written to when flushing the given `columns` of a page. `columns`
+/// is relative to the first column of the display, and `column_offset` is added to get the RAM
+/// columns.
+const fn ram_columns(columns: Range<usize>, column_offset: u8) -> Range<usize> {
+    (columns.start + column_offset as usize)..(columns.end + column_offset as usize)
+}
+
+/// Commands used to position the cursor at the given RAM column of a page. The first byte is the
+/// control byte for a command stream, followed by "set page address", "set lower column address"
+/// and "set higher column address".
+const fn page_start_commands(page: u8, ram_column: u8) -> [u8; 4] {
+    [
+        COMMAND_MODE,
+        0xB0 | page,
+        ram_column & 0x0F,
+        0x10 | (ram_column >> 4),
+    ]
+}
+
+const _: () = {
+    // A full flush of a 128 pixel wide display skips the first 2 RAM columns
+    let columns = ram_columns(0..128, DisplaySize128x64::COLUMN_OFFSET);
+    assert!(columns.start == 2 && columns.end == 130);
+    let commands = page_start_commands(3, columns.start as u8);
+    assert!(commands[0] == COMMAND_MODE);
+    assert!(commands[1] == 0xB3);
+    assert!(commands[2] == 0x02);
+    assert!(commands[3] == 0x10);
+
+    // Partial flushes of the last columns stay within the connected columns
+    let columns = ram_columns(126..128, DisplaySize128x64::COLUMN_OFFSET);
+    assert!(columns.start == 128 && columns.end == 130);
+    let commands = page_start_commands(7, columns.start as u8);
+    assert!(commands[1] == 0xB7);
+    assert!(commands[2] == 0x00);
+    assert!(commands[3] == 0x18);
+
+    // A 132 pixel wide display uses the full display RAM, without an offset
+    let columns = ram_columns(0..132, DisplaySize132x64::COLUMN_OFFSET);
+    assert!(columns.start == 0 && columns.end == RAM_COLUMNS);
+    let columns = ram_columns(20..131, DisplaySize132x64::COLUMN_OFFSET);
+    assert!(columns.start == 20 && columns.end == 131);
+    let commands = page_start_commands(0, columns.start as u8);
+    assert!(commands[1] == 0xB0);
+    assert!(commands[2] == 0x04);
+    assert!(commands[3] == 0x11);
+    let commands = page_start_commands(0, 131);
+    assert!(commands[2] == 0x03);
+    assert!(commands[3] == 0x18);
+};
+
+/// SH1106 driver, which buffers the graphics that get drawn to it. Create this using
+/// [`setup_driver`].
+pub struct Sh1106<DI, S: Sh1106Size> {
+    i2c: DI,
+    rotation: DisplayRotation,
+    buffer: [u8; MAX_BUFFER_SIZE],
+    /// Contents of the display RAM, as of the last flush.
+    flushed: [u8; MAX_BUFFER_SIZE],
+    /// Whether the next flush should send the entire frame buffer, regardless of what changed.
+    full_flush: bool,
+    _size: PhantomData<S>,
+}
+
+impl<DI: Write<Error = E>, E: Debug, S: Sh1106Size> Sh1106<DI, S> {
+    /// Create a new SH1106 driver that uses the provided I2C peripheral. This does not send
+    /// anything to the display, so [`Sh1106::init`] must be called before it can be used.
+    /// [`setup_driver`] does this for you.
+    pub fn new(i2c: DI, rotation: DisplayRotation) -> Self {
+        const {
+            assert!(
+                S::WIDTH as usize + S::COLUMN_OFFSET as usize <= RAM_COLUMNS,
+                "The display's columns (`WIDTH` and `COLUMN_OFFSET`) must fit in the SH1106's 132 columns of display RAM"
+            )
+        };
+        Self {
+            i2c,
+            rotation,
+            buffer: [0; MAX_BUFFER_SIZE],
+            flushed: [0; MAX_BUFFER_SIZE],
+            full_flush: true,
+            _size: PhantomData,
+        }
+    }
+
+    fn send_commands(&mut self, commands: &[u8]) -> Result<(), E> {
+        let mut buf = [0; 8];
+        buf[1..(commands.len() + 1)].copy_from_slice(commands);
+        self.i2c.write(I2C_ADDRESS, &buf[..(commands.len() + 1)])
+    }
+
+    /// Initialize the display, and apply the current rotation.
+    pub fn init(&mut self) -> Result<(), E> {
+        self.send_commands(&[0xAE])?; // Display off
+        self.send_commands(&[0xD5, 0x80])?; // Clock divide ratio / oscillator frequency
+        self.send_commands(&[0xA8, S::HEIGHT - 1])?; // Multiplex ratio
+        self.send_commands(&[0xD3, 0x00])?; // Display offset
+        self.send_commands(&[0x40])?; // Display start line
+        self.send_commands(&[0xAD, 0x8B])?; // Enable the DC-DC converter
+        self.send_commands(&[0xDA, S::COM_PINS])?; // COM pins hardware configuration
+        self.send_commands(&[0x81, 0x80])?; // Contrast
+        self.send_commands(&[0xD9, 0x22])?; // Pre-charge period
+        self.send_commands(&[0xDB, 0x40])?; // VCOM deselect level
+        self.send_commands(&[0xA4])?; // Display RAM contents
+        self.send_commands(&[0xA6])?; // Non-inverted display
+        self.set_rotation(self.rotation)?;
+        self.invalidate();
+        self.send_commands(&[0xAF]) // Display on
+    }
+
+    /// Get the current rotation of the display.
+    pub fn rotation(&self) -> DisplayRotation {
+        self.rotation
+    }
+
+    /// Change the rotation of the display. The contents of the frame buffer will need to be
+    /// redrawn if the display is rotated by 90 or 270 degrees.
+    pub fn set_rotation(&mut self, rotation: DisplayRotation) -> Result<(), E> {
+        self.rotation = rotation;
+
+        let (segment_remap, com_scan) = match rotation {
+            DisplayRotation::Rotate0 => (0xA1, 0xC8),
+            DisplayRotation::Rotate90 => (0xA1, 0xC0),
+            DisplayRotation::Rotate180 => (0xA0, 0xC0),
+            DisplayRotation::Rotate270 => (0xA0, 0xC8),
+        };
+
+        self.send_commands(&[segment_remap, com_scan])
+    }
+
+    /// Turn the display on or off. The contents of the display RAM are retained while the
+    /// display is off.
+    pub fn set_display_on(&mut self, on: bool) -> Result<(), E> {
+        self.send_commands(&[if on { 0xAF } else { 0xAE }])
+    }
+
+    /// Set the value of a pixel in the frame buffer. `x` and `y` are in the rotated coordinate
+    /// space. Pixels outside of the display are ignored.
+    pub fn set_pixel(&mut self, x: u32, y: u32, on: bool) {
+        let (col, row) = match self.rotation {
+            DisplayRotation::Rotate0 | DisplayRotation::Rotate180 => (x, y),
+            DisplayRotation::Rotate90 | DisplayRotation::Rotate270 => (y, x),
+        };
+
+        if col >= S::WIDTH as u32 || row >= S::HEIGHT as u32 {
+            return;
+        }
+
+        let idx = (row / 8) as usize * S::WIDTH as usize + col as usize;
+        let bit = 1 << (row % 8);
+
+        if on {
+            self.buffer[idx] |= bit;
+        } else {
+            self.buffer[idx] &= !bit;
+        }
+    }
+
+    /// Make the next call to [`Sh1106::flush`] send the entire frame buffer to the display.
+    pub fn invalidate(&mut self) {
+        self.full_flush = true;
+    }
+
+    /// Send the parts of the frame buffer that changed since the last flush to the display.
+    pub fn flush(&mut self) -> Result<(), E> {
+        let width = S::WIDTH as usize;
+        let mut data = [0; 133];
+        data[0] = DATA_MODE;
+
+        for page in 0..(S::HEIGHT / 8) {
+            let page_start = page as usize * width;
+            let page_end = page_start + width;
+
+            let changed = if self.full_flush {
+                0..width
+            } else {
+                match crate::display::changed_range(
+                    &self.flushed[page_start..page_end],
+                    &self.buffer[page_start..page_end],
+                ) {
+                    Some(changed) => changed,
+                    None => continue,
+                }
+            };
+
+            let columns = ram_columns(changed.clone(), S::COLUMN_OFFSET);
+            self.i2c
+                .write(I2C_ADDRESS, &page_start_commands(page, columns.start as u8))?;
+
+            let (start, end) = (page_start + changed.start, page_start + changed.end);
+            data[1..(changed.len() + 1)].copy_from_slice(&self.buffer[start..end]);
+            self.i2c.write(I2C_ADDRESS, &data[..(changed.len() + 1)])?;
+            self.flushed[start..end].copy_from_slice(&self.buffer[start..end]);
+        }
+
+        self.full_flush = false;
+
+        Ok(())
+    }
+}
+
+impl<DI, S: Sh1106Size> OriginDimensions for Sh1106<DI, S> {
+    fn size(&self) -> Size {
+        match self.rotation {
+            DisplayRotation::Rotate0 | DisplayRotation::Rotate180 => {
+                Size::new(S::WIDTH as u32, S::HEIGHT as u32)
+            }
+            DisplayRotation::Rotate90 | DisplayRotation::Rotate270 => {
+                Size::new(S::HEIGHT as u32, S::WIDTH as u32)
+            }
+        }
+    }
+}
+
+impl<DI: Write<Error = E>, E: Debug, S: Sh1106Size> DrawTarget for Sh1106<DI, S> {
+    type Color = BinaryColor;
+
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if point.x >= 0 && point.y >= 0 {
+                self.set_pixel(point.x as u32, point.y as u32, color.is_on());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.buffer.fill(if color.is_on() { 0xFF } else { 0x00 });
+        Ok(())
+    }
+}
+
+/// Create an instance of the SH1106 driver with the provided I2C peripheral, size and rotation.
+pub fn setup_driver<DI: Write<Error = impl Debug>, S: Sh1106Size>(
+    i2c: DI,
+    _size: S,
+    rotation: DisplayRotation,
+) -> Sh1106<DI, S> {
+    let mut display = Sh1106::new(i2c, rotation);
+
+    if let Err(err) = display.init() {
+        error!(
+            "[SH1106] Could not initialize display: {}",
+            Debug2Format(&err)
+        );
+    }
+
+    display
+}
+
+/// A trait that keyboards must implement to use the SH1106 driver for displaying information.
+pub trait Sh1106I2cDisplayDriver {
+    /// Update the SH1106 screen. The frame buffer gets cleared, and
+    /// [`DisplayDevice::WIDGETS`](crate::display::DisplayDevice::WIDGETS) are drawn before this
+    /// function is called. After this function is called, the display will be flushed. So, an
+    /// implementor simply needs to create any extra graphics to display on the screen, and does
+    /// not need to clear the frame buffer or flush the data to the screen.
+    async fn on_update<S: Sh1106Size>(_display: &mut Sh1106<impl Write<Error = impl Debug>, S>) {}
+}
+
+#[cfg(feature = "display")]
+impl<DI: Write<Error = impl Debug>, S: Sh1106Size> crate::hw::PowerManaged for Sh1106<DI, S> {
+    async fn enter_low_power(&mut self) {
+        if let Err(err) = self.set_display_on(false) {
+            error!(
+                "[SH1106] Could not turn off display: {}",
+                Debug2Format(&err)
+            );
+        }
+    }
+
+    async fn exit_low_power(&mut self) {
+        if let Err(err) = self.set_display_on(true) {
+            error!("[SH1106] Could not turn on display: {}", Debug2Format(&err));
+        }
+    }
+}
+
+#[cfg(feature = "display")]
+impl<
+        DI: Write<Error = impl Debug>,
+        S: Sh1106Size,
+        K: Sh1106I2cDisplayDriver + crate::display::DisplayDevice,
+    > crate::display::DisplayDriver<K> for Sh1106<DI, S>
+{
+    async fn on_update(&mut self) {
+        self.clear(BinaryColor::Off).unwrap();
+        match self.rotation() {
+            DisplayRotation::Rotate0 | DisplayRotation::Rotate180 => {
+                crate::display::draw_widgets(
+                    self,
+                    K::WIDGETS,
+                    crate::display::Orientation::Horizontal,
+                    8,
+                )
+                .await;
+            }
+            DisplayRotation::Rotate90 | DisplayRotation::Rotate270 => {
+                crate::display::draw_widgets(
+                    self,
+                    K::WIDGETS,
+                    crate::display::Orientation::Vertical,
+                    12,
+                )
+                .await;
+            }
+        }
+        K::on_update(self).await;
+        if let Err(err) = self.flush() {
+            error!("[SH1106] Could not flush display: {}", Debug2Format(&err));
+        }
+    }
+
+    async fn turn_off(&mut self) {
+        if let Err(err) = self.set_display_on(false) {
+            error!(
+                "[SH1106] Could not turn off display: {}",
+                Debug2Format(&err)
+            );
+        }
+    }
+
+    async fn turn_on(&mut self) {
+        if let Err(err) = self.set_display_on(true) {
+            error!("[SH1106] Could not turn on display: {}", Debug2Format(&err));
+        }
+    }
+
+    fn invalidate(&mut self) {
+        Sh1106::invalidate(self);
+    }
+}
@@ -0,0 +1,451 @@
+//! Rumcake driver implementation for Sharp memory LCDs, like the LS011B7DH03 used by the
+//! nice!view.
+//!
+//! This driver provides implementations for
+//! [`DisplayDriver`](`crate::display::DisplayDriver`).
+//!
+//! Memory LCDs store 1 bit per pixel, and are updated one line at a time. The driver keeps a copy
+//! of the last frame that was sent to the display, and only sends the lines that changed. The
+//! panel also requires the polarity of its common electrode (VCOM) to be inverted regularly, to
+//! avoid damaging the liquid crystals. The driver toggles VCOM with every command it sends, and
+//! the display task calls [`DisplayDriver::maintain`](`crate::display::DisplayDriver::maintain`)
+//! to keep toggling it while the display isn't being updated.
+//!
+//! The display is connected using an SPI peripheral, which must be configured in SPI mode 0,
+//! with a frequency of 1MHz or lower. Note that the chip select pin of a memory LCD is active
+//! high, so it is controlled by the driver, and must not be managed by the SPI peripheral.
+//!
+//! To use this driver for the display feature, keyboards must implement
+//! [`SharpMemoryLcdDisplayDriver`]. The result of [`setup_driver`] should be passed to a display
+//! task.
+
+use core::convert::Infallible;
+use core::marker::PhantomData;
+
+use defmt::{error, Debug2Format};
+use embassy_time::Timer;
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::{DrawTarget, OriginDimensions, Size};
+use embedded_graphics::Pixel;
+use embedded_hal::digital::v2::OutputPin;
+use embedded_hal_async::spi::SpiBus;
+
+pub use rumcake_macros::setup_sharp_lcd;
+
+/// Largest frame buffer needed by a supported display (128x128 pixels).
+const MAX_BUFFER_SIZE: usize = 128 * 128 / 8;
+
+/// Largest number of bytes needed to store one line of a supported display.
+const MAX_LINE_SIZE: usize = 160 / 8;
+
+// The panel expects the mode bits and line addresses to be sent LSB first. These values are
+// already reversed, so that the SPI peripheral can send them MSB first.
+const MODE_WRITE: u8 = 0x80;
+const MODE_VCOM: u8 = 0x40;
+const MODE_CLEAR: u8 = 0x20;
+
+/// Describes the dimensions of the display.
+pub trait SharpMemoryLcdSize {
+    /// Width of the display in pixels. This must be a multiple of 8.
+    const WIDTH: u8;
+
+    /// Height of the display in pixels.
+    const HEIGHT: u8;
+}
+
+/// 160x68 pixel display, used by the LS011B7DH03 (nice!view).
+pub struct DisplaySize160x68;
+impl SharpMemoryLcdSize for DisplaySize160x68 {
+    const WIDTH: u8 = 160;
+    const HEIGHT: u8 = 68;
+}
+
+/// 128x128 pixel display, used by the LS013B7DH03.
+pub struct DisplaySize128x128;
+impl SharpMemoryLcdSize for DisplaySize128x128 {
+    const WIDTH: u8 = 128;
+    const HEIGHT: u8 = 128;
+}
+
+/// Rotation of the display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayRotation {
+    /// No rotation.
+    Rotate0,
+    /// Rotate the display 90 degrees clockwise.
+    Rotate90,
+    /// Rotate the display 180 degrees.
+    Rotate180,
+    /// Rotate the display 270 degrees clockwise.
+    Rotate270,
+}
+
+/// Errors that can be returned by [`SharpMemoryLcd`].
+#[derive(Debug)]
+pub enum SharpMemoryLcdError<E> {
+    /// An error occurred while communicating with the display over SPI.
+    Spi(E),
+    /// The chip select pin could not be set.
+    Pin,
+}
+
+impl<E> From<E> for SharpMemoryLcdError<E> {
+    fn from(value: E) -> Self {
+        Self::Spi(value)
+    }
+}
+
+/// Sharp memory LCD driver, which buffers the graphics that get drawn to it. Create this using
+/// [`setup_driver`].
+pub struct SharpMemoryLcd<S: SpiBus, P: OutputPin, D: SharpMemoryLcdSize> {
+    spi: S,
+    cs: P,
+    rotation: DisplayRotation,
+    /// Frame buffer. Pixels that are on are stored as `0`, since the panel shows `1` as white.
+    buffer: [u8; MAX_BUFFER_SIZE],
+    /// Contents of the display's memory, as of the last flush.
+    flushed: [u8; MAX_BUFFER_SIZE],
+    /// Whether the next flush should send the entire frame buffer, regardless of what changed.
+    full_flush: bool,
+    vcom: bool,
+    display_on: bool,
+    _size: PhantomData<D>,
+}
+
+impl<S: SpiBus, P: OutputPin, D: SharpMemoryLcdSize> SharpMemoryLcd<S, P, D> {
+    pub fn new(spi: S, cs: P, rotation: DisplayRotation) -> Self {
+        Self {
+            spi,
+            cs,
+            rotation,
+            buffer: [0xFF; MAX_BUFFER_SIZE],
+            flushed: [0xFF; MAX_BUFFER_SIZE],
+            full_flush: true,
+            vcom: false,
+            display_on: true,
+            _size: PhantomData,
+        }
+    }
+
+    /// Mode bits for the next command, with the VCOM bit toggled.
+    fn mode(&mut self, mode: u8) -> u8 {
+        self.vcom = !self.vcom;
+        if self.vcom {
+            mode | MODE_VCOM
+        } else {
+            mode
+        }
+    }
+
+    async fn select(&mut self) -> Result<(), SharpMemoryLcdError<S::Error>> {
+        self.cs.set_high().map_err(|_| SharpMemoryLcdError::Pin)?;
+        Timer::after_micros(3).await;
+        Ok(())
+    }
+
+    async fn deselect(&mut self) -> Result<(), SharpMemoryLcdError<S::Error>> {
+        self.spi.flush().await?;
+        Timer::after_micros(1).await;
+        self.cs.set_low().map_err(|_| SharpMemoryLcdError::Pin)
+    }
+
+    /// Send a command that doesn't contain any line data.
+    async fn send_command(&mut self, mode: u8) -> Result<(), SharpMemoryLcdError<S::Error>> {
+        let mode = self.mode(mode);
+        self.select().await?;
+        let result = self.spi.write(&[mode, 0x00]).await;
+        self.deselect().await?;
+        Ok(result?)
+    }
+
+    /// Clear the memory of the display, making it completely white, and clear the frame buffer.
+    pub async fn clear_display(&mut self) -> Result<(), SharpMemoryLcdError<S::Error>> {
+        self.send_command(MODE_CLEAR).await?;
+        self.buffer.fill(0xFF);
+        self.flushed.fill(0xFF);
+        Ok(())
+    }
+
+    /// Invert the polarity of VCOM, without changing the contents of the display.
+    pub async fn toggle_vcom(&mut self) -> Result<(), SharpMemoryLcdError<S::Error>> {
+        self.send_command(0x00).await
+    }
+
+    /// Get the current rotation of the display.
+    pub fn rotation(&self) -> DisplayRotation {
+        self.rotation
+    }
+
+    /// Change the rotation of the display. The contents of the frame buffer will need to be
+    /// redrawn after rotating the display.
+    pub fn set_rotation(&mut self, rotation: DisplayRotation) {
+        self.rotation = rotation;
+    }
+
+    /// Turn the display on or off. Memory LCDs don't have an off state, so turning the display
+    /// off clears it instead. While the display is off, flushes are ignored.
+    pub async fn set_display_on(&mut self, on: bool) -> Result<(), SharpMemoryLcdError<S::Error>> {
+        self.display_on = on;
+
+        if on {
+            self.invalidate();
+            Ok(())
+        } else {
+            self.clear_display().await
+        }
+    }
+
+    /// Set the value of a pixel in the frame buffer. `x` and `y` are in the rotated coordinate
+    /// space. Pixels outside of the display are ignored.
+    pub fn set_pixel(&mut self, x: u32, y: u32, on: bool) {
+        let (width, height) = (D::WIDTH as u32, D::HEIGHT as u32);
+        let (col, row) = match self.rotation {
+            DisplayRotation::Rotate0 => (x, y),
+            DisplayRotation::Rotate90 => (y, height.wrapping_sub(x + 1)),
+            DisplayRotation::Rotate180 => (width.wrapping_sub(x + 1), height.wrapping_sub(y + 1)),
+            DisplayRotation::Rotate270 => (width.wrapping_sub(y + 1), x),
+        };
+
+        if col >= width || row >= height {
+            return;
+        }
+
+        let idx = row as usize * (width as usize / 8) + col as usize / 8;
+        let bit = 0x80 >> (col % 8);
+
+        if on {
+            self.buffer[idx] &= !bit;
+        } else {
+            self.buffer[idx] |= bit;
+        }
+    }
+
+    /// Make the next call to [`SharpMemoryLcd::flush`] send the entire frame buffer to the
+    /// display.
+    pub fn invalidate(&mut self) {
+        self.full_flush = true;
+    }
+
+    /// Send the lines of the frame buffer that changed since the last flush to the display. If no
+    /// lines changed, VCOM is toggled instead.
+    pub async fn flush(&mut self) -> Result<(), SharpMemoryLcdError<S::Error>> {
+        if !self.display_on {
+            return self.toggle_vcom().await;
+        }
+
+        let line_size = D::WIDTH as usize / 8;
+        let changed_line = |display: &Self, line: usize| {
+            let range = (line * line_size)..((line + 1) * line_size);
+            display.full_flush || display.buffer[range.clone()] != display.flushed[range]
+        };
+
+        if !(0..D::HEIGHT as usize).any(|line| changed_line(self, line)) {
+            return self.toggle_vcom().await;
+        }
+
+        let mode = self.mode(MODE_WRITE);
+        self.select().await?;
+
+        let result = async {
+            self.spi.write(&[mode]).await?;
+
+            let mut data = [0; MAX_LINE_SIZE + 2];
+            for line in 0..D::HEIGHT as usize {
+                if !changed_line(self, line) {
+                    continue;
+                }
+
+                let range = (line * line_size)..((line + 1) * line_size);
+
+                // Line addresses start at 1. The last byte is a dummy byte.
+                data[0] = (line as u8 + 1).reverse_bits();
+                data[1..(line_size + 1)].copy_from_slice(&self.buffer[range.clone()]);
+                data[line_size + 1] = 0x00;
+                self.spi.write(&data[..(line_size + 2)]).await?;
+
+                self.flushed[range.clone()].copy_from_slice(&self.buffer[range]);
+            }
+
+            self.spi.write(&[0x00]).await
+        }
+        .await;
+
+        self.deselect().await?;
+        self.full_flush = false;
+
+        Ok(result?)
+    }
+}
+
+impl<S: SpiBus, P: OutputPin, D: SharpMemoryLcdSize> OriginDimensions for SharpMemoryLcd<S, P, D> {
+    fn size(&self) -> Size {
+        match self.rotation {
+            DisplayRotation::Rotate0 | DisplayRotation::Rotate180 => {
+                Size::new(D::WIDTH as u32, D::HEIGHT as u32)
+            }
+            DisplayRotation::Rotate90 | DisplayRotation::Rotate270 => {
+                Size::new(D::HEIGHT as u32, D::WIDTH as u32)
+            }
+        }
+    }
+}
+
+impl<S: SpiBus, P: OutputPin, D: SharpMemoryLcdSize> DrawTarget for SharpMemoryLcd<S, P, D> {
+    type Color = BinaryColor;
+
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if point.x >= 0 && point.y >= 0 {
+                self.set_pixel(point.x as u32, point.y as u32, color.is_on());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.buffer.fill(if color.is_on() { 0x00 } else { 0xFF });
+        Ok(())
+    }
+}
+
+/// Create an instance of the Sharp memory LCD driver with the provided SPI bus, chip select pin,
+/// size and rotation. The display will be cleared.
+pub async fn setup_driver<S: SpiBus, P: OutputPin, D: SharpMemoryLcdSize>(
+    spi: S,
+    mut cs: P,
+    _size: D,
+    rotation: DisplayRotation,
+) -> SharpMemoryLcd<S, P, D> {
+    // The chip select pin is active high
+    if cs.set_low().is_err() {
+        error!("[SHARP_MEMORY_LCD] Could not set the chip select pin");
+    }
+
+    let mut display = SharpMemoryLcd::new(spi, cs, rotation);
+
+    if let Err(err) = display.clear_display().await {
+        error!(
+            "[SHARP_MEMORY_LCD] Could not initialize display: {}",
+            Debug2Format(&err)
+        );
+    }
+
+    display
+}
+
+/// A trait that keyboards must implement to use the Sharp memory LCD driver for displaying
+/// information.
+pub trait SharpMemoryLcdDisplayDriver {
+    /// Update the display. The frame buffer gets cleared, and
+    /// [`DisplayDevice::WIDGETS`](crate::display::DisplayDevice::WIDGETS) are drawn before this
+    /// function is called. After this function is called, the display will be flushed. So, an
+    /// implementor simply needs to create any extra graphics to display on the screen, and does
+    /// not need to clear the frame buffer or flush the data to the screen.
+    async fn on_update<S: SpiBus, P: OutputPin, D: SharpMemoryLcdSize>(
+        _display: &mut SharpMemoryLcd<S, P, D>,
+    ) {
+    }
+}
+
+#[cfg(feature = "display")]
+impl<S: SpiBus, P: OutputPin, D: SharpMemoryLcdSize> crate::hw::PowerManaged
+    for SharpMemoryLcd<S, P, D>
+{
+    async fn enter_low_power(&mut self) {
+        if let Err(err) = self.set_display_on(false).await {
+            error!(
+                "[SHARP_MEMORY_LCD] Could not turn off display: {}",
+                Debug2Format(&err)
+            );
+        }
+    }
+
+    async fn exit_low_power(&mut self) {
+        if let Err(err) = self.set_display_on(true).await {
+            error!(
+                "[SHARP_MEMORY_LCD] Could not turn on display: {}",
+                Debug2Format(&err)
+            );
+        }
+    }
+}
+
+#[cfg(feature = "display")]
+impl<
+        S: SpiBus,
+        P: OutputPin,
+        D: SharpMemoryLcdSize,
+        K: SharpMemoryLcdDisplayDriver + crate::display::DisplayDevice,
+    > crate::display::DisplayDriver<K> for SharpMemoryLcd<S, P, D>
+{
+    async fn on_update(&mut self) {
+        self.clear(BinaryColor::Off).unwrap();
+        match self.rotation() {
+            DisplayRotation::Rotate0 | DisplayRotation::Rotate180 => {
+                crate::display::draw_widgets(
+                    self,
+                    K::WIDGETS,
+                    crate::display::Orientation::Horizontal,
+                    8,
+                )
+                .await;
+            }
+            DisplayRotation::Rotate90 | DisplayRotation::Rotate270 => {
+                crate::display::draw_widgets(
+                    self,
+                    K::WIDGETS,
+                    crate::display::Orientation::Vertical,
+                    12,
+                )
+                .await;
+            }
+        }
+        K::on_update(self).await;
+        if let Err(err) = self.flush().await {
+            error!(
+                "[SHARP_MEMORY_LCD] Could not flush display: {}",
+                Debug2Format(&err)
+            );
+        }
+    }
+
+    async fn turn_off(&mut self) {
+        if let Err(err) = self.set_display_on(false).await {
+            error!(
+                "[SHARP_MEMORY_LCD] Could not turn off display: {}",
+                Debug2Format(&err)
+            );
+        }
+    }
+
+    async fn turn_on(&mut self) {
+        if let Err(err) = self.set_display_on(true).await {
+            error!(
+                "[SHARP_MEMORY_LCD] Could not turn on display: {}",
+                Debug2Format(&err)
+            );
+        }
+    }
+
+    fn invalidate(&mut self) {
+        SharpMemoryLcd::invalidate(self);
+    }
+
+    /// VCOM must be inverted at least once per second.
+    const MAINTENANCE_INTERVAL: u64 = 500;
+
+    async fn maintain(&mut self) {
+        if let Err(err) = self.toggle_vcom().await {
+            error!(
+                "[SHARP_MEMORY_LCD] Could not toggle VCOM: {}",
+                Debug2Format(&err)
+            );
+        }
+    }
+}
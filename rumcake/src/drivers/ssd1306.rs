@@ -3,14 +3,22 @@
 //! This driver provides implementations for
 //! [`DisplayDriver`](`crate::display::DisplayDriver`),
 //!
+//! The driver keeps its own frame buffer, along with a copy of the last frame that was sent to the
+//! display. When flushing, only the columns that changed in each page are sent, and pages that
+//! didn't change are skipped entirely.
+//!
 //! To use this driver for the display feature, keyboards must implement
 //! [`Ssd1306I2cDisplayDriver`](display::Ssd1306I2cDisplayDriver). The result of [`setup_driver`]
 //! should be passed to a display task.
 
+use core::convert::Infallible;
 use core::fmt::Debug;
 
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::{DrawTarget, OriginDimensions, Size};
+use embedded_graphics::Pixel;
 use embedded_hal::blocking::i2c::Write;
-use ssd1306::mode::BufferedGraphicsMode;
+use ssd1306::mode::BasicMode;
 use ssd1306::prelude::{DisplayConfig, I2CInterface};
 use ssd1306::rotation::DisplayRotation;
 use ssd1306::size::DisplaySize;
@@ -20,54 +28,170 @@ pub use ssd1306 as driver;
 
 pub use rumcake_macros::setup_ssd1306;
 
+/// Largest frame buffer needed by an SSD1306 (128x64 pixels).
+const MAX_BUFFER_SIZE: usize = 128 * 64 / 8;
+
+/// SSD1306 driver, which buffers the graphics that get drawn to it. Create this using
+/// [`setup_driver`].
+pub struct Ssd1306Display<DI, S: DisplaySize> {
+    display: Ssd1306<I2CInterface<DI>, S, BasicMode>,
+    buffer: [u8; MAX_BUFFER_SIZE],
+    /// Contents of the display RAM, as of the last flush.
+    flushed: [u8; MAX_BUFFER_SIZE],
+    /// Whether the next flush should send the entire frame buffer, regardless of what changed.
+    full_flush: bool,
+}
+
+impl<DI: Write<Error = E>, E: Debug, S: DisplaySize> Ssd1306Display<DI, S> {
+    /// Get the underlying [`Ssd1306`] instance, which can be used to send other commands to the
+    /// display.
+    pub fn driver(&mut self) -> &mut Ssd1306<I2CInterface<DI>, S, BasicMode> {
+        &mut self.display
+    }
+
+    /// Get the current rotation of the display.
+    pub fn rotation(&self) -> DisplayRotation {
+        self.display.rotation()
+    }
+
+    /// Set the value of a pixel in the frame buffer. `x` and `y` are in the rotated coordinate
+    /// space. Pixels outside of the display are ignored.
+    pub fn set_pixel(&mut self, x: u32, y: u32, on: bool) {
+        let (col, row) = match self.rotation() {
+            DisplayRotation::Rotate0 | DisplayRotation::Rotate180 => (x, y),
+            DisplayRotation::Rotate90 | DisplayRotation::Rotate270 => (y, x),
+        };
+
+        if col >= S::WIDTH as u32 || row >= S::HEIGHT as u32 {
+            return;
+        }
+
+        let idx = (row / 8) as usize * S::WIDTH as usize + col as usize;
+        let bit = 1 << (row % 8);
+
+        if on {
+            self.buffer[idx] |= bit;
+        } else {
+            self.buffer[idx] &= !bit;
+        }
+    }
+
+    /// Make the next call to [`Ssd1306Display::flush`] send the entire frame buffer to the
+    /// display.
+    pub fn invalidate(&mut self) {
+        self.full_flush = true;
+    }
+
+    /// Send the parts of the frame buffer that changed since the last flush to the display.
+    pub fn flush(
+        &mut self,
+    ) -> Result<(), <Ssd1306<I2CInterface<DI>, S, BasicMode> as DisplayConfig>::Error> {
+        let width = S::WIDTH as usize;
+
+        for page in 0..(S::HEIGHT / 8) {
+            let page_start = page as usize * width;
+            let page_end = page_start + width;
+
+            let changed = if self.full_flush {
+                0..width
+            } else {
+                match crate::display::changed_range(
+                    &self.flushed[page_start..page_end],
+                    &self.buffer[page_start..page_end],
+                ) {
+                    Some(changed) => changed,
+                    None => continue,
+                }
+            };
+
+            self.display.set_draw_area(
+                (changed.start as u8 + S::OFFSETX, page * 8 + S::OFFSETY),
+                (changed.end as u8 + S::OFFSETX, page * 8 + 8 + S::OFFSETY),
+            )?;
+
+            let (start, end) = (page_start + changed.start, page_start + changed.end);
+            self.display.draw(&self.buffer[start..end])?;
+            self.flushed[start..end].copy_from_slice(&self.buffer[start..end]);
+        }
+
+        self.full_flush = false;
+
+        Ok(())
+    }
+}
+
+impl<DI, S: DisplaySize> OriginDimensions for Ssd1306Display<DI, S> {
+    fn size(&self) -> Size {
+        let (width, height) = self.display.dimensions();
+        Size::new(width as u32, height as u32)
+    }
+}
+
+impl<DI: Write<Error = E>, E: Debug, S: DisplaySize> DrawTarget for Ssd1306Display<DI, S> {
+    type Color = BinaryColor;
+
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if point.x >= 0 && point.y >= 0 {
+                self.set_pixel(point.x as u32, point.y as u32, color.is_on());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.buffer.fill(if color.is_on() { 0xFF } else { 0x00 });
+        Ok(())
+    }
+}
+
 /// Create an instance of the SSD1306 driver with the provided I2C peripheral, and default size and
 /// rotation.
 pub fn setup_driver<DI: Write<Error = impl Debug>, S: DisplaySize>(
     i2c: DI,
     size: S,
     rotation: DisplayRotation,
-) -> Ssd1306<I2CInterface<DI>, S, BufferedGraphicsMode<S>> {
-    let mut display =
-        Ssd1306::new(I2CDisplayInterface::new(i2c), size, rotation).into_buffered_graphics_mode();
+) -> Ssd1306Display<DI, S> {
+    let mut display = Ssd1306::new(I2CDisplayInterface::new(i2c), size, rotation);
     display.init().unwrap();
 
-    display
+    Ssd1306Display {
+        display,
+        buffer: [0; MAX_BUFFER_SIZE],
+        flushed: [0; MAX_BUFFER_SIZE],
+        full_flush: true,
+    }
 }
 
 /// A trait that keyboards must implement to use the SSD1306 driver for displaying information.
 pub trait Ssd1306I2cDisplayDriver {
-    /// Update the SSD1306 screen. The frame buffer gets cleared before this function is called.
-    /// After this function is called, the display will be flushed. So, an implementor simply
-    /// needs to create the graphics to display on the screen, and does not need to clear the
-    /// frame buffer or flush the data to the screen.
+    /// Update the SSD1306 screen. The frame buffer gets cleared, and
+    /// [`DisplayDevice::WIDGETS`](crate::display::DisplayDevice::WIDGETS) are drawn before this
+    /// function is called. After this function is called, the display will be flushed. So, an
+    /// implementor simply needs to create any extra graphics to display on the screen, and does
+    /// not need to clear the frame buffer or flush the data to the screen.
     async fn on_update<S: DisplaySize>(
-        display: &mut Ssd1306<
-            I2CInterface<impl Write<Error = impl Debug>>,
-            S,
-            BufferedGraphicsMode<S>,
-        >,
+        _display: &mut Ssd1306Display<impl Write<Error = impl Debug>, S>,
     ) {
-        #[cfg(feature = "display")]
-        {
-            match display.rotation() {
-                DisplayRotation::Rotate0 | DisplayRotation::Rotate180 => {
-                    crate::display::on_update_default(
-                        display,
-                        crate::display::Orientation::Horizontal,
-                        8,
-                    )
-                    .await;
-                }
-                DisplayRotation::Rotate90 | DisplayRotation::Rotate270 => {
-                    crate::display::on_update_default(
-                        display,
-                        crate::display::Orientation::Vertical,
-                        12,
-                    )
-                    .await;
-                }
-            }
-        }
+    }
+}
+
+#[cfg(feature = "display")]
+impl<DI: Write<Error = impl Debug>, S: DisplaySize> crate::hw::PowerManaged
+    for Ssd1306Display<DI, S>
+{
+    async fn enter_low_power(&mut self) {
+        self.display.set_display_on(false).unwrap();
+    }
+
+    async fn exit_low_power(&mut self) {
+        self.display.set_display_on(true).unwrap();
     }
 }
 
@@ -76,21 +200,43 @@ impl<
         DI: Write<Error = impl Debug>,
         S: DisplaySize,
         K: Ssd1306I2cDisplayDriver + crate::display::DisplayDevice,
-    > crate::display::DisplayDriver<K> for Ssd1306<I2CInterface<DI>, S, BufferedGraphicsMode<S>>
+    > crate::display::DisplayDriver<K> for Ssd1306Display<DI, S>
 {
     async fn on_update(&mut self) {
-        use embedded_graphics::prelude::DrawTarget;
-        self.clear(embedded_graphics::pixelcolor::BinaryColor::Off)
-            .unwrap();
+        self.clear(BinaryColor::Off).unwrap();
+        match self.rotation() {
+            DisplayRotation::Rotate0 | DisplayRotation::Rotate180 => {
+                crate::display::draw_widgets(
+                    self,
+                    K::WIDGETS,
+                    crate::display::Orientation::Horizontal,
+                    8,
+                )
+                .await;
+            }
+            DisplayRotation::Rotate90 | DisplayRotation::Rotate270 => {
+                crate::display::draw_widgets(
+                    self,
+                    K::WIDGETS,
+                    crate::display::Orientation::Vertical,
+                    12,
+                )
+                .await;
+            }
+        }
         K::on_update(self).await;
         self.flush().unwrap();
     }
 
     async fn turn_off(&mut self) {
-        self.set_display_on(false).unwrap();
+        self.display.set_display_on(false).unwrap();
     }
 
     async fn turn_on(&mut self) {
-        self.set_display_on(true).unwrap();
+        self.display.set_display_on(true).unwrap();
+    }
+
+    fn invalidate(&mut self) {
+        Ssd1306Display::invalidate(self);
     }
 }
@@ -0,0 +1,265 @@
+//! Rumcake driver implementation for analog (pressure-based) pointing sticks.
+//!
+//! This driver provides an implementation for [`PointerDriver`]. Both axes of the pointing stick
+//! are read using the ADC sampler, so you must use `setup_adc_sampler!` to configure the analog
+//! pins that the stick is connected to.
+//!
+//! To use this driver, keyboards must implement [`TrackpointDevice`]. The result of
+//! [`setup_trackpoint!`] should be returned by the `driver_setup_fn` used for the pointer task.
+
+use defmt::{debug, warn};
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::keyboard::MatrixSampler;
+use crate::pointer::{PointerDevice, PointerDriver};
+
+pub use rumcake_macros::setup_trackpoint;
+
+/// A trait that keyboards must implement to use a pointing stick.
+///
+/// Sensitivity, acceleration and negative inertia are applied in fixed point, where `256`
+/// represents a multiplier of `1`.
+pub trait TrackpointDevice: PointerDevice {
+    /// How often the pointing stick should be sampled, in milliseconds.
+    const TRACKPOINT_POLL_INTERVAL: u64 = 10;
+
+    /// Number of samples that get averaged on startup to find the resting position of the stick.
+    const TRACKPOINT_CALIBRATION_SAMPLES: u16 = 32;
+
+    /// Readings that are within this distance from the resting position are ignored. Increase this
+    /// if the cursor moves when the stick isn't being touched.
+    const TRACKPOINT_DEADZONE: u16 = 12;
+
+    /// Linear portion of the response curve. With a value of `256`, each ADC unit of deflection
+    /// outside of the deadzone moves the cursor by 1 count every poll.
+    const TRACKPOINT_SENSITIVITY: u16 = 24;
+
+    /// Quadratic portion of the response curve. Higher values make the cursor accelerate faster
+    /// as more pressure is applied. Set this to `0` for a linear response.
+    const TRACKPOINT_ACCELERATION: u16 = 4;
+
+    /// Strength of the negative inertia effect. Changes in pressure are exaggerated by this
+    /// amount, which makes the cursor start quickly, and stop without overshooting. Set this to
+    /// `0` to disable negative inertia.
+    const TRACKPOINT_NEGATIVE_INERTIA: u16 = 192;
+
+    /// While the stick is idle (inside the deadzone), the resting position is moved towards the
+    /// current reading by `1 / 2^TRACKPOINT_RECENTER_RATE` of the difference every poll. This
+    /// compensates for slow drift caused by temperature changes. Lower values recenter faster.
+    const TRACKPOINT_RECENTER_RATE: u8 = 6;
+
+    /// If the stick reports a deflection that doesn't change (within
+    /// [`TrackpointDevice::TRACKPOINT_DRIFT_TOLERANCE`]) for this many milliseconds, the stick is
+    /// assumed to have drifted, and the resting position is reset to the current reading. Set this
+    /// to `0` to disable this behaviour.
+    const TRACKPOINT_DRIFT_TIMEOUT: u64 = 3000;
+
+    /// Maximum change in readings (in ADC units) that is still considered to be "not moving" when
+    /// detecting drift.
+    const TRACKPOINT_DRIFT_TOLERANCE: u16 = 3;
+
+    /// Whether to invert the X axis.
+    const TRACKPOINT_INVERT_X: bool = false;
+
+    /// Whether to invert the Y axis.
+    const TRACKPOINT_INVERT_Y: bool = false;
+}
+
+/// Errors that can be returned by [`Trackpoint`].
+#[derive(Debug)]
+pub enum TrackpointError {
+    /// A sample could not be obtained for one of the axes. Make sure that the channels passed to
+    /// [`Trackpoint::new`] are configured in `setup_adc_sampler!`.
+    MissingSample,
+}
+
+struct Axis {
+    ch: usize,
+    sub_ch: usize,
+    /// Resting position, with 8 fractional bits.
+    center: i32,
+    /// Last raw reading, used for drift detection.
+    last_reading: i32,
+    /// Last output of the response curve (before negative inertia), with 8 fractional bits.
+    last_output: i32,
+    /// Sub-count movement that hasn't been sent yet, with 8 fractional bits.
+    remainder: i32,
+}
+
+impl Axis {
+    fn new((ch, sub_ch): (usize, usize)) -> Self {
+        Self {
+            ch,
+            sub_ch,
+            center: 0,
+            last_reading: 0,
+            last_output: 0,
+            remainder: 0,
+        }
+    }
+
+    fn deflection(&self, reading: i32) -> i32 {
+        reading - (self.center >> 8)
+    }
+
+    fn recenter(&mut self, reading: i32, rate: u8) {
+        self.center += ((reading << 8) - self.center) / (1 << rate);
+    }
+
+    fn calibrate(&mut self, reading: i32) {
+        self.center = reading << 8;
+        self.last_output = 0;
+        self.remainder = 0;
+    }
+
+    fn process<K: TrackpointDevice>(&mut self, reading: i32, invert: bool) -> i16 {
+        let deflection = self.deflection(reading);
+        let magnitude = (deflection.abs() - K::TRACKPOINT_DEADZONE as i32).max(0);
+        let velocity = magnitude * deflection.signum();
+
+        let output = velocity * K::TRACKPOINT_SENSITIVITY as i32
+            + velocity * magnitude * K::TRACKPOINT_ACCELERATION as i32 / 16;
+
+        // Negative inertia: exaggerate changes in output, so that the cursor responds quickly
+        // when pressure is applied, and kicks back slightly when pressure is released.
+        let boosted =
+            output + (output - self.last_output) * K::TRACKPOINT_NEGATIVE_INERTIA as i32 / 256;
+        self.last_output = output;
+
+        if boosted == 0 {
+            self.remainder = 0;
+            return 0;
+        }
+
+        self.remainder += boosted;
+        let movement = self.remainder / 256;
+        self.remainder -= movement * 256;
+
+        let movement = movement.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+        if invert {
+            -movement
+        } else {
+            movement
+        }
+    }
+}
+
+/// Driver for an analog pointing stick. Create this using [`setup_trackpoint!`].
+pub struct Trackpoint<'a, S: MatrixSampler> {
+    sampler: &'a S,
+    x: Axis,
+    y: Axis,
+    stable_since: Option<Instant>,
+}
+
+impl<'a, S: MatrixSampler> Trackpoint<'a, S>
+where
+    i32: From<S::SampleType>,
+{
+    /// Create a new pointing stick driver. `x` and `y` are the `(ch, sub_ch)` pairs passed to
+    /// [`MatrixSampler::get_sample`] to read each axis.
+    pub fn new(sampler: &'a S, x: (usize, usize), y: (usize, usize)) -> Self {
+        Self {
+            sampler,
+            x: Axis::new(x),
+            y: Axis::new(y),
+            stable_since: None,
+        }
+    }
+
+    fn read(&self) -> Result<(i32, i32), TrackpointError> {
+        let x = self.sampler.get_sample(self.x.ch, self.x.sub_ch);
+        let y = self.sampler.get_sample(self.y.ch, self.y.sub_ch);
+
+        match (x, y) {
+            (Some(x), Some(y)) => Ok((i32::from(x), i32::from(y))),
+            _ => Err(TrackpointError::MissingSample),
+        }
+    }
+
+    fn compensate_drift<K: TrackpointDevice>(&mut self, x: i32, y: i32) {
+        let deadzone = K::TRACKPOINT_DEADZONE as i32;
+        let idle = self.x.deflection(x).abs() <= deadzone && self.y.deflection(y).abs() <= deadzone;
+
+        if idle {
+            self.x.recenter(x, K::TRACKPOINT_RECENTER_RATE);
+            self.y.recenter(y, K::TRACKPOINT_RECENTER_RATE);
+            self.stable_since = None;
+        } else if K::TRACKPOINT_DRIFT_TIMEOUT > 0 {
+            let tolerance = K::TRACKPOINT_DRIFT_TOLERANCE as i32;
+            let stable = (x - self.x.last_reading).abs() <= tolerance
+                && (y - self.y.last_reading).abs() <= tolerance;
+
+            match self.stable_since {
+                Some(since) if stable => {
+                    if since.elapsed() >= Duration::from_millis(K::TRACKPOINT_DRIFT_TIMEOUT) {
+                        warn!(
+                            "[TRACKPOINT] Stick reading has not changed for {} ms, recalibrating",
+                            K::TRACKPOINT_DRIFT_TIMEOUT
+                        );
+                        self.x.calibrate(x);
+                        self.y.calibrate(y);
+                        self.stable_since = None;
+                    }
+                }
+                None if stable => self.stable_since = Some(Instant::now()),
+                _ => self.stable_since = None,
+            }
+        }
+
+        self.x.last_reading = x;
+        self.y.last_reading = y;
+    }
+}
+
+impl<'a, K: TrackpointDevice, S: MatrixSampler> PointerDriver<K> for Trackpoint<'a, S>
+where
+    i32: From<S::SampleType>,
+{
+    type DriverError = TrackpointError;
+
+    const POLL_INTERVAL: u64 = K::TRACKPOINT_POLL_INTERVAL;
+
+    async fn init(&mut self) -> Result<(), Self::DriverError> {
+        let (mut sum_x, mut sum_y, mut count) = (0, 0, 0);
+
+        for _ in 0..K::TRACKPOINT_CALIBRATION_SAMPLES {
+            // Samples may not be available yet if the ADC sampler hasn't started
+            if let Ok((x, y)) = self.read() {
+                sum_x += x;
+                sum_y += y;
+                count += 1;
+            }
+
+            Timer::after(Duration::from_millis(K::TRACKPOINT_POLL_INTERVAL)).await;
+        }
+
+        if count == 0 {
+            return Err(TrackpointError::MissingSample);
+        }
+
+        self.x.calibrate(sum_x / count);
+        self.y.calibrate(sum_y / count);
+        self.x.last_reading = sum_x / count;
+        self.y.last_reading = sum_y / count;
+
+        debug!(
+            "[TRACKPOINT] Calibrated resting position: ({}, {})",
+            sum_x / count,
+            sum_y / count
+        );
+
+        Ok(())
+    }
+
+    async fn read_movement(&mut self) -> Result<(i16, i16), Self::DriverError> {
+        let (x, y) = self.read()?;
+
+        self.compensate_drift::<K>(x, y);
+
+        Ok((
+            self.x.process::<K>(x, K::TRACKPOINT_INVERT_X),
+            self.y.process::<K>(y, K::TRACKPOINT_INVERT_Y),
+        ))
+    }
+}
@@ -14,7 +14,6 @@
 
 use driver::Ws2812;
 use embedded_hal::digital::v2::OutputPin;
-use smart_leds::gamma;
 use smart_leds::RGB8;
 
 pub use rumcake_macros::{
@@ -149,7 +148,7 @@ where
         &mut self,
         colors: impl Iterator<Item = RGB8>,
     ) -> Result<(), Self::DriverWriteError> {
-        self.write_colors(gamma(colors));
+        self.write_colors(colors);
 
         Ok(())
     }
@@ -185,7 +184,7 @@ where
     async fn write(&mut self, brightness: u8) -> Result<(), Self::DriverWriteError> {
         let brightnesses = [(brightness, brightness, brightness).into(); K::NUM_LEDS];
 
-        self.write_colors(gamma(brightnesses.iter().cloned()));
+        self.write_colors(brightnesses.iter().cloned());
 
         Ok(())
     }
@@ -234,7 +233,7 @@ where
             }
         }
 
-        self.write_colors(gamma(brightnesses.iter().cloned()));
+        self.write_colors(brightnesses.iter().cloned());
 
         Ok(())
     }
@@ -287,7 +286,7 @@ where
             }
         }
 
-        self.write_colors(gamma(colors.iter().cloned()));
+        self.write_colors(colors.iter().cloned());
 
         Ok(())
     }
@@ -0,0 +1,329 @@
+//! Rumcake driver implementations for a WS2812 driver that uses an SPI peripheral to generate the
+//! data signal.
+//!
+//! Unlike the [bitbang driver](crate::drivers::ws2812_bitbang), this driver does not need to
+//! disable interrupts, since the timing of the signal is handled by the SPI peripheral. Each bit
+//! sent to the LEDs is encoded as 3 or 4 SPI bits, depending on the chosen
+//! [`Ws2812SpiEncoding`]. The SPI peripheral must be configured with the frequency specified by
+//! [`Ws2812SpiEncoding::FREQUENCY`]. Only the MOSI pin is used.
+//!
+//! This driver provides implementations for
+//! [`UnderglowDriver`](`crate::lighting::underglow::UnderglowDriver`),
+//! [`SimpleBacklightDriver`](`crate::lighting::simple_backlight::SimpleBacklightDriver`),
+//! [`SimpleBacklightMatrixDriver`](`crate::lighting::simple_backlight_matrix::SimpleBacklightMatrixDriver`),
+//! and
+//! [`RGBBacklightMatrixDriver`](`crate::lighting::rgb_backlight_matrix::RGBBacklightMatrixDriver`)
+//!
+//! To use this driver, pass the result of [`setup_driver`] to an underglow task, or backlight
+//! task. If you want to use this driver as a backlight matrix, you will need to implement
+//! [`WS2812SpiBacklightMatrixDriver`].
+
+use core::marker::PhantomData;
+
+use embedded_hal_async::spi::SpiBus;
+use smart_leds::RGB8;
+
+pub use rumcake_macros::{
+    setup_ws2812_spi, ws2812_get_led_from_matrix_coordinates as get_led_from_matrix_coordinates,
+};
+
+/// Determines how each bit sent to the LEDs is encoded as SPI bits.
+pub trait Ws2812SpiEncoding {
+    /// Number of SPI bits used to represent a single bit of WS2812 data.
+    const BITS: u8;
+
+    /// The frequency that the SPI peripheral must be configured with, in Hz.
+    const FREQUENCY: u32;
+
+    /// SPI bits representing a `0` bit.
+    const ZERO: u8;
+
+    /// SPI bits representing a `1` bit.
+    const ONE: u8;
+}
+
+/// Encodes each bit as 3 SPI bits, at 2.4MHz (~417ns per SPI bit). This uses less memory
+/// bandwidth than [`FourBitEncoding`], but has less timing margin.
+pub struct ThreeBitEncoding;
+
+impl Ws2812SpiEncoding for ThreeBitEncoding {
+    const BITS: u8 = 3;
+    const FREQUENCY: u32 = 2_400_000;
+    const ZERO: u8 = 0b100;
+    const ONE: u8 = 0b110;
+}
+
+/// Encodes each bit as 4 SPI bits, at 3.2MHz (~313ns per SPI bit).
+pub struct FourBitEncoding;
+
+impl Ws2812SpiEncoding for FourBitEncoding {
+    const BITS: u8 = 4;
+    const FREQUENCY: u32 = 3_200_000;
+    const ZERO: u8 = 0b1000;
+    const ONE: u8 = 0b1110;
+}
+
+// in microseconds, taken from WS2812 datasheet
+const RES: u64 = 280;
+
+/// Number of bytes that get encoded before they are sent to the SPI peripheral.
+const CHUNK_SIZE: usize = 96;
+
+/// WS2812 driver. `E` determines how the data gets encoded, which also determines the SPI
+/// frequency that must be used.
+pub struct Ws2812Spi<E: Ws2812SpiEncoding, S: SpiBus> {
+    spi: S,
+    _encoding: PhantomData<E>,
+}
+
+impl<E: Ws2812SpiEncoding, S: SpiBus> Ws2812Spi<E, S> {
+    /// Number of zero bytes that need to be sent to hold the data line low for the reset time.
+    const RESET_BYTES: usize = (RES * E::FREQUENCY as u64 / 8 / 1_000_000) as usize + 1;
+
+    pub fn new(spi: S) -> Ws2812Spi<E, S> {
+        Self {
+            spi,
+            _encoding: PhantomData,
+        }
+    }
+
+    /// Encode a byte into `out`, returning the number of bytes used.
+    fn encode_byte(data: u8, out: &mut [u8]) -> usize {
+        let mut bits: u32 = 0;
+        for i in (0..8).rev() {
+            let encoded = if (data >> i) & 1 == 1 {
+                E::ONE
+            } else {
+                E::ZERO
+            };
+            bits = (bits << E::BITS) | encoded as u32;
+        }
+
+        // 8 bits of data encoded with `E::BITS` bits each results in `E::BITS` bytes
+        let len = E::BITS as usize;
+        out[..len].copy_from_slice(&bits.to_be_bytes()[(4 - len)..]);
+        len
+    }
+
+    /// Hold the data line low, so that the LEDs latch the colors that were sent.
+    async fn latch(&mut self) -> Result<(), S::Error> {
+        let zeros = [0; CHUNK_SIZE];
+        let mut remaining = Self::RESET_BYTES;
+
+        while remaining > 0 {
+            let len = remaining.min(CHUNK_SIZE);
+            self.spi.write(&zeros[..len]).await?;
+            remaining -= len;
+        }
+
+        self.spi.flush().await
+    }
+
+    pub async fn write_colors(
+        &mut self,
+        colors: impl Iterator<Item = RGB8>,
+    ) -> Result<(), S::Error> {
+        let mut buf = [0; CHUNK_SIZE];
+        let mut len = 0;
+
+        for color in colors {
+            for byte in [color.g, color.r, color.b] {
+                if len + E::BITS as usize > CHUNK_SIZE {
+                    self.spi.write(&buf[..len]).await?;
+                    len = 0;
+                }
+
+                len += Self::encode_byte(byte, &mut buf[len..]);
+            }
+        }
+
+        if len > 0 {
+            self.spi.write(&buf[..len]).await?;
+        }
+
+        self.latch().await
+    }
+}
+
+/// Create an instance of the WS2812 SPI driver with the provided SPI bus. The SPI bus must be
+/// configured with the frequency specified by [`Ws2812SpiEncoding::FREQUENCY`].
+pub fn setup_driver<E: Ws2812SpiEncoding, S: SpiBus>(spi: S) -> Ws2812Spi<E, S> {
+    Ws2812Spi::new(spi)
+}
+
+/// A trait that keyboards must implement to use the WS2812 driver for simple backlighting.
+pub trait WS2812SpiSimpleBacklightDriver {
+    /// Number of WS2812 LEDs powered by this driver.
+    const NUM_LEDS: usize;
+}
+
+/// A trait that keyboards must implement to use the WS2812 driver for backlighting.
+pub trait WS2812SpiBacklightMatrixDriver {
+    /// Convert matrix coordinates in the form of (col, row) to a WS2812 LED index.
+    ///
+    /// It is recommended to use [`get_led_from_matrix_coordinates`] to implement this
+    /// function.
+    fn get_led_from_matrix_coordinates(x: u8, y: u8) -> Option<u8>;
+}
+
+#[cfg(feature = "underglow")]
+impl<E: Ws2812SpiEncoding, S: SpiBus, K: crate::lighting::underglow::UnderglowDevice>
+    crate::lighting::underglow::UnderglowDriver<K> for Ws2812Spi<E, S>
+where
+    [(); K::NUM_LEDS]:,
+{
+    type DriverWriteError = S::Error;
+
+    async fn write(
+        &mut self,
+        colors: impl Iterator<Item = RGB8>,
+    ) -> Result<(), Self::DriverWriteError> {
+        self.write_colors(colors).await
+    }
+
+    type DriverEnableError = ();
+
+    async fn turn_on(&mut self) -> Result<(), Self::DriverEnableError> {
+        // Don't need to do anything special, just let the next tick() get called.
+        Ok(())
+    }
+
+    type DriverDisableError = S::Error;
+
+    async fn turn_off(&mut self) -> Result<(), Self::DriverDisableError> {
+        self.write_colors([(0, 0, 0).into(); { K::NUM_LEDS }].iter().cloned())
+            .await
+    }
+}
+
+#[cfg(feature = "simple-backlight")]
+impl<
+        E: Ws2812SpiEncoding,
+        S: SpiBus,
+        K: WS2812SpiSimpleBacklightDriver + crate::lighting::simple_backlight::SimpleBacklightDevice,
+    > crate::lighting::simple_backlight::SimpleBacklightDriver<K> for Ws2812Spi<E, S>
+where
+    [(); K::NUM_LEDS]:,
+{
+    type DriverWriteError = S::Error;
+
+    async fn write(&mut self, brightness: u8) -> Result<(), Self::DriverWriteError> {
+        let brightnesses = [(brightness, brightness, brightness).into(); K::NUM_LEDS];
+
+        self.write_colors(brightnesses.iter().cloned()).await
+    }
+
+    type DriverEnableError = ();
+
+    async fn turn_on(&mut self) -> Result<(), Self::DriverEnableError> {
+        // Don't need to do anything special, just let the next tick() get called.
+        Ok(())
+    }
+
+    type DriverDisableError = S::Error;
+
+    async fn turn_off(&mut self) -> Result<(), Self::DriverDisableError> {
+        self.write_colors([(0, 0, 0).into(); K::NUM_LEDS].iter().cloned())
+            .await
+    }
+}
+
+#[cfg(feature = "simple-backlight-matrix")]
+impl<
+        E: Ws2812SpiEncoding,
+        S: SpiBus,
+        K: WS2812SpiBacklightMatrixDriver
+            + crate::lighting::simple_backlight_matrix::SimpleBacklightMatrixDevice,
+    > crate::lighting::simple_backlight_matrix::SimpleBacklightMatrixDriver<K> for Ws2812Spi<E, S>
+where
+    [(); K::LIGHTING_ROWS * K::LIGHTING_COLS]:,
+{
+    type DriverWriteError = S::Error;
+
+    async fn write(
+        &mut self,
+        buf: &[[u8; K::LIGHTING_COLS]; K::LIGHTING_ROWS],
+    ) -> Result<(), Self::DriverWriteError> {
+        let mut brightnesses = [RGB8::default(); { K::LIGHTING_ROWS * K::LIGHTING_COLS }];
+
+        for (row_num, row) in buf.iter().enumerate() {
+            for (col_num, val) in row.iter().enumerate() {
+                if let Some(offset) =
+                    K::get_led_from_matrix_coordinates(col_num as u8, row_num as u8)
+                {
+                    brightnesses[offset as usize] = (*val, *val, *val).into();
+                }
+            }
+        }
+
+        self.write_colors(brightnesses.iter().cloned()).await
+    }
+
+    type DriverEnableError = ();
+
+    async fn turn_on(&mut self) -> Result<(), Self::DriverEnableError> {
+        // Don't need to do anything special, just let the next tick() get called.
+        Ok(())
+    }
+
+    type DriverDisableError = S::Error;
+
+    async fn turn_off(&mut self) -> Result<(), Self::DriverDisableError> {
+        self.write_colors(
+            [(0, 0, 0).into(); { K::LIGHTING_ROWS * K::LIGHTING_COLS }]
+                .iter()
+                .cloned(),
+        )
+        .await
+    }
+}
+
+#[cfg(feature = "rgb-backlight-matrix")]
+impl<
+        E: Ws2812SpiEncoding,
+        S: SpiBus,
+        K: WS2812SpiBacklightMatrixDriver
+            + crate::lighting::rgb_backlight_matrix::RGBBacklightMatrixDevice,
+    > crate::lighting::rgb_backlight_matrix::RGBBacklightMatrixDriver<K> for Ws2812Spi<E, S>
+where
+    [(); K::LIGHTING_ROWS * K::LIGHTING_COLS]:,
+{
+    type DriverWriteError = S::Error;
+
+    async fn write(
+        &mut self,
+        buf: &[[RGB8; K::LIGHTING_COLS]; K::LIGHTING_ROWS],
+    ) -> Result<(), Self::DriverWriteError> {
+        let mut colors = [RGB8::default(); { K::LIGHTING_ROWS * K::LIGHTING_COLS }];
+
+        for (row_num, row) in buf.iter().enumerate() {
+            for (col_num, val) in row.iter().enumerate() {
+                if let Some(offset) =
+                    K::get_led_from_matrix_coordinates(col_num as u8, row_num as u8)
+                {
+                    colors[offset as usize] = *val;
+                }
+            }
+        }
+
+        self.write_colors(colors.iter().cloned()).await
+    }
+
+    type DriverEnableError = ();
+
+    async fn turn_on(&mut self) -> Result<(), Self::DriverEnableError> {
+        // Don't need to do anything special, just let the next tick() get called.
+        Ok(())
+    }
+
+    type DriverDisableError = S::Error;
+
+    async fn turn_off(&mut self) -> Result<(), Self::DriverDisableError> {
+        self.write_colors(
+            [(0, 0, 0).into(); { K::LIGHTING_ROWS * K::LIGHTING_COLS }]
+                .iter()
+                .cloned(),
+        )
+        .await
+    }
+}
@@ -0,0 +1,189 @@
+//! Dynamic macro feature.
+//!
+//! Dynamic macros are recorded on the keyboard itself, without using Via. After starting a
+//! recording with [`DynamicMacroCommand::StartRecording`], the keycodes that get pressed and
+//! released are saved to the macro's slot, until [`DynamicMacroCommand::StopRecording`] is used.
+//! The macro can then be played back with [`DynamicMacroCommand::Play`], which uses keyberon's
+//! [`Action::Sequence`].
+//!
+//! The keycodes are recorded after they have been processed by the layout, so layers, combos and
+//! other rumcake features are taken into account. The time between key presses is not recorded.
+//! Recorded macros are only stored in RAM, and are lost when the keyboard loses power.
+
+use core::ptr::{addr_of, addr_of_mut};
+
+use defmt::{debug, warn};
+use heapless::Vec;
+use keyberon::action::Action;
+use keyberon::layout::{Event, Layout as KeyberonLayout};
+use usbd_human_interface_device::page::Keyboard as KeyboardKeycode;
+
+use crate::keyboard::Keycode;
+
+/// Number of dynamic macros that can be recorded.
+pub const DYNAMIC_MACRO_SLOTS: usize = 2;
+
+/// Number of bytes available to each dynamic macro slot. Each key press or release uses 3 bytes,
+/// so a slot can hold up to 64 key taps.
+pub const DYNAMIC_MACRO_BUFFER_SIZE: usize = 384;
+
+/// Row used for the virtual keys that get pressed to play a dynamic macro. Column 0 of this row is
+/// used by the leader key feature, so dynamic macros use the columns after it.
+const DYNAMIC_MACRO_ROW: u8 = u8::MAX - 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+#[repr(u8)]
+/// An enumeration of possible commands that will be processed by the dynamic macro recorder.
+pub enum DynamicMacroCommand {
+    /// Start recording the macro in the given slot, from `0` to [`DYNAMIC_MACRO_SLOTS`] - 1. If a
+    /// macro is already being recorded, the recording is stopped instead.
+    StartRecording(u8) = 0,
+    /// Stop recording, and save the recorded macro to its slot.
+    StopRecording = 1,
+    /// Play the macro in the given slot. This does nothing while a macro is being recorded.
+    Play(u8) = 2,
+}
+
+static mut DYNAMIC_MACRO_BUFFERS: [[u8; DYNAMIC_MACRO_BUFFER_SIZE]; DYNAMIC_MACRO_SLOTS] =
+    [[0; DYNAMIC_MACRO_BUFFER_SIZE]; DYNAMIC_MACRO_SLOTS];
+static mut DYNAMIC_MACRO_SEQUENCES: [&[u8]; DYNAMIC_MACRO_SLOTS] = [&[]; DYNAMIC_MACRO_SLOTS];
+
+struct Recording {
+    slot: u8,
+    data: Vec<u8, DYNAMIC_MACRO_BUFFER_SIZE>,
+    /// Keycodes that were pressed the last time the report was recorded. This is `None` until the
+    /// first report after the recording started, so that keys that were already held (like the
+    /// record key itself) are not recorded.
+    last_keys: Option<Vec<KeyboardKeycode, 24>>,
+}
+
+#[derive(Default)]
+pub(crate) struct DynamicMacroState {
+    recording: Option<Recording>,
+    release_pending: Option<u8>,
+}
+
+impl DynamicMacroState {
+    /// Returns `true` if the virtual key of a played macro still needs to be released.
+    pub(crate) fn is_pending(&self) -> bool {
+        self.release_pending.is_some()
+    }
+
+    pub(crate) fn command<const C: usize, const R: usize, const L: usize>(
+        &mut self,
+        layout: &mut KeyberonLayout<C, R, L, Keycode>,
+        command: DynamicMacroCommand,
+    ) {
+        match command {
+            DynamicMacroCommand::StartRecording(slot) => {
+                if self.recording.is_some() {
+                    self.stop();
+                    return;
+                }
+
+                if slot as usize >= DYNAMIC_MACRO_SLOTS {
+                    warn!("[DYNAMIC_MACRO] Invalid dynamic macro slot: {}", slot);
+                    return;
+                }
+
+                debug!("[DYNAMIC_MACRO] Recording dynamic macro {}", slot);
+                self.recording = Some(Recording {
+                    slot,
+                    data: Vec::new(),
+                    last_keys: None,
+                });
+            }
+            DynamicMacroCommand::StopRecording => self.stop(),
+            DynamicMacroCommand::Play(slot) => {
+                if self.recording.is_some() || self.release_pending.is_some() {
+                    return;
+                }
+
+                if slot as usize >= DYNAMIC_MACRO_SLOTS {
+                    warn!("[DYNAMIC_MACRO] Invalid dynamic macro slot: {}", slot);
+                    return;
+                }
+
+                // SAFETY: the sequences are only accessed by the layout task
+                let sequence: &'static &'static [u8] =
+                    unsafe { &*addr_of!(DYNAMIC_MACRO_SEQUENCES[slot as usize]) };
+                debug!("[DYNAMIC_MACRO] Playing dynamic macro {}", slot);
+                layout.event_with_action(
+                    Event::Press(DYNAMIC_MACRO_ROW, slot + 1),
+                    Action::Sequence(sequence),
+                );
+                self.release_pending = Some(slot);
+            }
+        }
+    }
+
+    fn stop(&mut self) {
+        let Some(recording) = self.recording.take() else {
+            return;
+        };
+
+        debug!(
+            "[DYNAMIC_MACRO] Saved dynamic macro {} ({} bytes)",
+            recording.slot,
+            recording.data.len()
+        );
+
+        let slot = recording.slot as usize;
+        let len = recording.data.len();
+
+        // SAFETY: the buffers and sequences are only accessed by the layout task. A sequence that
+        // is currently being played back will continue with the new contents of the buffer.
+        unsafe {
+            (*addr_of_mut!(DYNAMIC_MACRO_BUFFERS))[slot][..len].copy_from_slice(&recording.data);
+            DYNAMIC_MACRO_SEQUENCES[slot] = &(*addr_of!(DYNAMIC_MACRO_BUFFERS))[slot][..len];
+        }
+    }
+
+    /// Record the keycodes that are about to be sent to the host, if a macro is being recorded.
+    pub(crate) fn record(&mut self, keys: &[KeyboardKeycode]) {
+        let Some(recording) = &mut self.recording else {
+            return;
+        };
+
+        let Some(last_keys) = &mut recording.last_keys else {
+            recording.last_keys = Some(Vec::from_slice(keys).unwrap_or_default());
+            return;
+        };
+
+        let released = last_keys
+            .iter()
+            .filter(|k| !keys.contains(*k))
+            .map(|k| (3, *k));
+        let pressed = keys
+            .iter()
+            .filter(|k| !last_keys.contains(*k))
+            .map(|k| (2, *k));
+
+        let full = released.chain(pressed).any(|(kind, key)| {
+            recording
+                .data
+                .extend_from_slice(&[1, kind, key as u8])
+                .is_err()
+        });
+
+        last_keys.clear();
+        last_keys.extend_from_slice(keys).ok();
+
+        if full {
+            warn!("[DYNAMIC_MACRO] Dynamic macro buffer is full, stopping recording");
+            self.stop();
+        }
+    }
+
+    /// Release the virtual key of a played macro. This should be called before processing new
+    /// events.
+    pub(crate) fn tick<const C: usize, const R: usize, const L: usize>(
+        &mut self,
+        layout: &mut KeyberonLayout<C, R, L, Keycode>,
+    ) {
+        if let Some(slot) = self.release_pending.take() {
+            layout.event(Event::Release(DYNAMIC_MACRO_ROW, slot + 1));
+        }
+    }
+}
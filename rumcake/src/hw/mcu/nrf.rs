@@ -25,8 +25,8 @@ use embassy_sync::mutex::Mutex;
 use embassy_sync::signal::Signal;
 use embassy_time::{Duration, Timer};
 
-use crate::hw::BATTERY_LEVEL_STATE;
-use crate::keyboard::MatrixSampler;
+use crate::hw::{battery_level_from_voltage, BatteryDevice, BATTERY_LEVEL_STATE};
+use crate::keyboard::{Keyboard, MatrixSampler};
 
 pub use rumcake_macros::{
     nrf_input_pin as input_pin, nrf_output_pin as output_pin,
@@ -34,6 +34,9 @@ pub use rumcake_macros::{
     nrf_setup_i2c as setup_i2c,
 };
 
+#[cfg(feature = "audio")]
+pub use rumcake_macros::nrf_setup_i2s as setup_i2s;
+
 pub use embassy_nrf;
 
 #[cfg(feature = "nrf-ble")]
@@ -88,9 +91,9 @@ pub fn setup_usb_driver<'a, K: crate::usb::USBKeyboard + 'a>(
         embassy_nrf::interrupt::POWER_CLOCK.set_priority(embassy_nrf::interrupt::Priority::P2);
 
         let mut config = embassy_usb::Config::new(K::USB_VID, K::USB_PID);
-        config.manufacturer.replace(K::MANUFACTURER);
-        config.product.replace(K::PRODUCT);
-        config.serial_number.replace(K::SERIAL_NUMBER);
+        config.manufacturer.replace(K::get_usb_manufacturer());
+        config.product.replace(K::get_usb_product());
+        config.serial_number.replace(K::get_usb_serial_number());
         config.max_power = 100;
 
         #[cfg(feature = "nrf-ble")]
@@ -143,11 +146,6 @@ impl crate::storage::FlashStorage for Flash {
         use embedded_storage::nor_flash::ReadNorFlash;
         self.flash.read(offset, bytes)
     }
-
-    fn blocking_read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
-        use embedded_storage::nor_flash::ReadNorFlash;
-        self.flash.read(offset, bytes)
-    }
 }
 
 /// Construct an instance of [`Flash`]. This usually needs to be passed to
@@ -166,6 +164,72 @@ pub fn setup_internal_softdevice_flash(sd: &nrf_softdevice::Softdevice) -> nrf_s
     nrf_softdevice::Flash::take(sd)
 }
 
+#[cfg(feature = "audio")]
+/// An audio driver that plays samples using the I2S peripheral. Samples are transferred to the
+/// peripheral using EasyDMA, double-buffered with `NS` samples in each buffer. `SR` is the sample
+/// rate that the I2S peripheral was configured with, which must match
+/// [`crate::audio::AudioDevice::SAMPLE_RATE`]. It is recommended to use [`setup_i2s`] to create
+/// this driver.
+pub struct I2SAudioDriver<'d, T: embassy_nrf::i2s::Instance, const NS: usize, const SR: u32> {
+    stream: embassy_nrf::i2s::OutputStream<'d, T, i16, 2, NS>,
+    started: bool,
+}
+
+#[cfg(feature = "audio")]
+impl<'d, T: embassy_nrf::i2s::Instance, const NS: usize, const SR: u32>
+    I2SAudioDriver<'d, T, NS, SR>
+{
+    /// Create a new driver from an I2S output stream.
+    pub fn new(stream: embassy_nrf::i2s::OutputStream<'d, T, i16, 2, NS>) -> Self {
+        Self {
+            stream,
+            started: false,
+        }
+    }
+}
+
+#[cfg(feature = "audio")]
+impl<
+        K: crate::audio::AudioDevice,
+        T: embassy_nrf::i2s::Instance,
+        const NS: usize,
+        const SR: u32,
+    > crate::audio::AudioDriver<K> for I2SAudioDriver<'_, T, NS, SR>
+{
+    type DriverWriteError = embassy_nrf::i2s::Error;
+
+    async fn write(&mut self, buf: &[i16]) -> Result<(), Self::DriverWriteError> {
+        const {
+            assert!(
+                K::SAMPLE_RATE == SR,
+                "The I2S sample rate must match `AudioDevice::SAMPLE_RATE`"
+            )
+        };
+
+        for chunk in buf.chunks(NS) {
+            let out = self.stream.buffer();
+            out[..chunk.len()].copy_from_slice(chunk);
+            out[chunk.len()..].fill(0);
+
+            if self.started {
+                self.stream.send().await?;
+            } else {
+                self.stream.start().await?;
+                self.started = true;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn stop(&mut self) {
+        if self.started {
+            self.stream.stop().await;
+            self.started = false;
+        }
+    }
+}
+
 pub type AdcSampleType = i16;
 
 /// Different types of analog pins.
@@ -194,6 +258,8 @@ where
 {
     idx_to_pin_type: BlockingMutex<RefCell<[AnalogPinType<'a, MP>; C]>>,
     adc_sampler: Mutex<RawMutex, RawAdcSampler<'a, TIM, PPI0, PPI1, C>>,
+    #[cfg(feature = "scan-metrics")]
+    sample_times: crate::scan_metrics::SampleTimes<C>,
 }
 
 struct RawAdcSampler<'a, TIM, PPI0, PPI1, const C: usize>
@@ -259,6 +325,8 @@ where
                     ppi_ch1,
                 })
             },
+            #[cfg(feature = "scan-metrics")]
+            sample_times: crate::scan_metrics::SampleTimes::new(),
         }
     }
 
@@ -278,6 +346,9 @@ where
 
         let mut bufs = [[[0; C + 1]; 1]; 2];
 
+        #[cfg(feature = "scan-metrics")]
+        let mut last_sample = embassy_time::Instant::now();
+
         // sample acquisition time: 3 microseconds (based on default saadc::Config)
         // sample conversion time: 2 microseconds (worst case, based on datasheet)
         // 1/(tacq + tconv): 200kHz
@@ -294,6 +365,16 @@ where
             30,
             &mut bufs,
             move |buf| {
+                // All channels are sampled at the same time, so they all share the same sample time
+                #[cfg(feature = "scan-metrics")]
+                {
+                    let now = embassy_time::Instant::now();
+                    for ch in 0..C {
+                        self.sample_times.record(ch, now - last_sample);
+                    }
+                    last_sample = now;
+                }
+
                 let buf = buf[0];
                 self.idx_to_pin_type.lock(|pin_types| {
                     let mut pin_types = pin_types.borrow_mut();
@@ -339,6 +420,14 @@ where
                 .map(|value| (value - i16::MIN) as u16)
         })
     }
+
+    /// Get the time between the two latest samples of the analog pin at index `ch`. All analog
+    /// pins are sampled at the same time, so this is the same for every pin. Requires the
+    /// `scan-metrics` feature.
+    #[cfg(feature = "scan-metrics")]
+    pub fn get_sample_time(&self, ch: usize) -> Option<Duration> {
+        self.sample_times.get(ch)
+    }
 }
 
 impl<
@@ -362,7 +451,8 @@ where
 
 static BAT_SAMPLE_CHANNEL: Signal<RawMutex, AdcSampleType> = Signal::new();
 
-pub async fn adc_task<'a, const MP: usize, const N: usize>(
+pub async fn adc_task<'a, K: Keyboard, const MP: usize, const N: usize>(
+    _k: K,
     sampler: &AdcSampler<
         'a,
         impl Instance,
@@ -374,22 +464,33 @@ pub async fn adc_task<'a, const MP: usize, const N: usize>(
 ) where
     [(); N + 1]:,
     [(); 2_usize.pow(MP as u32)]:,
+    [(); <K::BatteryDeviceType as BatteryDevice>::BATTERY_FILTER_WINDOW]:,
 {
     let adc_fut = sampler.run_sampler();
 
     let bat_fut = async {
+        // Moving average of the latest voltage readings
+        let mut readings = [0; <K::BatteryDeviceType as BatteryDevice>::BATTERY_FILTER_WINDOW];
+        let mut next = 0;
+        let mut count = 0;
+
         loop {
             let sample = BAT_SAMPLE_CHANNEL.wait().await;
-            let mv = sample * 5;
 
-            let pct = if mv >= 4200 {
-                100
-            } else if mv <= 3450 {
-                0
+            if !readings.is_empty() {
+                readings[next] = sample * 5;
+                next = (next + 1) % readings.len();
+                count = (count + 1).min(readings.len());
+            }
+
+            let mv = if count > 0 {
+                (readings[..count].iter().map(|mv| *mv as u32).sum::<u32>() / count as u32) as u16
             } else {
-                (mv * 2 / 15 - 459) as u8
+                sample * 5
             };
 
+            let pct = battery_level_from_voltage::<K::BatteryDeviceType>(mv);
+
             BATTERY_LEVEL_STATE.set(pct).await;
 
             Timer::after(Duration::from_secs(10)).await;
@@ -23,7 +23,7 @@ use embassy_sync::blocking_mutex::ThreadModeMutex;
 pub use rumcake_macros::{
     rp_input_pin as input_pin, rp_output_pin as output_pin,
     rp_setup_adc_sampler as setup_adc_sampler, rp_setup_buffered_uart as setup_buffered_uart,
-    rp_setup_i2c as setup_i2c,
+    rp_setup_i2c as setup_i2c, rp_setup_spi_txonly as setup_spi_txonly,
 };
 
 pub use embassy_rp;
@@ -72,9 +72,9 @@ pub fn setup_usb_driver<'a, K: crate::usb::USBKeyboard>(
         );
 
         let mut config = embassy_usb::Config::new(K::USB_VID, K::USB_PID);
-        config.manufacturer.replace(K::MANUFACTURER);
-        config.product.replace(K::PRODUCT);
-        config.serial_number.replace(K::SERIAL_NUMBER);
+        config.manufacturer.replace(K::get_usb_manufacturer());
+        config.product.replace(K::get_usb_product());
+        config.serial_number.replace(K::get_usb_serial_number());
         config.max_power = 500;
 
         let usb_driver = Driver::new(USB::steal(), Irqs);
@@ -107,6 +107,8 @@ pub type AdcSampleType = u16;
 /// keyboard matrix.
 pub struct AdcSampler<'a, const MP: usize, const C: usize> {
     adc_sampler: BlockingMutex<RefCell<RawAdcSampler<'a, MP, C>>>,
+    #[cfg(feature = "scan-metrics")]
+    sample_times: crate::scan_metrics::SampleTimes<C>,
 }
 
 struct RawAdcSampler<'a, const MP: usize, const C: usize> {
@@ -134,6 +136,8 @@ impl<'a, const MP: usize, const C: usize> AdcSampler<'a, MP, C> {
                 channels: analog_pins,
                 adc,
             })),
+            #[cfg(feature = "scan-metrics")]
+            sample_times: crate::scan_metrics::SampleTimes::new(),
         }
     }
 
@@ -142,7 +146,10 @@ impl<'a, const MP: usize, const C: usize> AdcSampler<'a, MP, C> {
     /// multiplexed, the `sub_ch` argument is used to determine which multiplexer channel to sample
     /// from. Otherwise, the `sub_ch` argument is ignored.
     pub fn get_sample(&self, ch: usize, sub_ch: usize) -> Option<AdcSampleType> {
-        self.adc_sampler.lock(|adc_sampler| {
+        #[cfg(feature = "scan-metrics")]
+        let start = embassy_time::Instant::now();
+
+        let sample = self.adc_sampler.lock(|adc_sampler| {
             let mut adc_sampler = adc_sampler.borrow_mut();
             let RawAdcSampler {
                 idx_to_pin_type,
@@ -157,7 +164,21 @@ impl<'a, const MP: usize, const C: usize> AdcSampler<'a, MP, C> {
                 }
                 AnalogPinType::Direct => adc.blocking_read(&mut channels[ch]).unwrap(),
             })
-        })
+        });
+
+        #[cfg(feature = "scan-metrics")]
+        if sample.is_some() {
+            self.sample_times.record(ch, start.elapsed());
+        }
+
+        sample
+    }
+
+    /// Get the time that it took to obtain the latest sample from the analog pin at index `ch`.
+    /// Requires the `scan-metrics` feature.
+    #[cfg(feature = "scan-metrics")]
+    pub fn get_sample_time(&self, ch: usize) -> Option<embassy_time::Duration> {
+        self.sample_times.get(ch)
     }
 }
 
@@ -132,9 +132,9 @@ pub fn setup_usb_driver<'a, K: crate::usb::USBKeyboard>(
         );
 
         let mut config = embassy_usb::Config::new(K::USB_VID, K::USB_PID);
-        config.manufacturer.replace(K::MANUFACTURER);
-        config.product.replace(K::PRODUCT);
-        config.serial_number.replace(K::SERIAL_NUMBER);
+        config.manufacturer.replace(K::get_usb_manufacturer());
+        config.product.replace(K::get_usb_product());
+        config.serial_number.replace(K::get_usb_serial_number());
         config.max_power = 500;
 
         let usb_driver = Driver::new(USB::steal(), Irqs, PA12::steal(), PA11::steal());
@@ -166,6 +166,8 @@ pub type AdcSampleType = u16;
 /// keyboard matrix.
 pub struct AdcSampler<'a, ADC: Instance, const MP: usize, const C: usize> {
     adc_sampler: BlockingMutex<RefCell<RawAdcSampler<'a, ADC, MP, C>>>,
+    #[cfg(feature = "scan-metrics")]
+    sample_times: crate::scan_metrics::SampleTimes<C>,
 }
 
 struct RawAdcSampler<'a, ADC: Instance, const MP: usize, const C: usize> {
@@ -191,6 +193,8 @@ impl<'a, ADC: Instance, const MP: usize, const C: usize> AdcSampler<'a, ADC, MP,
                 idx_to_pin_type,
                 analog_pins,
             })),
+            #[cfg(feature = "scan-metrics")]
+            sample_times: crate::scan_metrics::SampleTimes::new(),
         }
     }
 
@@ -199,7 +203,10 @@ impl<'a, ADC: Instance, const MP: usize, const C: usize> AdcSampler<'a, ADC, MP,
     /// multiplexed, the `sub_ch` argument is used to determine which multiplexer channel to sample
     /// from. Otherwise, the `sub_ch` argument is ignored.
     pub fn get_sample(&self, ch: usize, sub_ch: usize) -> Option<AdcSampleType> {
-        self.adc_sampler.lock(|adc_sampler| {
+        #[cfg(feature = "scan-metrics")]
+        let start = embassy_time::Instant::now();
+
+        let sample = self.adc_sampler.lock(|adc_sampler| {
             let mut adc_sampler = adc_sampler.borrow_mut();
             let RawAdcSampler {
                 adc,
@@ -214,7 +221,21 @@ impl<'a, ADC: Instance, const MP: usize, const C: usize> AdcSampler<'a, ADC, MP,
                 }
                 AnalogPinType::Direct => block_on(adc.read(&mut analog_pins[ch])),
             })
-        })
+        });
+
+        #[cfg(feature = "scan-metrics")]
+        if sample.is_some() {
+            self.sample_times.record(ch, start.elapsed());
+        }
+
+        sample
+    }
+
+    /// Get the time that it took to obtain the latest sample from the analog pin at index `ch`.
+    /// Requires the `scan-metrics` feature.
+    #[cfg(feature = "scan-metrics")]
+    pub fn get_sample_time(&self, ch: usize) -> Option<embassy_time::Duration> {
+        self.sample_times.get(ch)
     }
 }
 
@@ -351,11 +372,6 @@ impl crate::storage::FlashStorage for Flash {
         use embedded_storage::nor_flash::ReadNorFlash;
         self.flash.read(offset, bytes)
     }
-
-    fn blocking_read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
-        use embedded_storage::nor_flash::ReadNorFlash;
-        self.flash.read(offset, bytes)
-    }
 }
 
 /// Construct an instance of [`Flash`]. This usually needs to be passed to
@@ -15,22 +15,30 @@ compile_error!("Please enable only one chip feature flag.");
 #[cfg_attr(feature = "rp", path = "mcu/rp.rs")]
 pub mod platform;
 
+pub mod shared_i2c;
+
 use crate::hw::platform::jump_to_bootloader;
+use crate::keyboard::Keyboard;
 use crate::State;
 use core::cell::UnsafeCell;
 use core::mem::MaybeUninit;
 use core::ptr::read_volatile;
 use core::ptr::write_volatile;
-use defmt::info;
+use defmt::{info, warn};
 use embassy_futures::select;
 use embassy_sync::channel::Channel;
 use embassy_sync::signal::Signal;
 use embassy_time::Timer;
 use embedded_hal::digital::v2::OutputPin;
+use keyberon::layout::Event;
+use postcard::experimental::max_size::MaxSize;
+use serde::{Deserialize, Serialize};
 
 use platform::RawMutex;
 use usbd_human_interface_device::device::consumer::MultipleConsumerReport;
 use usbd_human_interface_device::device::keyboard::NKROBootKeyboardReport;
+#[cfg(feature = "pointer")]
+use usbd_human_interface_device::device::mouse::WheelMouseReport;
 
 /// State that contains the current battery level. `rumcake` may or may not use this static
 /// internally, depending on what MCU is being used. The contents of this state is usually set by a
@@ -43,16 +51,108 @@ pub static BATTERY_LEVEL_STATE: State<u8> = State::new(
         &crate::display::BATTERY_LEVEL_LISTENER,
         #[cfg(feature = "bluetooth")]
         &crate::bluetooth::BATTERY_LEVEL_LISTENER,
+        #[cfg(all(feature = "split-central", feature = "display"))]
+        &crate::split::central::DISPLAY_STATE_LISTENER,
     ],
 );
 
+/// State that contains the last battery level reported by a peripheral device in a split keyboard
+/// setup. This is set by the [`central_task`](crate::split::central::central_task) when a
+/// [`MessageToCentral::BatteryLevel`](crate::split::MessageToCentral::BatteryLevel) message is
+/// received.
+#[cfg(feature = "split-central")]
+pub static PERIPHERAL_BATTERY_LEVEL_STATE: State<u8> = State::new(
+    100,
+    &[
+        #[cfg(feature = "display")]
+        &crate::display::BATTERY_LEVEL_LISTENER,
+        #[cfg(feature = "bluetooth")]
+        &crate::bluetooth::BATTERY_LEVEL_LISTENER,
+    ],
+);
+
+/// State that contains the last battery level reported by the central device in a split keyboard
+/// setup. This is set by the [`peripheral_task`](crate::split::peripheral::peripheral_task) when a
+/// [`MessageToPeripheral::DisplayState`](crate::split::MessageToPeripheral::DisplayState) message
+/// is received.
+#[cfg(all(feature = "split-peripheral", feature = "display"))]
+pub static CENTRAL_BATTERY_LEVEL_STATE: State<u8> =
+    State::new(100, &[&crate::display::BATTERY_LEVEL_LISTENER]);
+
+/// Get the battery level that should be reported to the user. On split keyboard central devices,
+/// this is the lowest value out of [`BATTERY_LEVEL_STATE`] and [`PERIPHERAL_BATTERY_LEVEL_STATE`].
+/// On peripherals with a display, this is the lowest value out of [`BATTERY_LEVEL_STATE`] and
+/// [`CENTRAL_BATTERY_LEVEL_STATE`]. Otherwise, this is the same as [`BATTERY_LEVEL_STATE`].
+pub async fn get_lowest_battery_level() -> u8 {
+    let level = BATTERY_LEVEL_STATE.get().await;
+
+    #[cfg(feature = "split-central")]
+    let level = level.min(PERIPHERAL_BATTERY_LEVEL_STATE.get().await);
+
+    #[cfg(all(feature = "split-peripheral", feature = "display"))]
+    let level = level.min(CENTRAL_BATTERY_LEVEL_STATE.get().await);
+
+    level
+}
+
+/// A trait that defines how battery voltage readings are converted to a battery level. Set
+/// [`crate::keyboard::Keyboard::BatteryDeviceType`] to your keyboard type after implementing this
+/// trait to change these settings.
+pub trait BatteryDevice {
+    /// Number of battery voltage readings to average when calculating the battery level. Readings
+    /// are taken every 10 seconds. Defaults to `1`, which disables smoothing.
+    const BATTERY_FILTER_WINDOW: usize = 1;
+
+    /// Points on the discharge curve of your battery, as `(millivolts, percentage)` pairs, ordered
+    /// from lowest to highest voltage. The battery level is interpolated between these points.
+    /// Defaults to a linear curve from 3450mV (0%) to 4200mV (100%).
+    const BATTERY_DISCHARGE_CURVE: &'static [(u16, u8)] = &[(3450, 0), (4200, 100)];
+}
+
+/// Default battery settings, used if [`crate::keyboard::Keyboard::BatteryDeviceType`] is not set.
+pub struct DefaultBatteryDevice;
+impl BatteryDevice for DefaultBatteryDevice {}
+
+/// Convert a battery voltage (in millivolts) to a battery level, using
+/// [`BatteryDevice::BATTERY_DISCHARGE_CURVE`].
+pub fn battery_level_from_voltage<B: BatteryDevice>(mv: u16) -> u8 {
+    let curve = B::BATTERY_DISCHARGE_CURVE;
+
+    let (Some(&(min_mv, min_pct)), Some(&(max_mv, max_pct))) = (curve.first(), curve.last()) else {
+        return 100;
+    };
+
+    if mv <= min_mv {
+        return min_pct;
+    }
+
+    if mv >= max_mv {
+        return max_pct;
+    }
+
+    curve
+        .windows(2)
+        .find_map(|points| {
+            let [(low_mv, low_pct), (high_mv, high_pct)] = [points[0], points[1]];
+            (mv < high_mv).then(|| {
+                let progress = (mv - low_mv) as i32;
+                let range = (high_mv - low_mv) as i32;
+                let change = (high_pct as i32 - low_pct as i32) * progress / range;
+                (low_pct as i32 + change) as u8
+            })
+        })
+        .unwrap_or(max_pct)
+}
+
 /// Possible settings used to determine how the firmware will choose the destination for HID
 /// reports.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, MaxSize)]
 #[non_exhaustive]
 pub enum OutputMode {
     Usb,
     Bluetooth,
+    /// Send HID reports over USB if USB is running, otherwise send them over Bluetooth.
+    Auto,
 }
 
 /// State that contains the desired output mode. This configures how the firmware will decide to
@@ -68,6 +168,8 @@ pub static OUTPUT_MODE_STATE: State<OutputMode> = State::new(
         &OUTPUT_MODE_STATE_LISTENER,
         #[cfg(feature = "display")]
         &crate::display::OUTPUT_MODE_STATE_LISTENER,
+        #[cfg(all(feature = "split-central", feature = "display"))]
+        &crate::split::central::DISPLAY_STATE_LISTENER,
     ],
 );
 
@@ -86,10 +188,18 @@ pub static CURRENT_OUTPUT_STATE: State<Option<HIDOutput>> = State::new(
         &crate::usb::KB_CURRENT_OUTPUT_STATE_LISTENER,
         #[cfg(feature = "usb")]
         &crate::usb::CONSUMER_CURRENT_OUTPUT_STATE_LISTENER,
+        #[cfg(all(feature = "usb", feature = "pointer"))]
+        &crate::usb::MOUSE_CURRENT_OUTPUT_STATE_LISTENER,
         #[cfg(all(feature = "usb", feature = "via"))]
         &crate::usb::VIA_CURRENT_OUTPUT_STATE_LISTENER,
+        #[cfg(all(feature = "usb", feature = "raw-hid"))]
+        &crate::usb::RAW_HID_CURRENT_OUTPUT_STATE_LISTENER,
         #[cfg(feature = "bluetooth")]
         &crate::bluetooth::CURRENT_OUTPUT_STATE_LISTENER,
+        #[cfg(feature = "idle")]
+        &crate::idle::IDLE_CURRENT_OUTPUT_STATE_LISTENER,
+        #[cfg(feature = "status-led")]
+        &crate::status_led::STATUS_LED_CURRENT_OUTPUT_STATE_LISTENER,
     ],
 );
 
@@ -118,13 +228,53 @@ pub enum HardwareCommand {
     /// If your keyboard is connected to a USB device, this will **NOT** disconnect your keyboard
     /// from it. It will simply output the HID reports to the connected bluetooth device.
     OutputBluetooth = 2,
+    /// Automatically choose between USB and bluetooth operation. HID reports will be sent to the
+    /// connected USB device if USB is running, otherwise they will be sent to the connected
+    /// bluetooth device.
+    ///
+    /// This will **NOT** disconnect your keyboard from either host device.
+    OutputAuto = 3,
+    /// Switch between NKRO and 6KRO keyboard reports over USB. 6KRO reports can be used for
+    /// BIOS/UEFI screens that don't understand NKRO reports. See
+    /// [`crate::usb::USB_NKRO_STATE`].
+    ToggleNKRO = 4,
+    /// Send NKRO keyboard reports over USB.
+    EnableNKRO = 5,
+    /// Send 6KRO keyboard reports over USB.
+    DisableNKRO = 6,
 }
 
-pub async fn output_switcher() {
+pub async fn output_switcher<K: Keyboard>(_k: K) {
     // This task doesn't need to run if only one of USB or Bluetooth is enabled, and there are no
     // HardwareCommand members on the user's layout.
     let switcher_fut = async {
+        // Last values that were passed to the `Keyboard` callbacks. These start with the same
+        // values as their states.
+        #[cfg(feature = "usb")]
+        let mut last_usb_running = false;
+        #[cfg(feature = "bluetooth")]
+        let mut last_bluetooth_connected = false;
+        let mut last_output = None;
+
         loop {
+            #[cfg(feature = "usb")]
+            {
+                let usb_running = crate::usb::USB_RUNNING_STATE.get().await;
+                if usb_running != last_usb_running {
+                    last_usb_running = usb_running;
+                    K::on_usb_state_change(usb_running).await;
+                }
+            }
+
+            #[cfg(feature = "bluetooth")]
+            {
+                let bluetooth_connected = crate::bluetooth::BLUETOOTH_CONNECTED_STATE.get().await;
+                if bluetooth_connected != last_bluetooth_connected {
+                    last_bluetooth_connected = bluetooth_connected;
+                    K::on_bluetooth_state_change(bluetooth_connected).await;
+                }
+            }
+
             let output = match OUTPUT_MODE_STATE.get().await {
                 #[cfg(feature = "usb")]
                 OutputMode::Usb => {
@@ -142,6 +292,26 @@ pub async fn output_switcher() {
                         None
                     }
                 }
+                OutputMode::Auto => {
+                    #[cfg(feature = "usb")]
+                    let usb_running = crate::usb::USB_RUNNING_STATE.get().await;
+                    #[cfg(not(feature = "usb"))]
+                    let usb_running = false;
+
+                    #[cfg(feature = "bluetooth")]
+                    let bluetooth_connected =
+                        crate::bluetooth::BLUETOOTH_CONNECTED_STATE.get().await;
+                    #[cfg(not(feature = "bluetooth"))]
+                    let bluetooth_connected = false;
+
+                    if usb_running {
+                        Some(HIDOutput::Usb)
+                    } else if bluetooth_connected {
+                        Some(HIDOutput::Bluetooth)
+                    } else {
+                        None
+                    }
+                }
                 #[allow(unreachable_patterns)]
                 _ => None,
             };
@@ -149,6 +319,11 @@ pub async fn output_switcher() {
             CURRENT_OUTPUT_STATE.set(output).await;
             info!("[HW] Output updated: {:?}", defmt::Debug2Format(&output));
 
+            if output != last_output {
+                last_output = output;
+                K::on_output_change(output).await;
+            }
+
             // Wait for a change in state before attempting to update the output again.
             select::select3(
                 USB_RUNNING_STATE_LISTENER.wait(),
@@ -166,6 +341,11 @@ pub async fn output_switcher() {
                     OUTPUT_MODE_STATE.set(match OUTPUT_MODE_STATE.get().await {
                         OutputMode::Usb => OutputMode::Bluetooth,
                         OutputMode::Bluetooth => OutputMode::Usb,
+                        // Switch away from the output that is currently being used
+                        OutputMode::Auto => match CURRENT_OUTPUT_STATE.get().await {
+                            Some(HIDOutput::Usb) => OutputMode::Bluetooth,
+                            _ => OutputMode::Usb,
+                        },
                     });
                 }
                 HardwareCommand::OutputUSB => {
@@ -174,6 +354,25 @@ pub async fn output_switcher() {
                 HardwareCommand::OutputBluetooth => {
                     OUTPUT_MODE_STATE.set(OutputMode::Bluetooth);
                 }
+                HardwareCommand::OutputAuto => {
+                    OUTPUT_MODE_STATE.set(OutputMode::Auto);
+                }
+                #[cfg(feature = "usb")]
+                HardwareCommand::ToggleNKRO => {
+                    crate::usb::USB_NKRO_STATE
+                        .set(!crate::usb::USB_NKRO_STATE.get().await)
+                        .await;
+                }
+                #[cfg(feature = "usb")]
+                HardwareCommand::EnableNKRO => {
+                    crate::usb::USB_NKRO_STATE.set(true).await;
+                }
+                #[cfg(feature = "usb")]
+                HardwareCommand::DisableNKRO => {
+                    crate::usb::USB_NKRO_STATE.set(false).await;
+                }
+                #[allow(unreachable_patterns)]
+                _ => {}
             }
         }
     };
@@ -187,20 +386,60 @@ const BOOTLOADER_MAGIC: u32 = 0xDEADBEEF;
 #[link_section = ".uninit.FLAG"]
 static mut FLAG: UnsafeCell<MaybeUninit<u32>> = UnsafeCell::new(MaybeUninit::uninit());
 
-pub async unsafe fn check_double_tap_bootloader(timeout: u64) {
+/// Jump to the bootloader if the reset button is pressed twice within `timeout` milliseconds.
+///
+/// The second reset must happen at least [`Keyboard::BOOTLOADER_DOUBLE_TAP_MIN_INTERVAL`]
+/// milliseconds after the first boot. If [`Keyboard::BOOTLOADER_DOUBLE_TAP_CONFIRM_KEY`] is set,
+/// that key must also be held during the second boot to confirm the jump to the bootloader.
+pub async unsafe fn check_double_tap_bootloader<K: Keyboard>(_k: K, timeout: u64) {
     if read_volatile(FLAG.get().cast::<u32>()) == BOOTLOADER_MAGIC {
         write_volatile(FLAG.get().cast(), 0);
 
-        jump_to_bootloader();
+        match K::BOOTLOADER_DOUBLE_TAP_CONFIRM_KEY {
+            Some(key) => {
+                if wait_for_confirm_key(key, timeout).await {
+                    jump_to_bootloader();
+                }
+                info!("[HW] Confirmation key was not held, skipping bootloader.");
+            }
+            None => jump_to_bootloader(),
+        }
     }
 
+    // Resets that happen before the minimum interval has passed are ignored
+    Timer::after_millis(K::BOOTLOADER_DOUBLE_TAP_MIN_INTERVAL).await;
+
     write_volatile(FLAG.get().cast(), BOOTLOADER_MAGIC);
 
-    Timer::after_millis(timeout).await;
+    Timer::after_millis(timeout.saturating_sub(K::BOOTLOADER_DOUBLE_TAP_MIN_INTERVAL)).await;
 
     write_volatile(FLAG.get().cast(), 0);
 }
 
+/// Wait for the key at the given layout coordinates to be pressed. Returns `false` if the key was
+/// not pressed within `timeout` milliseconds.
+async fn wait_for_confirm_key((row, col): (u8, u8), timeout: u64) -> bool {
+    let Ok(mut subscriber) = crate::keyboard::MATRIX_EVENTS.subscriber() else {
+        warn!("[HW] Could not subscribe to matrix events to check the confirmation key.");
+        return false;
+    };
+
+    let wait_fut = async {
+        loop {
+            if let Event::Press(pressed_row, pressed_col) = subscriber.next_message_pure().await {
+                if pressed_row == row && pressed_col == col {
+                    break;
+                }
+            }
+        }
+    };
+
+    matches!(
+        select::select(wait_fut, Timer::after_millis(timeout)).await,
+        select::Either::First(())
+    )
+}
+
 extern "C" {
     /// This static value will have an address equal to the `__config_start` address in your
     /// `memory.x` file. You must set this, along with [`__config_end`], if you're using on-chip
@@ -215,10 +454,20 @@ extern "C" {
     pub static __config_end: u32;
 }
 
+/// Maximum number of multiplexers that can be cascaded using [`Multiplexer::new_cascaded`].
+pub const MAX_CASCADED_MULTIPLEXERS: usize = 8;
+
+/// An analog multiplexer, controlled by `P` select pins, which can select between `2^P` channels.
+///
+/// This can also represent multiple multiplexers that share the same select pins and output pin,
+/// where each multiplexer has its own enable pin (see [`Multiplexer::new_cascaded`]). In this
+/// case, `P` also includes the bits used to choose which multiplexer is enabled.
 pub struct Multiplexer<T, const P: usize> {
     cur_channel: u8,
     pins: [Option<T>; P],
     en: Option<T>,
+    /// Number of bits used by the select pins, and the enable pins of each cascaded multiplexer.
+    cascade: Option<(u8, [Option<T>; MAX_CASCADED_MULTIPLEXERS])>,
 }
 
 impl<E, T: OutputPin<Error = E>, const P: usize> Multiplexer<T, P> {
@@ -227,27 +476,85 @@ impl<E, T: OutputPin<Error = E>, const P: usize> Multiplexer<T, P> {
             pins,
             en,
             cur_channel: 0,
+            cascade: None,
+        };
+        let _ = multiplexer.select_channel(0);
+        multiplexer
+    }
+
+    /// Create a set of `M` cascaded multiplexers, which share the same `S` select pins and output
+    /// pin. Each multiplexer must have an active-low enable pin, provided in `enable_pins`.
+    ///
+    /// Channels are numbered consecutively, so with two 16-channel multiplexers, channels `0` to
+    /// `15` belong to the first multiplexer, and channels `16` to `31` belong to the second one.
+    /// `P` must be large enough to fit the select pins, and the bits needed to choose one of the
+    /// `M` multiplexers.
+    pub fn new_cascaded<const S: usize, const M: usize>(
+        select_pins: [Option<T>; S],
+        enable_pins: [T; M],
+    ) -> Self {
+        assert!(
+            M <= MAX_CASCADED_MULTIPLEXERS,
+            "Too many cascaded multiplexers."
+        );
+        assert!(
+            S + M.next_power_of_two().trailing_zeros() as usize <= P,
+            "Not enough bits to select a channel on all of the cascaded multiplexers."
+        );
+
+        let mut select_pins = select_pins.into_iter();
+        let mut enable_pins = enable_pins.into_iter();
+        let mut multiplexer = Self {
+            pins: core::array::from_fn(|_| select_pins.next().flatten()),
+            en: None,
+            cur_channel: 0,
+            cascade: Some((S as u8, core::array::from_fn(|_| enable_pins.next()))),
         };
         let _ = multiplexer.select_channel(0);
         multiplexer
     }
 
-    pub fn select_channel(&mut self, mut channel: u8) -> Result<(), E> {
+    /// Select the given channel. For cascaded multiplexers, this also enables the multiplexer
+    /// that the channel belongs to, and disables the other multiplexers.
+    pub fn select_channel(&mut self, channel: u8) -> Result<(), E> {
+        let mut bits = channel;
         for i in 0..P {
             if let Some(ref mut pin) = self.pins[i] {
-                if channel & 0x01 == 0x01 {
+                if bits & 0x01 == 0x01 {
                     pin.set_high()?;
                 } else {
                     pin.set_low()?;
                 }
             }
-            channel >>= 1;
+            bits >>= 1;
         }
+
+        if let Some((select_bits, ref mut enable_pins)) = self.cascade {
+            let selected = (channel as usize) >> select_bits;
+
+            // Disable the other multiplexers first, so that only one is driving the output
+            for (i, en) in enable_pins.iter_mut().enumerate() {
+                if let Some(en) = en {
+                    if i != selected {
+                        en.set_high()?;
+                    }
+                }
+            }
+
+            if let Some(Some(en)) = enable_pins.get_mut(selected) {
+                en.set_low()?;
+            }
+        }
+
         self.cur_channel = channel;
         Ok(())
     }
 
     pub fn enable(&mut self) -> Result<(), E> {
+        if self.cascade.is_some() {
+            return self.select_channel(self.cur_channel);
+        }
+
         if let Some(ref mut en) = self.en {
             en.set_low()?;
         }
@@ -255,6 +562,12 @@ impl<E, T: OutputPin<Error = E>, const P: usize> Multiplexer<T, P> {
     }
 
     pub fn disable(&mut self) -> Result<(), E> {
+        if let Some((_, ref mut enable_pins)) = self.cascade {
+            for en in enable_pins.iter_mut().flatten() {
+                en.set_high()?;
+            }
+        }
+
         if let Some(ref mut en) = self.en {
             en.set_high()?;
         }
@@ -262,6 +575,21 @@ impl<E, T: OutputPin<Error = E>, const P: usize> Multiplexer<T, P> {
     }
 }
 
+/// A trait that drivers can implement to reduce their power usage while the keyboard is idle.
+///
+/// If the `idle` feature is enabled, the task that owns the driver calls
+/// [`PowerManaged::enter_low_power`] when the keyboard goes idle, and
+/// [`PowerManaged::exit_low_power`] when it wakes up (see [`crate::idle::IDLE_STATE`]). By
+/// default, both of these do nothing.
+pub trait PowerManaged {
+    /// Put the driver into a low power state, e.g. by turning off the peripheral it controls.
+    async fn enter_low_power(&mut self) {}
+
+    /// Restore the driver to the state it was in before [`PowerManaged::enter_low_power`] was
+    /// called.
+    async fn exit_low_power(&mut self) {}
+}
+
 pub trait HIDDevice {
     fn get_keyboard_report_send_channel() -> &'static Channel<RawMutex, NKROBootKeyboardReport, 1> {
         static KEYBOARD_REPORT_HID_SEND_CHANNEL: Channel<RawMutex, NKROBootKeyboardReport, 1> =
@@ -275,6 +603,13 @@ pub trait HIDDevice {
         &CONSUMER_REPORT_HID_SEND_CHANNEL
     }
 
+    #[cfg(feature = "pointer")]
+    fn get_mouse_report_send_channel() -> &'static Channel<RawMutex, WheelMouseReport, 1> {
+        static MOUSE_REPORT_HID_SEND_CHANNEL: Channel<RawMutex, WheelMouseReport, 1> =
+            Channel::new();
+        &MOUSE_REPORT_HID_SEND_CHANNEL
+    }
+
     #[cfg(feature = "via")]
     fn get_via_hid_send_channel() -> &'static Channel<RawMutex, [u8; 32], 1> {
         static VIA_REPORT_HID_SEND_CHANNEL: Channel<RawMutex, [u8; 32], 1> = Channel::new();
@@ -286,4 +621,16 @@ pub trait HIDDevice {
         static VIA_REPORT_HID_RECEIVE_CHANNEL: Channel<RawMutex, [u8; 32], 1> = Channel::new();
         &VIA_REPORT_HID_RECEIVE_CHANNEL
     }
+
+    #[cfg(feature = "raw-hid")]
+    fn get_raw_hid_send_channel() -> &'static Channel<RawMutex, [u8; 32], 1> {
+        static RAW_HID_REPORT_SEND_CHANNEL: Channel<RawMutex, [u8; 32], 1> = Channel::new();
+        &RAW_HID_REPORT_SEND_CHANNEL
+    }
+
+    #[cfg(feature = "raw-hid")]
+    fn get_raw_hid_receive_channel() -> &'static Channel<RawMutex, [u8; 32], 1> {
+        static RAW_HID_REPORT_RECEIVE_CHANNEL: Channel<RawMutex, [u8; 32], 1> = Channel::new();
+        &RAW_HID_REPORT_RECEIVE_CHANNEL
+    }
 }
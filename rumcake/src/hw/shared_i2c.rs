@@ -0,0 +1,36 @@
+//! Utilities for sharing an I2C bus between multiple drivers.
+//!
+//! Drivers usually take ownership of the I2C peripheral that is passed to them, so two drivers
+//! can't normally be connected to the same I2C bus (e.g. a display, and a pointing device). To get
+//! around this, the I2C peripheral can be placed in a [`SharedI2cBus`], which is stored in a
+//! `static`. Each driver can then be given its own [`SharedI2cDevice`], which locks the bus for
+//! the duration of each transaction.
+//!
+//! [`SharedI2cDevice`] implements the blocking I2C traits from `embedded-hal`, so it can be used
+//! with the display drivers (e.g. [`setup_ssd1306`](crate::drivers::ssd1306::setup_ssd1306) and
+//! [`setup_sh1106`](crate::drivers::sh1106::setup_sh1106) using the `i2c_bus` argument).
+
+use core::cell::RefCell;
+
+use embassy_embedded_hal::shared_bus::blocking::i2c::I2cDevice;
+use embassy_sync::blocking_mutex::Mutex;
+
+use super::platform::RawMutex;
+
+/// An I2C bus that can be shared between multiple drivers. This should be stored in a `static`
+/// (for example, using an [`embassy_sync::once_lock::OnceLock`]), so that a [`SharedI2cDevice`]
+/// can be created for each driver using [`shared_i2c_device`].
+pub type SharedI2cBus<T> = Mutex<RawMutex, RefCell<T>>;
+
+/// A handle to a [`SharedI2cBus`] that can be passed to a driver.
+pub type SharedI2cDevice<T> = I2cDevice<'static, RawMutex, T>;
+
+/// Create a new [`SharedI2cBus`] using the provided I2C peripheral.
+pub const fn new_shared_i2c_bus<T>(i2c: T) -> SharedI2cBus<T> {
+    Mutex::new(RefCell::new(i2c))
+}
+
+/// Create a new [`SharedI2cDevice`] that can be passed to a driver.
+pub fn shared_i2c_device<T>(bus: &'static SharedI2cBus<T>) -> SharedI2cDevice<T> {
+    I2cDevice::new(bus)
+}
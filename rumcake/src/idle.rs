@@ -0,0 +1,257 @@
+//! Idle mode, for saving power after a period of inactivity.
+//!
+//! [`idle_task`] listens for key events on [`MATRIX_EVENTS`]. If no keys are pressed or released
+//! for [`IdleDevice::IDLE_TIMEOUT`] milliseconds, the keyboard enters idle mode: all lighting
+//! animators are turned off, and the matrix is only scanned every
+//! [`IdleDevice::IDLE_SCAN_INTERVAL`] milliseconds. The next key event wakes the keyboard up, which
+//! restores the scan rate and turns the lighting animators that were on back on.
+//!
+//! By default, the keyboard does not enter idle mode while it is outputting to USB (see
+//! [`CURRENT_OUTPUT_STATE`]), so it keeps full performance while it is powered over USB. The
+//! current idle status is stored in [`IDLE_STATE`].
+//!
+//! Other tasks can also save power while the keyboard is idle. For example, the display task
+//! calls [`PowerManaged::enter_low_power`](crate::hw::PowerManaged::enter_low_power) on the
+//! display driver, and stops drawing until the keyboard wakes up.
+
+use core::cell::Cell;
+
+use defmt::{info, warn};
+use embassy_futures::select::{select, Either};
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Timer};
+
+use crate::hw::platform::{BlockingMutex, RawMutex};
+use crate::hw::{HIDOutput, CURRENT_OUTPUT_STATE};
+use crate::keyboard::{KeyboardLayout, MATRIX_EVENTS};
+use crate::State;
+
+/// A trait that keyboards can implement to configure idle mode.
+pub trait IdleDevice {
+    /// How long the keyboard has to be inactive (no key presses or releases) before it enters
+    /// idle mode, in milliseconds. Defaults to 5 minutes.
+    const IDLE_TIMEOUT: u64 = 300000;
+
+    /// Time between matrix scans while the keyboard is idle, in milliseconds. A higher value
+    /// saves more power, but the first key press after going idle will take longer to register.
+    const IDLE_SCAN_INTERVAL: u64 = 20;
+
+    /// Whether the keyboard should enter idle mode while it is outputting to USB. By default, the
+    /// keyboard keeps full performance while it is connected over USB.
+    const IDLE_ON_USB: bool = false;
+}
+
+/// Default [`IdleDevice`] implementation, which uses all of the default values.
+pub struct DefaultIdleDevice;
+impl IdleDevice for DefaultIdleDevice {}
+
+/// State that contains whether the keyboard is currently in idle mode.
+pub static IDLE_STATE: State<bool> = State::new(
+    false,
+    &[
+        #[cfg(feature = "display")]
+        &crate::display::IDLE_STATE_LISTENER,
+    ],
+);
+
+/// Time between matrix scans while the keyboard is idle. This is `None` while the keyboard is
+/// active, in which case [`crate::keyboard::KeyboardMatrix::MATRIX_SCAN_INTERVAL_US`] is used.
+static IDLE_SCAN_INTERVAL: BlockingMutex<Cell<Option<Duration>>> =
+    BlockingMutex::new(Cell::new(None));
+
+/// Get the time that the matrix polling task should wait between matrix scans while the keyboard
+/// is idle, or `None` if the keyboard is active.
+pub(crate) fn idle_scan_interval() -> Option<Duration> {
+    IDLE_SCAN_INTERVAL.lock(|interval| interval.get())
+}
+
+pub(crate) static IDLE_CURRENT_OUTPUT_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
+
+/// Check whether the keyboard is allowed to be idle with the current output.
+async fn can_idle<K: KeyboardLayout>() -> bool {
+    <K::IdleDeviceType as IdleDevice>::IDLE_ON_USB
+        || !matches!(CURRENT_OUTPUT_STATE.get().await, Some(HIDOutput::Usb))
+}
+
+/// Lighting animators that were turned off when entering idle mode.
+#[derive(Default)]
+struct DisabledLighting {
+    #[cfg(feature = "simple-backlight")]
+    simple_backlight: bool,
+    #[cfg(feature = "simple-backlight-matrix")]
+    simple_backlight_matrix: bool,
+    #[cfg(feature = "rgb-backlight-matrix")]
+    rgb_backlight_matrix: bool,
+    #[cfg(feature = "underglow")]
+    underglow: bool,
+}
+
+impl DisabledLighting {
+    /// Turn off all lighting animators that are currently on.
+    async fn turn_off<K: KeyboardLayout>() -> Self {
+        #[allow(unused_mut)]
+        let mut disabled = Self::default();
+
+        #[cfg(feature = "simple-backlight")]
+        {
+            use crate::lighting::simple_backlight::private::MaybeSimpleBacklightDevice;
+            use crate::lighting::simple_backlight::SimpleBacklightCommand;
+
+            if let (Some(state), Some(channel)) = (
+                K::SimpleBacklightDeviceType::get_state(),
+                K::SimpleBacklightDeviceType::get_command_channel(),
+            ) {
+                if state.get().await.enabled {
+                    channel.send(SimpleBacklightCommand::TurnOff).await;
+                    disabled.simple_backlight = true;
+                }
+            }
+        }
+
+        #[cfg(feature = "simple-backlight-matrix")]
+        {
+            use crate::lighting::simple_backlight_matrix::private::MaybeSimpleBacklightMatrixDevice;
+            use crate::lighting::simple_backlight_matrix::SimpleBacklightMatrixCommand;
+
+            if let (Some(state), Some(channel)) = (
+                K::SimpleBacklightMatrixDeviceType::get_state(),
+                K::SimpleBacklightMatrixDeviceType::get_command_channel(),
+            ) {
+                if state.get().await.enabled {
+                    channel.send(SimpleBacklightMatrixCommand::TurnOff).await;
+                    disabled.simple_backlight_matrix = true;
+                }
+            }
+        }
+
+        #[cfg(feature = "rgb-backlight-matrix")]
+        {
+            use crate::lighting::rgb_backlight_matrix::private::MaybeRGBBacklightMatrixDevice;
+            use crate::lighting::rgb_backlight_matrix::RGBBacklightMatrixCommand;
+
+            if let (Some(state), Some(channel)) = (
+                K::RGBBacklightMatrixDeviceType::get_state(),
+                K::RGBBacklightMatrixDeviceType::get_command_channel(),
+            ) {
+                if state.get().await.enabled {
+                    channel.send(RGBBacklightMatrixCommand::TurnOff).await;
+                    disabled.rgb_backlight_matrix = true;
+                }
+            }
+        }
+
+        #[cfg(feature = "underglow")]
+        {
+            use crate::lighting::underglow::private::MaybeUnderglowDevice;
+            use crate::lighting::underglow::UnderglowCommand;
+
+            if let (Some(state), Some(channel)) = (
+                K::UnderglowDeviceType::get_state(),
+                K::UnderglowDeviceType::get_command_channel(),
+            ) {
+                if state.get().await.enabled {
+                    channel.send(UnderglowCommand::TurnOff).await;
+                    disabled.underglow = true;
+                }
+            }
+        }
+
+        disabled
+    }
+
+    /// Turn the lighting animators that were turned off by [`DisabledLighting::turn_off`] back
+    /// on.
+    async fn turn_on<K: KeyboardLayout>(self) {
+        #[cfg(feature = "simple-backlight")]
+        if self.simple_backlight {
+            use crate::lighting::simple_backlight::private::MaybeSimpleBacklightDevice;
+            use crate::lighting::simple_backlight::SimpleBacklightCommand;
+
+            if let Some(channel) = K::SimpleBacklightDeviceType::get_command_channel() {
+                channel.send(SimpleBacklightCommand::TurnOn).await;
+            }
+        }
+
+        #[cfg(feature = "simple-backlight-matrix")]
+        if self.simple_backlight_matrix {
+            use crate::lighting::simple_backlight_matrix::private::MaybeSimpleBacklightMatrixDevice;
+            use crate::lighting::simple_backlight_matrix::SimpleBacklightMatrixCommand;
+
+            if let Some(channel) = K::SimpleBacklightMatrixDeviceType::get_command_channel() {
+                channel.send(SimpleBacklightMatrixCommand::TurnOn).await;
+            }
+        }
+
+        #[cfg(feature = "rgb-backlight-matrix")]
+        if self.rgb_backlight_matrix {
+            use crate::lighting::rgb_backlight_matrix::private::MaybeRGBBacklightMatrixDevice;
+            use crate::lighting::rgb_backlight_matrix::RGBBacklightMatrixCommand;
+
+            if let Some(channel) = K::RGBBacklightMatrixDeviceType::get_command_channel() {
+                channel.send(RGBBacklightMatrixCommand::TurnOn).await;
+            }
+        }
+
+        #[cfg(feature = "underglow")]
+        if self.underglow {
+            use crate::lighting::underglow::private::MaybeUnderglowDevice;
+            use crate::lighting::underglow::UnderglowCommand;
+
+            if let Some(channel) = K::UnderglowDeviceType::get_command_channel() {
+                channel.send(UnderglowCommand::TurnOn).await;
+            }
+        }
+    }
+}
+
+pub async fn idle_task<K: KeyboardLayout>(_k: K) {
+    let Ok(mut subscriber) = MATRIX_EVENTS.subscriber() else {
+        warn!("[IDLE] Could not subscribe to matrix events. The keyboard will not go idle.");
+        return;
+    };
+
+    let timeout = Duration::from_millis(<K::IdleDeviceType as IdleDevice>::IDLE_TIMEOUT);
+    let idle_scan_interval =
+        Duration::from_millis(<K::IdleDeviceType as IdleDevice>::IDLE_SCAN_INTERVAL);
+
+    loop {
+        // Any key event restarts the timeout
+        if let Either::First(_) =
+            select(subscriber.next_message_pure(), Timer::after(timeout)).await
+        {
+            continue;
+        }
+
+        if !can_idle::<K>().await {
+            continue;
+        }
+
+        info!("[IDLE] Entering idle mode");
+        IDLE_CURRENT_OUTPUT_STATE_LISTENER.reset();
+        let disabled_lighting = DisabledLighting::turn_off::<K>().await;
+        IDLE_SCAN_INTERVAL.lock(|interval| interval.set(Some(idle_scan_interval)));
+        IDLE_STATE.set(true).await;
+
+        // Wake up on the next key event, or if we start outputting to USB
+        loop {
+            match select(
+                subscriber.next_message_pure(),
+                IDLE_CURRENT_OUTPUT_STATE_LISTENER.wait(),
+            )
+            .await
+            {
+                Either::First(_) => break,
+                Either::Second(()) => {
+                    if !can_idle::<K>().await {
+                        break;
+                    }
+                }
+            }
+        }
+
+        info!("[IDLE] Waking up");
+        IDLE_SCAN_INTERVAL.lock(|interval| interval.set(None));
+        IDLE_STATE.set(false).await;
+        disabled_lighting.turn_on::<K>().await;
+    }
+}
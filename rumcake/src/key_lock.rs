@@ -0,0 +1,93 @@
+//! Key lock feature.
+//!
+//! Pressing [`crate::keyboard::Keycode::KeyLock`] arms key lock. The next key that gets pressed
+//! is then locked: its press is sent to the layout as usual, but its release is not, so the key
+//! stays held until it is pressed again. Since the key is held in the layout, this works with any
+//! action, including modifiers and layer keys. For example, locking a layer key keeps the layer
+//! active until the layer key is pressed again.
+//!
+//! Pressing the key lock key again before pressing another key disarms key lock. Up to
+//! [`MAX_LOCKED_KEYS`] keys can be locked at the same time.
+//!
+//! Key lock only changes the events that are sent to the layout. [`crate::keyboard::MATRIX_EVENTS`]
+//! still receives every physical press and release of a locked key, so subscribers always see
+//! balanced presses and releases. The release that is sent to the layout when a key is unlocked
+//! is not published.
+
+use defmt::debug;
+use heapless::Vec;
+use keyberon::action::Action;
+use keyberon::layout::{Event, Layout as KeyberonLayout};
+
+use crate::keyboard::Keycode;
+
+/// Maximum number of keys that can be locked at the same time.
+pub const MAX_LOCKED_KEYS: usize = 8;
+
+#[derive(Default)]
+pub(crate) struct KeyLockState {
+    armed: bool,
+    /// Keys that are being held in the layout, even though they were released.
+    locked: Vec<(u8, u8), MAX_LOCKED_KEYS>,
+    /// Locked keys that were pressed again to unlock them. Their release was already sent to the
+    /// layout, so their physical release is ignored.
+    unlocking: Vec<(u8, u8), MAX_LOCKED_KEYS>,
+}
+
+impl KeyLockState {
+    /// Arm key lock, or disarm it if it was already armed.
+    pub(crate) fn toggle(&mut self) {
+        self.armed = !self.armed;
+        debug!("[KEY_LOCK] Key lock armed: {}", self.armed);
+    }
+
+    /// Process a matrix event. Returns the event if it should be sent to the layout.
+    pub(crate) fn event<const C: usize, const R: usize, const L: usize>(
+        &mut self,
+        layout: &mut KeyberonLayout<C, R, L, Keycode>,
+        event: Event,
+    ) -> Option<Event> {
+        match event {
+            Event::Press(row, col) => {
+                if let Some(pos) = self.locked.iter().position(|key| *key == (row, col)) {
+                    debug!("[KEY_LOCK] Key unlocked");
+                    self.locked.swap_remove(pos);
+                    // Can't fail, since `unlocking` has the same capacity as `locked`, and keys
+                    // are only added to `unlocking` after being removed from `locked`
+                    let _ = self.unlocking.push((row, col));
+                    return Some(Event::Release(row, col));
+                }
+
+                // The key lock key itself disarms key lock instead of getting locked
+                let layer = layout.current_layer();
+                let is_key_lock_key = matches!(
+                    layout.get_action((row, col), layer),
+                    Some(Action::Custom(Keycode::KeyLock))
+                );
+
+                if self.armed && !is_key_lock_key {
+                    self.armed = false;
+                    if self.locked.push((row, col)).is_ok() {
+                        debug!("[KEY_LOCK] Key locked");
+                    } else {
+                        debug!("[KEY_LOCK] Too many keys are locked, not locking key");
+                    }
+                }
+
+                Some(event)
+            }
+            Event::Release(row, col) => {
+                if let Some(pos) = self.unlocking.iter().position(|key| *key == (row, col)) {
+                    self.unlocking.swap_remove(pos);
+                    return None;
+                }
+
+                if self.locked.contains(&(row, col)) {
+                    return None;
+                }
+
+                Some(event)
+            }
+        }
+    }
+}
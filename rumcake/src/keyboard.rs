@@ -12,11 +12,12 @@ use embassy_futures::select::{select, select_array, Either};
 use embassy_sync::channel::Channel;
 use embassy_sync::mutex::Mutex;
 use embassy_sync::pubsub::{PubSubBehavior, PubSubChannel};
-use embassy_time::{Duration, Ticker, Timer};
+use embassy_time::{Duration, Instant, Ticker, Timer};
 use embedded_hal::digital::v2::{InputPin, OutputPin};
 use embedded_hal_async::digital::Wait;
 use heapless::Vec;
-use keyberon::analog::{AnalogActuator, AnalogAcutationMode};
+use keyberon::action::{Action, HoldTapAction, HoldTapConfig, TapDanceAction, TapDanceConfig};
+use keyberon::analog::{AnalogActuator, AnalogAcutationMode, SocdPair, SocdResolver};
 use keyberon::debounce::Debouncer;
 use keyberon::layout::{CustomEvent, Event, Layers, Layout as KeyberonLayout};
 use keyberon::matrix::{AnalogMatrix, DirectPinMatrix, Matrix};
@@ -29,12 +30,15 @@ use usbd_human_interface_device::{
 #[cfg(feature = "media-keycodes")]
 pub use usbd_human_interface_device::page::Consumer;
 
+pub use keyberon::debounce::DebounceAlgorithm;
+
 use crate::hw::platform::RawMutex;
 use crate::hw::{HIDDevice, CURRENT_OUTPUT_STATE};
+use crate::State;
 
 pub use rumcake_macros::{
     build_analog_matrix, build_direct_pin_matrix, build_layout, build_standard_matrix,
-    remap_matrix, setup_encoders,
+    remap_matrix, sequence, setup_combo_buffer, setup_encoders, setup_tap_dance_buffer,
 };
 
 /// Basic keyboard trait that must be implemented to use rumcake. Defines basic keyboard information.
@@ -53,6 +57,35 @@ pub trait Keyboard {
 
     /// Firmware version number for your keyboard.
     const FIRMWARE_REVISION: &'static str = "1";
+
+    /// Minimum amount of time (in milliseconds) that must pass after the first reset, before a
+    /// second reset will be treated as a double tap. Resets that happen too quickly are ignored.
+    /// This is only used if `bootloader_double_tap_reset` is enabled. Defaults to `0`.
+    const BOOTLOADER_DOUBLE_TAP_MIN_INTERVAL: u64 = 0;
+
+    /// A key (in layout coordinates, `(row, col)`) that must be held during the second boot to
+    /// confirm a jump to the bootloader. This is only used if `bootloader_double_tap_reset` is
+    /// enabled. If `None` (default), a double tap will always jump to the bootloader.
+    const BOOTLOADER_DOUBLE_TAP_CONFIRM_KEY: Option<(u8, u8)> = None;
+
+    /// The type that determines how the battery level is calculated. Set this to your keyboard
+    /// type if you want to implement [`crate::hw::BatteryDevice`] yourself. Defaults to
+    /// [`crate::hw::DefaultBatteryDevice`].
+    type BatteryDeviceType: crate::hw::BatteryDevice = crate::hw::DefaultBatteryDevice;
+
+    /// Called when the destination of HID reports ([`crate::hw::CURRENT_OUTPUT_STATE`]) changes,
+    /// with the new destination. By default this does nothing.
+    async fn on_output_change(_output: Option<crate::hw::HIDOutput>) {}
+
+    /// Called when the keyboard connects to, or disconnects from a USB host. `running` is `true`
+    /// if the keyboard is connected. By default this does nothing.
+    #[cfg(feature = "usb")]
+    async fn on_usb_state_change(_running: bool) {}
+
+    /// Called when the keyboard connects to, or disconnects from a Bluetooth host. `connected` is
+    /// `true` if the keyboard is connected. By default this does nothing.
+    #[cfg(feature = "bluetooth")]
+    async fn on_bluetooth_state_change(_connected: bool) {}
 }
 
 /// A trait that must be implemented on a device that communicates with the host device.
@@ -65,7 +98,17 @@ pub trait KeyboardLayout {
         &POLLED_EVENTS_CHANNEL
     }
 
-    const NUM_ENCODERS: usize = 0; // No encoder via support yet. This is the default if not set in QMK
+    /// Number of encoders that can be remapped using Via. This is the default if not set in QMK.
+    const NUM_ENCODERS: usize = 0;
+
+    /// Positions in the layout that each encoder's clockwise and counter-clockwise rotations are
+    /// sent to, as `[cw_pos, ccw_pos]`. These should match the positions returned by
+    /// [`DeviceWithEncoders::get_layout_mappings`], and there should be one entry for each of the
+    /// [`KeyboardLayout::NUM_ENCODERS`] encoders.
+    ///
+    /// When an encoder is remapped using Via, the new action is placed in the layout at the
+    /// corresponding position, so that the encoder task will use it.
+    const ENCODER_MAPPINGS: &'static [[(u8, u8); 2]] = &[];
 
     /// Number of columns in the layout.
     ///
@@ -96,6 +139,60 @@ pub trait KeyboardLayout {
     fn get_original_layout(
     ) -> Layers<{ Self::LAYOUT_COLS }, { Self::LAYOUT_ROWS }, { Self::LAYERS }, Keycode>;
 
+    /// Time between layout ticks, in microseconds. Defaults to `1000`.
+    ///
+    /// The layout processes at most one matrix event per tick, so this limits how often the
+    /// keyboard report can change. Timeouts in the layout (e.g. hold-tap timeouts, tap dances and
+    /// [`KeyboardLayout::COMBO_TIMEOUT`]) are counted in ticks, so they are only in milliseconds
+    /// when the default interval is used. Delays in Via macros are converted to ticks, so they
+    /// are not affected.
+    const LAYOUT_TICK_INTERVAL_US: u64 = 1000;
+
+    /// Maximum amount of time (in milliseconds) that can pass between the first and last key press
+    /// of a combo.
+    const COMBO_TIMEOUT: u64 = 50;
+
+    /// Amount of time (in milliseconds) that [`Keycode::Bootloader`] must be held for before the
+    /// keyboard jumps to the bootloader. Releasing the key earlier does nothing, which helps avoid
+    /// accidental triggers. Defaults to `500`.
+    const BOOTLOADER_KEY_HOLD_TIME: u64 = 500;
+
+    /// Optional tri-layer, as `(lower, upper, adjust)` layer indices. While the `lower` and `upper`
+    /// layers are both active (e.g. by holding both of their layer keys), the `adjust` layer is
+    /// activated. Defaults to `None`, which disables the tri-layer.
+    const TRI_LAYER: Option<(usize, usize, usize)> = None;
+
+    /// Pairs of keys that perform an action when they are both held, as `(key, key, action)`. The
+    /// keys are layout coordinates in the form of (row, col). Defaults to an empty slice.
+    ///
+    /// Unlike combos, the keys can be pressed at any time, in any order. When the second key of a
+    /// pair is pressed, the first key is released, and `action` is pressed instead. Neither key is
+    /// sent to the layout until both keys have been released, and `action` is released as soon as
+    /// one of them is released.
+    const KEY_PAIRS: &'static [((u8, u8), (u8, u8), Action<Keycode>)] = &[];
+
+    /// The number of combos that your keyboard can store. You should use [`setup_combo_buffer`]
+    /// to set this constant.
+    const COMBO_COUNT: usize = 0;
+
+    /// Obtain a reference to the combos used by this layout. You should use
+    /// [`setup_combo_buffer`] to implement this. If this returns `Some`, then
+    /// [`KeyboardLayout::COMBO_COUNT`] should be non-zero.
+    fn get_combo_buffer() -> Option<&'static mut ComboBuffer<{ Self::COMBO_COUNT }>> {
+        None
+    }
+
+    /// The number of tap dances that can be changed while your keyboard is running (e.g. using
+    /// Vial). You should use [`setup_tap_dance_buffer`] to set this constant.
+    const TAP_DANCE_COUNT: usize = 0;
+
+    /// Obtain a reference to the dynamic tap dances used by this layout. You should use
+    /// [`setup_tap_dance_buffer`] to implement this. If this returns `Some`, then
+    /// [`KeyboardLayout::TAP_DANCE_COUNT`] should be non-zero.
+    fn get_tap_dance_buffer() -> Option<&'static mut [TapDanceEntry]> {
+        None
+    }
+
     /// Handle a [`Keycode::User`] event. By default this does nothing.
     ///
     /// `press` is set to `true` if the event was a key press. Otherwise, it will be `false`. `id`
@@ -112,6 +209,63 @@ pub trait KeyboardLayout {
     ) {
     }
 
+    /// Called by the layout task every time the layout is ticked, after the latest matrix event
+    /// has been processed (including combos and leader sequences), but before the layout
+    /// generates keycodes for the next report. By default this does nothing.
+    ///
+    /// This can be used to implement custom behaviour using the [`KeyberonLayout`] API, e.g.
+    /// [`KeyberonLayout::event`] to inject key events, or [`KeyberonLayout::change_action`].
+    /// Injected events are processed in the same tick.
+    ///
+    /// The layout is ticked once for each matrix event, and once every millisecond while actions are
+    /// pending (e.g. tap-hold keys that haven't been resolved yet, or one-shot keys). Otherwise, this
+    /// function is not called until the next matrix event.
+    ///
+    /// This function is called while the layout's mutex is locked, so it must not try to lock
+    /// [`KeyboardLayout::get_layout`] again, and it should return quickly to avoid delaying key
+    /// events. It is never called re-entrantly.
+    fn on_tick(
+        _layout: &mut KeyberonLayout<
+            { Self::LAYOUT_COLS },
+            { Self::LAYOUT_ROWS },
+            { Self::LAYERS },
+            Keycode,
+        >,
+    ) {
+    }
+
+    /// The type that determines how [`Keycode::Unicode`] characters are typed. Set this to your
+    /// keyboard type if you want to implement [`crate::unicode::UnicodeDevice`] yourself. Defaults
+    /// to [`crate::unicode::DefaultUnicodeDevice`].
+    #[cfg(feature = "unicode")]
+    type UnicodeDeviceType: crate::unicode::UnicodeDevice = crate::unicode::DefaultUnicodeDevice;
+
+    /// The type that controls the behaviour of [`Keycode::CapsWord`]. Set this to your keyboard
+    /// type if you want to implement [`crate::caps_word::CapsWordDevice`] yourself. Defaults to
+    /// [`crate::caps_word::DefaultCapsWordDevice`].
+    #[cfg(feature = "caps-word")]
+    type CapsWordDeviceType: crate::caps_word::CapsWordDevice =
+        crate::caps_word::DefaultCapsWordDevice;
+
+    /// The type that defines the sequences used by [`Keycode::Leader`]. Set this to your keyboard
+    /// type after implementing [`crate::leader_key::LeaderKeyDevice`]. Defaults to
+    /// [`crate::leader_key::DefaultLeaderKeyDevice`], which has no sequences.
+    #[cfg(feature = "leader-key")]
+    type LeaderKeyDeviceType: crate::leader_key::LeaderKeyDevice =
+        crate::leader_key::DefaultLeaderKeyDevice;
+
+    /// The type that configures auto shift. Set this to your keyboard type if you want to
+    /// implement [`crate::auto_shift::AutoShiftDevice`] yourself. Defaults to
+    /// [`crate::auto_shift::DefaultAutoShiftDevice`].
+    #[cfg(feature = "auto-shift")]
+    type AutoShiftDeviceType: crate::auto_shift::AutoShiftDevice =
+        crate::auto_shift::DefaultAutoShiftDevice;
+
+    /// The type that configures idle mode. Set this to your keyboard type if you want to implement
+    /// [`crate::idle::IdleDevice`] yourself. Defaults to [`crate::idle::DefaultIdleDevice`].
+    #[cfg(feature = "idle")]
+    type IdleDeviceType: crate::idle::IdleDevice = crate::idle::DefaultIdleDevice;
+
     #[cfg(feature = "simple-backlight")]
     type SimpleBacklightDeviceType: crate::lighting::simple_backlight::private::MaybeSimpleBacklightDevice =
         crate::lighting::private::EmptyLightingDevice;
@@ -127,6 +281,236 @@ pub trait KeyboardLayout {
         crate::lighting::private::EmptyLightingDevice;
 }
 
+/// Maximum number of keys that can be used to trigger a combo.
+pub const MAX_COMBO_KEYS: usize = 4;
+
+/// A combo, which performs `action` when all of the `keys` are pressed at the same time.
+#[derive(Debug, Clone, Copy)]
+pub struct Combo {
+    /// Layout coordinates of the keys in the form of (row, col). Unused slots are set to `None`.
+    pub keys: [Option<(u8, u8)>; MAX_COMBO_KEYS],
+    /// Action to perform when the combo is triggered.
+    pub action: Action<Keycode>,
+}
+
+impl Combo {
+    fn key_count(&self) -> usize {
+        self.keys.iter().flatten().count()
+    }
+
+    fn contains(&self, coord: (u8, u8)) -> bool {
+        self.keys.contains(&Some(coord))
+    }
+
+    fn contains_all(&self, coords: &[(u8, u8)]) -> bool {
+        coords.iter().all(|coord| self.contains(*coord))
+    }
+}
+
+/// Data structure that contains the combos of a keyboard layout. Requires the number of combos
+/// that can be stored to be specified.
+#[derive(Debug)]
+pub struct ComboBuffer<const N: usize> {
+    combos: [Option<Combo>; N],
+}
+
+impl<const N: usize> ComboBuffer<N> {
+    pub const fn new() -> Self {
+        Self { combos: [None; N] }
+    }
+
+    /// Get the combo stored at the given index.
+    pub fn get_combo(&self, idx: usize) -> Option<&Combo> {
+        self.combos.get(idx).and_then(|combo| combo.as_ref())
+    }
+
+    /// Store a combo at the given index. Passing `None` removes the combo. If the index is out of
+    /// bounds, this does nothing.
+    pub fn set_combo(&mut self, idx: usize, combo: Option<Combo>) {
+        if let Some(slot) = self.combos.get_mut(idx) {
+            *slot = combo
+        }
+    }
+
+    /// Remove all the combos.
+    pub fn clear(&mut self) {
+        self.combos.fill(None)
+    }
+}
+
+/// A tap dance that can be changed while the keyboard is running. Each action is set to
+/// [`Action::NoOp`] if it is unused.
+#[derive(Debug, Clone, Copy)]
+pub struct DynamicTapDance {
+    /// Action to perform when the key is tapped once.
+    pub on_tap: Action<Keycode>,
+    /// Action to perform when the key is held.
+    pub on_hold: Action<Keycode>,
+    /// Action to perform when the key is tapped twice.
+    pub on_double_tap: Action<Keycode>,
+    /// Action to perform when the key is tapped, and then held.
+    pub on_tap_hold: Action<Keycode>,
+    /// Time in milliseconds that can pass between taps, and how long the key must be held to
+    /// perform the hold actions.
+    pub timeout: u16,
+}
+
+const EMPTY_HOLD_TAP: HoldTapAction<Keycode, keyberon::key_code::KeyCode> = HoldTapAction {
+    timeout: 0,
+    hold: Action::NoOp,
+    tap: Action::NoOp,
+    config: HoldTapConfig::Default,
+    tap_hold_interval: 0,
+};
+
+const EMPTY_TAP_DANCE_ACTIONS: &[Action<Keycode>] = &[Action::NoOp];
+
+/// Storage for a [`DynamicTapDance`], and the keyberon actions that it gets translated into.
+/// Create these using [`setup_tap_dance_buffer`].
+#[derive(Debug)]
+pub struct TapDanceEntry {
+    tap_dance: Option<DynamicTapDance>,
+    hold_taps: [HoldTapAction<Keycode, keyberon::key_code::KeyCode>; 2],
+    actions: [Action<Keycode>; 2],
+    action: TapDanceAction<Keycode, keyberon::key_code::KeyCode>,
+}
+
+impl TapDanceEntry {
+    pub const fn new() -> Self {
+        Self {
+            tap_dance: None,
+            hold_taps: [EMPTY_HOLD_TAP; 2],
+            actions: [Action::NoOp; 2],
+            action: TapDanceAction {
+                actions: EMPTY_TAP_DANCE_ACTIONS,
+                timeout: 0,
+                config: TapDanceConfig::Lazy,
+            },
+        }
+    }
+
+    /// Get the tap dance stored in this entry.
+    pub fn get_tap_dance(&self) -> Option<&DynamicTapDance> {
+        self.tap_dance.as_ref()
+    }
+
+    /// Store a tap dance in this entry. Passing `None` removes the tap dance, which makes
+    /// [`TapDanceEntry::action`] do nothing.
+    ///
+    /// The tap dance is translated into a lazy keyberon tap dance with up to 2 actions, one for
+    /// each number of taps. If a tap dance has a hold action, the action for that number of taps
+    /// will be a hold-tap action.
+    pub fn set_tap_dance(&'static mut self, tap_dance: Option<DynamicTapDance>) {
+        let Self {
+            tap_dance: stored,
+            hold_taps: [single_hold_tap, double_hold_tap],
+            actions,
+            action,
+        } = self;
+
+        *stored = tap_dance;
+
+        let Some(tap_dance) = tap_dance else {
+            action.actions = EMPTY_TAP_DANCE_ACTIONS;
+            return;
+        };
+
+        let single = Self::hold_tap_or_tap(
+            single_hold_tap,
+            tap_dance.on_tap,
+            tap_dance.on_hold,
+            tap_dance.timeout,
+        );
+        let double = Self::hold_tap_or_tap(
+            double_hold_tap,
+            tap_dance.on_double_tap,
+            tap_dance.on_tap_hold,
+            tap_dance.timeout,
+        );
+
+        *actions = [single, double];
+        let actions: &'static [Action<Keycode>; 2] = actions;
+
+        *action = TapDanceAction {
+            // If there is no double tap action, the single tap action can be performed immediately
+            actions: if matches!(double, Action::NoOp) {
+                &actions[..1]
+            } else {
+                &actions[..]
+            },
+            timeout: tap_dance.timeout,
+            config: TapDanceConfig::Lazy,
+        };
+    }
+
+    fn hold_tap_or_tap(
+        slot: &'static mut HoldTapAction<Keycode, keyberon::key_code::KeyCode>,
+        tap: Action<Keycode>,
+        hold: Action<Keycode>,
+        timeout: u16,
+    ) -> Action<Keycode> {
+        if matches!(hold, Action::NoOp) {
+            return tap;
+        }
+
+        *slot = HoldTapAction {
+            timeout,
+            hold,
+            tap,
+            config: HoldTapConfig::Default,
+            tap_hold_interval: 0,
+        };
+
+        Action::HoldTap(slot)
+    }
+
+    /// Get the action that can be placed in a layout to use this tap dance.
+    pub fn action(&'static self) -> Action<Keycode> {
+        Action::TapDance(&self.action)
+    }
+
+    /// Check if `action` refers to the tap dance in this entry.
+    pub fn is_action(&self, action: &TapDanceAction<Keycode, keyberon::key_code::KeyCode>) -> bool {
+        core::ptr::eq(&self.action, action)
+    }
+}
+
+/// Get the total length of the sequences in `parts`. This is used by [`sequence`] to determine
+/// the length of the sequence that it creates.
+pub const fn sequence_len(parts: &[&[u8]]) -> usize {
+    let mut len = 0;
+    let mut i = 0;
+    while i < parts.len() {
+        len += parts[i].len();
+        i += 1;
+    }
+    len
+}
+
+/// Join the sequences in `parts` into one sequence, which can be used in a keyberon
+/// [`Action::Sequence`]. `N` must be equal to [`sequence_len`]. It is recommended to use
+/// [`sequence`] instead, which does this for you.
+pub const fn concat_sequences<const N: usize>(parts: &[&[u8]]) -> [u8; N] {
+    assert!(
+        sequence_len(parts) == N,
+        "The length of the sequence does not match the length of the array"
+    );
+
+    let mut bytes = [0; N];
+    let mut len = 0;
+    let mut i = 0;
+    while i < parts.len() {
+        let mut j = 0;
+        while j < parts[i].len() {
+            bytes[len] = parts[i][j];
+            len += 1;
+            j += 1;
+        }
+        i += 1;
+    }
+    bytes
+}
+
 /// A mutex-guaraded [`keyberon::layout::Layout`]. This also stores the original layout, so that it
 /// can be reset to it's initial state if modifications are made to it.
 pub struct Layout<const C: usize, const R: usize, const L: usize> {
@@ -149,6 +533,17 @@ pub trait DeviceWithEncoders {
     fn get_encoders() -> [impl Encoder; Self::ENCODER_COUNT];
 
     fn get_layout_mappings() -> [[(u8, u8); 3]; Self::ENCODER_COUNT];
+
+    /// Acceleration curves for each encoder, as a list of `(detents_per_second, multiplier)`
+    /// pairs. If an encoder is rotated in the same direction at a rate of at least
+    /// `detents_per_second`, each detent will tap the mapped key `multiplier` times. If multiple
+    /// thresholds are reached, the highest multiplier is used. An empty curve disables
+    /// acceleration, so each detent taps the mapped key once.
+    ///
+    /// By default, acceleration is disabled for all encoders.
+    fn get_acceleration_curves() -> [&'static [(u16, u8)]; Self::ENCODER_COUNT] {
+        [&[]; Self::ENCODER_COUNT]
+    }
 }
 
 pub trait Encoder {
@@ -201,6 +596,114 @@ impl<SW: Wait + InputPin, A: Wait, B: InputPin> Encoder for EC11Encoder<SW, A, B
     }
 }
 
+/// How often a [`PolledEncoder`] reads its inputs.
+const POLLED_ENCODER_INTERVAL: Duration = Duration::from_millis(1);
+
+/// An encoder whose push button and A/B outputs are read by polling functions, instead of waiting
+/// for interrupts on dedicated GPIO pins. This can be used for encoders that are read through a
+/// shift register or multiplexer. Each function should return `true` if its line is high.
+pub struct PolledEncoder {
+    sw: fn() -> bool,
+    a: fn() -> bool,
+    b: fn() -> bool,
+    last_sw: bool,
+    last_a: bool,
+}
+
+impl PolledEncoder {
+    pub fn new(sw: fn() -> bool, a: fn() -> bool, b: fn() -> bool) -> Self {
+        Self {
+            sw,
+            a,
+            b,
+            last_sw: sw(),
+            last_a: a(),
+        }
+    }
+
+    pub async fn wait_for_event(&mut self) -> EncoderEvent {
+        loop {
+            let sw = (self.sw)();
+            if sw != self.last_sw {
+                self.last_sw = sw;
+                return if sw {
+                    EncoderEvent::Release
+                } else {
+                    EncoderEvent::Press
+                };
+            }
+
+            // Same decoding as `EC11Encoder`, on the falling edge of A
+            let a = (self.a)();
+            if a != self.last_a {
+                self.last_a = a;
+                if !a {
+                    return if (self.b)() {
+                        EncoderEvent::CounterClockwiseRotation
+                    } else {
+                        EncoderEvent::ClockwiseRotation
+                    };
+                }
+            }
+
+            Timer::after(POLLED_ENCODER_INTERVAL).await;
+        }
+    }
+}
+
+impl Encoder for PolledEncoder {
+    async fn wait_for_event(&mut self) -> EncoderEvent {
+        self.wait_for_event().await
+    }
+}
+
+/// An [`Encoder`] that can be either an [`EC11Encoder`] (or any other encoder type `E`), or a
+/// [`PolledEncoder`]. This is used by `setup_encoders!` when both kinds of encoders are used.
+pub enum AnyEncoder<E> {
+    Interrupt(E),
+    Polled(PolledEncoder),
+}
+
+impl<E: Encoder> Encoder for AnyEncoder<E> {
+    async fn wait_for_event(&mut self) -> EncoderEvent {
+        match self {
+            AnyEncoder::Interrupt(encoder) => encoder.wait_for_event().await,
+            AnyEncoder::Polled(encoder) => encoder.wait_for_event().await,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct EncoderAccelerationState {
+    /// Time and direction (`true` for clockwise) of the last rotation.
+    last_rotation: Option<(Instant, bool)>,
+}
+
+impl EncoderAccelerationState {
+    /// Register a rotation, returning the number of times that the mapped key should be tapped.
+    fn rotate(&mut self, curve: &[(u16, u8)], clockwise: bool) -> u8 {
+        let now = Instant::now();
+
+        let taps = match self.last_rotation {
+            Some((last, last_clockwise)) if last_clockwise == clockwise => {
+                let rate = 1000 / (now - last).as_millis().max(1);
+                curve
+                    .iter()
+                    .filter(|(threshold, _)| rate >= *threshold as u64)
+                    .map(|(_, multiplier)| *multiplier)
+                    .max()
+                    .unwrap_or(1)
+                    .max(1)
+            }
+            _ => 1,
+        };
+
+        self.last_rotation = Some((now, clockwise));
+
+        taps
+    }
+}
+
 /// A trait that must be implemented for any device that needs to poll a switch matrix.
 pub trait KeyboardMatrix {
     /// The layout to send matrix events to.
@@ -211,9 +714,45 @@ pub trait KeyboardMatrix {
     type PeripheralDeviceType: crate::split::peripheral::private::MaybePeripheralDevice =
         crate::split::peripheral::private::EmptyPeripheralDevice;
 
-    /// Debounce setting.
+    /// Debounce setting. Despite the name, this is the number of consecutive matrix scans that a
+    /// key's state must be stable for, so it only corresponds to milliseconds when the scan
+    /// interval is 1ms. Changing [`KeyboardMatrix::MATRIX_SCAN_INTERVAL_US`], or entering idle
+    /// mode (which scans less often), changes the actual debounce time.
     const DEBOUNCE_MS: u16 = 5;
 
+    /// Debounce algorithm used by the matrix. Defaults to [`DebounceAlgorithm::Symmetric`], which
+    /// waits for a key's state to be stable for [`KeyboardMatrix::DEBOUNCE_MS`] before reporting
+    /// it. [`DebounceAlgorithm::Eager`] reports changes immediately, and then ignores changes for
+    /// [`KeyboardMatrix::DEBOUNCE_MS`], which results in lower latency.
+    ///
+    /// This does not apply to analog matrices.
+    const DEBOUNCE_ALGORITHM: DebounceAlgorithm = DebounceAlgorithm::Symmetric;
+
+    /// Time between matrix scans, in microseconds. Defaults to `500`. Lowering this lets key
+    /// presses reach the layout sooner, at the cost of higher power usage.
+    ///
+    /// If the `idle` feature is enabled, this is only used while the keyboard is active (see
+    /// [`crate::idle::IdleDevice::IDLE_SCAN_INTERVAL`]).
+    const MATRIX_SCAN_INTERVAL_US: u64 = 500;
+
+    /// Whether every key in the matrix has a diode. If this is `false`, the matrix polling task
+    /// ignores key presses that complete a rectangle of pressed keys (e.g. pressing the 4th key
+    /// of a 2x2 block), since these could be "ghost" key presses caused by the other three keys.
+    /// Defaults to `true`, which disables this check.
+    const HAS_DIODES: bool = true;
+
+    /// Optional function to provide per-key debounce overrides, in milliseconds. Entries are
+    /// indexed by matrix coordinates (`[row][col]`, before [`KeyboardMatrix::remap_to_layout`] is
+    /// applied).
+    ///
+    /// If an entry is non-zero, a change in that key's state must remain stable for the given
+    /// amount of time before a press or release event is emitted. This is applied on top of
+    /// [`KeyboardMatrix::DEBOUNCE_MS`], so overrides should be larger than the global value.
+    /// Entries set to `0` (default) only use the global debounce setting.
+    fn get_debounce_overrides() -> [[u8; Self::MATRIX_COLS]; Self::MATRIX_ROWS] {
+        [[0; Self::MATRIX_COLS]; Self::MATRIX_ROWS]
+    }
+
     /// Number of matrix columns.
     ///
     /// It is recommended to use one of the `build_*_matrix` macros to set this constant.
@@ -251,14 +790,23 @@ pub fn setup_standard_keyboard_matrix<
     cols: [I; CS],
     rows: [O; RS],
     debounce_ms: u16,
+    debounce_algorithm: DebounceAlgorithm,
 ) -> Result<PollableStandardMatrix<I, O, CS, RS>, E> {
     let matrix = Matrix::new(cols, rows)?;
-    let debouncer = Debouncer::new([[false; CS]; RS], [[false; CS]; RS], debounce_ms);
+    let debouncer = Debouncer::new_with_algorithm(
+        [[false; CS]; RS],
+        [[false; CS]; RS],
+        debounce_ms,
+        debounce_algorithm,
+    );
     Ok((matrix, debouncer))
 }
 
 /// Setup a diodeless keyboard matrix, with a debouncer. The output of this function can be passed
 /// to the matrix polling task directly.
+///
+/// Pins marked with `true` in `active_high` are considered pressed when they read high. All other
+/// pins are considered pressed when they read low.
 pub fn setup_direct_pin_keyboard_matrix<
     E,
     I: InputPin<Error = E>,
@@ -266,24 +814,52 @@ pub fn setup_direct_pin_keyboard_matrix<
     const RS: usize,
 >(
     pins: [[Option<I>; CS]; RS],
+    active_high: [[bool; CS]; RS],
     debounce_ms: u16,
+    debounce_algorithm: DebounceAlgorithm,
 ) -> Result<PollableDirectPinMatrix<I, CS, RS>, E> {
-    let matrix = DirectPinMatrix::new(pins)?;
-    let debouncer = Debouncer::new([[false; CS]; RS], [[false; CS]; RS], debounce_ms);
+    let matrix = DirectPinMatrix::new_with_active_high(pins, active_high)?;
+    let debouncer = Debouncer::new_with_algorithm(
+        [[false; CS]; RS],
+        [[false; CS]; RS],
+        debounce_ms,
+        debounce_algorithm,
+    );
     Ok((matrix, debouncer))
 }
 
 /// Setup an analog keyboard matrix. The output of this function can be passed to the matrix
 /// polling task directly.
-pub fn setup_analog_keyboard_matrix<S: MatrixSampler, const CS: usize, const RS: usize>(
+///
+/// `actuation_points` contains the default actuation point of each key, where `0` represents an
+/// unpressed switch, and `255` represents a fully pressed switch. `modes` determines how each key
+/// is actuated (e.g. rapid trigger), and `sensitivities` contains the `(press, release)`
+/// thresholds used for each key. Events for each pair of keys in
+/// `socd_pairs` are resolved, so that only one key in each pair is pressed at a time.
+pub fn setup_analog_keyboard_matrix<
+    S: MatrixSampler,
+    const CS: usize,
+    const RS: usize,
+    const SOCD: usize,
+>(
     sampler: &S,
     pos_to_ch: [[(u8, u8); CS]; RS],
     ranges: [[Range<S::SampleType>; CS]; RS],
-) -> PollableAnalogMatrix<S, CS, RS> {
+    actuation_points: [[u8; CS]; RS],
+    modes: [[AnalogAcutationMode; CS]; RS],
+    sensitivities: [[(u8, u8); CS]; RS],
+    socd_pairs: [SocdPair; SOCD],
+) -> PollableAnalogMatrix<S, CS, RS, SOCD> {
     let sampler = AnalogMatrixSampler { pos_to_ch, sampler };
     let matrix = AnalogMatrix::new(ranges);
-    let actuator = AnalogActuator::new([[AnalogAcutationMode::default(); CS]; RS], [[127; CS]; RS]);
-    (sampler, matrix, actuator)
+    let mut actuator = AnalogActuator::new(modes, actuation_points);
+    for (row, sensitivities) in sensitivities.iter().enumerate() {
+        for (col, (press, release)) in sensitivities.iter().enumerate() {
+            let _ = actuator.set_sensitivity(row, col, *press, *release);
+        }
+    }
+    let socd = SocdResolver::new(socd_pairs);
+    (sampler, matrix, actuator, socd)
 }
 
 /// Custom keycodes used to interact with other rumcake features.
@@ -324,6 +900,49 @@ pub enum Keycode {
     /// Underglow keycode, which can be any variant in
     /// [`crate::lighting::underglow::UnderglowCommand`]
     Underglow(crate::lighting::underglow::UnderglowCommand) = 6,
+
+    #[cfg(feature = "unicode")]
+    /// Unicode keycode, which types the given code point using the input method set by
+    /// [`KeyboardLayout::UnicodeDeviceType`]
+    Unicode(u32) = 7,
+
+    #[cfg(feature = "audio")]
+    /// Keycode used to control key sounds, which can be any variant in
+    /// [`crate::audio::AudioCommand`]
+    Audio(crate::audio::AudioCommand) = 8,
+
+    #[cfg(feature = "caps-word")]
+    /// Keycode used to control caps word, which can be any variant in
+    /// [`crate::caps_word::CapsWordCommand`]
+    CapsWord(crate::caps_word::CapsWordCommand) = 9,
+
+    #[cfg(feature = "leader-key")]
+    /// Leader key, which starts a leader sequence. The sequences are defined by
+    /// [`KeyboardLayout::LeaderKeyDeviceType`]
+    Leader = 10,
+
+    #[cfg(feature = "pointer")]
+    /// Mouse key, which can be any variant in [`crate::pointer::mouse::MouseKey`]
+    Mouse(crate::pointer::mouse::MouseKey) = 11,
+
+    /// Bootloader key, which jumps to the bootloader after being held for
+    /// [`KeyboardLayout::BOOTLOADER_KEY_HOLD_TIME`] milliseconds
+    Bootloader = 12,
+
+    #[cfg(feature = "bluetooth")]
+    /// Keycode used to manage Bluetooth host profiles, which can be any variant in
+    /// [`crate::bluetooth::BluetoothCommand`]
+    Bluetooth(crate::bluetooth::BluetoothCommand) = 13,
+
+    #[cfg(feature = "key-lock")]
+    /// Key lock key, which keeps the next key that gets pressed held until it is pressed again.
+    /// See [`crate::key_lock`]
+    KeyLock = 14,
+
+    #[cfg(feature = "dynamic-macros")]
+    /// Keycode used to record and play dynamic macros, which can be any variant in
+    /// [`crate::dynamic_macro::DynamicMacroCommand`]
+    DynamicMacro(crate::dynamic_macro::DynamicMacroCommand) = 15,
 }
 
 pub struct PollableMatrix<T> {
@@ -345,6 +964,23 @@ impl<T: Pollable> PollableMatrix<T> {
 pub trait Pollable {
     /// Poll the matrix for events
     fn events(&mut self) -> impl Iterator<Item = Event>;
+
+    /// Obtain the normalized analog values (`0` to `255`) of keys that have changed since they
+    /// were last obtained, as `(row, col, value)`. Only applicable to analog matrices.
+    fn changed_analog_values(&mut self) -> impl Iterator<Item = (u8, u8, u8)> {
+        core::iter::empty()
+    }
+
+    /// Get the actuation point for a key. Only applicable to analog matrices.
+    fn get_actuation_point(&self, _row: usize, _col: usize) -> Option<u8> {
+        None
+    }
+
+    /// Set the actuation point for a key. Returns `false` if the actuation point could not be set.
+    /// Only applicable to analog matrices.
+    fn set_actuation_point(&mut self, _row: usize, _col: usize, _value: u8) -> bool {
+        false
+    }
 }
 
 pub type PollableStandardMatrix<I, O, const CS: usize, const RS: usize> =
@@ -409,14 +1045,15 @@ enum SampleError {
     NoSampleForKeyPosition(usize, usize),
 }
 
-pub type PollableAnalogMatrix<'a, S, const CS: usize, const RS: usize> = (
+pub type PollableAnalogMatrix<'a, S, const CS: usize, const RS: usize, const SOCD: usize = 0> = (
     AnalogMatrixSampler<'a, S, CS, RS>,
     AnalogMatrix<<S as MatrixSampler>::SampleType, CS, RS>,
     AnalogActuator<CS, RS>,
+    SocdResolver<SOCD>,
 );
 
-impl<S: MatrixSampler, const CS: usize, const RS: usize> Pollable
-    for PollableAnalogMatrix<'_, S, CS, RS>
+impl<S: MatrixSampler, const CS: usize, const RS: usize, const SOCD: usize> Pollable
+    for PollableAnalogMatrix<'_, S, CS, RS, SOCD>
 where
     u32: From<S::SampleType>,
 {
@@ -430,7 +1067,22 @@ where
             })
             .unwrap();
 
-        self.2.events(matrix_state)
+        let socd = &mut self.3;
+        self.2
+            .events(matrix_state)
+            .flat_map(move |event| socd.process(event))
+    }
+
+    fn changed_analog_values(&mut self) -> impl Iterator<Item = (u8, u8, u8)> {
+        self.2.changed_values()
+    }
+
+    fn get_actuation_point(&self, row: usize, col: usize) -> Option<u8> {
+        self.2.get_actuation_point(row, col)
+    }
+
+    fn set_actuation_point(&mut self, row: usize, col: usize, value: u8) -> bool {
+        self.2.set_actuation_point(row, col, value).is_ok()
     }
 }
 
@@ -439,6 +1091,8 @@ where
     [(); K::ENCODER_COUNT]:,
 {
     let mappings = K::get_layout_mappings();
+    let curves = K::get_acceleration_curves();
+    let mut acceleration = [EncoderAccelerationState::default(); K::ENCODER_COUNT];
     let mut encoders = K::get_encoders();
 
     let layout_channel = <K::Layout as private::MaybeKeyboardLayout>::get_matrix_events_channel();
@@ -461,13 +1115,16 @@ where
         let (event, idx) = select_array(futures).await;
 
         let [sw_pos, cw_pos, ccw_pos] = mappings[idx];
+        let mut taps = 1;
 
         match event {
             EncoderEvent::ClockwiseRotation => {
+                taps = acceleration[idx].rotate(curves[idx], true);
                 events.push(Event::Press(cw_pos.0, cw_pos.1));
                 events.push(Event::Release(cw_pos.0, cw_pos.1));
             }
             EncoderEvent::CounterClockwiseRotation => {
+                taps = acceleration[idx].rotate(curves[idx], false);
                 events.push(Event::Press(ccw_pos.0, ccw_pos.1));
                 events.push(Event::Release(ccw_pos.0, ccw_pos.1));
             }
@@ -479,9 +1136,13 @@ where
             }
         };
 
-        for e in &events {
-            if let Some(layout_channel) = layout_channel {
-                layout_channel.send(*e).await;
+        // Each tap is sent to the position in the layout, so accelerated rotations will use the
+        // same action as a regular detent.
+        for _ in 0..taps {
+            for e in &events {
+                if let Some(layout_channel) = layout_channel {
+                    layout_channel.send(*e).await;
+                }
             }
         }
 
@@ -489,12 +1150,198 @@ where
     }
 }
 
-pub async fn matrix_poll<K: KeyboardMatrix + 'static>(_k: K) {
-    let matrix = K::get_matrix();
-    let layout_channel = <K::Layout as private::MaybeKeyboardLayout>::get_matrix_events_channel();
+#[derive(Clone, Copy, Default)]
+struct DebounceOverrideState {
+    /// Last state reported by the matrix debouncer.
+    pressed: bool,
+    /// Last state that was sent to the layout.
+    reported: bool,
+    /// Time at which `pressed` started to differ from `reported`.
+    changed_at: Option<Instant>,
+}
+
+/// Keeps track of pressed keys, and blocks key presses that could be caused by ghosting in a
+/// matrix without diodes.
+struct GhostBlocker<const CS: usize, const RS: usize> {
+    /// Keys that are pressed, and were reported to the layout.
+    pressed: [[bool; CS]; RS],
+    /// Keys that are pressed, but were blocked because they could be ghost key presses. Releases
+    /// for these keys are also blocked.
+    blocked: [[bool; CS]; RS],
+}
+
+impl<const CS: usize, const RS: usize> GhostBlocker<CS, RS> {
+    const fn new() -> Self {
+        Self {
+            pressed: [[false; CS]; RS],
+            blocked: [[false; CS]; RS],
+        }
+    }
+
+    /// Check if pressing the given key would complete a rectangle of pressed keys.
+    const fn is_ghost(&self, row: usize, col: usize) -> bool {
+        let mut other_row = 0;
+        while other_row < RS {
+            if other_row != row && self.pressed[other_row][col] {
+                let mut other_col = 0;
+                while other_col < CS {
+                    if other_col != col
+                        && self.pressed[row][other_col]
+                        && self.pressed[other_row][other_col]
+                    {
+                        return true;
+                    }
+                    other_col += 1;
+                }
+            }
+            other_row += 1;
+        }
+        false
+    }
+
+    /// Process a matrix event. Returns `None` if the event should be ignored.
+    const fn filter(&mut self, event: Event) -> Option<Event> {
+        let (row, col, pressed) = match event {
+            Event::Press(row, col) => (row as usize, col as usize, true),
+            Event::Release(row, col) => (row as usize, col as usize, false),
+        };
+
+        if row >= RS || col >= CS {
+            return Some(event);
+        }
+
+        if pressed {
+            if self.is_ghost(row, col) {
+                self.blocked[row][col] = true;
+                return None;
+            }
+        } else if self.blocked[row][col] {
+            self.blocked[row][col] = false;
+            return None;
+        }
+
+        self.pressed[row][col] = pressed;
+        Some(event)
+    }
+
+    /// Process a matrix event, filtering it only if the matrix does not have diodes (see
+    /// [`KeyboardMatrix::HAS_DIODES`]). Returns `None` if the event should be ignored.
+    const fn process(&mut self, has_diodes: bool, event: Event) -> Option<Event> {
+        if has_diodes {
+            Some(event)
+        } else {
+            self.filter(event)
+        }
+    }
+}
+
+const _: () = {
+    // Press three keys of a 2x2 block. Without diodes, the 4th key would also read as pressed.
+    let mut blocker = GhostBlocker::<2, 2>::new();
+    assert!(blocker.process(false, Event::Press(0, 0)).is_some());
+    assert!(blocker.process(false, Event::Press(0, 1)).is_some());
+    assert!(blocker.process(false, Event::Press(1, 0)).is_some());
+    assert!(blocker.process(false, Event::Press(1, 1)).is_none());
+    assert!(blocker.process(false, Event::Release(1, 1)).is_none());
+
+    // Once the rectangle is broken, the 4th key can be pressed again
+    assert!(blocker.process(false, Event::Release(0, 0)).is_some());
+    assert!(blocker.process(false, Event::Press(1, 1)).is_some());
+};
+
+const _: () = {
+    // Releasing a reported key while the 4th key is blocked breaks the rectangle. The blocked
+    // key's release is still ignored, since its press was never reported.
+    let mut blocker = GhostBlocker::<2, 2>::new();
+    assert!(blocker.process(false, Event::Press(0, 0)).is_some());
+    assert!(blocker.process(false, Event::Press(0, 1)).is_some());
+    assert!(blocker.process(false, Event::Press(1, 0)).is_some());
+    assert!(blocker.process(false, Event::Press(1, 1)).is_none());
+    assert!(matches!(
+        blocker.process(false, Event::Release(0, 1)),
+        Some(Event::Release(0, 1))
+    ));
+    assert!(blocker.process(false, Event::Release(1, 1)).is_none());
+    assert!(matches!(
+        blocker.process(false, Event::Press(1, 1)),
+        Some(Event::Press(1, 1))
+    ));
+    assert!(blocker.process(false, Event::Release(1, 1)).is_some());
+};
+
+const _: () = {
+    // Keys outside of the tracked matrix are passed through, and don't affect ghost detection
+    let mut blocker = GhostBlocker::<2, 2>::new();
+    assert!(blocker.process(false, Event::Press(0, 0)).is_some());
+    assert!(blocker.process(false, Event::Press(0, 1)).is_some());
+    assert!(matches!(
+        blocker.process(false, Event::Press(2, 0)),
+        Some(Event::Press(2, 0))
+    ));
+    assert!(matches!(
+        blocker.process(false, Event::Press(2, 1)),
+        Some(Event::Press(2, 1))
+    ));
+    assert!(blocker.process(false, Event::Press(1, 0)).is_some());
+    assert!(blocker.process(false, Event::Press(1, 1)).is_none());
+    assert!(blocker.process(false, Event::Release(2, 1)).is_some());
+};
+
+const _: () = {
+    // Matrices with diodes don't block any keys
+    let mut blocker = GhostBlocker::<2, 2>::new();
+    assert!(blocker.process(true, Event::Press(0, 0)).is_some());
+    assert!(blocker.process(true, Event::Press(0, 1)).is_some());
+    assert!(blocker.process(true, Event::Press(1, 0)).is_some());
+    assert!(matches!(
+        blocker.process(true, Event::Press(1, 1)),
+        Some(Event::Press(1, 1))
+    ));
+    assert!(matches!(
+        blocker.process(true, Event::Release(1, 1)),
+        Some(Event::Release(1, 1))
+    ));
+};
+
+async fn send_matrix_event<K: KeyboardMatrix>(e: Event) {
+    let (row, col) = e.coord();
+    let (new_row, new_col) = K::remap_to_layout(row, col);
+
+    let remapped_event = match e {
+        Event::Press(_, _) => Event::Press(new_row, new_col),
+        Event::Release(_, _) => Event::Release(new_row, new_col),
+    };
+
+    info!(
+        "[KEYBOARD] Key event: {:?}, Remapped: {:?}",
+        Debug2Format(&e),
+        Debug2Format(&remapped_event)
+    );
+
+    if let Some(layout_channel) =
+        <K::Layout as private::MaybeKeyboardLayout>::get_matrix_events_channel()
+    {
+        layout_channel.send(remapped_event).await
+    };
 
     #[cfg(feature = "split-peripheral")]
-    let peripheral_channel = <K::PeripheralDeviceType as crate::split::peripheral::private::MaybePeripheralDevice>::get_matrix_events_channel();
+    if let Some(peripheral_channel) = <K::PeripheralDeviceType as crate::split::peripheral::private::MaybePeripheralDevice>::get_matrix_events_channel() {
+        peripheral_channel.send(remapped_event).await
+    };
+}
+
+pub async fn matrix_poll<K: KeyboardMatrix + 'static>(_k: K)
+where
+    [(); K::MATRIX_COLS]:,
+    [(); K::MATRIX_ROWS]:,
+{
+    let matrix = K::get_matrix();
+    let debounce_overrides = K::get_debounce_overrides();
+    let mut override_states = [[DebounceOverrideState::default(); K::MATRIX_COLS]; K::MATRIX_ROWS];
+    let mut ghost_blocker = GhostBlocker::<{ K::MATRIX_COLS }, { K::MATRIX_ROWS }>::new();
+
+    #[cfg(feature = "scan-metrics")]
+    let mut scan_counter = crate::scan_metrics::ScanCounter::new();
 
     loop {
         {
@@ -502,31 +1349,319 @@ pub async fn matrix_poll<K: KeyboardMatrix + 'static>(_k: K) {
             let mut matrix = matrix.matrix.lock().await;
             let events = matrix.events();
             for e in events {
+                let e = match ghost_blocker.process(K::HAS_DIODES, e) {
+                    Some(e) => e,
+                    None => {
+                        debug!(
+                            "[KEYBOARD] Blocked possible ghost key: {:?}",
+                            Debug2Format(&e)
+                        );
+                        continue;
+                    }
+                };
+
                 let (row, col) = e.coord();
-                let (new_row, new_col) = K::remap_to_layout(row, col);
 
-                let remapped_event = match e {
-                    Event::Press(_, _) => Event::Press(new_row, new_col),
-                    Event::Release(_, _) => Event::Release(new_row, new_col),
-                };
+                // Keys with a debounce override are sent later, once their state is stable
+                if let Some(state) = override_states
+                    .get_mut(row as usize)
+                    .and_then(|states| states.get_mut(col as usize))
+                {
+                    if debounce_overrides[row as usize][col as usize] > 0 {
+                        state.pressed = e.is_press();
+                        state.changed_at = (state.pressed != state.reported).then(Instant::now);
+                        continue;
+                    }
+                }
+
+                send_matrix_event::<K>(e).await;
+            }
+        }
 
-                info!(
-                    "[KEYBOARD] Key event: {:?}, Remapped: {:?}",
-                    Debug2Format(&e),
-                    Debug2Format(&remapped_event)
-                );
+        #[cfg(feature = "scan-metrics")]
+        scan_counter.record_scan().await;
 
-                if let Some(layout_channel) = layout_channel {
-                    layout_channel.send(remapped_event).await
+        for (row, states) in override_states.iter_mut().enumerate() {
+            for (col, state) in states.iter_mut().enumerate() {
+                let Some(changed_at) = state.changed_at else {
+                    continue;
                 };
 
-                #[cfg(feature = "split-peripheral")]
-                if let Some(peripheral_channel) = peripheral_channel {
-                    peripheral_channel.send(remapped_event).await
+                if changed_at.elapsed()
+                    >= Duration::from_millis(debounce_overrides[row][col] as u64)
+                {
+                    state.reported = state.pressed;
+                    state.changed_at = None;
+
+                    let e = if state.pressed {
+                        Event::Press(row as u8, col as u8)
+                    } else {
+                        Event::Release(row as u8, col as u8)
+                    };
+                    send_matrix_event::<K>(e).await;
+                }
+            }
+        }
+
+        let scan_interval = Duration::from_micros(K::MATRIX_SCAN_INTERVAL_US);
+        #[cfg(feature = "idle")]
+        let scan_interval = crate::idle::idle_scan_interval().unwrap_or(scan_interval);
+        Timer::after(scan_interval).await;
+    }
+}
+
+/// Row used for the virtual keys that get pressed when a combo is triggered. The column is the
+/// index of the combo.
+const COMBO_ROW: u8 = u8::MAX;
+
+struct ActiveCombo {
+    idx: u8,
+    /// Keys of the combo that are still being held.
+    held: Vec<(u8, u8), MAX_COMBO_KEYS>,
+    released: bool,
+}
+
+/// Holds back key presses that may be part of a combo, until a combo is triggered, or it becomes
+/// clear that no combo is being pressed.
+#[derive(Default)]
+struct ComboState {
+    pending: Vec<(u8, u8), MAX_COMBO_KEYS>,
+    pending_since: Option<Instant>,
+    active: Vec<ActiveCombo, 4>,
+}
+
+impl ComboState {
+    fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Find a combo that consists of exactly the pending keys.
+    fn find_exact(&self, combos: &[Option<Combo>]) -> Option<(usize, Combo)> {
+        combos.iter().enumerate().find_map(|(idx, combo)| {
+            combo
+                .filter(|combo| {
+                    combo.key_count() == self.pending.len() && combo.contains_all(&self.pending)
+                })
+                .map(|combo| (idx, combo))
+        })
+    }
+
+    /// Send the pending key presses to the layout as normal key presses.
+    fn flush<const C: usize, const R: usize, const L: usize>(
+        &mut self,
+        layout: &mut KeyberonLayout<C, R, L, Keycode>,
+    ) {
+        for (row, col) in &self.pending {
+            layout.event(Event::Press(*row, *col));
+        }
+        self.pending.clear();
+        self.pending_since = None;
+    }
+
+    fn trigger<const C: usize, const R: usize, const L: usize>(
+        &mut self,
+        layout: &mut KeyberonLayout<C, R, L, Keycode>,
+        idx: usize,
+        combo: Combo,
+    ) {
+        debug!("[KEYBOARD] Triggered combo {}", idx);
+        layout.event_with_action(Event::Press(COMBO_ROW, idx as u8), combo.action);
+
+        let active = ActiveCombo {
+            idx: idx as u8,
+            held: self.pending.clone(),
+            released: false,
+        };
+        if self.active.push(active).is_err() {
+            // Too many combos are being held, so just tap the combo instead
+            layout.event(Event::Release(COMBO_ROW, idx as u8));
+        }
+
+        self.pending.clear();
+        self.pending_since = None;
+    }
+
+    fn event<const C: usize, const R: usize, const L: usize>(
+        &mut self,
+        combos: &[Option<Combo>],
+        layout: &mut KeyberonLayout<C, R, L, Keycode>,
+        event: Event,
+    ) {
+        match event {
+            Event::Press(row, col) => {
+                let could_trigger = |pending: &[(u8, u8)]| {
+                    combos
+                        .iter()
+                        .flatten()
+                        .any(|combo| combo.contains_all(pending))
                 };
+
+                if self.pending.push((row, col)).is_err() || !could_trigger(&self.pending) {
+                    // This key can't be part of a combo with the keys that are already pending
+                    self.pending.retain(|coord| *coord != (row, col));
+                    self.flush(layout);
+
+                    if !could_trigger(&[(row, col)]) {
+                        layout.event(event);
+                        return;
+                    }
+
+                    self.pending.push((row, col)).unwrap();
+                }
+
+                if self.pending_since.is_none() {
+                    self.pending_since = Some(Instant::now());
+                }
+
+                // Trigger the combo immediately, unless a bigger combo could still be pressed
+                if let Some((idx, combo)) = self.find_exact(combos) {
+                    let bigger_combo = combos.iter().flatten().any(|other| {
+                        other.key_count() > self.pending.len() && other.contains_all(&self.pending)
+                    });
+
+                    if !bigger_combo {
+                        self.trigger(layout, idx, combo);
+                    }
+                }
+            }
+            Event::Release(row, col) => {
+                if self.pending.contains(&(row, col)) {
+                    match self.find_exact(combos) {
+                        Some((idx, combo)) => self.trigger(layout, idx, combo),
+                        None => self.flush(layout),
+                    }
+                }
+
+                if let Some(pos) = self
+                    .active
+                    .iter()
+                    .position(|active| active.held.contains(&(row, col)))
+                {
+                    let active = &mut self.active[pos];
+                    active.held.retain(|coord| *coord != (row, col));
+
+                    // The combo is released as soon as one of its keys is released
+                    if !active.released {
+                        layout.event(Event::Release(COMBO_ROW, active.idx));
+                        active.released = true;
+                    }
+
+                    if active.held.is_empty() {
+                        self.active.swap_remove(pos);
+                    }
+
+                    return;
+                }
+
+                layout.event(event);
             }
         }
-        Timer::after(Duration::from_micros(500)).await;
+    }
+
+    /// Resolve the pending key presses if the combo timeout has passed.
+    fn tick<const C: usize, const R: usize, const L: usize>(
+        &mut self,
+        combos: &[Option<Combo>],
+        layout: &mut KeyberonLayout<C, R, L, Keycode>,
+        timeout: u64,
+    ) {
+        if let Some(since) = self.pending_since {
+            if since.elapsed() >= Duration::from_millis(timeout) {
+                match self.find_exact(combos) {
+                    Some((idx, combo)) => self.trigger(layout, idx, combo),
+                    None => self.flush(layout),
+                }
+            }
+        }
+    }
+}
+
+/// Maximum number of keys from [`KeyboardLayout::KEY_PAIRS`] that can be tracked at the same time.
+const MAX_HELD_KEY_PAIR_KEYS: usize = 8;
+
+/// Tracks keys that are part of a pair in [`KeyboardLayout::KEY_PAIRS`]. Key pairs use the
+/// virtual keys in [`COMBO_ROW`], starting from the last column.
+#[derive(Default)]
+struct KeyPairState {
+    /// Keys that are part of a pair and are currently being held.
+    held: Vec<(u8, u8), MAX_HELD_KEY_PAIR_KEYS>,
+    /// Keys whose release should not be sent to the layout, because they were used by a pair.
+    suppressed: Vec<(u8, u8), MAX_HELD_KEY_PAIR_KEYS>,
+    /// Index of the pair that is currently pressed.
+    active: Option<usize>,
+}
+
+impl KeyPairState {
+    /// Process a matrix event. Returns the event that should be sent to the layout, if any.
+    fn event<const C: usize, const R: usize, const L: usize>(
+        &mut self,
+        pairs: &[((u8, u8), (u8, u8), Action<Keycode>)],
+        layout: &mut KeyberonLayout<C, R, L, Keycode>,
+        event: Event,
+    ) -> Option<Event> {
+        let coord = event.coord();
+
+        if event.is_release() {
+            self.held.retain(|held| *held != coord);
+
+            let Some(pos) = self.suppressed.iter().position(|key| *key == coord) else {
+                return Some(event);
+            };
+            self.suppressed.swap_remove(pos);
+
+            if let Some(idx) = self.active {
+                let (first, second, _) = pairs[idx];
+                if coord == first || coord == second {
+                    layout.event(Event::Release(COMBO_ROW, u8::MAX - idx as u8));
+                    self.active = None;
+                }
+            }
+
+            return None;
+        }
+
+        if !pairs
+            .iter()
+            .any(|(first, second, _)| coord == *first || coord == *second)
+        {
+            return Some(event);
+        }
+
+        if self.active.is_none() {
+            let pair = pairs
+                .iter()
+                .enumerate()
+                .find_map(|(idx, (first, second, _))| {
+                    if coord == *first && self.held.contains(second) {
+                        Some((idx, *second))
+                    } else if coord == *second && self.held.contains(first) {
+                        Some((idx, *first))
+                    } else {
+                        None
+                    }
+                });
+
+            if let Some((idx, other)) = pair {
+                if self.suppressed.len() + 2 <= MAX_HELD_KEY_PAIR_KEYS {
+                    debug!("[KEYBOARD] Triggered key pair {}", idx);
+                    self.held.retain(|held| *held != other);
+                    self.suppressed.push(coord).unwrap();
+                    self.suppressed.push(other).unwrap();
+                    self.active = Some(idx);
+
+                    layout.event_with_action(
+                        Event::Press(COMBO_ROW, u8::MAX - idx as u8),
+                        pairs[idx].2,
+                    );
+
+                    // The first key was already sent to the layout, so it needs to be released
+                    return Some(Event::Release(other.0, other.1));
+                }
+            }
+        }
+
+        let _ = self.held.push(coord);
+        Some(event)
     }
 }
 
@@ -534,24 +1669,67 @@ pub async fn matrix_poll<K: KeyboardMatrix + 'static>(_k: K) {
 /// backlight reactive effects) The coordinates received will be remapped according to the
 /// implementation of [`KeyboardMatrix::remap_to_layout`].
 ///
-/// There can be a maximum of 4 subscribers, and the number of subscribers actually used
-/// depend on what features you have enabled. With underglow and backlight enabled, 2 subscriber
-/// slots will be used.
-pub static MATRIX_EVENTS: PubSubChannel<RawMutex, Event, 4, 4, 1> = PubSubChannel::new();
+/// Events are published as soon as they are received from the matrix, before they are processed
+/// by features that can delay or consume key presses (leader key, key lock, auto shift, key
+/// pairs and combos). This means subscribers always receive the physical key presses and
+/// releases, even if they never reach the layout.
+///
+/// There can be a maximum of 10 subscribers, and the number of subscribers actually used
+/// depend on what features you have enabled. Each lighting feature (underglow and the backlight
+/// features), audio, WPM tracking, idle detection, Via/Vial, Via key events, and confirming a
+/// bootloader jump each use 1 subscriber slot.
+pub static MATRIX_EVENTS: PubSubChannel<RawMutex, Event, 4, 10, 1> = PubSubChannel::new();
+
+/// State that contains the index of the currently active layer. This is the highest layer that is
+/// being held or toggled, or the default layer (set using [`Action::DefaultLayer`]) if there
+/// is none. This is updated by the layout task after each tick of the layout, if the active layer
+/// has changed.
+pub static CURRENT_LAYER_STATE: State<usize> = State::new(
+    0,
+    &[
+        #[cfg(feature = "display")]
+        &crate::display::CURRENT_LAYER_LISTENER,
+        #[cfg(feature = "underglow")]
+        &crate::lighting::underglow::UNDERGLOW_LAYER_LISTENER,
+        #[cfg(all(feature = "split-central", feature = "display"))]
+        &crate::split::central::DISPLAY_STATE_LISTENER,
+    ],
+);
 
 pub async fn layout_collect<K: KeyboardLayout + HIDDevice + 'static>(_k: K)
 where
     [(); K::LAYERS]:,
     [(); K::LAYOUT_COLS]:,
     [(); K::LAYOUT_ROWS]:,
+    [(); K::COMBO_COUNT]:,
 {
+    // Rows 254 and 255 are reserved for virtual keys (combos and leader sequences)
+    const {
+        assert!(
+            K::LAYOUT_ROWS <= u8::MAX as usize - 1 && K::LAYOUT_COLS <= u8::MAX as usize + 1,
+            "Layouts can have at most 254 rows and 256 columns, since keyberon uses u8 coordinates"
+        )
+    };
+
+    // Combos and key pairs share the same row of virtual keys
+    const {
+        assert!(
+            K::COMBO_COUNT + K::KEY_PAIRS.len() <= u8::MAX as usize + 1,
+            "The total number of combos and key pairs can be at most 256"
+        )
+    };
+
     let mut last_keys = Vec::<KeyboardKeycode, 24>::new();
     let layout = K::get_layout();
 
+    if K::TRI_LAYER.is_some() {
+        layout.layout.lock().await.set_tri_layer(K::TRI_LAYER);
+    }
+
     #[cfg(feature = "media-keycodes")]
     let mut codes = [Consumer::Unassigned; 4];
 
-    let mut ticker = Ticker::every(Duration::from_millis(1));
+    let mut ticker = Ticker::every(Duration::from_micros(K::LAYOUT_TICK_INTERVAL_US));
     let matrix_channel = K::get_matrix_events_channel();
     let matrix_event_publisher = MATRIX_EVENTS.immediate_publisher();
 
@@ -561,8 +1739,31 @@ where
     let keyboard_report = K::get_keyboard_report_send_channel();
 
     let mut should_tick_repeatedly = false;
+    let mut combo_state = ComboState::default();
+    let mut key_pair_state = KeyPairState::default();
+    let mut current_layer = CURRENT_LAYER_STATE.get().await;
+
+    #[cfg(feature = "caps-word")]
+    let mut caps_word = crate::caps_word::CapsWordState::default();
+
+    #[cfg(feature = "leader-key")]
+    let mut leader_key = crate::leader_key::LeaderKeyState::default();
+
+    #[cfg(feature = "key-lock")]
+    let mut key_lock = crate::key_lock::KeyLockState::default();
+
+    #[cfg(feature = "auto-shift")]
+    let mut auto_shift = crate::auto_shift::AutoShiftState::default();
+
+    #[cfg(feature = "dynamic-macros")]
+    let mut dynamic_macro = crate::dynamic_macro::DynamicMacroState::default();
+
+    let mut bootloader_key_pressed_at = None;
 
     loop {
+        #[cfg(feature = "unicode")]
+        let mut unicode_codepoint = None;
+
         let keys = {
             let event = if should_tick_repeatedly {
                 matrix_channel.try_receive().ok()
@@ -570,16 +1771,85 @@ where
                 Some(matrix_channel.receive().await)
             };
 
+            // Publish the event before it gets intercepted by any features (e.g. leader key), so
+            // that subscribers always receive the physical key presses and releases. Just
+            // immediately publish since we don't want to hold up any key events to be converted
+            // into keycodes.
+            if let Some(event) = event {
+                matrix_event_publisher.publish_immediate(event);
+            }
+
             let mut layout = layout.layout.lock().await;
 
-            if let Some(event) = event {
-                layout.event(event);
-                matrix_event_publisher.publish_immediate(event); // Just immediately publish since we don't want to hold up any key events to be converted into keycodes.
+            let combos = match K::get_combo_buffer() {
+                Some(buffer) => &buffer.combos[..],
+                None => &[],
+            };
+
+            #[cfg(feature = "leader-key")]
+            leader_key.tick::<K::LeaderKeyDeviceType, _, _, _>(layout.deref_mut());
+
+            #[cfg(feature = "dynamic-macros")]
+            dynamic_macro.tick(layout.deref_mut());
+
+            #[cfg(feature = "leader-key")]
+            let event = event.and_then(|event| {
+                leader_key.event::<K::LeaderKeyDeviceType, _, _, _>(layout.deref_mut(), event)
+            });
+
+            #[cfg(feature = "key-lock")]
+            let event = event.and_then(|event| key_lock.event(layout.deref_mut(), event));
+
+            // Auto shift can delay presses and releases, which get sent on a later tick
+            #[cfg(feature = "auto-shift")]
+            let events = {
+                let mut events = Vec::<Event, 4>::new();
+                events.extend(auto_shift.tick::<K::AutoShiftDeviceType>());
+                if let Some(event) = event {
+                    events.extend(
+                        auto_shift
+                            .event::<K::AutoShiftDeviceType, _, _, _>(layout.deref_mut(), event),
+                    );
+                }
+                events
             };
 
+            #[cfg(not(feature = "auto-shift"))]
+            let events = event;
+
+            for event in events {
+                if let Some(event) = key_pair_state.event(K::KEY_PAIRS, layout.deref_mut(), event) {
+                    combo_state.event(combos, layout.deref_mut(), event);
+                }
+            }
+
+            combo_state.tick(combos, layout.deref_mut(), K::COMBO_TIMEOUT);
+
+            K::on_tick(layout.deref_mut());
+
             let tick = layout.tick();
 
-            let new_layout_state = layout.is_active();
+            #[allow(unused_mut)]
+            let mut new_layout_state = layout.is_active() || combo_state.is_pending();
+
+            #[cfg(feature = "leader-key")]
+            {
+                new_layout_state |= leader_key.is_pending();
+            }
+
+            #[cfg(feature = "auto-shift")]
+            {
+                new_layout_state |= auto_shift.is_pending();
+            }
+
+            #[cfg(feature = "dynamic-macros")]
+            {
+                new_layout_state |= dynamic_macro.is_pending();
+            }
+
+            // Keep ticking while the bootloader key is held, so that we can check the hold time
+            new_layout_state |= bootloader_key_pressed_at.is_some();
+
             if !should_tick_repeatedly && new_layout_state {
                 ticker.reset()
             }
@@ -646,6 +1916,46 @@ where
                             .send(command)
                             .await;
                     }
+                    #[cfg(feature = "audio")]
+                    Keycode::Audio(command) => {
+                        crate::audio::AUDIO_COMMAND_CHANNEL.send(command).await;
+                    }
+                    #[cfg(feature = "bluetooth")]
+                    Keycode::Bluetooth(command) => {
+                        crate::bluetooth::BLUETOOTH_COMMAND_CHANNEL.send(command).await;
+                    }
+                    #[cfg(feature = "caps-word")]
+                    Keycode::CapsWord(command) => {
+                        caps_word.command(command);
+                    }
+                    #[cfg(feature = "leader-key")]
+                    Keycode::Leader => {
+                        leader_key.start();
+                    }
+                    #[cfg(feature = "key-lock")]
+                    Keycode::KeyLock => {
+                        key_lock.toggle();
+                    }
+                    #[cfg(feature = "dynamic-macros")]
+                    Keycode::DynamicMacro(command) => {
+                        dynamic_macro.command(layout.deref_mut(), command);
+                    }
+                    #[cfg(feature = "pointer")]
+                    Keycode::Mouse(key) => {
+                        crate::pointer::mouse::register_mouse_key(key, true);
+                    }
+                    Keycode::Bootloader => {
+                        bootloader_key_pressed_at = Some(Instant::now());
+                        if !should_tick_repeatedly {
+                            ticker.reset()
+                        }
+                        should_tick_repeatedly = true;
+                    }
+                    #[cfg(feature = "unicode")]
+                    Keycode::Unicode(codepoint) => {
+                        // Sent once the layout is unlocked, since the sequence takes a while
+                        unicode_codepoint = Some(codepoint);
+                    }
                 },
                 CustomEvent::Release(keycode) => match keycode {
                     Keycode::User(id) => {
@@ -660,23 +1970,76 @@ where
                             .send(MultipleConsumerReport { codes })
                             .await;
                     }
+                    #[cfg(feature = "pointer")]
+                    Keycode::Mouse(key) => {
+                        crate::pointer::mouse::register_mouse_key(key, false);
+                    }
+                    Keycode::Bootloader => {
+                        bootloader_key_pressed_at = None;
+                    }
                     #[allow(unreachable_patterns)]
                     _ => {}
                 },
             }
 
+            if bootloader_key_pressed_at.is_some_and(|pressed_at| {
+                pressed_at.elapsed() >= Duration::from_millis(K::BOOTLOADER_KEY_HOLD_TIME)
+            }) {
+                info!("[KEYBOARD] Bootloader key held, jumping to bootloader");
+                crate::hw::platform::jump_to_bootloader();
+            }
+
+            // Checked after processing custom keycodes, since user keycodes can also change layers
+            let new_layer = layout.current_layer();
+            if new_layer != current_layer {
+                current_layer = new_layer;
+                CURRENT_LAYER_STATE.set(new_layer).await;
+            }
+
             debug!("[KEYBOARD] Collecting keyboard keycodes");
 
-            let keys = layout
+            #[allow(unused_mut)]
+            let mut keys = layout
                 .keycodes()
                 .filter_map(|k| KeyboardKeycode::try_from(k as u8).ok())
                 .collect::<Vec<KeyboardKeycode, 24>>();
 
+            #[cfg(feature = "caps-word")]
+            if caps_word.update::<K::CapsWordDeviceType>(layout.keycodes())
+                && !keys.contains(&KeyboardKeycode::LeftShift)
+            {
+                keys.push(KeyboardKeycode::LeftShift).ok();
+            }
+
+            #[cfg(feature = "auto-shift")]
+            if auto_shift.should_shift() && !keys.contains(&KeyboardKeycode::LeftShift) {
+                keys.push(KeyboardKeycode::LeftShift).ok();
+            }
+
+            #[cfg(feature = "dynamic-macros")]
+            dynamic_macro.record(&keys);
+
             debug!("[KEYBOARD] Collected {:?}", Debug2Format(&keys));
 
             keys
         }; // unlock the layout, so that another task can register new layout events
 
+        #[cfg(feature = "unicode")]
+        if let Some(codepoint) = unicode_codepoint {
+            if CURRENT_OUTPUT_STATE.get().await.is_some() {
+                crate::unicode::send_unicode_input(
+                    <K::UnicodeDeviceType as crate::unicode::UnicodeDevice>::UNICODE_MODE,
+                    <K::UnicodeDeviceType as crate::unicode::UnicodeDevice>::HOST_LAYOUT,
+                    codepoint,
+                    keyboard_report,
+                )
+                .await;
+
+                // Make sure the currently held keys get re-sent after the sequence
+                last_keys.clear();
+            }
+        }
+
         if last_keys != keys {
             last_keys.clone_from(&keys);
 
@@ -704,8 +2067,109 @@ pub(crate) mod private {
     use keyberon::layout::Event;
 
     use crate::hw::platform::RawMutex;
+    #[cfg(feature = "storage")]
+    use crate::storage::{FlashStorage, StorageDevice, StorageKey, StorageService};
+
+    use super::{KeyboardLayout, KeyboardMatrix, Pollable};
+
+    pub struct EmptyKeyboardMatrix;
+    impl MaybeKeyboardMatrix for EmptyKeyboardMatrix {}
+
+    pub trait MaybeKeyboardMatrix {
+        async fn take_changed_analog_values(_buf: &mut [(u8, u8, u8)]) -> usize {
+            0
+        }
+
+        async fn get_actuation_point(_row: u8, _col: u8) -> Option<u8> {
+            None
+        }
+
+        async fn set_actuation_point(_row: u8, _col: u8, _value: u8) -> bool {
+            false
+        }
+
+        #[cfg(feature = "storage")]
+        async fn initialize_actuation_points<F: FlashStorage, S: StorageDevice>(
+            _database: &StorageService<'_, F, S>,
+        ) where
+            [(); F::ERASE_SIZE]:,
+        {
+        }
+
+        #[cfg(feature = "storage")]
+        async fn save_actuation_points<F: FlashStorage, S: StorageDevice>(
+            _database: &StorageService<'_, F, S>,
+        ) where
+            [(); F::ERASE_SIZE]:,
+        {
+        }
+    }
+
+    impl<T: KeyboardMatrix> MaybeKeyboardMatrix for T
+    where
+        [(); T::MATRIX_COLS * T::MATRIX_ROWS]:,
+    {
+        async fn take_changed_analog_values(buf: &mut [(u8, u8, u8)]) -> usize {
+            let mut matrix = T::get_matrix().matrix.lock().await;
+            let mut count = 0;
+            for (slot, value) in buf.iter_mut().zip(matrix.changed_analog_values()) {
+                *slot = value;
+                count += 1;
+            }
+            count
+        }
+
+        async fn get_actuation_point(row: u8, col: u8) -> Option<u8> {
+            let matrix = T::get_matrix().matrix.lock().await;
+            matrix.get_actuation_point(row as usize, col as usize)
+        }
+
+        async fn set_actuation_point(row: u8, col: u8, value: u8) -> bool {
+            let mut matrix = T::get_matrix().matrix.lock().await;
+            matrix.set_actuation_point(row as usize, col as usize, value)
+        }
+
+        #[cfg(feature = "storage")]
+        async fn initialize_actuation_points<F: FlashStorage, S: StorageDevice>(
+            database: &StorageService<'_, F, S>,
+        ) where
+            [(); F::ERASE_SIZE]:,
+        {
+            let metadata = [T::MATRIX_COLS as u8, T::MATRIX_ROWS as u8];
+            let _ = database
+                .check_metadata(StorageKey::AnalogActuationPoints, &metadata)
+                .await;
+
+            if let Ok(stored_data) = database.read_raw(StorageKey::AnalogActuationPoints).await {
+                let mut matrix = T::get_matrix().matrix.lock().await;
+                for (i, value) in stored_data.iter().enumerate() {
+                    matrix.set_actuation_point(i / T::MATRIX_COLS, i % T::MATRIX_COLS, *value);
+                }
+            }
+        }
 
-    use super::KeyboardLayout;
+        #[cfg(feature = "storage")]
+        async fn save_actuation_points<F: FlashStorage, S: StorageDevice>(
+            database: &StorageService<'_, F, S>,
+        ) where
+            [(); F::ERASE_SIZE]:,
+        {
+            let mut buf = [0; T::MATRIX_COLS * T::MATRIX_ROWS];
+            {
+                let matrix = T::get_matrix().matrix.lock().await;
+                for (i, value) in buf.iter_mut().enumerate() {
+                    if let Some(point) =
+                        matrix.get_actuation_point(i / T::MATRIX_COLS, i % T::MATRIX_COLS)
+                    {
+                        *value = point;
+                    }
+                }
+            }
+            let _ = database
+                .write_raw(StorageKey::AnalogActuationPoints, &buf)
+                .await;
+        }
+    }
 
     pub struct EmptyKeyboardLayout;
     impl MaybeKeyboardLayout for EmptyKeyboardLayout {}
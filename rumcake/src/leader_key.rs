@@ -0,0 +1,174 @@
+//! Leader key feature.
+//!
+//! Pressing [`crate::keyboard::Keycode::Leader`] starts a leader sequence. The keys that are
+//! pressed afterwards are not sent to the layout. Instead, they are matched against
+//! [`LeaderKeyDevice::LEADER_SEQUENCES`], and if a sequence matches, its action is tapped. If the
+//! keys don't match any sequence, or the sequence isn't completed within
+//! [`LeaderKeyDevice::LEADER_TIMEOUT`] milliseconds, the leader sequence is aborted without doing
+//! anything.
+//!
+//! Keys pressed during a leader sequence are still published to
+//! [`crate::keyboard::MATRIX_EVENTS`], so features like WPM tracking, reactive lighting and idle
+//! detection still count them.
+//!
+//! To define leader sequences, implement [`LeaderKeyDevice`], and set
+//! [`crate::keyboard::KeyboardLayout::LeaderKeyDeviceType`].
+
+use defmt::debug;
+use embassy_time::{Duration, Instant};
+use heapless::Vec;
+use keyberon::action::Action;
+use keyberon::layout::{Event, Layout as KeyberonLayout};
+
+use crate::keyboard::Keycode;
+
+/// Maximum number of keys that can be in a leader sequence.
+pub const MAX_LEADER_KEYS: usize = 8;
+
+/// Row used for the virtual key that gets tapped when a leader sequence is matched.
+const LEADER_ROW: u8 = u8::MAX - 1;
+
+/// A trait that keyboards can implement to define leader sequences.
+pub trait LeaderKeyDevice {
+    /// Time in milliseconds, starting from the leader key press, that a leader sequence has to be
+    /// completed in.
+    const LEADER_TIMEOUT: u64 = 1000;
+
+    /// Leader sequences, and the actions that they trigger. Each sequence is a list of layout
+    /// coordinates in the form of (row, col), which must be pressed in order after the leader key.
+    ///
+    /// If a sequence is also the start of a longer sequence, the shorter sequence is only
+    /// triggered once [`LeaderKeyDevice::LEADER_TIMEOUT`] has passed. Sequences longer than
+    /// [`MAX_LEADER_KEYS`] can not be matched.
+    const LEADER_SEQUENCES: &'static [(&'static [(u8, u8)], Action<Keycode>)] = &[];
+}
+
+/// Default leader key behaviour, used if
+/// [`crate::keyboard::KeyboardLayout::LeaderKeyDeviceType`] is not set. This has no sequences,
+/// so every leader sequence is aborted.
+pub struct DefaultLeaderKeyDevice;
+impl LeaderKeyDevice for DefaultLeaderKeyDevice {}
+
+#[derive(Default)]
+pub(crate) struct LeaderKeyState {
+    started: Option<Instant>,
+    sequence: Vec<(u8, u8), MAX_LEADER_KEYS>,
+    /// Keys pressed during the leader sequence that are still being held. Their releases don't
+    /// get sent to the layout.
+    held: Vec<(u8, u8), MAX_LEADER_KEYS>,
+    release_pending: bool,
+}
+
+impl LeaderKeyState {
+    /// Returns `true` if a leader sequence is in progress, or if the virtual key still needs to
+    /// be released.
+    pub(crate) fn is_pending(&self) -> bool {
+        self.started.is_some() || self.release_pending
+    }
+
+    /// Start a new leader sequence. Any sequence that was already in progress is discarded.
+    pub(crate) fn start(&mut self) {
+        debug!("[LEADER_KEY] Leader sequence started");
+        self.started = Some(Instant::now());
+        self.sequence.clear();
+    }
+
+    fn stop(&mut self) {
+        self.started = None;
+        self.sequence.clear();
+    }
+
+    /// Find a sequence that is exactly the keys pressed so far, and determine if there are any
+    /// longer sequences that start with the keys pressed so far.
+    fn find<D: LeaderKeyDevice>(&self) -> (Option<Action<Keycode>>, bool) {
+        let mut exact = None;
+        let mut longer = false;
+
+        for (sequence, action) in D::LEADER_SEQUENCES {
+            if sequence.starts_with(&self.sequence) {
+                if sequence.len() == self.sequence.len() {
+                    exact = exact.or(Some(*action));
+                } else {
+                    longer = true;
+                }
+            }
+        }
+
+        (exact, longer)
+    }
+
+    fn trigger<const C: usize, const R: usize, const L: usize>(
+        &mut self,
+        layout: &mut KeyberonLayout<C, R, L, Keycode>,
+        action: Action<Keycode>,
+    ) {
+        debug!("[LEADER_KEY] Leader sequence matched");
+        layout.event_with_action(Event::Press(LEADER_ROW, 0), action);
+        self.release_pending = true;
+        self.stop();
+    }
+
+    /// Process a matrix event. Returns the event if it should be sent to the layout.
+    pub(crate) fn event<D: LeaderKeyDevice, const C: usize, const R: usize, const L: usize>(
+        &mut self,
+        layout: &mut KeyberonLayout<C, R, L, Keycode>,
+        event: Event,
+    ) -> Option<Event> {
+        match event {
+            Event::Press(row, col) => {
+                if self.started.is_none() {
+                    return Some(event);
+                }
+
+                if self.sequence.push((row, col)).is_err() || self.held.push((row, col)).is_err() {
+                    debug!("[LEADER_KEY] Leader sequence aborted, too many keys");
+                    self.stop();
+                    return None;
+                }
+
+                match self.find::<D>() {
+                    (Some(action), false) => self.trigger(layout, action),
+                    (None, false) => {
+                        debug!("[LEADER_KEY] Leader sequence aborted, no matching sequence");
+                        self.stop();
+                    }
+                    (_, true) => {}
+                }
+
+                None
+            }
+            Event::Release(row, col) => {
+                if let Some(pos) = self.held.iter().position(|key| *key == (row, col)) {
+                    self.held.swap_remove(pos);
+                    return None;
+                }
+
+                Some(event)
+            }
+        }
+    }
+
+    /// Release the virtual key of a matched sequence, and resolve the current sequence if the
+    /// leader timeout has passed. This should be called before processing new events.
+    pub(crate) fn tick<D: LeaderKeyDevice, const C: usize, const R: usize, const L: usize>(
+        &mut self,
+        layout: &mut KeyberonLayout<C, R, L, Keycode>,
+    ) {
+        if self.release_pending {
+            layout.event(Event::Release(LEADER_ROW, 0));
+            self.release_pending = false;
+        }
+
+        if let Some(started) = self.started {
+            if started.elapsed() >= Duration::from_millis(D::LEADER_TIMEOUT) {
+                match self.find::<D>() {
+                    (Some(action), _) if !self.sequence.is_empty() => self.trigger(layout, action),
+                    _ => {
+                        debug!("[LEADER_KEY] Leader sequence timed out");
+                        self.stop();
+                    }
+                }
+            }
+        }
+    }
+}
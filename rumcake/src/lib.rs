@@ -6,8 +6,15 @@
 #![warn(missing_docs)]
 #![doc = include_str!("../../README.md")]
 
+use core::cell::RefCell;
+use core::future::poll_fn;
+use core::task::Poll;
+
+use embassy_sync::blocking_mutex::Mutex as BlockingMutex;
 use embassy_sync::mutex::{Mutex, MutexGuard};
 use embassy_sync::signal::Signal;
+use embassy_sync::waitqueue::MultiWakerRegistration;
+use heapless::Vec;
 
 use crate::hw::platform::RawMutex;
 
@@ -29,11 +36,35 @@ trait Cycle {
     fn decrement(&mut self);
 }
 
+/// Maximum number of listeners that can be added to a [`State`] using [`State::add_listener`].
+pub const MAX_DYNAMIC_STATE_LISTENERS: usize = 4;
+
+/// Error returned by [`State::add_listener`] if [`MAX_DYNAMIC_STATE_LISTENERS`] listeners have
+/// already been added.
+#[derive(Debug)]
+pub struct ListenerCapacityError;
+
+/// Maximum number of tasks that can wait on a [`State`] using [`State::wait_until`] at the same
+/// time. If more tasks are waiting, all of them get woken up and re-check their predicate.
+pub const MAX_STATE_WAITERS: usize = 4;
+
+/// Tasks waiting in [`State::wait_until`], along with a counter that gets incremented on every
+/// notification, so that waiters can tell if the state changed since they last read it.
+struct StateWaiters {
+    generation: u32,
+    wakers: MultiWakerRegistration<MAX_STATE_WAITERS>,
+}
+
 /// Data structure that allows you to notify listeners about any changes to the data being managed.
 /// This can be useful when you want a task to react to changes to certain data.
 pub struct State<'a, T: Clone + PartialEq> {
     data: Mutex<RawMutex, T>,
     listeners: &'a [&'a Signal<RawMutex, ()>],
+    dynamic_listeners: BlockingMutex<
+        RawMutex,
+        RefCell<Vec<&'a Signal<RawMutex, ()>, MAX_DYNAMIC_STATE_LISTENERS>>,
+    >,
+    waiters: BlockingMutex<RawMutex, RefCell<StateWaiters>>,
 }
 
 impl<'a, T: Clone + PartialEq> State<'a, T> {
@@ -42,9 +73,32 @@ impl<'a, T: Clone + PartialEq> State<'a, T> {
         Self {
             data: Mutex::new(data),
             listeners,
+            dynamic_listeners: BlockingMutex::new(RefCell::new(Vec::new())),
+            waiters: BlockingMutex::new(RefCell::new(StateWaiters {
+                generation: 0,
+                wakers: MultiWakerRegistration::new(),
+            })),
         }
     }
 
+    /// Add a listener at runtime, in addition to the listeners that were passed to
+    /// [`State::new`]. This allows optional code to react to changes to the state, without the
+    /// state needing to know about it at compile time.
+    ///
+    /// Up to [`MAX_DYNAMIC_STATE_LISTENERS`] listeners can be added. If this limit has been
+    /// reached, the listener is not added, and an error is returned.
+    pub fn add_listener(
+        &self,
+        listener: &'a Signal<RawMutex, ()>,
+    ) -> Result<(), ListenerCapacityError> {
+        self.dynamic_listeners.lock(|listeners| {
+            listeners
+                .borrow_mut()
+                .push(listener)
+                .map_err(|_| ListenerCapacityError)
+        })
+    }
+
     /// Obtain the state's current value.
     pub async fn get(&self) -> T {
         self.data.lock().await.clone()
@@ -102,11 +156,53 @@ impl<'a, T: Clone + PartialEq> State<'a, T> {
         update_result
     }
 
+    /// Wait until the state's value satisfies `pred`, and return the first value that does. If the
+    /// current value already satisfies `pred`, this returns immediately.
+    ///
+    /// This does not use any of the state's listeners, so it is safe to call from multiple tasks
+    /// at the same time. See [`MAX_STATE_WAITERS`].
+    pub async fn wait_until(&self, pred: impl Fn(&T) -> bool) -> T {
+        loop {
+            // Read the generation before the value, so that a change made after the read still
+            // wakes up the wait below.
+            let generation = self.waiters.lock(|waiters| waiters.borrow().generation);
+
+            let value = self.get().await;
+            if pred(&value) {
+                return value;
+            }
+
+            poll_fn(|cx| {
+                self.waiters.lock(|waiters| {
+                    let mut waiters = waiters.borrow_mut();
+                    if waiters.generation != generation {
+                        return Poll::Ready(());
+                    }
+                    waiters.wakers.register(cx.waker());
+                    Poll::Pending
+                })
+            })
+            .await;
+        }
+    }
+
     /// Send a signal to the listeners. Normally used to notify listeners of any changes to state.
     fn notify_listeners(&self) {
         for listener in self.listeners.iter() {
             listener.signal(());
         }
+
+        self.dynamic_listeners.lock(|listeners| {
+            for listener in listeners.borrow().iter() {
+                listener.signal(());
+            }
+        });
+
+        self.waiters.lock(|waiters| {
+            let mut waiters = waiters.borrow_mut();
+            waiters.generation = waiters.generation.wrapping_add(1);
+            waiters.wakers.wake();
+        });
     }
 }
 
@@ -115,15 +211,46 @@ pub use once_cell;
 
 pub use rumcake_macros::keyboard_main as keyboard;
 
+pub mod color;
 pub mod keyboard;
 mod math;
 
 #[cfg(feature = "storage")]
 pub mod storage;
 
+#[cfg(feature = "unicode")]
+pub mod unicode;
+
+#[cfg(feature = "caps-word")]
+pub mod caps_word;
+
+#[cfg(feature = "leader-key")]
+pub mod leader_key;
+
+#[cfg(feature = "auto-shift")]
+pub mod auto_shift;
+
+#[cfg(feature = "key-lock")]
+pub mod key_lock;
+
+#[cfg(feature = "dynamic-macros")]
+pub mod dynamic_macro;
+
+#[cfg(feature = "wpm")]
+pub mod wpm;
+
+#[cfg(feature = "scan-metrics")]
+pub mod scan_metrics;
+
+#[cfg(feature = "idle")]
+pub mod idle;
+
 #[cfg(feature = "lighting")]
 pub mod lighting;
 
+#[cfg(feature = "status-led")]
+pub mod status_led;
+
 #[cfg(feature = "usb")]
 pub mod usb;
 
@@ -133,6 +260,9 @@ pub mod via;
 #[cfg(feature = "vial")]
 pub mod vial;
 
+#[cfg(feature = "raw-hid")]
+pub mod raw_hid;
+
 #[cfg(any(feature = "split-peripheral", feature = "split-central"))]
 pub mod split;
 
@@ -142,6 +272,15 @@ pub mod bluetooth;
 #[cfg(feature = "display")]
 pub mod display;
 
+#[cfg(feature = "audio")]
+pub mod audio;
+
+#[cfg(feature = "pointer")]
+pub mod pointer;
+
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
+
 pub mod hw;
 
 pub mod drivers;
@@ -154,10 +293,34 @@ pub mod tasks {
     pub use crate::lighting::lighting_storage_task;
     #[cfg(feature = "lighting")]
     pub use crate::lighting::lighting_task;
+    #[cfg(feature = "underglow")]
+    pub use crate::lighting::underglow::underglow_layer_task;
+    #[cfg(all(feature = "underglow", feature = "storage"))]
+    pub use crate::lighting::underglow::underglow_zone_storage_task;
 
     #[cfg(feature = "display")]
     pub use crate::display::display_task;
 
+    #[cfg(feature = "wpm")]
+    pub use crate::wpm::wpm_task;
+
+    #[cfg(feature = "idle")]
+    pub use crate::idle::idle_task;
+
+    #[cfg(feature = "audio")]
+    pub use crate::audio::audio_task;
+    #[cfg(feature = "status-led")]
+    pub use crate::status_led::status_led_task;
+
+    #[cfg(feature = "pointer")]
+    pub use crate::pointer::mouse::mouse_keys_task;
+    #[cfg(feature = "pointer")]
+    pub use crate::pointer::{pointer_driver_task, pointer_task};
+
+    #[cfg(all(feature = "usb", feature = "storage"))]
+    pub use crate::usb::nkro_storage_task;
+    #[cfg(all(feature = "usb", feature = "pointer"))]
+    pub use crate::usb::usb_hid_mouse_write_task;
     #[cfg(feature = "usb")]
     pub use crate::usb::{start_usb, usb_hid_consumer_write_task, usb_hid_kb_write_task};
 
@@ -171,6 +334,11 @@ pub mod tasks {
     #[cfg(feature = "vial")]
     pub use crate::vial::vial_process_task;
 
+    #[cfg(feature = "raw-hid")]
+    pub use crate::raw_hid::raw_hid_process_task;
+    #[cfg(all(feature = "usb", feature = "raw-hid"))]
+    pub use crate::usb::{usb_hid_raw_hid_read_task, usb_hid_raw_hid_write_task};
+
     #[cfg(feature = "split-central")]
     pub use crate::split::central::central_task;
 
@@ -183,6 +351,10 @@ pub mod tasks {
     #[cfg(feature = "nrf-ble")]
     pub use crate::hw::platform::softdevice_task;
 
+    #[cfg(all(feature = "bluetooth", feature = "storage"))]
+    pub use crate::bluetooth::bluetooth_profile_storage_task;
+    #[cfg(feature = "bluetooth")]
+    pub use crate::bluetooth::bluetooth_profile_task;
     #[cfg(all(feature = "nrf", feature = "bluetooth"))]
     pub use crate::bluetooth::nrf_ble::nrf_ble_task;
 
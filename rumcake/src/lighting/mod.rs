@@ -3,6 +3,7 @@ use embassy_futures::select::{select, select3, Either, Either3};
 use embassy_sync::channel::Channel;
 use embassy_time::{Duration, Ticker};
 use keyberon::layout::Event;
+use smart_leds::RGB8;
 
 use crate::hw::platform::RawMutex;
 use crate::keyboard::MATRIX_EVENTS;
@@ -144,6 +145,72 @@ bitflags! {
     }
 }
 
+/// Lookup table used for gamma correction, with a gamma of 2.2. Each lighting animator uses this
+/// table to convert the brightness of each LED (or each color channel) before sending it to the
+/// driver, so that changes in brightness look linear. This can be turned off using the
+/// `GAMMA_CORRECTION_ENABLED` constant of the corresponding lighting device trait.
+///
+/// Since gamma correction is done by the animators, lighting drivers should not apply it again.
+pub const GAMMA_TABLE: [u8; 256] = {
+    // Each entry is `round(255 * (i / 255) ^ (11 / 5))`, calculated with integers. Since the
+    // table never decreases, the search for each entry can start from the floor of the previous
+    // entry.
+    const DIVISOR: u128 = 255u128.pow(6);
+    let mut table = [0; 256];
+    let mut floor: u128 = 0;
+    let mut i = 0;
+    while i < table.len() {
+        let target = (i as u128).pow(11);
+        while floor < 255 && (floor + 1).pow(5) * DIVISOR <= target {
+            floor += 1;
+        }
+        table[i] = if floor < 255 && (2 * floor + 1).pow(5) * DIVISOR <= 32 * target {
+            floor as u8 + 1
+        } else {
+            floor as u8
+        };
+        i += 1;
+    }
+    table
+};
+
+// Make sure that the gamma table keeps the full range of brightness values, and never decreases.
+const _: () = {
+    assert!(GAMMA_TABLE[0] == 0);
+    assert!(GAMMA_TABLE[255] == 255);
+    let mut i = 1;
+    while i < GAMMA_TABLE.len() {
+        assert!(GAMMA_TABLE[i - 1] <= GAMMA_TABLE[i]);
+        i += 1;
+    }
+};
+
+/// Apply gamma correction to a brightness value using [`GAMMA_TABLE`].
+pub fn gamma_correct(value: u8) -> u8 {
+    GAMMA_TABLE[value as usize]
+}
+
+/// Apply gamma correction to each channel of an RGB color using [`GAMMA_TABLE`].
+pub fn gamma_correct_rgb(color: RGB8) -> RGB8 {
+    RGB8::new(
+        gamma_correct(color.r),
+        gamma_correct(color.g),
+        gamma_correct(color.b),
+    )
+}
+
+/// Scale each channel of an RGB color by the corresponding channel of `correction`, where a value
+/// of `255` leaves the channel unchanged. This can be used to correct the color cast of an LED
+/// strip (e.g. white looking green). Each lighting animator applies this after gamma correction,
+/// using the `COLOR_CORRECTION` constant of the corresponding lighting device trait.
+pub fn color_correct_rgb(color: RGB8, correction: RGB8) -> RGB8 {
+    RGB8::new(
+        crate::math::scale(color.r, correction.r),
+        crate::math::scale(color.g, correction.g),
+        crate::math::scale(color.b, correction.b),
+    )
+}
+
 /// Trait which can be used to implement an animator that can be used with the lighting task.
 pub trait Animator {
     /// Type used to control the animator.
@@ -203,7 +270,12 @@ pub async fn lighting_task<A: Animator + 'static>(
     mut animator: A,
     buf_channel: Option<&Channel<RawMutex, A::BufferUpdateArgs, 4>>,
 ) {
-    let mut subscriber = MATRIX_EVENTS.subscriber().unwrap();
+    let Ok(mut subscriber) = MATRIX_EVENTS.subscriber() else {
+        defmt::warn!(
+            "[LIGHTING] Could not subscribe to matrix events. The lighting task will not run."
+        );
+        return;
+    };
     let channel = A::get_command_channel();
     let mut ticker = Ticker::every(Duration::from_millis(1000 / A::FPS as u64));
 
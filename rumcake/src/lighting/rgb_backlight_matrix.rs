@@ -1,6 +1,6 @@
 use core::fmt::Debug;
 
-use defmt::{error, warn, Debug2Format};
+use defmt::{error, info, warn, Debug2Format};
 use embassy_sync::channel::Channel;
 use keyberon::layout::Event;
 use num_derive::FromPrimitive;
@@ -10,11 +10,15 @@ use rand_core::SeedableRng;
 use ringbuffer::{ConstGenericRingBuffer, RingBuffer};
 use rumcake_macros::{generate_items_from_enum_variants, Cycle, LEDEffect};
 use serde::{Deserialize, Serialize};
-use smart_leds::hsv::Hsv;
 use smart_leds::RGB8;
 
+use crate::color::{hsv_to_rgb, Hsv};
 use crate::hw::platform::RawMutex;
-use crate::lighting::{get_led_layout_bounds, Animator, BacklightMatrixDevice, LayoutBounds};
+use crate::lighting::{
+    color_correct_rgb, gamma_correct_rgb, get_led_layout_bounds, Animator, BacklightMatrixDevice,
+    LEDFlags, LayoutBounds,
+};
+use crate::math::{scale, sqrtf};
 use crate::{Cycle, LEDEffect, State};
 
 /// A trait that keyboards must implement to use backlight features.
@@ -27,6 +31,17 @@ pub trait RGBBacklightMatrixDevice: BacklightMatrixDevice {
     /// **This does not have any effect if the selected animation is static.**
     const FPS: usize = 20;
 
+    /// Whether gamma correction should be applied to each color channel of each LED
+    /// before it is sent to the driver, so that changes in brightness look linear. See
+    /// [`crate::lighting::GAMMA_TABLE`].
+    const GAMMA_CORRECTION_ENABLED: bool = true;
+
+    /// Color correction applied to each LED after gamma correction. Each color channel is scaled
+    /// by the corresponding channel of this color, so lowering a channel can be used to correct
+    /// the color cast of your LEDs. See [`crate::lighting::color_correct_rgb`]. Defaults to white,
+    /// which leaves the colors unchanged.
+    const COLOR_CORRECTION: RGB8 = RGB8::new(255, 255, 255);
+
     /// Get a reference to a channel that can receive commands to control the underglow animator
     /// from other tasks.
     #[inline(always)]
@@ -72,9 +87,58 @@ pub trait RGBBacklightMatrixDevice: BacklightMatrixDevice {
     type CentralDevice: crate::split::central::private::MaybeCentralDevice =
         crate::split::central::private::EmptyCentralDevice;
 
+    /// Whether [`RGBBacklightMatrixDevice::lighting_overlay`] should be applied. If this is
+    /// `true`, the animator will render a new frame at the rate specified by
+    /// [`RGBBacklightMatrixDevice::FPS`], even if the selected effect is static, so that changes
+    /// to your indicators are always displayed.
+    const LIGHTING_OVERLAY_ENABLED: bool = false;
+
+    /// Optional hook used to draw indicators (e.g. caps lock, active layer, low battery) on top of
+    /// the current effect. This is called every frame, after the effect has been rendered, with a
+    /// copy of the frame buffer that will be sent to the driver. Changes made to `buf` do not
+    /// affect the frame buffer used by the effect.
+    ///
+    /// You can change specific LEDs by indexing `buf` directly (`buf[row][col]`), or by LED flags
+    /// using [`overlay_by_flags`]. This is only used if
+    /// [`RGBBacklightMatrixDevice::LIGHTING_OVERLAY_ENABLED`] is `true`.
+    async fn lighting_overlay(
+        buf: &mut [[RGB8; Self::LIGHTING_COLS]; Self::LIGHTING_ROWS],
+        config: &RGBBacklightMatrixConfig,
+    ) {
+    }
+
     rgb_backlight_matrix_effect_items!();
 }
 
+/// Set the color of all LEDs that contain the given `flags` in the frame buffer. This can be used
+/// to implement [`RGBBacklightMatrixDevice::lighting_overlay`].
+pub fn overlay_by_flags<D: RGBBacklightMatrixDevice>(
+    buf: &mut [[RGB8; D::LIGHTING_COLS]; D::LIGHTING_ROWS],
+    flags: LEDFlags,
+    color: RGB8,
+) {
+    let matrix = D::get_backlight_matrix();
+    for (buf_row, flags_row) in buf.iter_mut().zip(matrix.flags.iter()) {
+        for (led, led_flags) in buf_row.iter_mut().zip(flags_row.iter()) {
+            if led_flags.contains(flags) {
+                *led = color;
+            }
+        }
+    }
+}
+
+/// Send a command to the RGB backlight matrix animator without waiting, using the channel returned by
+/// [`RGBBacklightMatrixDevice::get_command_channel`]. Returns `true` if the command was queued.
+///
+/// This can be used in contexts that can't `.await`, like
+/// [`KeyboardLayout::on_user_keycode`](crate::keyboard::KeyboardLayout::on_user_keycode). The
+/// channel can only hold 2 commands, so the command will be dropped if the channel is full.
+pub fn send_rgb_backlight_matrix_command_nonblocking<D: RGBBacklightMatrixDevice>(
+    command: RGBBacklightMatrixCommand,
+) -> bool {
+    D::get_command_channel().try_send(command).is_ok()
+}
+
 pub(crate) mod private {
     use embassy_sync::channel::Channel;
 
@@ -168,6 +232,18 @@ pub trait RGBBacklightMatrixDriver<K: RGBBacklightMatrixDevice> {
     /// [`RGBBacklightMatrixDriver::write`] due to the animator being disabled, so you will need to
     /// turn off the LEDs somehow. For example, you can write a brightness of 0 to all LEDs.
     async fn turn_off(&mut self) -> Result<(), Self::DriverDisableError>;
+
+    /// The type of diagnostics that the driver will return from [`RGBBacklightMatrixDriver::read_status`].
+    type DriverStatus: Debug = ();
+
+    /// Read the fault or thermal status of the LED driver (e.g. open/short LED detection), if the
+    /// driver supports it. This gets called after the LEDs are turned on, and the result is logged.
+    ///
+    /// The default implementation returns `None`, which means that the driver does not support
+    /// reading its status.
+    async fn read_status(&mut self) -> Option<Self::DriverStatus> {
+        None
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, MaxSize)]
@@ -325,6 +401,7 @@ pub enum RGBBacklightMatrixEffect {
     PixelFractal,
 
     #[animated]
+    #[reactive]
     TypingHeatmap,
 
     #[animated]
@@ -442,6 +519,17 @@ impl RGBBacklightMatrixEffect {
     }
 }
 
+/// Heat added to a key every time it is pressed, for the typing heatmap effect.
+const HEATMAP_INCREASE: u32 = 32;
+
+/// Maximum distance (in LED coordinates) that the heat of a key press spreads to, for the typing
+/// heatmap effect.
+const HEATMAP_SPREAD: u32 = 40;
+
+/// Amount of time (in units of [`RGBBacklightMatrixAnimator::get_time`]) it takes for the heat of
+/// each key to decrease by 1, for the typing heatmap effect.
+const HEATMAP_DECAY_TIME: u32 = 8;
+
 pub struct RGBBacklightMatrixAnimator<K: RGBBacklightMatrixDevice, D: RGBBacklightMatrixDriver<K>>
 where
     [(); K::LIGHTING_COLS]:,
@@ -449,7 +537,9 @@ where
 {
     config: RGBBacklightMatrixConfig,
     buf: [[RGB8; K::LIGHTING_COLS]; K::LIGHTING_ROWS], // Stores the brightness/value of each LED
-    last_presses: ConstGenericRingBuffer<((u8, u8), u32), 8>, // Stores the row and col of the last 8 key presses, and the time (in ticks) it was pressed
+    last_presses: ConstGenericRingBuffer<((u8, u8), u32), 8>, // Stores the row and col of the last 8 key presses, and the time it was pressed
+    heatmap: [[u8; K::LIGHTING_COLS]; K::LIGHTING_ROWS], // Stores how often each key has been pressed recently
+    heatmap_decay_time: u32,
     tick: u32,
     driver: D,
     bounds: LayoutBounds,
@@ -468,6 +558,8 @@ where
             tick: 0,
             driver,
             last_presses: ConstGenericRingBuffer::new(),
+            heatmap: [[0; D::LIGHTING_COLS]; D::LIGHTING_ROWS],
+            heatmap_decay_time: 0,
             buf: [[RGB8::new(0, 0, 0); D::LIGHTING_COLS]; D::LIGHTING_ROWS],
             bounds: get_led_layout_bounds::<D>(),
             rng: SmallRng::seed_from_u64(1337),
@@ -477,7 +569,15 @@ where
     pub async fn turn_on(&mut self) {
         if let Err(err) = self.driver.turn_on().await {
             warn!("[BACKLIGHT] Animations have been enabled, but the backlight LEDs could not be turned on: {}", Debug2Format(&err));
+            return;
         };
+
+        if let Some(status) = self.driver.read_status().await {
+            info!(
+                "[BACKLIGHT] Backlight driver status: {}",
+                Debug2Format(&status)
+            );
+        }
     }
 
     pub async fn turn_off(&mut self) {
@@ -554,15 +654,37 @@ where
             }
             RGBBacklightMatrixCommand::ResetTime => {
                 self.tick = 0;
+                self.heatmap_decay_time = 0;
             }
         };
     }
 
-    pub fn set_brightness_for_each_led(&mut self, calc: impl Fn(&mut Self, f32, u8) -> Hsv) {
-        unimplemented!()
+    /// Time that has passed since the animator started, adjusted for the configured speed.
+    /// `time` should increment by 255 every second at the default speed.
+    fn get_time(&self) -> u32 {
+        (self.tick << 8)
+            / (((D::FPS as u32) << 8)
+                / (self.config.speed as u32 + 128 + (self.config.speed as u32 >> 1)))
+    }
+
+    pub fn set_brightness_for_each_led(
+        &mut self,
+        calc: impl Fn(&mut Self, u32, (u8, u8), (u8, u8)) -> RGB8,
+    ) {
+        let time = self.get_time();
+
+        for row in 0..D::LIGHTING_ROWS {
+            for col in 0..D::LIGHTING_COLS {
+                if let Some(position) = D::get_backlight_matrix().layout[row][col] {
+                    self.buf[row][col] = calc(self, time, (row as u8, col as u8), position)
+                }
+            }
+        }
     }
 
     pub fn register_event(&mut self, event: Event) {
+        let time = self.get_time();
+
         match event {
             Event::Press(row, col) => {
                 match self
@@ -572,7 +694,7 @@ where
                         *pressed_row == row && *pressed_col == col
                     }) {
                     Some(press) => {
-                        press.1 = self.tick;
+                        press.1 = time;
                     }
                     None => {
                         // Check if the matrix position corresponds to a LED position before pushing
@@ -583,15 +705,49 @@ where
                             .and_then(|pos| *pos)
                             .is_some()
                         {
-                            self.last_presses.push(((row, col), self.tick));
+                            self.last_presses.push(((row, col), time));
                         }
                     }
                 };
+
+                if D::TYPING_HEATMAP_ENABLED {
+                    self.heat_up(row, col);
+                }
             }
             Event::Release(_row, _col) => {} // nothing for now. maybe change some effects to behave depending on the state of a key.
         }
     }
 
+    /// Increase the heat of the LED at the pressed key, and the LEDs around it.
+    fn heat_up(&mut self, row: u8, col: u8) {
+        let layout = &D::get_backlight_matrix().layout;
+        let Some((key_x, key_y)) = layout
+            .get(row as usize)
+            .and_then(|row| row.get(col as usize))
+            .and_then(|pos| *pos)
+        else {
+            return;
+        };
+
+        for (heat_row, layout_row) in self.heatmap.iter_mut().zip(layout.iter()) {
+            for (heat, pos) in heat_row.iter_mut().zip(layout_row.iter()) {
+                let Some((led_x, led_y)) = pos else {
+                    continue;
+                };
+
+                let dx = key_x.abs_diff(*led_x) as u32;
+                let dy = key_y.abs_diff(*led_y) as u32;
+                let dist = sqrtf((dx.pow(2) + dy.pow(2)) as f32) as u32;
+
+                // The pressed key heats up the most, and nearby keys heat up less the further
+                // away they are.
+                if let Some(amount) = HEATMAP_SPREAD.checked_sub(dist) {
+                    *heat = heat.saturating_add((HEATMAP_INCREASE * amount / HEATMAP_SPREAD) as u8);
+                }
+            }
+        }
+    }
+
     pub async fn tick(&mut self) {
         if !self.config.enabled {
             return;
@@ -629,7 +785,30 @@ where
             RGBBacklightMatrixEffect::PixelRain => todo!(),
             RGBBacklightMatrixEffect::PixelFlow => todo!(),
             RGBBacklightMatrixEffect::PixelFractal => todo!(),
-            RGBBacklightMatrixEffect::TypingHeatmap => todo!(),
+            RGBBacklightMatrixEffect::TypingHeatmap => {
+                if D::TYPING_HEATMAP_ENABLED {
+                    // Cool down every key over time
+                    let time = self.get_time();
+                    let decay = time.saturating_sub(self.heatmap_decay_time) / HEATMAP_DECAY_TIME;
+                    if decay > 0 {
+                        self.heatmap_decay_time += decay * HEATMAP_DECAY_TIME;
+                        self.heatmap
+                            .iter_mut()
+                            .flatten()
+                            .for_each(|heat| *heat = heat.saturating_sub(decay as u8));
+                    }
+
+                    self.set_brightness_for_each_led(|animator, _time, (row, col), _pos| {
+                        // Cold keys are blue and dim, while hot keys are red and bright
+                        let heat = animator.heatmap[row as usize][col as usize];
+                        hsv_to_rgb(Hsv {
+                            h: animator.config.hue.wrapping_add(170 - scale(heat, 170)),
+                            s: animator.config.sat,
+                            v: scale(heat.saturating_mul(3), animator.config.val),
+                        })
+                    })
+                }
+            }
             RGBBacklightMatrixEffect::DigitalRain => todo!(),
             RGBBacklightMatrixEffect::SolidReactiveSimple => todo!(),
             RGBBacklightMatrixEffect::SolidReactive => todo!(),
@@ -639,7 +818,51 @@ where
             RGBBacklightMatrixEffect::SolidReactiveMultiCross => todo!(),
             RGBBacklightMatrixEffect::SolidReactiveNexus => todo!(),
             RGBBacklightMatrixEffect::SolidReactiveMultiNexus => todo!(),
-            RGBBacklightMatrixEffect::Splash => todo!(),
+            RGBBacklightMatrixEffect::Splash => {
+                if D::SPLASH_ENABLED {
+                    self.set_brightness_for_each_led(|animator, time, _coord, (led_x, led_y)| {
+                        // Each key press creates a ring that expands outwards from the key, and
+                        // fades as it grows. Overlapping rings are blended additively.
+                        animator.last_presses.iter().fold(
+                            RGB8::new(0, 0, 0),
+                            |color, ((pressed_row, pressed_col), press_time)| {
+                                let Some((key_x, key_y)) = D::get_backlight_matrix().layout
+                                    [*pressed_row as usize]
+                                    [*pressed_col as usize]
+                                else {
+                                    return color;
+                                };
+
+                                let dx = key_x.abs_diff(led_x) as u32;
+                                let dy = key_y.abs_diff(led_y) as u32;
+                                let dist = sqrtf((dx.pow(2) + dy.pow(2)) as f32) as u32;
+
+                                // The ring hasn't reached this LED yet, or has already faded
+                                let Some(effect) =
+                                    (time.abs_diff(*press_time) * 2).checked_sub(dist)
+                                else {
+                                    return color;
+                                };
+                                if effect > u8::MAX as u32 {
+                                    return color;
+                                }
+
+                                let splash = hsv_to_rgb(Hsv {
+                                    h: animator.config.hue.wrapping_add(effect as u8),
+                                    s: animator.config.sat,
+                                    v: scale(u8::MAX - effect as u8, animator.config.val),
+                                });
+
+                                RGB8::new(
+                                    color.r.saturating_add(splash.r),
+                                    color.g.saturating_add(splash.g),
+                                    color.b.saturating_add(splash.b),
+                                )
+                            },
+                        )
+                    })
+                }
+            }
             RGBBacklightMatrixEffect::MultiSplash => todo!(),
             RGBBacklightMatrixEffect::SolidSplash => todo!(),
             RGBBacklightMatrixEffect::SolidMultiSplash => todo!(),
@@ -647,7 +870,33 @@ where
             RGBBacklightMatrixEffect::DirectSet => {} // We just move onto calling the driver, since the frame buffer is updated by the backlight task
         }
 
-        if let Err(err) = self.driver.write(&self.buf).await {
+        let color_correction_enabled = D::COLOR_CORRECTION != RGB8::new(255, 255, 255);
+        let result = if D::LIGHTING_OVERLAY_ENABLED
+            || D::GAMMA_CORRECTION_ENABLED
+            || color_correction_enabled
+        {
+            let mut frame = self.buf;
+            if D::LIGHTING_OVERLAY_ENABLED {
+                D::lighting_overlay(&mut frame, &self.config).await;
+            }
+            if D::GAMMA_CORRECTION_ENABLED {
+                frame
+                    .iter_mut()
+                    .flatten()
+                    .for_each(|color| *color = gamma_correct_rgb(*color));
+            }
+            if color_correction_enabled {
+                frame
+                    .iter_mut()
+                    .flatten()
+                    .for_each(|color| *color = color_correct_rgb(*color, D::COLOR_CORRECTION));
+            }
+            self.driver.write(&frame).await
+        } else {
+            self.driver.write(&self.buf).await
+        };
+
+        if let Err(err) = result {
             error!(
                 "[BACKLIGHT] Couldn't update backlight colors: {}",
                 Debug2Format(&err)
@@ -694,7 +943,13 @@ where
     }
 
     fn is_waiting_for_command(&self) -> bool {
-        !(self.config.enabled && self.config.effect.is_animated())
+        !(self.config.enabled && (self.config.effect.is_animated() || D::LIGHTING_OVERLAY_ENABLED))
+    }
+
+    fn register_matrix_event(&mut self, event: Event) {
+        if self.config.enabled && self.config.effect.is_reactive() {
+            self.register_event(event)
+        }
     }
 
     fn process_command(&mut self, command: Self::CommandType) {
@@ -724,24 +979,36 @@ where
             use crate::split::central::private::MaybeCentralDevice;
             if let Some(channel) = D::CentralDevice::get_message_to_peripheral_channel() {
                 channel
-                    .send(crate::split::MessageToPeripheral::RGBBacklightMatrix(
-                        RGBBacklightMatrixCommand::ResetTime,
-                    ))
+                    .send(
+                        crate::split::MessageToPeripheral::RGBBacklightMatrix(
+                            RGBBacklightMatrixCommand::ResetTime,
+                        )
+                        .into(),
+                    )
                     .await;
                 channel
-                    .send(crate::split::MessageToPeripheral::RGBBacklightMatrix(
-                        RGBBacklightMatrixCommand::SetEffect(self.config.effect),
-                    ))
+                    .send(
+                        crate::split::MessageToPeripheral::RGBBacklightMatrix(
+                            RGBBacklightMatrixCommand::SetEffect(self.config.effect),
+                        )
+                        .into(),
+                    )
                     .await;
                 channel
-                    .send(crate::split::MessageToPeripheral::RGBBacklightMatrix(
-                        RGBBacklightMatrixCommand::SetValue(self.config.val),
-                    ))
+                    .send(
+                        crate::split::MessageToPeripheral::RGBBacklightMatrix(
+                            RGBBacklightMatrixCommand::SetValue(self.config.val),
+                        )
+                        .into(),
+                    )
                     .await;
                 channel
-                    .send(crate::split::MessageToPeripheral::RGBBacklightMatrix(
-                        RGBBacklightMatrixCommand::SetSpeed(self.config.speed),
-                    ))
+                    .send(
+                        crate::split::MessageToPeripheral::RGBBacklightMatrix(
+                            RGBBacklightMatrixCommand::SetSpeed(self.config.speed),
+                        )
+                        .into(),
+                    )
                     .await;
             }
         }
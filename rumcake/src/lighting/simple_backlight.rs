@@ -2,7 +2,7 @@ use core::fmt::Debug;
 use core::marker::PhantomData;
 use core::u8;
 
-use defmt::{error, warn, Debug2Format};
+use defmt::{error, info, warn, Debug2Format};
 use embassy_sync::channel::Channel;
 use keyberon::layout::Event;
 use num_derive::FromPrimitive;
@@ -13,7 +13,7 @@ use rumcake_macros::{generate_items_from_enum_variants, Cycle, LEDEffect};
 use serde::{Deserialize, Serialize};
 
 use crate::hw::platform::RawMutex;
-use crate::lighting::Animator;
+use crate::lighting::{gamma_correct, Animator};
 use crate::math::{scale, sin};
 use crate::{Cycle, LEDEffect, State};
 
@@ -26,6 +26,10 @@ pub trait SimpleBacklightDevice {
     /// **This does not have any effect if the selected animation is static.**
     const FPS: usize = 20;
 
+    /// Whether gamma correction should be applied to the brightness before it is sent to the
+    /// driver, so that changes in brightness look linear. See [`crate::lighting::GAMMA_TABLE`].
+    const GAMMA_CORRECTION_ENABLED: bool = true;
+
     /// Get a reference to a channel that can receive commands to control the simple backlight
     /// animator from other tasks.
     #[inline(always)]
@@ -71,6 +75,18 @@ pub trait SimpleBacklightDevice {
     simple_backlight_effect_items!();
 }
 
+/// Send a command to the simple backlight animator without waiting, using the channel returned by
+/// [`SimpleBacklightDevice::get_command_channel`]. Returns `true` if the command was queued.
+///
+/// This can be used in contexts that can't `.await`, like
+/// [`KeyboardLayout::on_user_keycode`](crate::keyboard::KeyboardLayout::on_user_keycode). The
+/// channel can only hold 2 commands, so the command will be dropped if the channel is full.
+pub fn send_simple_backlight_command_nonblocking<D: SimpleBacklightDevice>(
+    command: SimpleBacklightCommand,
+) -> bool {
+    D::get_command_channel().try_send(command).is_ok()
+}
+
 pub(crate) mod private {
     use embassy_sync::channel::Channel;
 
@@ -132,6 +148,18 @@ pub trait SimpleBacklightDriver<K: SimpleBacklightDevice> {
     /// [`SimpleBacklightDriver::write`] due to the animator being disabled, so you will need to
     /// turn off the LEDs somehow. For example, you can write a brightness of 0 to all LEDs.
     async fn turn_off(&mut self) -> Result<(), Self::DriverDisableError>;
+
+    /// The type of diagnostics that the driver will return from [`SimpleBacklightDriver::read_status`].
+    type DriverStatus: Debug = ();
+
+    /// Read the fault or thermal status of the LED driver (e.g. open/short LED detection), if the
+    /// driver supports it. This gets called after the LEDs are turned on, and the result is logged.
+    ///
+    /// The default implementation returns `None`, which means that the driver does not support
+    /// reading its status.
+    async fn read_status(&mut self) -> Option<Self::DriverStatus> {
+        None
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, MaxSize)]
@@ -241,7 +269,15 @@ impl<D: SimpleBacklightDevice, R: SimpleBacklightDriver<D>> SimpleBacklightAnima
     pub async fn turn_on(&mut self) {
         if let Err(err) = self.driver.turn_on().await {
             warn!("[SIMPLE_BACKLIGHT] Animations have been enabled, but the backlight LEDs could not be turned on: {}", Debug2Format(&err));
+            return;
         };
+
+        if let Some(status) = self.driver.read_status().await {
+            info!(
+                "[SIMPLE_BACKLIGHT] Backlight driver status: {}",
+                Debug2Format(&status)
+            );
+        }
     }
 
     pub async fn turn_off(&mut self) {
@@ -349,7 +385,13 @@ impl<D: SimpleBacklightDevice, R: SimpleBacklightDriver<D>> SimpleBacklightAnima
             }
         }
 
-        if let Err(err) = self.driver.write(self.buf).await {
+        let brightness = if D::GAMMA_CORRECTION_ENABLED {
+            gamma_correct(self.buf)
+        } else {
+            self.buf
+        };
+
+        if let Err(err) = self.driver.write(brightness).await {
             error!(
                 "[BACKLIGHT] Couldn't update backlight: {}",
                 Debug2Format(&err)
@@ -423,24 +465,36 @@ impl<D: SimpleBacklightDevice, R: SimpleBacklightDriver<D>> Animator
             use crate::split::central::private::MaybeCentralDevice;
             if let Some(channel) = D::CentralDevice::get_message_to_peripheral_channel() {
                 channel
-                    .send(crate::split::MessageToPeripheral::SimpleBacklight(
-                        SimpleBacklightCommand::ResetTime,
-                    ))
+                    .send(
+                        crate::split::MessageToPeripheral::SimpleBacklight(
+                            SimpleBacklightCommand::ResetTime,
+                        )
+                        .into(),
+                    )
                     .await;
                 channel
-                    .send(crate::split::MessageToPeripheral::SimpleBacklight(
-                        SimpleBacklightCommand::SetEffect(self.config.effect),
-                    ))
+                    .send(
+                        crate::split::MessageToPeripheral::SimpleBacklight(
+                            SimpleBacklightCommand::SetEffect(self.config.effect),
+                        )
+                        .into(),
+                    )
                     .await;
                 channel
-                    .send(crate::split::MessageToPeripheral::SimpleBacklight(
-                        SimpleBacklightCommand::SetValue(self.config.val),
-                    ))
+                    .send(
+                        crate::split::MessageToPeripheral::SimpleBacklight(
+                            SimpleBacklightCommand::SetValue(self.config.val),
+                        )
+                        .into(),
+                    )
                     .await;
                 channel
-                    .send(crate::split::MessageToPeripheral::SimpleBacklight(
-                        SimpleBacklightCommand::SetSpeed(self.config.speed),
-                    ))
+                    .send(
+                        crate::split::MessageToPeripheral::SimpleBacklight(
+                            SimpleBacklightCommand::SetSpeed(self.config.speed),
+                        )
+                        .into(),
+                    )
                     .await;
             }
         }
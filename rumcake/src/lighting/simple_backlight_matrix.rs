@@ -2,7 +2,7 @@ use core::f32::consts::PI;
 use core::fmt::Debug;
 use core::u8;
 
-use defmt::{error, warn, Debug2Format};
+use defmt::{error, info, warn, Debug2Format};
 use embassy_sync::channel::Channel;
 use keyberon::layout::Event;
 use num_derive::FromPrimitive;
@@ -15,7 +15,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::hw::platform::RawMutex;
 use crate::lighting::{
-    get_led_layout_bounds, Animator, BacklightMatrixDevice, LEDFlags, LayoutBounds,
+    gamma_correct, get_led_layout_bounds, Animator, BacklightMatrixDevice, LEDFlags, LayoutBounds,
 };
 use crate::math::{atan2f, cos, scale, sin, sqrtf};
 use crate::{Cycle, LEDEffect, State};
@@ -30,6 +30,11 @@ pub trait SimpleBacklightMatrixDevice: BacklightMatrixDevice {
     /// **This does not have any effect if the selected animation is static.**
     const FPS: usize = 20;
 
+    /// Whether gamma correction should be applied to the brightness of each LED
+    /// before it is sent to the driver, so that changes in brightness look linear. See
+    /// [`crate::lighting::GAMMA_TABLE`].
+    const GAMMA_CORRECTION_ENABLED: bool = true;
+
     /// Get a reference to a channel that can receive commands to control the underglow animator
     /// from other tasks.
     #[inline(always)]
@@ -79,6 +84,18 @@ pub trait SimpleBacklightMatrixDevice: BacklightMatrixDevice {
     simple_backlight_matrix_effect_items!();
 }
 
+/// Send a command to the simple backlight matrix animator without waiting, using the channel returned by
+/// [`SimpleBacklightMatrixDevice::get_command_channel`]. Returns `true` if the command was queued.
+///
+/// This can be used in contexts that can't `.await`, like
+/// [`KeyboardLayout::on_user_keycode`](crate::keyboard::KeyboardLayout::on_user_keycode). The
+/// channel can only hold 2 commands, so the command will be dropped if the channel is full.
+pub fn send_simple_backlight_matrix_command_nonblocking<D: SimpleBacklightMatrixDevice>(
+    command: SimpleBacklightMatrixCommand,
+) -> bool {
+    D::get_command_channel().try_send(command).is_ok()
+}
+
 pub(crate) mod private {
     use embassy_sync::channel::Channel;
 
@@ -161,6 +178,18 @@ pub trait SimpleBacklightMatrixDriver<K: SimpleBacklightMatrixDevice> {
     /// [`SimpleBacklightMatrixDriver::write`] due to the animator being disabled, so you will need to
     /// turn off the LEDs somehow. For example, you can write a brightness of 0 to all LEDs.
     async fn turn_off(&mut self) -> Result<(), Self::DriverDisableError>;
+
+    /// The type of diagnostics that the driver will return from [`SimpleBacklightMatrixDriver::read_status`].
+    type DriverStatus: Debug = ();
+
+    /// Read the fault or thermal status of the LED driver (e.g. open/short LED detection), if the
+    /// driver supports it. This gets called after the LEDs are turned on, and the result is logged.
+    ///
+    /// The default implementation returns `None`, which means that the driver does not support
+    /// reading its status.
+    async fn read_status(&mut self) -> Option<Self::DriverStatus> {
+        None
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, MaxSize)]
@@ -281,6 +310,9 @@ pub enum SimpleBacklightMatrixEffect {
     #[animated]
     #[reactive]
     ReactiveSplash,
+
+    #[animated]
+    IndicatorBreathing,
 }
 
 impl SimpleBacklightMatrixEffect {
@@ -306,6 +338,7 @@ impl SimpleBacklightMatrixEffect {
             SimpleBacklightMatrixEffect::ReactiveCross => D::REACTIVE_CROSS_ENABLED,
             SimpleBacklightMatrixEffect::ReactiveNexus => D::REACTIVE_NEXUS_ENABLED,
             SimpleBacklightMatrixEffect::ReactiveSplash => D::REACTIVE_SPLASH_ENABLED,
+            SimpleBacklightMatrixEffect::IndicatorBreathing => D::INDICATOR_BREATHING_ENABLED,
         }
     }
 }
@@ -347,7 +380,15 @@ where
     pub async fn turn_on(&mut self) {
         if let Err(err) = self.driver.turn_on().await {
             warn!("[BACKLIGHT] Animations have been enabled, but the backlight LEDs could not be turned on: {}", Debug2Format(&err));
+            return;
         };
+
+        if let Some(status) = self.driver.read_status().await {
+            info!(
+                "[BACKLIGHT] Backlight driver status: {}",
+                Debug2Format(&status)
+            );
+        }
     }
 
     pub async fn turn_off(&mut self) {
@@ -788,9 +829,34 @@ where
                     })
                 }
             }
+            SimpleBacklightMatrixEffect::IndicatorBreathing => {
+                if D::INDICATOR_BREATHING_ENABLED {
+                    self.set_brightness_for_each_led(|_animator, time, (row, col), _pos| {
+                        // Only indicator LEDs breathe, other LEDs stay at a steady brightness
+                        if D::get_backlight_matrix().flags[row as usize][col as usize]
+                            .contains(LEDFlags::INDICATOR)
+                        {
+                            sin((time >> 2) as u8) // 4 seconds for one full cycle
+                        } else {
+                            u8::MAX
+                        }
+                    })
+                }
+            }
         }
 
-        if let Err(err) = self.driver.write(&self.buf).await {
+        let result = if D::GAMMA_CORRECTION_ENABLED {
+            let mut frame = self.buf;
+            frame
+                .iter_mut()
+                .flatten()
+                .for_each(|brightness| *brightness = gamma_correct(*brightness));
+            self.driver.write(&frame).await
+        } else {
+            self.driver.write(&self.buf).await
+        };
+
+        if let Err(err) = result {
             error!(
                 "[BACKLIGHT] Couldn't update backlight: {}",
                 Debug2Format(&err)
@@ -867,24 +933,36 @@ where
             use crate::split::central::private::MaybeCentralDevice;
             if let Some(channel) = D::CentralDevice::get_message_to_peripheral_channel() {
                 channel
-                    .send(crate::split::MessageToPeripheral::SimpleBacklightMatrix(
-                        SimpleBacklightMatrixCommand::ResetTime,
-                    ))
+                    .send(
+                        crate::split::MessageToPeripheral::SimpleBacklightMatrix(
+                            SimpleBacklightMatrixCommand::ResetTime,
+                        )
+                        .into(),
+                    )
                     .await;
                 channel
-                    .send(crate::split::MessageToPeripheral::SimpleBacklightMatrix(
-                        SimpleBacklightMatrixCommand::SetEffect(self.config.effect),
-                    ))
+                    .send(
+                        crate::split::MessageToPeripheral::SimpleBacklightMatrix(
+                            SimpleBacklightMatrixCommand::SetEffect(self.config.effect),
+                        )
+                        .into(),
+                    )
                     .await;
                 channel
-                    .send(crate::split::MessageToPeripheral::SimpleBacklightMatrix(
-                        SimpleBacklightMatrixCommand::SetValue(self.config.val),
-                    ))
+                    .send(
+                        crate::split::MessageToPeripheral::SimpleBacklightMatrix(
+                            SimpleBacklightMatrixCommand::SetValue(self.config.val),
+                        )
+                        .into(),
+                    )
                     .await;
                 channel
-                    .send(crate::split::MessageToPeripheral::SimpleBacklightMatrix(
-                        SimpleBacklightMatrixCommand::SetSpeed(self.config.speed),
-                    ))
+                    .send(
+                        crate::split::MessageToPeripheral::SimpleBacklightMatrix(
+                            SimpleBacklightMatrixCommand::SetSpeed(self.config.speed),
+                        )
+                        .into(),
+                    )
                     .await;
             }
         }
@@ -2,6 +2,7 @@ use core::fmt::Debug;
 
 use defmt::{error, warn, Debug2Format};
 use embassy_sync::channel::Channel;
+use embassy_sync::signal::Signal;
 use keyberon::layout::Event;
 use num_derive::FromPrimitive;
 use postcard::experimental::max_size::MaxSize;
@@ -9,14 +10,15 @@ use rand::rngs::SmallRng;
 use rand_core::{RngCore, SeedableRng};
 use rumcake_macros::{generate_items_from_enum_variants, Cycle, LEDEffect};
 use serde::{Deserialize, Serialize};
-use smart_leds::hsv::{hsv2rgb, Hsv};
 use smart_leds::RGB8;
 
+use crate::color::{hsv_to_rgb, Hsv};
 use crate::hw::platform::RawMutex;
+use crate::keyboard::CURRENT_LAYER_STATE;
 use crate::math::{scale, sin};
 use crate::{Cycle, LEDEffect, State};
 
-use super::Animator;
+use super::{color_correct_rgb, gamma_correct_rgb, Animator};
 
 /// A trait that keyboards must implement to use the underglow animator.
 pub trait UnderglowDevice {
@@ -28,12 +30,43 @@ pub trait UnderglowDevice {
     /// **This does not have any effect if the selected animation is static.**
     const FPS: usize = 30;
 
+    /// Whether gamma correction should be applied to each color channel of each LED
+    /// before it is sent to the driver, so that changes in brightness look linear. See
+    /// [`crate::lighting::GAMMA_TABLE`].
+    const GAMMA_CORRECTION_ENABLED: bool = true;
+
+    /// Color correction applied to each LED after gamma correction. Each color channel is scaled
+    /// by the corresponding channel of this color, so lowering a channel can be used to correct
+    /// the color cast of your LEDs. See [`crate::lighting::color_correct_rgb`]. Defaults to white,
+    /// which leaves the colors unchanged.
+    const COLOR_CORRECTION: RGB8 = RGB8::new(255, 255, 255);
+
     /// The number of LEDs used for underglow.
     ///
     /// This number will be used to determine the size of the frame buffer for underglow
     /// animations.
     const NUM_LEDS: usize;
 
+    /// The number of underglow zones. Each zone has its own [`UnderglowConfig`], so different
+    /// parts of the LED strip can display different effects.
+    ///
+    /// By default, all of the LEDs belong to a single zone.
+    const NUM_ZONES: usize = 1;
+
+    /// The number of LEDs in each zone, in the order that they appear on the LED strip. This
+    /// must contain [`UnderglowDevice::NUM_ZONES`] entries, which add up to
+    /// [`UnderglowDevice::NUM_LEDS`]. This is checked at compile time.
+    const ZONE_LEDS: &'static [usize] = &[Self::NUM_LEDS];
+
+    /// Underglow configurations to use for specific layers, in the form of `(layer, config)`.
+    /// When a layer in this list becomes the active layer (see
+    /// [`CURRENT_LAYER_STATE`](crate::keyboard::CURRENT_LAYER_STATE)), its config is applied to
+    /// the selected zone. When the active layer changes to a layer that isn't in this list, the
+    /// config that was used before is restored.
+    ///
+    /// By default, this is empty, so the underglow config is never changed by the active layer.
+    const LAYER_LIGHTING: &'static [(usize, UnderglowConfig)] = &[];
+
     /// Get a reference to a channel that can receive commands to control the underglow animator
     /// from other tasks.
     #[inline(always)]
@@ -62,6 +95,18 @@ pub trait UnderglowDevice {
         &UNDERGLOW_CONFIG_STATE
     }
 
+    /// Get a reference to a state object that contains the index and configuration of the zone
+    /// that is currently selected with [`UnderglowCommand::SelectZone`]. If your device only has
+    /// one zone, this will always contain the same configuration as [`UnderglowDevice::get_state`].
+    #[inline(always)]
+    fn get_selected_zone_state() -> &'static State<'static, (u8, UnderglowConfig)> {
+        /// State that contains the configuration of the selected underglow zone.
+        static UNDERGLOW_SELECTED_ZONE_STATE: State<(u8, UnderglowConfig)> =
+            State::new((0, UnderglowConfig::default()), &[]);
+
+        &UNDERGLOW_SELECTED_ZONE_STATE
+    }
+
     #[cfg(feature = "storage")]
     #[inline(always)]
     fn get_state_listener() -> &'static embassy_sync::signal::Signal<RawMutex, ()> {
@@ -82,6 +127,51 @@ pub trait UnderglowDevice {
     underglow_effect_items!();
 }
 
+pub(crate) static UNDERGLOW_LAYER_LISTENER: Signal<RawMutex, ()> = Signal::new();
+
+/// Apply the configs in [`UnderglowDevice::LAYER_LIGHTING`] when the active layer changes. If
+/// [`UnderglowDevice::LAYER_LIGHTING`] is empty, this task exits immediately.
+pub async fn underglow_layer_task<D: UnderglowDevice>(_d: D) {
+    if D::LAYER_LIGHTING.is_empty() {
+        return;
+    }
+
+    let channel = D::get_command_channel();
+    let selected_zone_state = D::get_selected_zone_state();
+
+    // Config that was used before switching to a layer in `LAYER_LIGHTING`
+    let mut saved_config = None;
+
+    loop {
+        UNDERGLOW_LAYER_LISTENER.wait().await;
+        let layer = CURRENT_LAYER_STATE.get().await;
+
+        match D::LAYER_LIGHTING.iter().find(|(l, _)| *l == layer) {
+            Some((_, config)) => {
+                if saved_config.is_none() {
+                    saved_config = Some(selected_zone_state.get().await.1);
+                }
+                channel.send(UnderglowCommand::SetConfig(*config)).await;
+            }
+            None => {
+                if let Some(config) = saved_config.take() {
+                    channel.send(UnderglowCommand::SetConfig(config)).await;
+                }
+            }
+        }
+    }
+}
+
+/// Send a command to the underglow animator without waiting, using the channel returned by
+/// [`UnderglowDevice::get_command_channel`]. Returns `true` if the command was queued.
+///
+/// This can be used in contexts that can't `.await`, like
+/// [`KeyboardLayout::on_user_keycode`](crate::keyboard::KeyboardLayout::on_user_keycode). The
+/// channel can only hold 2 commands, so the command will be dropped if the channel is full.
+pub fn send_underglow_command_nonblocking<D: UnderglowDevice>(command: UnderglowCommand) -> bool {
+    D::get_command_channel().try_send(command).is_ok()
+}
+
 pub(crate) mod private {
     use embassy_sync::channel::Channel;
 
@@ -100,6 +190,11 @@ pub(crate) mod private {
         fn get_state() -> Option<&'static State<'static, UnderglowConfig>> {
             None
         }
+
+        #[inline(always)]
+        fn get_selected_zone_state() -> Option<&'static State<'static, (u8, UnderglowConfig)>> {
+            None
+        }
     }
 
     impl<T: UnderglowDevice> MaybeUnderglowDevice for T {
@@ -112,6 +207,11 @@ pub(crate) mod private {
         fn get_state() -> Option<&'static State<'static, UnderglowConfig>> {
             Some(T::get_state())
         }
+
+        #[inline(always)]
+        fn get_selected_zone_state() -> Option<&'static State<'static, (u8, UnderglowConfig)>> {
+            Some(T::get_selected_zone_state())
+        }
     }
 }
 
@@ -204,6 +304,18 @@ pub enum UnderglowCommand {
     #[cfg(feature = "storage")]
     SaveConfig = 18,
     ResetTime = 19, // normally used internally for syncing LEDs for split keyboards
+    /// Restrict the effect to the LEDs from index `start` (inclusive) to `end` (exclusive). LEDs
+    /// outside of this range are left untouched. `end` is clamped to [`UnderglowDevice::NUM_LEDS`].
+    SetRange {
+        start: u8,
+        end: u8,
+    } = 20,
+    /// Allow the effect to use all of the LEDs again.
+    ResetRange = 21,
+    /// Select the zone that subsequent commands will control. Zone 0 is selected by default.
+    SelectZone(u8) = 22,
+    /// Replace the whole configuration of the selected zone.
+    SetConfig(UnderglowConfig) = 23,
 }
 
 #[generate_items_from_enum_variants("const {variant_shouty_snake_case}_ENABLED: bool = true")]
@@ -275,15 +387,31 @@ impl UnderglowEffect {
     }
 }
 
+/// Get the total number of LEDs in a list of zones (see [`UnderglowDevice::ZONE_LEDS`]).
+const fn total_zone_leds(zone_leds: &[usize]) -> usize {
+    let mut total = 0;
+    let mut i = 0;
+    while i < zone_leds.len() {
+        total += zone_leds[i];
+        i += 1;
+    }
+    total
+}
+
 pub struct UnderglowAnimator<D: UnderglowDevice, R: UnderglowDriver<D>>
 where
     [(); D::NUM_LEDS]:,
+    [(); D::NUM_ZONES]:,
 {
-    config: UnderglowConfig,
+    configs: [UnderglowConfig; D::NUM_ZONES],
+    selected_zone: usize, // Zone that commands are applied to
+    zone: usize,          // Zone that is currently being rendered
+    enabled: bool,        // Whether the LEDs have been turned on using the driver
     buf: [RGB8; D::NUM_LEDS],
     twinkle_state: [(Hsv, u8); D::NUM_LEDS], // For the twinkle effect specifically, tracks the lifespan of lit LEDs.
     tick: u32,
-    time_of_last_press: u32,
+    time_of_last_press: [u32; D::NUM_ZONES],
+    range: (usize, usize), // Range of LEDs that the effect is rendered to, set by `UnderglowCommand::SetRange`.
     driver: R,
     rng: SmallRng,
 }
@@ -291,100 +419,141 @@ where
 impl<D: UnderglowDevice, R: UnderglowDriver<D>> UnderglowAnimator<D, R>
 where
     [(); D::NUM_LEDS]:,
+    [(); D::NUM_ZONES]:,
 {
+    /// Create a new underglow animator. `config` is used for the first zone, and the other zones
+    /// use the default config.
     pub fn new(config: UnderglowConfig, driver: R) -> Self {
+        const {
+            assert!(
+                D::ZONE_LEDS.len() == D::NUM_ZONES,
+                "`ZONE_LEDS` must contain an entry for each of the `NUM_ZONES` underglow zones"
+            )
+        };
+        const {
+            assert!(
+                total_zone_leds(D::ZONE_LEDS) == D::NUM_LEDS,
+                "The number of LEDs in each zone (`ZONE_LEDS`) must add up to `NUM_LEDS`"
+            )
+        };
+        #[cfg(feature = "storage")]
+        const {
+            assert!(
+                D::NUM_ZONES <= MAX_UNDERGLOW_ZONES,
+                "Only the configs of up to `MAX_UNDERGLOW_ZONES` underglow zones can be stored"
+            )
+        };
+
+        let mut configs = [UnderglowConfig::default(); D::NUM_ZONES];
+        if let Some(first) = configs.first_mut() {
+            *first = config;
+        }
+
         Self {
-            config,
+            configs,
+            selected_zone: 0,
+            zone: 0,
+            enabled: false,
             tick: 0,
             driver,
-            time_of_last_press: 0,
-            twinkle_state: [(
-                Hsv {
-                    hue: 0,
-                    sat: 0,
-                    val: 0,
-                },
-                0,
-            ); D::NUM_LEDS],
+            time_of_last_press: [0; D::NUM_ZONES],
+            range: (0, D::NUM_LEDS),
+            twinkle_state: [(Hsv { h: 0, s: 0, v: 0 }, 0); D::NUM_LEDS],
             buf: [RGB8::new(0, 0, 0); D::NUM_LEDS],
             rng: SmallRng::seed_from_u64(239810),
         }
     }
 
+    /// Get the start (inclusive) and end (exclusive) LED indices of a zone.
+    fn zone_bounds(zone: usize) -> (usize, usize) {
+        let start: usize = D::ZONE_LEDS.iter().take(zone).sum();
+        let end = start + D::ZONE_LEDS.get(zone).copied().unwrap_or(0);
+        (start.min(D::NUM_LEDS), end.min(D::NUM_LEDS))
+    }
+
+    /// Get the config of the zone that is currently being rendered.
+    fn config(&self) -> &UnderglowConfig {
+        &self.configs[self.zone]
+    }
+
     pub async fn turn_on(&mut self) {
+        self.enabled = true;
         if let Err(err) = self.driver.turn_on().await {
             warn!("[UNDERGLOW] Animations have been enabled, but the underglow LEDs could not be turned on: {}", Debug2Format(&err));
         };
     }
 
     pub async fn turn_off(&mut self) {
+        self.enabled = false;
         if let Err(err) = self.driver.turn_off().await {
             warn!("[UNDERGLOW] Animations have been disabled, but the underglow LEDs could not be turned off: {}", Debug2Format(&err));
         };
     }
 
     pub fn process_command(&mut self, command: UnderglowCommand) {
+        let config = &mut self.configs[self.selected_zone];
+
         match command {
             UnderglowCommand::Toggle => {
-                self.config.enabled = !self.config.enabled;
+                config.enabled = !config.enabled;
             }
             UnderglowCommand::TurnOn => {
-                self.config.enabled = true;
+                config.enabled = true;
             }
             UnderglowCommand::TurnOff => {
-                self.config.enabled = false;
+                config.enabled = false;
             }
             UnderglowCommand::NextEffect => {
                 // We assume that there is always at least one effect enabled
                 while {
-                    self.config.effect.increment();
-                    !self.config.effect.is_enabled::<D>()
+                    config.effect.increment();
+                    !config.effect.is_enabled::<D>()
                 } {}
             }
             UnderglowCommand::PrevEffect => {
                 while {
-                    self.config.effect.decrement();
-                    !self.config.effect.is_enabled::<D>()
+                    config.effect.decrement();
+                    !config.effect.is_enabled::<D>()
                 } {}
             }
             UnderglowCommand::SetEffect(effect) => {
-                self.config.effect = effect;
+                config.effect = effect;
             }
             UnderglowCommand::SetHue(hue) => {
-                self.config.hue = hue;
+                config.hue = hue;
             }
             UnderglowCommand::IncreaseHue(amount) => {
-                self.config.hue = self.config.hue.saturating_add(amount);
+                config.hue = config.hue.saturating_add(amount);
             }
             UnderglowCommand::DecreaseHue(amount) => {
-                self.config.hue = self.config.hue.saturating_sub(amount);
+                config.hue = config.hue.saturating_sub(amount);
             }
             UnderglowCommand::SetSaturation(sat) => {
-                self.config.sat = sat;
+                config.sat = sat;
             }
             UnderglowCommand::IncreaseSaturation(amount) => {
-                self.config.sat = self.config.sat.saturating_add(amount);
+                config.sat = config.sat.saturating_add(amount);
             }
             UnderglowCommand::DecreaseSaturation(amount) => {
-                self.config.sat = self.config.sat.saturating_sub(amount);
+                config.sat = config.sat.saturating_sub(amount);
             }
             UnderglowCommand::SetValue(val) => {
-                self.config.val = val;
+                config.val = val;
             }
             UnderglowCommand::IncreaseValue(amount) => {
-                self.config.val = self.config.val.saturating_add(amount);
+                config.val = config.val.saturating_add(amount);
             }
             UnderglowCommand::DecreaseValue(amount) => {
-                self.config.val = self.config.val.saturating_sub(amount);
+                config.val = config.val.saturating_sub(amount);
             }
             UnderglowCommand::SetSpeed(speed) => {
-                self.config.speed = speed;
+                config.speed = speed;
             }
             UnderglowCommand::IncreaseSpeed(amount) => {
-                self.config.speed = self.config.speed.saturating_add(amount);
+                config.speed = config.speed.saturating_add(amount);
             }
             UnderglowCommand::DecreaseSpeed(amount) => {
-                self.config.speed = self.config.speed.saturating_sub(amount);
+                config.speed = config.speed.saturating_sub(amount);
             }
             #[cfg(feature = "storage")]
             UnderglowCommand::SaveConfig => {
@@ -393,74 +562,128 @@ where
             UnderglowCommand::ResetTime => {
                 self.tick = 0;
             }
+            UnderglowCommand::SetRange { start, end } => {
+                let end = (end as usize).min(D::NUM_LEDS);
+                self.range = ((start as usize).min(end), end);
+            }
+            UnderglowCommand::ResetRange => {
+                self.range = (0, D::NUM_LEDS);
+            }
+            UnderglowCommand::SelectZone(zone) => {
+                if (zone as usize) < D::NUM_ZONES {
+                    self.selected_zone = zone as usize;
+                } else {
+                    warn!("[UNDERGLOW] Tried to select an unknown zone: {}", zone);
+                }
+            }
+            UnderglowCommand::SetConfig(new_config) => {
+                *config = new_config;
+            }
         };
     }
 
     pub fn set_brightness_for_each_led(&mut self, calc: impl Fn(&mut Self, u32, u8) -> Hsv) {
-        let time = (self.tick << 8)
-            / (((D::FPS as u32) << 8)
-                / (self.config.speed as u32 + 128 + (self.config.speed as u32 >> 1))); // `time` should increment by 255 every second
-
-        for led in 0..D::NUM_LEDS {
-            let mut hsv = calc(self, time, led as u8);
-            hsv.val = scale(hsv.val, self.config.val);
-            self.buf[led] = hsv2rgb(hsv);
+        let speed = self.config().speed as u32;
+        let time = (self.tick << 8) / (((D::FPS as u32) << 8) / (speed + 128 + (speed >> 1))); // `time` should increment by 255 every second
+
+        // Only render the LEDs in the current zone that are also in the range set by `UnderglowCommand::SetRange`
+        let (start, end) = Self::zone_bounds(self.zone);
+        for led in start.max(self.range.0)..end.min(self.range.1) {
+            let mut hsv = calc(self, time, (led - start) as u8);
+            hsv.v = scale(hsv.v, self.config().val);
+            self.buf[led] = hsv_to_rgb(hsv);
         }
     }
 
     pub fn register_event(&mut self, event: Event) {
-        if self.config.enabled && self.config.effect.is_reactive() {
-            match event {
-                Event::Press(_x, _y) => {
-                    self.time_of_last_press = (self.tick << 8)
-                        / (((D::FPS as u32) << 8)
-                            / (self.config.speed as u32 + 128 + (self.config.speed as u32 >> 1)));
+        for (config, time_of_last_press) in self.configs.iter().zip(&mut self.time_of_last_press) {
+            if config.enabled && config.effect.is_reactive() {
+                match event {
+                    Event::Press(_x, _y) => {
+                        *time_of_last_press = (self.tick << 8)
+                            / (((D::FPS as u32) << 8)
+                                / (config.speed as u32 + 128 + (config.speed as u32 >> 1)));
+                    }
+                    Event::Release(_x, _y) => {} // nothing for now. maybe change some effects to behave depending on the state of a key.
                 }
-                Event::Release(_x, _y) => {} // nothing for now. maybe change some effects to behave depending on the state of a key.
             }
         }
     }
 
     pub async fn tick(&mut self) {
-        if !self.config.enabled {
+        if !self.configs.iter().any(|config| config.enabled) {
             return;
         }
 
-        match self.config.effect {
+        for zone in 0..D::NUM_ZONES {
+            self.zone = zone;
+
+            if self.config().enabled {
+                self.render_effect();
+            } else {
+                self.set_brightness_for_each_led(|_animator, _time, _led| Hsv::default());
+            }
+        }
+
+        let frame = self.buf.iter().map(|color| {
+            let color = if D::GAMMA_CORRECTION_ENABLED {
+                gamma_correct_rgb(*color)
+            } else {
+                *color
+            };
+            color_correct_rgb(color, D::COLOR_CORRECTION)
+        });
+
+        if let Err(err) = self.driver.write(frame).await {
+            error!(
+                "[UNDERGLOW] Couldn't update underglow colors: {}",
+                Debug2Format(&err)
+            );
+        };
+
+        self.tick += 1;
+    }
+
+    /// Render the effect of the current zone to the frame buffer.
+    fn render_effect(&mut self) {
+        let (start, end) = Self::zone_bounds(self.zone);
+        let num_leds = end - start;
+
+        match self.config().effect {
             UnderglowEffect::Solid => {
                 if D::SOLID_ENABLED {
                     self.set_brightness_for_each_led(|animator, _time, _led| Hsv {
-                        hue: animator.config.hue,
-                        sat: animator.config.sat,
-                        val: u8::MAX,
+                        h: animator.config().hue,
+                        s: animator.config().sat,
+                        v: u8::MAX,
                     })
                 }
             }
             UnderglowEffect::Breathing => {
                 if D::BREATHING_ENABLED {
                     self.set_brightness_for_each_led(|animator, time, _led| Hsv {
-                        hue: animator.config.hue,
-                        sat: animator.config.sat,
-                        val: sin((time >> 2) as u8), // 4 seconds for one full cycle
+                        h: animator.config().hue,
+                        s: animator.config().sat,
+                        v: sin((time >> 2) as u8), // 4 seconds for one full cycle
                     })
                 }
             }
             UnderglowEffect::RainbowMood => {
                 if D::RAINBOW_MOOD_ENABLED {
                     self.set_brightness_for_each_led(|animator, time, _led| Hsv {
-                        hue: (time >> 4) as u8, // 16 seconds for a full cycle
-                        sat: animator.config.sat,
-                        val: u8::MAX,
+                        h: (time >> 4) as u8, // 16 seconds for a full cycle
+                        s: animator.config().sat,
+                        v: u8::MAX,
                     })
                 }
             }
             UnderglowEffect::RainbowSwirl => {
                 if D::RAINBOW_SWIRL_ENABLED {
                     self.set_brightness_for_each_led(|animator, time, led| Hsv {
-                        hue: ((((led as u16) << 8) / D::NUM_LEDS as u16) as u8)
+                        h: ((((led as u16) << 8) / num_leds as u16) as u8)
                             .wrapping_add((time >> 4) as u8), // 16 seconds for a full cycle
-                        sat: animator.config.sat,
-                        val: u8::MAX,
+                        s: animator.config().sat,
+                        v: u8::MAX,
                     })
                 }
             }
@@ -469,16 +692,16 @@ where
                     let length = 4;
 
                     self.set_brightness_for_each_led(|animator, time, led| {
-                        let pos = scale(time as u8, D::NUM_LEDS as u8); // 1 second for a full cycle
+                        let pos = scale(time as u8, num_leds as u8); // 1 second for a full cycle
 
                         for j in 0..length {
-                            let lit = (pos + j) % D::NUM_LEDS as u8;
+                            let lit = (pos + j) % num_leds as u8;
 
                             if led == lit {
                                 return Hsv {
-                                    hue: animator.config.hue,
-                                    sat: animator.config.sat,
-                                    val: (u8::MAX as u16 * (j + 1) as u16 / length as u16) as u8,
+                                    h: animator.config().hue,
+                                    s: animator.config().sat,
+                                    v: (u8::MAX as u16 * (j + 1) as u16 / length as u16) as u8,
                                 };
                             }
                         }
@@ -492,31 +715,31 @@ where
                     let length: u32 = 4;
 
                     self.set_brightness_for_each_led(|animator, time, led| {
-                        let pos = ((time * D::NUM_LEDS as u32) >> 8)
-                            % ((D::NUM_LEDS as u32 + length - 1) * 2); // 1 second to traverse a length of NUM_LEDS
+                        let pos =
+                            ((time * num_leds as u32) >> 8) % ((num_leds as u32 + length - 1) * 2); // 1 second to traverse the length of the zone
 
-                        let direction = if pos >= (D::NUM_LEDS as u32 + length - 1) {
+                        let direction = if pos >= (num_leds as u32 + length - 1) {
                             1 // going back
                         } else {
                             0 // going forward
                         };
 
                         let start = if direction == 1 {
-                            2 * D::NUM_LEDS as u32 - pos + length - 2
+                            2 * num_leds as u32 - pos + length - 2
                         } else {
                             pos - length + 1
                         } as i32;
 
                         let end = if direction == 1 {
-                            2 * D::NUM_LEDS as u32 - pos + 2 * length - 3
+                            2 * num_leds as u32 - pos + 2 * length - 3
                         } else {
                             pos
                         } as i32;
 
                         Hsv {
-                            hue: animator.config.hue,
-                            sat: animator.config.sat,
-                            val: if start <= led as i32 && led as i32 <= end {
+                            h: animator.config().hue,
+                            s: animator.config().sat,
+                            v: if start <= led as i32 && led as i32 <= end {
                                 u8::MAX
                             } else {
                                 0
@@ -533,14 +756,14 @@ where
                         let hue = 85 * pos.pow(3) / (pos.pow(3) + (32 - pos).pow(3)); // Cubic bezier curve transition from QMK
 
                         Hsv {
-                            hue: if led % 2 == 1 {
+                            h: if led % 2 == 1 {
                                 hue as u8
                             } else {
                                 (85 - hue) as u8
                             },
-                            sat: animator.config.sat,
+                            s: animator.config().sat,
                             // val calculation modified from QMK to use animator's val setting
-                            val: (u8::MAX - (3 * (42 - (hue % 85).abs_diff(42)) as u8) / 2),
+                            v: (u8::MAX - (3 * (42 - (hue % 85).abs_diff(42)) as u8) / 2),
                         }
                     })
                 }
@@ -551,11 +774,11 @@ where
                     const GRADIENT_RANGES: [u16; 5] = [255, 170, 127, 85, 64];
 
                     self.set_brightness_for_each_led(|animator, _time, led| {
-                        let hue = led as u16 * GRADIENT_RANGES[4] / D::NUM_LEDS as u16;
+                        let hue = led as u16 * GRADIENT_RANGES[4] / num_leds as u16;
                         Hsv {
-                            hue: animator.config.hue + hue as u8,
-                            sat: animator.config.sat,
-                            val: u8::MAX,
+                            h: animator.config().hue + hue as u8,
+                            s: animator.config().sat,
+                            v: u8::MAX,
                         }
                     })
                 }
@@ -568,27 +791,27 @@ where
                         // Test red
                         if pos == 0 {
                             return Hsv {
-                                hue: 0,
-                                sat: animator.config.sat,
-                                val: u8::MAX,
+                                h: 0,
+                                s: animator.config().sat,
+                                v: u8::MAX,
                             };
                         }
 
                         // Test green
                         if pos == 1 {
                             return Hsv {
-                                hue: 85,
-                                sat: animator.config.sat,
-                                val: u8::MAX,
+                                h: 85,
+                                s: animator.config().sat,
+                                v: u8::MAX,
                             };
                         }
 
                         // Test blue
                         if pos == 2 {
                             return Hsv {
-                                hue: 170,
-                                sat: animator.config.sat,
-                                val: u8::MAX,
+                                h: 170,
+                                s: animator.config().sat,
+                                v: u8::MAX,
                             };
                         }
 
@@ -600,14 +823,13 @@ where
                 if D::ALTERNATING_ENABLED {
                     self.set_brightness_for_each_led(|animator, time, led| {
                         let pos = (time >> 8) % 2; // Flip every second
-                        let threshold = (D::NUM_LEDS / 2) as u16;
+                        let threshold = (num_leds / 2) as u16;
                         let led = led as u16;
 
                         Hsv {
-                            hue: animator.config.hue,
-                            sat: animator.config.sat,
-                            val: if (pos == 1 && led < threshold) || (pos == 0 && led >= threshold)
-                            {
+                            h: animator.config().hue,
+                            s: animator.config().sat,
+                            v: if (pos == 1 && led < threshold) || (pos == 0 && led >= threshold) {
                                 u8::MAX
                             } else {
                                 0
@@ -618,14 +840,18 @@ where
             }
             UnderglowEffect::Twinkle => {
                 if D::TWINKLE_ENABLED {
-                    let adjusted_fps = (((D::FPS as u32) << 8)
-                        / (self.config.speed as u32 + 128 + (self.config.speed as u32 >> 1)))
-                        as u8;
+                    let speed = self.config().speed as u32;
+                    let adjusted_fps =
+                        (((D::FPS as u32) << 8) / (speed + 128 + (speed >> 1))) as u8;
 
                     self.set_brightness_for_each_led(|animator, _time, led| {
                         // we will dissect the bits of this random number to set some parameters
                         let rand = animator.rng.next_u32();
-                        let data = animator.twinkle_state.get_mut(led as usize).unwrap();
+                        let sat = animator.config().sat;
+                        let data = animator
+                            .twinkle_state
+                            .get_mut(start + led as usize)
+                            .unwrap();
 
                         // 5% chance of being selected
                         // check if the upper 8 bits correspond to a u8 that is less than 13
@@ -634,9 +860,9 @@ where
                             && animator.tick % (1 + scale(adjusted_fps, 13) as u32) == 0
                         {
                             // use the next 8 bits for hue
-                            data.0.hue = (rand >> 8) as u8;
+                            data.0.h = (rand >> 8) as u8;
                             // use the next 8 bits for saturation
-                            data.0.sat = (rand >> 16) as u8;
+                            data.0.s = (rand >> 16) as u8;
                             data.1 = u8::MAX;
                         }
 
@@ -644,9 +870,9 @@ where
                         data.1 = data.1.saturating_sub(u8::MAX / adjusted_fps);
 
                         Hsv {
-                            hue: data.0.hue,
-                            sat: scale(data.0.sat, animator.config.sat),
-                            val: sin(data.1.wrapping_sub(64)),
+                            h: data.0.h,
+                            s: scale(data.0.s, sat),
+                            v: sin(data.1.wrapping_sub(64)),
                         }
                     })
                 }
@@ -654,23 +880,15 @@ where
             UnderglowEffect::Reactive => {
                 if D::REACTIVE_ENABLED {
                     self.set_brightness_for_each_led(|animator, time, _led| Hsv {
-                        hue: animator.config.hue,
-                        sat: animator.config.sat,
-                        val: (u8::MAX as u32).saturating_sub(time - animator.time_of_last_press)
+                        h: animator.config().hue,
+                        s: animator.config().sat,
+                        v: (u8::MAX as u32)
+                            .saturating_sub(time - animator.time_of_last_press[animator.zone])
                             as u8, // LED fades after one second
                     })
                 }
             }
         }
-
-        if let Err(err) = self.driver.write(self.buf.iter().cloned()).await {
-            error!(
-                "[UNDERGLOW] Couldn't update underglow colors: {}",
-                Debug2Format(&err)
-            );
-        };
-
-        self.tick += 1;
     }
 
     #[cfg(feature = "storage")]
@@ -685,6 +903,7 @@ where
 impl<D: UnderglowDevice, R: UnderglowDriver<D>> Animator for UnderglowAnimator<D, R>
 where
     [(); D::NUM_LEDS]:,
+    [(); D::NUM_ZONES]:,
 {
     type CommandType = UnderglowCommand;
 
@@ -695,9 +914,20 @@ where
     const FPS: usize = D::FPS;
 
     async fn initialize(&mut self) {
-        self.config = D::get_state().get().await;
+        self.configs[0] = D::get_state().get().await;
+
+        // Restore the configs of every zone, if they were obtained from storage
+        #[cfg(feature = "storage")]
+        if let Some(stored) = UNDERGLOW_ZONE_CONFIGS.lock().await.as_ref() {
+            self.configs.copy_from_slice(&stored[..D::NUM_ZONES]);
+            D::get_state().quiet_set(self.configs[0]).await;
+        }
 
-        match self.config.enabled {
+        D::get_selected_zone_state()
+            .quiet_set((0, self.configs[0]))
+            .await;
+
+        match self.configs.iter().any(|config| config.enabled) {
             true => self.turn_on().await,
             false => self.turn_off().await,
         }
@@ -708,7 +938,10 @@ where
     }
 
     fn is_waiting_for_command(&self) -> bool {
-        !(self.config.enabled && self.config.effect.is_animated())
+        !self
+            .configs
+            .iter()
+            .any(|config| config.enabled && config.effect.is_animated())
     }
 
     fn process_command(&mut self, command: Self::CommandType) {
@@ -716,17 +949,27 @@ where
     }
 
     async fn handle_state_change(&mut self) {
-        // Update the config state, after updating the animator's own copy, and check if it was enabled/disabled
-        let toggled = D::get_state()
-            .update(|config| {
-                let toggled = config.enabled != self.config.enabled;
-                **config = self.config;
-                toggled
-            })
+        // Update the config states, after updating the animator's own copy. Only the first zone's
+        // config is stored in the main config state.
+        let config = self.configs[self.selected_zone];
+        D::get_state().set(self.configs[0]).await;
+        D::get_selected_zone_state()
+            .set((self.selected_zone as u8, config))
             .await;
 
-        if toggled {
-            match self.config.enabled {
+        // Save the configs of every zone, if they are being stored
+        #[cfg(feature = "storage")]
+        if let Some(stored) = UNDERGLOW_ZONE_CONFIGS.lock().await.as_mut() {
+            if stored[..D::NUM_ZONES] != self.configs {
+                stored[..D::NUM_ZONES].copy_from_slice(&self.configs);
+                UNDERGLOW_ZONE_CONFIGS_SAVE_SIGNAL.signal(());
+            }
+        }
+
+        // Only turn off the LEDs if all of the zones have been disabled
+        let enabled = self.configs.iter().any(|config| config.enabled);
+        if enabled != self.enabled {
+            match enabled {
                 true => self.turn_on().await,
                 false => self.turn_off().await,
             }
@@ -738,34 +981,60 @@ where
             use crate::split::central::private::MaybeCentralDevice;
             if let Some(channel) = D::CentralDevice::get_message_to_peripheral_channel() {
                 channel
-                    .send(crate::split::MessageToPeripheral::Underglow(
-                        UnderglowCommand::ResetTime,
-                    ))
+                    .send(
+                        crate::split::MessageToPeripheral::Underglow(UnderglowCommand::ResetTime)
+                            .into(),
+                    )
                     .await;
+                if D::NUM_ZONES > 1 {
+                    channel
+                        .send(
+                            crate::split::MessageToPeripheral::Underglow(
+                                UnderglowCommand::SelectZone(self.selected_zone as u8),
+                            )
+                            .into(),
+                        )
+                        .await;
+                }
                 channel
-                    .send(crate::split::MessageToPeripheral::Underglow(
-                        UnderglowCommand::SetEffect(self.config.effect),
-                    ))
+                    .send(
+                        crate::split::MessageToPeripheral::Underglow(UnderglowCommand::SetEffect(
+                            config.effect,
+                        ))
+                        .into(),
+                    )
                     .await;
                 channel
-                    .send(crate::split::MessageToPeripheral::Underglow(
-                        UnderglowCommand::SetHue(self.config.hue),
-                    ))
+                    .send(
+                        crate::split::MessageToPeripheral::Underglow(UnderglowCommand::SetHue(
+                            config.hue,
+                        ))
+                        .into(),
+                    )
                     .await;
                 channel
-                    .send(crate::split::MessageToPeripheral::Underglow(
-                        UnderglowCommand::SetSaturation(self.config.sat),
-                    ))
+                    .send(
+                        crate::split::MessageToPeripheral::Underglow(
+                            UnderglowCommand::SetSaturation(config.sat),
+                        )
+                        .into(),
+                    )
                     .await;
                 channel
-                    .send(crate::split::MessageToPeripheral::Underglow(
-                        UnderglowCommand::SetValue(self.config.val),
-                    ))
+                    .send(
+                        crate::split::MessageToPeripheral::Underglow(UnderglowCommand::SetValue(
+                            config.val,
+                        ))
+                        .into(),
+                    )
                     .await;
                 channel
-                    .send(crate::split::MessageToPeripheral::Underglow(
-                        UnderglowCommand::SetSpeed(self.config.speed),
-                    ))
+                    .send(
+                        crate::split::MessageToPeripheral::Underglow(UnderglowCommand::SetSpeed(
+                            config.speed,
+                        ))
+                        .into(),
+                    )
                     .await;
             }
         }
@@ -783,19 +1052,92 @@ where
 }
 
 #[cfg(feature = "storage")]
-use storage::*;
+pub use storage::*;
 
 #[cfg(feature = "storage")]
 mod storage {
+    use core::any::TypeId;
+
+    use defmt::{info, warn, Debug2Format};
+    use embassy_futures::select::{select, Either};
+    use embassy_sync::mutex::Mutex;
     use embassy_sync::signal::Signal;
+    use embassy_time::{Duration, Timer};
 
     use crate::hw::platform::RawMutex;
+    use crate::storage::{FlashStorage, StorageDevice, StorageKey, StorageService};
 
-    use super::{UnderglowAnimator, UnderglowDevice, UnderglowDriver};
+    use super::{UnderglowAnimator, UnderglowConfig, UnderglowDevice, UnderglowDriver};
 
     pub(super) static UNDERGLOW_CONFIG_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
     pub(super) static UNDERGLOW_SAVE_SIGNAL: Signal<RawMutex, ()> = Signal::new();
 
+    /// Maximum number of underglow zones that can have their configs saved to storage.
+    pub const MAX_UNDERGLOW_ZONES: usize = 8;
+
+    /// Configs of every underglow zone. This is `None` until the configs are obtained from storage
+    /// using [`initialize_underglow_zone_data`].
+    pub(super) static UNDERGLOW_ZONE_CONFIGS: Mutex<
+        RawMutex,
+        Option<[UnderglowConfig; MAX_UNDERGLOW_ZONES]>,
+    > = Mutex::new(None);
+    pub(super) static UNDERGLOW_ZONE_CONFIGS_SAVE_SIGNAL: Signal<RawMutex, ()> = Signal::new();
+
+    /// Obtains the configs of every underglow zone from storage. If it fails to get data, the
+    /// zones will use the default config. This must be called before the underglow animator is
+    /// initialized, and [`underglow_zone_storage_task`] should be spawned to save any changes.
+    pub async fn initialize_underglow_zone_data<S: StorageDevice + 'static, F: FlashStorage>(
+        database: &StorageService<'_, F, S>,
+    ) where
+        [(); F::ERASE_SIZE]:,
+    {
+        let metadata: [u8; core::mem::size_of::<TypeId>()] =
+            unsafe { core::mem::transmute(TypeId::of::<[UnderglowConfig; MAX_UNDERGLOW_ZONES]>()) };
+        let _ = database
+            .check_metadata(StorageKey::UnderglowZoneConfigs, &metadata)
+            .await;
+
+        let configs = if let Ok(configs) = database.read(StorageKey::UnderglowZoneConfigs).await {
+            info!(
+                "[UNDERGLOW] Obtained zone configs from storage: {}",
+                Debug2Format(&configs)
+            );
+            configs
+        } else {
+            warn!("[UNDERGLOW] Could not get zone configs from storage, using default configs.");
+            [UnderglowConfig::default(); MAX_UNDERGLOW_ZONES]
+        };
+
+        UNDERGLOW_ZONE_CONFIGS.lock().await.replace(configs);
+    }
+
+    /// Task that saves the configs of every underglow zone to storage, once they haven't been
+    /// changed for 5 seconds.
+    pub async fn underglow_zone_storage_task<S: StorageDevice, F: FlashStorage>(
+        database: &StorageService<'_, F, S>,
+    ) where
+        [(); F::ERASE_SIZE]:,
+    {
+        loop {
+            UNDERGLOW_ZONE_CONFIGS_SAVE_SIGNAL.wait().await;
+
+            // Wait for the configs to stop changing
+            while let Either::Second(()) = select(
+                Timer::after(Duration::from_secs(5)),
+                UNDERGLOW_ZONE_CONFIGS_SAVE_SIGNAL.wait(),
+            )
+            .await
+            {}
+
+            let configs = *UNDERGLOW_ZONE_CONFIGS.lock().await;
+            if let Some(configs) = configs {
+                let _ = database
+                    .write(StorageKey::UnderglowZoneConfigs, configs)
+                    .await;
+            }
+        }
+    }
+
     pub struct UnderglowStorage<D, R> {
         pub(super) _device_phantom: core::marker::PhantomData<D>,
         pub(super) _driver_phantom: core::marker::PhantomData<R>,
@@ -805,6 +1147,7 @@ mod storage {
         for UnderglowStorage<D, R>
     where
         [(); D::NUM_LEDS]:,
+        [(); D::NUM_ZONES]:,
     {
         type Animator = UnderglowAnimator<D, R>;
 
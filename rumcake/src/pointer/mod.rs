@@ -0,0 +1,207 @@
+//! Pointing device feature.
+//!
+//! Pointing devices (trackpoints, trackballs, optical sensors, etc.) move the host's cursor by
+//! sending mouse HID reports. To use the pointing device feature, keyboards must implement
+//! [`PointerDevice`], and provide a driver that implements [`PointerDriver`].
+//!
+//! Drivers generate [`MouseEvent`]s, which are collected by [`pointer_task`] and turned into HID
+//! reports. Keyboards without a pointing device can also move the cursor using [`mouse`] keys.
+
+use core::fmt::Debug;
+
+use defmt::{error, warn, Debug2Format};
+use embassy_sync::channel::Channel;
+use embassy_time::{Duration, Ticker};
+use usbd_human_interface_device::device::mouse::WheelMouseReport;
+
+use crate::hw::platform::RawMutex;
+use crate::hw::{HIDDevice, HIDOutput, CURRENT_OUTPUT_STATE};
+
+pub mod mouse;
+
+/// Mouse buttons that can be pressed by a pointing device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MouseButton {
+    Left = 0,
+    Right = 1,
+    Middle = 2,
+    Back = 3,
+    Forward = 4,
+}
+
+/// Events that can be generated by a pointing device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseEvent {
+    /// Move the cursor by the given amount on the X and Y axes.
+    Movement(i16, i16),
+    /// Scroll vertically and horizontally by the given amount.
+    Scroll(i8, i8),
+    /// Press a mouse button.
+    Press(MouseButton),
+    /// Release a mouse button.
+    Release(MouseButton),
+}
+
+pub(crate) static POINTER_EVENT_CHANNEL: Channel<RawMutex, MouseEvent, 8> = Channel::new();
+
+/// A trait that keyboards must implement to use a pointing device.
+pub trait PointerDevice: HIDDevice {}
+
+pub(crate) mod private {
+    use embassy_sync::channel::Channel;
+    use usbd_human_interface_device::device::mouse::WheelMouseReport;
+
+    use crate::hw::platform::RawMutex;
+
+    use super::PointerDevice;
+
+    pub struct EmptyPointerDevice;
+    impl MaybePointerDevice for EmptyPointerDevice {}
+
+    pub trait MaybePointerDevice {
+        #[inline(always)]
+        fn get_mouse_report_send_channel() -> Option<&'static Channel<RawMutex, WheelMouseReport, 1>>
+        {
+            None
+        }
+    }
+
+    impl<T: PointerDevice> MaybePointerDevice for T {
+        #[inline(always)]
+        fn get_mouse_report_send_channel() -> Option<&'static Channel<RawMutex, WheelMouseReport, 1>>
+        {
+            Some(T::get_mouse_report_send_channel())
+        }
+    }
+}
+
+/// A trait that a driver must implement in order to control the cursor.
+pub trait PointerDriver<K: PointerDevice> {
+    /// The type of error that the driver will return if [`PointerDriver::read_movement`] fails.
+    type DriverError: Debug;
+
+    /// How often the driver should be polled for movement, in milliseconds.
+    const POLL_INTERVAL: u64 = 10;
+
+    /// Called once, before the driver is polled for the first time. You can use this to
+    /// calibrate the sensor.
+    async fn init(&mut self) -> Result<(), Self::DriverError> {
+        Ok(())
+    }
+
+    /// Read the movement since the last time the driver was polled. The first value is the
+    /// movement on the X axis, and the second value is the movement on the Y axis.
+    async fn read_movement(&mut self) -> Result<(i16, i16), Self::DriverError>;
+}
+
+pub async fn pointer_driver_task<K: PointerDevice, D: PointerDriver<K>>(_k: K, mut driver: D) {
+    if let Err(err) = driver.init().await {
+        error!(
+            "[POINTER] Couldn't initialize pointer driver: {}",
+            Debug2Format(&err)
+        );
+    }
+
+    let mut ticker = Ticker::every(Duration::from_millis(D::POLL_INTERVAL));
+
+    loop {
+        match driver.read_movement().await {
+            Ok((0, 0)) => {}
+            Ok((x, y)) => {
+                POINTER_EVENT_CHANNEL.send(MouseEvent::Movement(x, y)).await;
+            }
+            Err(err) => {
+                error!("[POINTER] Couldn't read movement: {}", Debug2Format(&err));
+            }
+        }
+
+        ticker.next().await;
+    }
+}
+
+#[derive(Default)]
+struct PendingReport {
+    buttons: u8,
+    x: i32,
+    y: i32,
+    vertical_wheel: i32,
+    horizontal_wheel: i32,
+}
+
+impl PendingReport {
+    fn register_event(&mut self, event: MouseEvent) {
+        match event {
+            MouseEvent::Movement(x, y) => {
+                self.x += x as i32;
+                self.y += y as i32;
+            }
+            MouseEvent::Scroll(vertical, horizontal) => {
+                self.vertical_wheel += vertical as i32;
+                self.horizontal_wheel += horizontal as i32;
+            }
+            MouseEvent::Press(button) => self.buttons |= 1 << button as u8,
+            MouseEvent::Release(button) => self.buttons &= !(1 << button as u8),
+        }
+    }
+
+    /// Drop any pending motion, while keeping track of the buttons that are held.
+    fn discard_motion(&mut self) {
+        *self = Self {
+            buttons: self.buttons,
+            ..Default::default()
+        };
+    }
+
+    fn has_motion(&self) -> bool {
+        self.x != 0 || self.y != 0 || self.vertical_wheel != 0 || self.horizontal_wheel != 0
+    }
+
+    /// Take as much of the pending motion as can fit in a single report.
+    fn take_report(&mut self) -> WheelMouseReport {
+        fn take(value: &mut i32) -> i8 {
+            let taken = (*value).clamp(-127, 127);
+            *value -= taken;
+            taken as i8
+        }
+
+        WheelMouseReport {
+            buttons: self.buttons,
+            x: take(&mut self.x),
+            y: take(&mut self.y),
+            vertical_wheel: take(&mut self.vertical_wheel),
+            horizontal_wheel: take(&mut self.horizontal_wheel),
+        }
+    }
+}
+
+pub async fn pointer_task<K: PointerDevice>(_k: K) {
+    let channel = K::get_mouse_report_send_channel();
+    let mut pending = PendingReport::default();
+
+    loop {
+        pending.register_event(POINTER_EVENT_CHANNEL.receive().await);
+
+        // Combine any other events that came in while we were waiting
+        while let Ok(event) = POINTER_EVENT_CHANNEL.try_receive() {
+            pending.register_event(event);
+        }
+
+        // Mouse reports are only sent over USB. If USB is not connected, the report channel can
+        // become filled, so we discard the report in that case, like keyboard reports.
+        if !matches!(CURRENT_OUTPUT_STATE.get().await, Some(HIDOutput::Usb)) {
+            warn!("[POINTER] Discarding report");
+            pending.discard_motion();
+            continue;
+        }
+
+        // Movement that doesn't fit in a single report is split across multiple reports
+        loop {
+            channel.send(pending.take_report()).await;
+
+            if !pending.has_motion() {
+                break;
+            }
+        }
+    }
+}
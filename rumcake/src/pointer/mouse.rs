@@ -0,0 +1,241 @@
+//! Mouse keys.
+//!
+//! Mouse keys let you control the cursor with keys in your layout, using
+//! [`crate::keyboard::Keycode::Mouse`]. While a movement key is held, the cursor starts moving at
+//! [`MouseKeyDevice::MOUSE_KEY_INITIAL_SPEED`], and accelerates to
+//! [`MouseKeyDevice::MOUSE_KEY_MAX_SPEED`] over [`MouseKeyDevice::MOUSE_KEY_TIME_TO_MAX`]
+//! milliseconds. Wheel keys accelerate in the same way, using the `MOUSE_KEY_WHEEL_*` constants.
+//! Button keys press and release mouse buttons.
+//!
+//! [`mouse_keys_task`] turns the held mouse keys into [`MouseEvent`]s, which are sent to the host
+//! by [`super::pointer_task`].
+
+use core::cell::Cell;
+
+use embassy_futures::select::select;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::hw::platform::{BlockingMutex, RawMutex};
+
+use super::{MouseButton, MouseEvent, POINTER_EVENT_CHANNEL};
+
+/// A trait that keyboards must implement to use mouse keys. All of the speeds are in pixels (or
+/// wheel steps) per interval.
+pub trait MouseKeyDevice {
+    /// How often the cursor is moved while a movement key is held, in milliseconds.
+    const MOUSE_KEY_INTERVAL: u64 = 16;
+
+    /// Speed of the cursor when a movement key is first pressed.
+    const MOUSE_KEY_INITIAL_SPEED: u16 = 2;
+
+    /// Speed of the cursor after a movement key has been held for
+    /// [`MouseKeyDevice::MOUSE_KEY_TIME_TO_MAX`] milliseconds.
+    const MOUSE_KEY_MAX_SPEED: u16 = 24;
+
+    /// Time in milliseconds that it takes for the cursor to reach its maximum speed.
+    const MOUSE_KEY_TIME_TO_MAX: u64 = 1000;
+
+    /// How often the wheel is scrolled while a wheel key is held, in milliseconds.
+    const MOUSE_KEY_WHEEL_INTERVAL: u64 = 80;
+
+    /// Speed of the wheel when a wheel key is first pressed.
+    const MOUSE_KEY_WHEEL_INITIAL_SPEED: u8 = 1;
+
+    /// Speed of the wheel after a wheel key has been held for
+    /// [`MouseKeyDevice::MOUSE_KEY_WHEEL_TIME_TO_MAX`] milliseconds.
+    const MOUSE_KEY_WHEEL_MAX_SPEED: u8 = 4;
+
+    /// Time in milliseconds that it takes for the wheel to reach its maximum speed.
+    const MOUSE_KEY_WHEEL_TIME_TO_MAX: u64 = 2000;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+#[repr(u8)]
+/// An enumeration of mouse keys that can be used in your layout.
+pub enum MouseKey {
+    /// Move the cursor up.
+    Up = 0,
+    /// Move the cursor down.
+    Down = 1,
+    /// Move the cursor to the left.
+    Left = 2,
+    /// Move the cursor to the right.
+    Right = 3,
+    /// Scroll up.
+    WheelUp = 4,
+    /// Scroll down.
+    WheelDown = 5,
+    /// Scroll to the left.
+    WheelLeft = 6,
+    /// Scroll to the right.
+    WheelRight = 7,
+    /// Press a mouse button.
+    Button(MouseButton) = 8,
+}
+
+impl MouseKey {
+    fn mask(self) -> u16 {
+        match self {
+            MouseKey::Up => 1 << 0,
+            MouseKey::Down => 1 << 1,
+            MouseKey::Left => 1 << 2,
+            MouseKey::Right => 1 << 3,
+            MouseKey::WheelUp => 1 << 4,
+            MouseKey::WheelDown => 1 << 5,
+            MouseKey::WheelLeft => 1 << 6,
+            MouseKey::WheelRight => 1 << 7,
+            MouseKey::Button(button) => 1 << (8 + button as u8),
+        }
+    }
+}
+
+const MOVEMENT_MASK: u16 = 0b1111;
+const WHEEL_MASK: u16 = 0b1111 << 4;
+const BUTTONS: [MouseButton; 5] = [
+    MouseButton::Left,
+    MouseButton::Right,
+    MouseButton::Middle,
+    MouseButton::Back,
+    MouseButton::Forward,
+];
+
+static HELD_MOUSE_KEYS: BlockingMutex<Cell<u16>> = BlockingMutex::new(Cell::new(0));
+static MOUSE_KEYS_CHANGED: Signal<RawMutex, ()> = Signal::new();
+
+/// Register the press or release of a mouse key. This never waits, so it can be called while
+/// the layout is locked.
+pub(crate) fn register_mouse_key(key: MouseKey, pressed: bool) {
+    HELD_MOUSE_KEYS.lock(|held| {
+        if pressed {
+            held.set(held.get() | key.mask());
+        } else {
+            held.set(held.get() & !key.mask());
+        }
+    });
+    MOUSE_KEYS_CHANGED.signal(());
+}
+
+/// Get the direction (`-1`, `0` or `1`) of an axis, given the masks of the negative and positive
+/// keys for that axis.
+fn direction(held: u16, negative: MouseKey, positive: MouseKey) -> i16 {
+    (held & positive.mask() != 0) as i16 - (held & negative.mask() != 0) as i16
+}
+
+/// Linearly interpolate between the initial and maximum speed, based on how long the keys have
+/// been held for.
+fn speed(held_for: Duration, initial: u16, max: u16, time_to_max: u64) -> i16 {
+    let held_for = held_for.as_millis();
+    let speed = if held_for >= time_to_max {
+        max
+    } else {
+        let (initial, max) = (initial as i64, max as i64);
+        (initial + (max - initial) * held_for as i64 / time_to_max as i64) as u16
+    };
+
+    speed.min(i16::MAX as u16) as i16
+}
+
+/// Tracks how long a group of mouse keys (movement or wheel) has been held for, and when the
+/// next event should be sent.
+#[derive(Default)]
+struct Acceleration {
+    started: Option<Instant>,
+    next: Option<Instant>,
+}
+
+impl Acceleration {
+    fn update(&mut self, active: bool, now: Instant) {
+        if !active {
+            *self = Self::default();
+        } else if self.started.is_none() {
+            // Move immediately when a key is first pressed
+            self.started = Some(now);
+            self.next = Some(now);
+        }
+    }
+
+    /// If an event is due, return how long the keys have been held for, and schedule the next
+    /// event.
+    fn poll(&mut self, now: Instant, interval: u64) -> Option<Duration> {
+        let (started, next) = (self.started?, self.next?);
+        if now < next {
+            return None;
+        }
+
+        // If we fell behind (e.g. because the pointer task was busy), don't try to catch up
+        let interval = Duration::from_millis(interval);
+        self.next = Some((next + interval).max(now + interval));
+        Some(now - started)
+    }
+}
+
+pub async fn mouse_keys_task<K: MouseKeyDevice>(_k: K) {
+    let mut buttons = 0;
+    let mut movement = Acceleration::default();
+    let mut wheel = Acceleration::default();
+
+    loop {
+        let held = HELD_MOUSE_KEYS.lock(|held| held.get());
+        let now = Instant::now();
+
+        // Buttons
+        for button in BUTTONS {
+            let mask = MouseKey::Button(button).mask();
+            if (held ^ buttons) & mask != 0 {
+                POINTER_EVENT_CHANNEL
+                    .send(if held & mask != 0 {
+                        MouseEvent::Press(button)
+                    } else {
+                        MouseEvent::Release(button)
+                    })
+                    .await;
+            }
+        }
+        buttons = held & !(MOVEMENT_MASK | WHEEL_MASK);
+
+        // Movement
+        movement.update(held & MOVEMENT_MASK != 0, now);
+        if let Some(held_for) = movement.poll(now, K::MOUSE_KEY_INTERVAL) {
+            let speed = speed(
+                held_for,
+                K::MOUSE_KEY_INITIAL_SPEED,
+                K::MOUSE_KEY_MAX_SPEED,
+                K::MOUSE_KEY_TIME_TO_MAX,
+            );
+            let x = direction(held, MouseKey::Left, MouseKey::Right) * speed;
+            let y = direction(held, MouseKey::Up, MouseKey::Down) * speed;
+            if x != 0 || y != 0 {
+                POINTER_EVENT_CHANNEL.send(MouseEvent::Movement(x, y)).await;
+            }
+        }
+
+        // Wheel
+        wheel.update(held & WHEEL_MASK != 0, now);
+        if let Some(held_for) = wheel.poll(now, K::MOUSE_KEY_WHEEL_INTERVAL) {
+            let speed = speed(
+                held_for,
+                K::MOUSE_KEY_WHEEL_INITIAL_SPEED as u16,
+                K::MOUSE_KEY_WHEEL_MAX_SPEED as u16,
+                K::MOUSE_KEY_WHEEL_TIME_TO_MAX,
+            )
+            .min(i8::MAX as i16);
+            let vertical = direction(held, MouseKey::WheelDown, MouseKey::WheelUp) * speed;
+            let horizontal = direction(held, MouseKey::WheelLeft, MouseKey::WheelRight) * speed;
+            if vertical != 0 || horizontal != 0 {
+                POINTER_EVENT_CHANNEL
+                    .send(MouseEvent::Scroll(vertical as i8, horizontal as i8))
+                    .await;
+            }
+        }
+
+        // Wait for the next event, or for the held keys to change
+        match movement.next.into_iter().chain(wheel.next).min() {
+            Some(next) => {
+                select(MOUSE_KEYS_CHANGED.wait(), Timer::at(next)).await;
+            }
+            None => MOUSE_KEYS_CHANGED.wait().await,
+        }
+    }
+}
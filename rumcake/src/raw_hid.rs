@@ -0,0 +1,59 @@
+//! Raw HID communication, for sending arbitrary data between your keyboard and a program running
+//! on your host device (similar to QMK's `raw_hid_receive` and `raw_hid_send`).
+//!
+//! To use raw HID, keyboards must implement [`RawHidDevice`]. Reports are sent and received over a
+//! separate vendor-defined HID interface (usage page `0xFF61`), so they do not interfere with
+//! Via/Vial, which uses usage page `0xFF60`. Currently, raw HID reports can only be sent and
+//! received over USB.
+//!
+//! Reports received from the host are passed to [`RawHidDevice::handle_raw_hid`] by
+//! [`raw_hid_process_task`]. To send a report to the host, send it to the channel returned by
+//! [`HIDDevice::get_raw_hid_send_channel`].
+
+use crate::hw::HIDDevice;
+
+/// Length of each raw HID report, in bytes.
+pub const RAW_HID_REPORT_LENGTH: usize = 32;
+
+/// A trait that keyboards must implement to use raw HID.
+pub trait RawHidDevice {
+    /// Handle a raw HID report sent by the host. By default, this does nothing.
+    ///
+    /// Nothing is sent back to the host automatically. To respond to the host, send a report to
+    /// the channel returned by [`HIDDevice::get_raw_hid_send_channel`] (e.g. using `try_send`,
+    /// since this function can not be `async`).
+    fn handle_raw_hid(_data: &mut [u8; RAW_HID_REPORT_LENGTH]) {}
+}
+
+/// Report descriptor used for raw HID. This is the same as Via's report descriptor, but uses a
+/// different usage page.
+pub(crate) const RAW_HID_REPORT_DESCRIPTOR: &[u8] = &[
+    0x06, 0x61, 0xFF, // Usage Page (Vendor Defined)
+    0x09, 0x61, // Usage (Vendor Defined)
+    0xA1, 0x01, // Collection (Application)
+    // Data to host
+    0x09, 0x62, //   Usage (Vendor Defined)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x26, 0xFF, 0x00, //   Logical Maximum (255)
+    0x95, 0x20, //   Report Count
+    0x75, 0x08, //   Report Size (8)
+    0x81, 0x02, //   Input (Data, Variable, Absolute)
+    // Data from host
+    0x09, 0x63, //   Usage (Vendor Defined)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x26, 0xFF, 0x00, //   Logical Maximum (255)
+    0x95, 0x20, //   Report Count
+    0x75, 0x08, //   Report Size (8)
+    0x91, 0x02, //   Output (Data, Variable, Absolute)
+    0xC0, // End Collection
+];
+
+/// Pass raw HID reports received from the host to [`RawHidDevice::handle_raw_hid`].
+pub async fn raw_hid_process_task<K: RawHidDevice + HIDDevice>(_k: K) {
+    let receive_channel = K::get_raw_hid_receive_channel();
+
+    loop {
+        let mut report = receive_channel.receive().await;
+        K::handle_raw_hid(&mut report);
+    }
+}
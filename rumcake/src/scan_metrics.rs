@@ -0,0 +1,92 @@
+//! Matrix scan metrics, for diagnosing slow matrix scans.
+//!
+//! While the `scan-metrics` feature is enabled, the matrix polling task counts how many times the
+//! matrix is scanned, and stores the number of scans per second in [`SCAN_RATE_STATE`]. The scan
+//! rate is also logged once every second.
+//!
+//! ADC samplers (e.g. those created by `setup_adc_sampler!`) also keep track of how long it took
+//! to obtain the latest sample for each analog pin, which can be retrieved using their
+//! `get_sample_time` method.
+
+use core::cell::Cell;
+
+use defmt::info;
+use embassy_time::{Duration, Instant};
+
+use crate::hw::platform::BlockingMutex;
+use crate::State;
+
+/// State that contains the number of matrix scans that were completed in the last second. This
+/// is updated by the matrix polling task once every second.
+pub static SCAN_RATE_STATE: State<u32> = State::new(
+    0,
+    &[
+        #[cfg(feature = "display")]
+        &crate::display::SCAN_RATE_LISTENER,
+    ],
+);
+
+/// Counts matrix scans, and updates [`SCAN_RATE_STATE`] once every second.
+pub(crate) struct ScanCounter {
+    scans: u32,
+    started: Instant,
+}
+
+impl ScanCounter {
+    pub(crate) fn new() -> Self {
+        Self {
+            scans: 0,
+            started: Instant::now(),
+        }
+    }
+
+    /// Record a completed matrix scan.
+    pub(crate) async fn record_scan(&mut self) {
+        self.scans += 1;
+
+        let elapsed = self.started.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            let rate = (self.scans as u64 * 1000 / elapsed.as_millis()) as u32;
+            info!("[SCAN_METRICS] Matrix scan rate: {} scans per second", rate);
+            SCAN_RATE_STATE.set(rate).await;
+
+            self.scans = 0;
+            self.started = Instant::now();
+        }
+    }
+}
+
+/// Stores how long it took to obtain the latest sample for each of the `C` channels of an ADC
+/// sampler.
+pub struct SampleTimes<const C: usize> {
+    times: BlockingMutex<Cell<[Duration; C]>>,
+}
+
+impl<const C: usize> SampleTimes<C> {
+    pub const fn new() -> Self {
+        Self {
+            times: BlockingMutex::new(Cell::new([Duration::from_ticks(0); C])),
+        }
+    }
+
+    pub(crate) fn record(&self, channel: usize, duration: Duration) {
+        self.times.lock(|times| {
+            let mut new_times = times.get();
+            if let Some(time) = new_times.get_mut(channel) {
+                *time = duration;
+            }
+            times.set(new_times);
+        });
+    }
+
+    /// Get the time that it took to obtain the latest sample for the given channel.
+    pub fn get(&self, channel: usize) -> Option<Duration> {
+        self.times.lock(|times| times.get().get(channel).copied())
+    }
+}
+
+impl<const C: usize> Default for SampleTimes<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
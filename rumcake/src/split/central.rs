@@ -5,18 +5,22 @@
 //! from other peripherals (see [`MessageToCentral`]). There should only be one central device. If
 //! the split keyboard also uses extra features like backlighting or underglow, the central device
 //! will also be responsible for sending their related commands to the peripherals (see
-//! [`MessageToPeripheral`]).
+//! [`MessageToPeripheral`]). Messages can also be sent to a specific peripheral using
+//! [`TargetedMessageToPeripheral`].
 
 use core::fmt::Debug;
 
-use defmt::{error, Debug2Format};
+use defmt::{error, warn, Debug2Format};
 use embassy_futures::select::{select, Either};
 use embassy_sync::channel::Channel;
+#[cfg(feature = "display")]
+use embassy_sync::signal::Signal;
 use embedded_io_async::ReadExactError;
 use postcard::Error;
 
-use super::{MessageToCentral, MessageToPeripheral};
+use super::{MessageToCentral, MessageToPeripheral, TargetedMessageToPeripheral};
 use crate::hw::platform::RawMutex;
+use crate::hw::PERIPHERAL_BATTERY_LEVEL_STATE;
 use crate::keyboard::KeyboardLayout;
 
 pub trait CentralDevice {
@@ -24,19 +28,53 @@ pub trait CentralDevice {
     type Layout: KeyboardLayout;
 
     /// Get a reference to a channel that can receive messages from other tasks to be sent to
-    /// peripherals.
-    fn get_message_to_peripheral_channel() -> &'static Channel<RawMutex, MessageToPeripheral, 4> {
-        static MESSAGE_TO_PERIPHERALS: Channel<RawMutex, MessageToPeripheral, 4> = Channel::new();
+    /// peripherals. A [`MessageToPeripheral`] can be converted into a
+    /// [`TargetedMessageToPeripheral`] to broadcast it to all peripherals.
+    fn get_message_to_peripheral_channel(
+    ) -> &'static Channel<RawMutex, TargetedMessageToPeripheral, 4> {
+        static MESSAGE_TO_PERIPHERALS: Channel<RawMutex, TargetedMessageToPeripheral, 4> =
+            Channel::new();
 
         &MESSAGE_TO_PERIPHERALS
     }
+
+    /// Handle a [`MessageToCentral::Custom`] message sent by a peripheral. By default, this does
+    /// nothing.
+    ///
+    /// The contents of the data are entirely defined by you. Peripherals can send these messages
+    /// using the channel returned by
+    /// [`PeripheralDevice::get_custom_message_channel`](crate::split::peripheral::PeripheralDevice::get_custom_message_channel).
+    fn handle_peripheral_custom_message(_data: &[u8]) {}
+
+    /// Whether the central device should send its display-related state to peripherals in a
+    /// [`MessageToPeripheral::DisplayState`] message whenever it changes. Enable this if your
+    /// peripherals have displays. By default, this is `false`.
+    #[cfg(feature = "display")]
+    const FORWARD_DISPLAY_STATE: bool = false;
+}
+
+/// Notified when any of the states in [`DisplayState`](super::DisplayState) change.
+#[cfg(feature = "display")]
+pub(crate) static DISPLAY_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
+
+#[cfg(feature = "display")]
+async fn collect_display_state() -> super::DisplayState {
+    super::DisplayState {
+        current_layer: crate::keyboard::CURRENT_LAYER_STATE.get().await as u8,
+        output_mode: crate::hw::OUTPUT_MODE_STATE.get().await,
+        #[cfg(feature = "wpm")]
+        wpm: crate::wpm::WPM_STATE.get().await,
+        #[cfg(not(feature = "wpm"))]
+        wpm: 0,
+        battery_level: crate::hw::BATTERY_LEVEL_STATE.get().await,
+    }
 }
 
 pub(crate) mod private {
     use embassy_sync::channel::Channel;
 
     use crate::hw::platform::RawMutex;
-    use crate::split::MessageToPeripheral;
+    use crate::split::TargetedMessageToPeripheral;
 
     use super::CentralDevice;
 
@@ -46,7 +84,7 @@ pub(crate) mod private {
     pub trait MaybeCentralDevice {
         #[inline(always)]
         fn get_message_to_peripheral_channel(
-        ) -> Option<&'static Channel<RawMutex, MessageToPeripheral, 4>> {
+        ) -> Option<&'static Channel<RawMutex, TargetedMessageToPeripheral, 4>> {
             None
         }
     }
@@ -54,7 +92,7 @@ pub(crate) mod private {
     impl<T: CentralDevice> MaybeCentralDevice for T {
         #[inline(always)]
         fn get_message_to_peripheral_channel(
-        ) -> Option<&'static Channel<RawMutex, MessageToPeripheral, 4>> {
+        ) -> Option<&'static Channel<RawMutex, TargetedMessageToPeripheral, 4>> {
             Some(T::get_message_to_peripheral_channel())
         }
     }
@@ -75,6 +113,23 @@ pub trait CentralDeviceDriver {
         &mut self,
         message: MessageToPeripheral,
     ) -> Result<(), CentralDeviceError<Self::DriverError>>;
+
+    /// Send a [`MessageToPeripheral`] to the peripheral at the given index using the driver. What
+    /// the index refers to depends on the driver.
+    ///
+    /// By default, the message is dropped, since the driver can't tell which peripheral the index
+    /// refers to. Drivers that can address individual peripherals should override this.
+    async fn send_message_to_peripheral(
+        &mut self,
+        index: usize,
+        _message: MessageToPeripheral,
+    ) -> Result<(), CentralDeviceError<Self::DriverError>> {
+        warn!(
+            "[SPLIT_CENTRAL] Driver can not send messages to individual peripherals, dropping message for peripheral {}.",
+            index
+        );
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -106,10 +161,35 @@ pub async fn central_task<K: CentralDevice>(_k: K, mut driver: impl CentralDevic
     let message_to_peripherals_channel = K::get_message_to_peripheral_channel();
     let matrix_events_channel = K::Layout::get_matrix_events_channel();
 
+    // Send the initial state to the peripherals
+    #[cfg(feature = "display")]
+    if K::FORWARD_DISPLAY_STATE {
+        DISPLAY_STATE_LISTENER.signal(());
+    }
+
     loop {
+        let message_to_peripherals_fut = async {
+            #[cfg(feature = "display")]
+            if K::FORWARD_DISPLAY_STATE {
+                return match select(
+                    message_to_peripherals_channel.receive(),
+                    DISPLAY_STATE_LISTENER.wait(),
+                )
+                .await
+                {
+                    Either::First(message) => message,
+                    Either::Second(()) => {
+                        MessageToPeripheral::DisplayState(collect_display_state().await).into()
+                    }
+                };
+            }
+
+            message_to_peripherals_channel.receive().await
+        };
+
         match select(
             driver.receive_message_from_peripherals(),
-            message_to_peripherals_channel.receive(),
+            message_to_peripherals_fut,
         )
         .await
         {
@@ -118,6 +198,12 @@ pub async fn central_task<K: CentralDevice>(_k: K, mut driver: impl CentralDevic
                     MessageToCentral::KeyPress(_, _) | MessageToCentral::KeyRelease(_, _) => {
                         matrix_events_channel.send(event.try_into().unwrap()).await;
                     }
+                    MessageToCentral::BatteryLevel(level) => {
+                        PERIPHERAL_BATTERY_LEVEL_STATE.set(level).await;
+                    }
+                    MessageToCentral::Custom(data) => {
+                        K::handle_peripheral_custom_message(&data);
+                    }
                 },
                 Err(err) => {
                     error!(
@@ -126,8 +212,16 @@ pub async fn central_task<K: CentralDevice>(_k: K, mut driver: impl CentralDevic
                     )
                 }
             },
-            Either::Second(message) => {
-                if let Err(err) = driver.broadcast_message_to_peripherals(message).await {
+            Either::Second(TargetedMessageToPeripheral {
+                peripheral,
+                message,
+            }) => {
+                let result = match peripheral {
+                    Some(index) => driver.send_message_to_peripheral(index, message).await,
+                    None => driver.broadcast_message_to_peripherals(message).await,
+                };
+
+                if let Err(err) = result {
                     error!(
                         "[SPLIT_CENTRAL] Error sending matrix events to peripheral: {}",
                         Debug2Format(&err)
@@ -10,6 +10,9 @@ pub mod central;
 #[cfg(feature = "split-peripheral")]
 pub mod peripheral;
 
+/// Length of the data contained in a [`MessageToCentral::Custom`] message, in bytes.
+pub const CUSTOM_MESSAGE_LENGTH: usize = 16;
+
 /// Possible messages that can be sent to a central device.
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, MaxSize)]
 #[repr(u8)]
@@ -18,6 +21,13 @@ pub enum MessageToCentral {
     KeyPress(u8, u8),
     /// Key release in the form of (row, col).
     KeyRelease(u8, u8),
+    /// Battery level of the peripheral, as a percentage.
+    BatteryLevel(u8),
+    /// Arbitrary data defined by the peripheral. This can be used to implement your own
+    /// peripheral to central protocol. Upon receiving this message, the central device will pass
+    /// the data to
+    /// [`CentralDevice::handle_peripheral_custom_message`](crate::split::central::CentralDevice::handle_peripheral_custom_message).
+    Custom([u8; CUSTOM_MESSAGE_LENGTH]),
 }
 
 /// Size of buffer used when sending messages to a central device
@@ -39,6 +49,7 @@ impl TryFrom<MessageToCentral> for Event {
         match message {
             MessageToCentral::KeyPress(row, col) => Ok(Event::Press(row, col)),
             MessageToCentral::KeyRelease(row, col) => Ok(Event::Release(row, col)),
+            MessageToCentral::BatteryLevel(_) | MessageToCentral::Custom(_) => Err(()),
         }
     }
 }
@@ -52,6 +63,7 @@ impl TryFrom<MessageToCentral> for Event {
         feature = "simple-backlight-matrix",
         feature = "rgb-backlight-matrix",
         feature = "underglow",
+        feature = "display",
     ),
     repr(u8)
 )]
@@ -77,7 +89,95 @@ pub enum MessageToPeripheral {
     #[cfg(feature = "underglow")]
     /// An [`UnderglowCommand`](crate::lighting::underglow::UnderglowCommand) to be processed by the peripheral's backlight animator.
     Underglow(crate::lighting::underglow::UnderglowCommand) = 6,
+
+    #[cfg(feature = "display")]
+    /// Display-related state of the central device, to be shown on the peripheral's display.
+    DisplayState(DisplayState) = 7,
+}
+
+/// State of the central device that is sent to peripherals in a
+/// [`MessageToPeripheral::DisplayState`] message. Peripherals use this to update their own copies
+/// of the corresponding states, so that their display can show the same information as the
+/// central device.
+#[cfg(feature = "display")]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, MaxSize)]
+pub struct DisplayState {
+    /// The currently active layer, obtained from [`crate::keyboard::CURRENT_LAYER_STATE`].
+    pub current_layer: u8,
+    /// The current output mode, obtained from [`crate::hw::OUTPUT_MODE_STATE`].
+    pub output_mode: crate::hw::OutputMode,
+    /// The current typing speed, obtained from [`crate::wpm::WPM_STATE`]. This is `0` if the
+    /// central device does not have the `wpm` feature enabled.
+    pub wpm: u16,
+    /// Battery level of the central device, obtained from [`crate::hw::BATTERY_LEVEL_STATE`].
+    pub battery_level: u8,
+}
+
+/// A [`MessageToPeripheral`], along with the peripheral that it should be sent to.
+///
+/// Converting a [`MessageToPeripheral`] into this type will create a message that gets
+/// broadcasted to all peripherals.
+#[derive(Debug, Clone, Copy)]
+pub struct TargetedMessageToPeripheral {
+    /// Index of the peripheral that the message should be sent to. If this is `None`, the message
+    /// is sent to all peripherals. The index of a peripheral depends on the driver being used.
+    pub peripheral: Option<usize>,
+    /// The message to send.
+    pub message: MessageToPeripheral,
+}
+
+impl From<MessageToPeripheral> for TargetedMessageToPeripheral {
+    fn from(message: MessageToPeripheral) -> Self {
+        Self {
+            peripheral: None,
+            message,
+        }
+    }
 }
 
 /// Size of buffer used when sending messages to a peripheral device
 pub const MESSAGE_TO_PERIPHERAL_BUFFER_SIZE: usize = MessageToPeripheral::POSTCARD_MAX_SIZE + 3;
+
+/// Number of bytes used by the CRC that is appended to each message sent by
+/// [`SerialSplitDriver`](crate::drivers::SerialSplitDriver). This is `0` if the `split-crc`
+/// feature is disabled.
+pub const CRC_LENGTH: usize = if cfg!(feature = "split-crc") { 2 } else { 0 };
+
+/// Calculate the CRC-16/CCITT-FALSE checksum of the given data. This is used to detect corrupted
+/// messages when the `split-crc` feature is enabled.
+#[cfg(feature = "split-crc")]
+pub const fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    let mut i = 0;
+    while i < data.len() {
+        crc ^= (data[i] as u16) << 8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        i += 1;
+    }
+    crc
+}
+
+#[cfg(feature = "split-crc")]
+const _: () = {
+    // Check value for CRC-16/CCITT-FALSE
+    assert!(crc16(b"123456789") == 0x29B1);
+
+    // Flipping any bit of a serialized `MessageToCentral::KeyPress(3, 7)` must be detected
+    const FRAME: [u8; 3] = [0x00, 0x03, 0x07];
+    const CRC: u16 = crc16(&FRAME);
+    let mut i = 0;
+    while i < FRAME.len() * 8 {
+        let mut flipped = FRAME;
+        flipped[i / 8] ^= 1 << (i % 8);
+        assert!(crc16(&flipped) != CRC, "A single-bit flip was not detected");
+        i += 1;
+    }
+};
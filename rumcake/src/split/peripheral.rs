@@ -9,15 +9,17 @@
 use core::fmt::Debug;
 
 use defmt::{error, Debug2Format};
-use embassy_futures::select::{select, Either};
+use embassy_futures::select::{select4, Either4};
 use embassy_sync::channel::Channel;
 use embassy_sync::pubsub::PubSubBehavior;
+use embassy_time::{Duration, Ticker};
 use embedded_io_async::ReadExactError;
 use keyberon::layout::Event;
 use postcard::Error;
 
-use super::{MessageToCentral, MessageToPeripheral};
+use super::{MessageToCentral, MessageToPeripheral, CUSTOM_MESSAGE_LENGTH};
 use crate::hw::platform::RawMutex;
+use crate::hw::BATTERY_LEVEL_STATE;
 use crate::keyboard::MATRIX_EVENTS;
 
 // Trait that devices must implement to serve as a peripheral in a split keyboard setup.
@@ -30,6 +32,21 @@ pub trait PeripheralDevice {
         &POLLED_EVENTS_CHANNEL
     }
 
+    /// Get a reference to a channel that can receive data from other tasks to be sent to the
+    /// central device in a [`MessageToCentral::Custom`] message. The central device will pass this
+    /// data to
+    /// [`CentralDevice::handle_peripheral_custom_message`](crate::split::central::CentralDevice::handle_peripheral_custom_message).
+    fn get_custom_message_channel() -> &'static Channel<RawMutex, [u8; CUSTOM_MESSAGE_LENGTH], 1> {
+        static CUSTOM_MESSAGE_CHANNEL: Channel<RawMutex, [u8; CUSTOM_MESSAGE_LENGTH], 1> =
+            Channel::new();
+
+        &CUSTOM_MESSAGE_CHANNEL
+    }
+
+    /// How often the peripheral should send its battery level to the central device, in
+    /// milliseconds. The battery level is read from [`BATTERY_LEVEL_STATE`].
+    const BATTERY_LEVEL_REPORT_INTERVAL: u64 = 30000;
+
     #[cfg(feature = "underglow")]
     type UnderglowDeviceType: crate::lighting::underglow::private::MaybeUnderglowDevice =
         crate::lighting::private::EmptyLightingDevice;
@@ -114,16 +131,20 @@ impl<E> From<ReadExactError<E>> for PeripheralDeviceError<E> {
 // This task replaces the `layout_collect` task, which is usually used on non-split keyboards for sending events to the keyboard layout
 pub async fn peripheral_task<K: PeripheralDevice>(_k: K, mut driver: impl PeripheralDeviceDriver) {
     let channel = K::get_matrix_events_channel();
+    let custom_message_channel = K::get_custom_message_channel();
     let matrix_event_publisher = MATRIX_EVENTS.immediate_publisher();
+    let mut battery_ticker = Ticker::every(Duration::from_millis(K::BATTERY_LEVEL_REPORT_INTERVAL));
 
     loop {
-        match select(
+        match select4(
             driver.receive_message_from_central(),
             channel.receive(),
+            battery_ticker.next(),
+            custom_message_channel.receive(),
         )
         .await
         {
-            Either::First(message) => match message {
+            Either4::First(message) => match message {
                 Ok(message) => match message {
                     #[cfg(feature = "simple-backlight")]
                     MessageToPeripheral::SimpleBacklight(command) => {
@@ -149,6 +170,18 @@ pub async fn peripheral_task<K: PeripheralDevice>(_k: K, mut driver: impl Periph
                             channel.send(command).await
                         }
                     }
+                    #[cfg(feature = "display")]
+                    MessageToPeripheral::DisplayState(state) => {
+                        crate::keyboard::CURRENT_LAYER_STATE
+                            .set(state.current_layer as usize)
+                            .await;
+                        crate::hw::OUTPUT_MODE_STATE.set(state.output_mode).await;
+                        #[cfg(feature = "wpm")]
+                        crate::wpm::WPM_STATE.set(state.wpm).await;
+                        crate::hw::CENTRAL_BATTERY_LEVEL_STATE
+                            .set(state.battery_level)
+                            .await;
+                    }
                     #[allow(unreachable_patterns)]
                     _ => {}
                 },
@@ -159,7 +192,7 @@ pub async fn peripheral_task<K: PeripheralDevice>(_k: K, mut driver: impl Periph
                     )
                 }
             },
-            Either::Second(event) => {
+            Either4::Second(event) => {
                 matrix_event_publisher.publish_immediate(event);
 
                 if let Err(err) = driver.send_message_to_central(event.into()).await {
@@ -169,6 +202,30 @@ pub async fn peripheral_task<K: PeripheralDevice>(_k: K, mut driver: impl Periph
                     )
                 };
             }
+            Either4::Third(()) => {
+                let level = BATTERY_LEVEL_STATE.get().await;
+
+                if let Err(err) = driver
+                    .send_message_to_central(MessageToCentral::BatteryLevel(level))
+                    .await
+                {
+                    error!(
+                        "[SPLIT_PERIPHERAL] Error sending battery level to central: {}",
+                        Debug2Format(&err)
+                    )
+                };
+            }
+            Either4::Fourth(data) => {
+                if let Err(err) = driver
+                    .send_message_to_central(MessageToCentral::Custom(data))
+                    .await
+                {
+                    error!(
+                        "[SPLIT_PERIPHERAL] Error sending custom message to central: {}",
+                        Debug2Format(&err)
+                    )
+                };
+            }
         }
     }
 }
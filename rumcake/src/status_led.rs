@@ -0,0 +1,150 @@
+//! A single status LED, which shows the current connection and output state of the keyboard.
+//!
+//! This is a lightweight alternative to the lighting features, for keyboards that only have one
+//! LED connected to a GPIO pin. [`status_led_task`] drives the pin using the
+//! [`StatusLedPattern`]s defined by [`StatusLedDevice`], based on [`CURRENT_OUTPUT_STATE`] and
+//! the Bluetooth connection state.
+
+use embassy_futures::select::{select, Either};
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Timer};
+use embedded_hal::digital::v2::OutputPin;
+
+use crate::hw::platform::RawMutex;
+use crate::hw::{HIDOutput, CURRENT_OUTPUT_STATE};
+
+/// A pattern that the status LED can display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusLedPattern {
+    /// Keep the LED off.
+    Off,
+    /// Keep the LED on.
+    On,
+    /// Repeatedly turn the LED on for `on_time` milliseconds, then off for `off_time`
+    /// milliseconds.
+    Blink { on_time: u16, off_time: u16 },
+}
+
+/// A trait that keyboards must implement to use a status LED. The default patterns keep the LED
+/// on while outputting to USB, blink slowly while outputting to a connected Bluetooth host, and
+/// blink quickly while waiting for a Bluetooth host to connect.
+pub trait StatusLedDevice {
+    /// Pattern to display while the keyboard is outputting to USB.
+    const STATUS_LED_USB_PATTERN: StatusLedPattern = StatusLedPattern::On;
+
+    /// Pattern to display while the keyboard is outputting to a Bluetooth host.
+    const STATUS_LED_BLUETOOTH_PATTERN: StatusLedPattern = StatusLedPattern::Blink {
+        on_time: 500,
+        off_time: 1500,
+    };
+
+    /// Pattern to display while the keyboard has no output, and is not connected to a Bluetooth
+    /// host. Only used if the `bluetooth` feature is enabled.
+    const STATUS_LED_ADVERTISING_PATTERN: StatusLedPattern = StatusLedPattern::Blink {
+        on_time: 100,
+        off_time: 100,
+    };
+
+    /// Pattern to display in any other case, such as when the keyboard is connected to a
+    /// Bluetooth host, but is set to output to USB while USB is not connected.
+    const STATUS_LED_DISCONNECTED_PATTERN: StatusLedPattern = StatusLedPattern::Off;
+}
+
+pub(crate) static STATUS_LED_CURRENT_OUTPUT_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
+#[cfg(feature = "bluetooth")]
+pub(crate) static STATUS_LED_BLUETOOTH_CONNECTED_STATE_LISTENER: Signal<RawMutex, ()> =
+    Signal::new();
+
+async fn current_pattern<K: StatusLedDevice>() -> StatusLedPattern {
+    match CURRENT_OUTPUT_STATE.get().await {
+        Some(HIDOutput::Usb) => K::STATUS_LED_USB_PATTERN,
+        Some(HIDOutput::Bluetooth) => K::STATUS_LED_BLUETOOTH_PATTERN,
+        #[allow(unreachable_patterns)]
+        _ => {
+            #[cfg(feature = "bluetooth")]
+            if !crate::bluetooth::BLUETOOTH_CONNECTED_STATE.get().await {
+                return K::STATUS_LED_ADVERTISING_PATTERN;
+            }
+
+            K::STATUS_LED_DISCONNECTED_PATTERN
+        }
+    }
+}
+
+/// Wait for a change in the output or connection state.
+async fn wait_for_change() {
+    #[cfg(feature = "bluetooth")]
+    select(
+        STATUS_LED_CURRENT_OUTPUT_STATE_LISTENER.wait(),
+        STATUS_LED_BLUETOOTH_CONNECTED_STATE_LISTENER.wait(),
+    )
+    .await;
+
+    #[cfg(not(feature = "bluetooth"))]
+    STATUS_LED_CURRENT_OUTPUT_STATE_LISTENER.wait().await;
+}
+
+/// Number of times the status LED blinks when the host requests a device indication.
+const INDICATION_BLINKS: u8 = 3;
+
+/// Time that the status LED stays on or off for during each blink of a device indication.
+const INDICATION_INTERVAL: Duration = Duration::from_millis(200);
+
+static STATUS_LED_INDICATION_SIGNAL: Signal<RawMutex, ()> = Signal::new();
+
+/// Blink the status LED a few times, so that the user can identify the keyboard. The LED goes
+/// back to its usual pattern afterwards.
+pub fn indicate() {
+    STATUS_LED_INDICATION_SIGNAL.signal(());
+}
+
+/// Display `pattern` on the status LED, until the output or connection state changes.
+async fn display_pattern(pin: &mut impl OutputPin, pattern: StatusLedPattern) {
+    match pattern {
+        StatusLedPattern::Off => {
+            let _ = pin.set_low();
+            wait_for_change().await;
+        }
+        StatusLedPattern::On => {
+            let _ = pin.set_high();
+            wait_for_change().await;
+        }
+        StatusLedPattern::Blink { on_time, off_time } => {
+            let on_time = Duration::from_millis(on_time as u64);
+            let off_time = Duration::from_millis(off_time as u64);
+
+            // Keep blinking until the state changes
+            loop {
+                let _ = pin.set_high();
+                if let Either::First(()) = select(wait_for_change(), Timer::after(on_time)).await {
+                    break;
+                }
+                let _ = pin.set_low();
+                if let Either::First(()) = select(wait_for_change(), Timer::after(off_time)).await {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Drive the status LED connected to `pin`, using the patterns defined by [`StatusLedDevice`].
+pub async fn status_led_task<K: StatusLedDevice>(_k: K, mut pin: impl OutputPin) {
+    loop {
+        let pattern = current_pattern::<K>().await;
+
+        if let Either::Second(()) = select(
+            display_pattern(&mut pin, pattern),
+            STATUS_LED_INDICATION_SIGNAL.wait(),
+        )
+        .await
+        {
+            for _ in 0..INDICATION_BLINKS {
+                let _ = pin.set_high();
+                Timer::after(INDICATION_INTERVAL).await;
+                let _ = pin.set_low();
+                Timer::after(INDICATION_INTERVAL).await;
+            }
+        }
+    }
+}
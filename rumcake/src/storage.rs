@@ -4,9 +4,13 @@
 //! your MCU's flash. As a result, a user will be able to configure things like backlight/underglow
 //! effect settings, or dynamic keymaps without losing their changes between keyboard restarts.
 //!
-//! To use this feature, you will need to add a `CONFIG` section, and its start and end address to
-//! your `memory.x` file. Refer to [`crate::hw::__config_start`], and the corresponding
-//! `feature-storage.md` doc for more information.
+//! To use this feature with your MCU's flash, you will need to add a `CONFIG` section, and its
+//! start and end address to your `memory.x` file. Refer to [`crate::hw::__config_start`], and the
+//! corresponding `feature-storage.md` doc for more information.
+//!
+//! Any other implementor of [`embedded_storage_async::nor_flash::NorFlash`] (e.g. an external SPI
+//! flash chip) can also be used, by implementing [`StorageDevice`] for your keyboard, and using
+//! the `external` storage driver.
 
 use core::cell::{Cell, RefCell};
 use core::fmt::Debug;
@@ -16,7 +20,6 @@ use core::marker::PhantomData;
 use defmt::{assert, debug};
 use defmt::{error, info, warn, Debug2Format};
 use embassy_sync::mutex::{Mutex, MutexGuard};
-use embedded_storage::nor_flash::ReadNorFlash;
 use embedded_storage_async::nor_flash::{
     ErrorType, NorFlash as AsyncNorFlash, ReadNorFlash as AsyncReadNorFlash,
 };
@@ -47,8 +50,12 @@ pub enum StorageKey {
     RGBBacklightMatrixConfig = 0x02,
     /// Key to store [`crate::lighting::underglow::UnderglowConfig`].
     UnderglowConfig = 0x10,
+    /// Key to store the configs of every underglow zone.
+    UnderglowZoneConfigs = 0x11,
     /// Key to store bluetooth profiles, used by the `nrf-ble` implementation of bluetooth host communication.
     BluetoothProfiles = 0x20,
+    /// Key to store whether NKRO keyboard reports are sent over USB.
+    UsbNKRO = 0x21,
     /// Key to store the currently set Via layout option.
     LayoutOptions = 0x30,
     /// Key to store the current state of the Via dynamic keyboard layout.
@@ -63,6 +70,10 @@ pub enum StorageKey {
     DynamicKeymapCombo = 0x41,
     /// Key to store the current state of the key overrides in the Vial dynamic keyboard layout.
     DynamicKeymapKeyOverride = 0x42,
+    /// Key to store the QMK settings that were changed using Vial.
+    QmkSettings = 0x43,
+    /// Key to store the actuation points of an analog matrix.
+    AnalogActuationPoints = 0x50,
 }
 
 #[repr(u8)]
@@ -71,6 +82,13 @@ enum StorageKeyType {
     Metadata,
 }
 
+/// Appended to a key to get the key of the temporary copy that is made while compacting storage.
+const COMPACTION_COPY: u8 = u8::MAX;
+
+/// Key that is stored while storage is being compacted. If this key exists when the storage
+/// service is set up, compaction was interrupted, so any leftover copies need to be restored.
+const COMPACTION_MARKER: [u8; 1] = [u8::MAX];
+
 /// A wrapper around a TicKV instance which allows you to receive requests to read, write or delete
 /// data from a storage peripheral.
 pub struct StorageService<'a, F: FlashStorage, S>
@@ -121,6 +139,9 @@ where
         // Initialize the database, formatting if needed
         initialise(&mut database).await.unwrap();
 
+        // Restore any data that was being moved if the last compaction was interrupted
+        recover_compaction::<F, S>(&mut database).await;
+
         self.database.get_or_init(|| Mutex::new(database));
     }
 
@@ -277,18 +298,18 @@ where
         );
 
         let result = match postcard::to_slice(&data, buffer) {
-            Ok(serialized) => {
+            Ok(_) => {
                 let _ =
                     invalidate_key(&mut database, &[key as u8, StorageKeyType::Data as u8]).await;
                 garbage_collect(&mut database).await.0.unwrap();
-                append_key(
+                append_key_or_compact::<F, S>(
                     &mut database,
                     &[key as u8, StorageKeyType::Data as u8],
-                    serialized,
-                    serialized.len(),
+                    |buffer| {
+                        postcard::to_slice(&data, buffer).map_or(0, |serialized| serialized.len())
+                    },
                 )
                 .await
-                .0
                 .map_err(|error| {
                     error!(
                         "[STORAGE] Write error for {}: {}",
@@ -316,25 +337,23 @@ where
     /// allowing you to write raw bytes to storage.
     pub async fn write_raw(&self, key: StorageKey, data: &[u8]) -> Result<(), ()> {
         let mut database = self.get_database().await;
-        let buffer = S::get_storage_buffer();
 
         info!(
             "[STORAGE] Writing new {} data.",
             Debug2Format(&<StorageKey as num::FromPrimitive>::from_u8(key as u8).unwrap()),
         );
 
-        buffer[..data.len()].copy_from_slice(data);
-
         let _ = invalidate_key(&mut database, &[key as u8, StorageKeyType::Data as u8]).await;
         garbage_collect(&mut database).await.0.unwrap();
-        let result = append_key(
+        let result = append_key_or_compact::<F, S>(
             &mut database,
             &[key as u8, StorageKeyType::Data as u8],
-            buffer,
-            data.len(),
+            |buffer| {
+                buffer[..data.len()].copy_from_slice(data);
+                data.len()
+            },
         )
         .await
-        .0
         .map_err(|error| {
             error!(
                 "[STORAGE] Write error for {}: {}",
@@ -346,24 +365,74 @@ where
         result.map(|_code| {})
     }
 
-    /// Deletes the data at a given key.
-    pub async fn delete(&self, key: StorageKey) -> Result<(), ()> {
+    /// Invalidate the data at a given key, and reclaim the space it used.
+    async fn invalidate_data(&self, key: StorageKey) -> Result<(), ErrorCode> {
         let mut database = self.get_database().await;
+        let result = invalidate_key(&mut database, &[key as u8, StorageKeyType::Data as u8])
+            .await
+            .0;
+        garbage_collect(&mut database).await.0.unwrap();
+
+        result.map(|_code| {})
+    }
 
+    /// Deletes the data at a given key.
+    pub async fn delete(&self, key: StorageKey) -> Result<(), ()> {
         info!(
             "[STORAGE] Deleting {} data.",
             Debug2Format(&<StorageKey as num::FromPrimitive>::from_u8(key as u8).unwrap()),
         );
 
-        let result = invalidate_key(&mut database, &[key as u8, StorageKeyType::Data as u8])
+        self.invalidate_data(key).await.map_err(|error| {
+            error!("[STORAGE] Delete error: {}", Debug2Format(&error));
+        })
+    }
+
+    /// Erase the data stored at a given key. After calling this, reading the key will fail, so
+    /// the feature that uses the key will fall back to its default values. Unlike
+    /// [`StorageService::delete`], this does not return an error if there is no data stored at
+    /// the given key.
+    pub async fn erase_key(&self, key: StorageKey) -> Result<(), ()> {
+        info!(
+            "[STORAGE] Erasing {} data.",
+            Debug2Format(&<StorageKey as num::FromPrimitive>::from_u8(key as u8).unwrap()),
+        );
+
+        match self.invalidate_data(key).await {
+            Ok(()) | Err(ErrorCode::KeyNotFound) => Ok(()),
+            Err(error) => {
+                error!(
+                    "[STORAGE] Erase error for {}: {}",
+                    Debug2Format(&<StorageKey as num::FromPrimitive>::from_u8(key as u8).unwrap()),
+                    Debug2Format(&error)
+                );
+                Err(())
+            }
+        }
+    }
+
+    /// Rewrite all of the stored data, so that space used by data that was replaced or deleted
+    /// can be reclaimed. This is done automatically if a write fails because the storage is full.
+    ///
+    /// Each entry is copied before its original is invalidated, so no data is lost if power is
+    /// lost while compacting. Compaction that was interrupted will be finished the next time
+    /// [`StorageService::setup()`] is called.
+    pub async fn compact(&self) -> Result<(), ()> {
+        let mut database = self.get_database().await;
+
+        info!("[STORAGE] Compacting storage.");
+
+        compact::<F, S>(&mut database)
             .await
-            .0
+            .map(|count| {
+                info!(
+                    "[STORAGE] Compaction complete, {} entries rewritten.",
+                    count
+                );
+            })
             .map_err(|error| {
-                error!("[STORAGE] Delete error: {}", Debug2Format(&error));
-            });
-        garbage_collect(&mut database).await.0.unwrap();
-
-        result.map(|_code| {})
+                error!("[STORAGE] Compaction error: {}", Debug2Format(&error));
+            })
     }
 }
 
@@ -514,6 +583,144 @@ async fn garbage_collect<'a, F: FlashStorage>(
     }
 }
 
+/// Append a key, compacting the database and trying again if there is not enough space. `fill`
+/// must place the value in the provided buffer and return its length. It is called again before
+/// retrying, since compaction overwrites the storage buffer.
+async fn append_key_or_compact<'a, F: FlashStorage, S: StorageDevice>(
+    database: &mut AsyncTicKV<'a, FlashDevice<'a, F>, { F::ERASE_SIZE }>,
+    key: &[u8],
+    fill: impl Fn(&mut [u8]) -> usize,
+) -> Result<SuccessCode, ErrorCode> {
+    let buffer = S::get_storage_buffer();
+    let length = fill(buffer);
+
+    match append_key(database, key, buffer, length).await.0 {
+        Err(ErrorCode::RegionFull) | Err(ErrorCode::FlashFull) => {
+            warn!("[STORAGE] Storage is full, compacting before trying again.");
+            compact::<F, S>(database).await?;
+
+            let buffer = S::get_storage_buffer();
+            let length = fill(buffer);
+            append_key(database, key, buffer, length).await.0
+        }
+        result => result,
+    }
+}
+
+/// Move the entry stored at `key` to a new location. A copy of the entry is written before the
+/// original is invalidated, so the data can be restored by [`recover_compaction`] if power is
+/// lost. Returns `false` if there is no entry stored at `key`.
+async fn rewrite_key<'a, F: FlashStorage, S: StorageDevice>(
+    database: &mut AsyncTicKV<'a, FlashDevice<'a, F>, { F::ERASE_SIZE }>,
+    key: [u8; 2],
+) -> Result<bool, ErrorCode> {
+    let copy = [key[0], key[1], COMPACTION_COPY];
+
+    let (result, buf, len) = get_key(database, &key, S::get_storage_buffer()).await;
+    match (result, buf) {
+        (Ok(_), Some(buf)) => {
+            append_key(database, &copy, buf, len).await.0?;
+        }
+        (Err(ErrorCode::KeyNotFound), _) => return Ok(false),
+        (Err(error), _) => return Err(error),
+        _ => unreachable!(),
+    }
+
+    invalidate_key(database, &key).await.0?;
+    garbage_collect(database).await.0?;
+
+    restore_copy::<F, S>(database, key).await?;
+
+    Ok(true)
+}
+
+/// Restore the original entry for `key` from its copy, if the original does not exist, and then
+/// invalidate the copy.
+async fn restore_copy<'a, F: FlashStorage, S: StorageDevice>(
+    database: &mut AsyncTicKV<'a, FlashDevice<'a, F>, { F::ERASE_SIZE }>,
+    key: [u8; 2],
+) -> Result<(), ErrorCode> {
+    let copy = [key[0], key[1], COMPACTION_COPY];
+
+    let (result, buf, len) = get_key(database, &copy, S::get_storage_buffer()).await;
+    let (buf, len) = match (result, buf) {
+        (Ok(_), Some(buf)) => (buf, len),
+        (Err(ErrorCode::KeyNotFound), _) => return Ok(()),
+        (Err(error), _) => return Err(error),
+        _ => unreachable!(),
+    };
+
+    match append_key(database, &key, buf, len).await.0 {
+        Ok(_) | Err(ErrorCode::KeyAlreadyExists) => {}
+        Err(error) => return Err(error),
+    }
+
+    invalidate_key(database, &copy).await.0?;
+    garbage_collect(database).await.0?;
+
+    Ok(())
+}
+
+/// Rewrite all of the entries in the database, so that regions that only contain invalidated
+/// entries can be erased. Returns the number of entries that were rewritten.
+async fn compact<'a, F: FlashStorage, S: StorageDevice>(
+    database: &mut AsyncTicKV<'a, FlashDevice<'a, F>, { F::ERASE_SIZE }>,
+) -> Result<usize, ErrorCode> {
+    garbage_collect(database).await.0?;
+
+    let marker = S::get_storage_buffer();
+    marker[0] = 1;
+    match append_key(database, &COMPACTION_MARKER, marker, 1).await.0 {
+        Ok(_) | Err(ErrorCode::KeyAlreadyExists) => {}
+        Err(error) => return Err(error),
+    }
+
+    let mut count = 0;
+    for key in (0..=u8::MAX).filter_map(<StorageKey as num::FromPrimitive>::from_u8) {
+        for key_type in [StorageKeyType::Data as u8, StorageKeyType::Metadata as u8] {
+            if rewrite_key::<F, S>(database, [key as u8, key_type]).await? {
+                count += 1;
+            }
+        }
+    }
+
+    invalidate_key(database, &COMPACTION_MARKER).await.0?;
+    garbage_collect(database).await.0?;
+
+    Ok(count)
+}
+
+/// Finish a compaction that was interrupted, by restoring any entries that were only stored as a
+/// copy.
+async fn recover_compaction<'a, F: FlashStorage, S: StorageDevice>(
+    database: &mut AsyncTicKV<'a, FlashDevice<'a, F>, { F::ERASE_SIZE }>,
+) {
+    if get_key(database, &COMPACTION_MARKER, S::get_storage_buffer())
+        .await
+        .0
+        .is_err()
+    {
+        return;
+    }
+
+    warn!("[STORAGE] Storage compaction was interrupted, restoring data.");
+
+    for key in (0..=u8::MAX).filter_map(<StorageKey as num::FromPrimitive>::from_u8) {
+        for key_type in [StorageKeyType::Data as u8, StorageKeyType::Metadata as u8] {
+            if let Err(error) = restore_copy::<F, S>(database, [key as u8, key_type]).await {
+                error!(
+                    "[STORAGE] Could not restore data for {}: {}",
+                    Debug2Format(&key),
+                    Debug2Format(&error)
+                );
+            }
+        }
+    }
+
+    let _ = invalidate_key(database, &COMPACTION_MARKER).await;
+    garbage_collect(database).await.0.unwrap();
+}
+
 /// Trait used by storage tasks to obtain a static mutable reference to a buffer.
 pub trait StorageDevice {
     /// Obtain a static mutable reference to a buffer to be used with a [`StorageService`]. The
@@ -571,15 +778,9 @@ pub trait FlashStorage {
     /// on the size of the provided data buffer. The implementor does not necessarily have to
     /// perform a read operation in an asynchronous/non-blocking manner.
     async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error>;
-
-    /// Equivalent to [`embedded_storage::nor_flash::ReadNorFlash::read`].
-    ///
-    /// Read data to the given address. The amount of data read from the flash peripheral depends
-    /// on the size of the provided data buffer.
-    fn blocking_read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error>;
 }
 
-impl<F: ReadNorFlash + AsyncNorFlash> FlashStorage for F {
+impl<F: AsyncNorFlash> FlashStorage for F {
     type Error = <Self as ErrorType>::Error;
 
     const ERASE_SIZE: usize = Self::ERASE_SIZE;
@@ -595,10 +796,6 @@ impl<F: ReadNorFlash + AsyncNorFlash> FlashStorage for F {
     async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
         AsyncReadNorFlash::read(self, offset, bytes).await
     }
-
-    fn blocking_read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
-        ReadNorFlash::read(self, offset, bytes)
-    }
 }
 
 /// Data structure that wraps around an implementor of [`FlashStorage`]. If you want to read, write
@@ -691,10 +888,34 @@ where
             &self.op_buf.borrow()[..len]
         );
 
-        // In the `write` method in the FlashController trait implementation, copied the existing
-        // page data and wrote the new data on top of it. This allows us to avoid creating another
-        // buffer with a size of F::ERASE_SIZE to store the read results of the page that we're
-        // writing to. This is good for MCUs that don't have a lot of RAM (e.g. STM32F072CB).
+        // In the `write` method in the FlashController trait implementation, we placed the new
+        // data in `op_buf`. We read the rest of the existing page data around it, so that we can
+        // avoid creating another buffer with a size of F::ERASE_SIZE to store the read results of
+        // the page that we're writing to. This is good for MCUs that don't have a lot of RAM (e.g.
+        // STM32F072CB).
+        let offset = address % F::ERASE_SIZE;
+        let page_start = self.start + address - offset;
+        let flash = self.flash.get_mut();
+        let (before, rest) = self.op_buf.get_mut().split_at_mut(offset);
+
+        if let Err(err) = flash.read(page_start as u32, before).await {
+            error!(
+                "[STORAGE_DRIVER] Failed to read page before writing: {}",
+                defmt::Debug2Format(&err),
+            );
+            return Err(err);
+        };
+
+        if let Err(err) = flash
+            .read((page_start + offset + len) as u32, &mut rest[len..])
+            .await
+        {
+            error!(
+                "[STORAGE_DRIVER] Failed to read page before writing: {}",
+                defmt::Debug2Format(&err),
+            );
+            return Err(err);
+        };
 
         if let Err(err) = self
             .flash
@@ -776,13 +997,10 @@ impl<'a, F: FlashStorage> FlashController<{ F::ERASE_SIZE }> for FlashDevice<'a,
     }
 
     fn write(&self, address: usize, buf: &[u8]) -> Result<(), tickv::ErrorCode> {
-        // Write the data to op_buf where the data should be in the page.
+        // Write the data to op_buf where the data should be in the page. The rest of the page is
+        // read from the flash peripheral before the write operation is performed.
         let offset = address % F::ERASE_SIZE;
         let mut op_buf = self.op_buf.borrow_mut();
-        self.flash
-            .borrow_mut()
-            .blocking_read((self.start + address - offset) as u32, op_buf.as_mut())
-            .map_err(|_| tickv::ErrorCode::WriteFail)?;
         op_buf[offset..(offset + buf.len())].copy_from_slice(buf);
         self.pending
             .set(Some(PendingOperation::Write(address, buf.len())));
@@ -816,10 +1034,6 @@ pub(crate) mod private {
         async fn read(&mut self, _offset: u32, _bytes: &mut [u8]) -> Result<(), Self::Error> {
             unreachable!()
         }
-
-        fn blocking_read(&mut self, _offset: u32, _bytes: &mut [u8]) -> Result<(), Self::Error> {
-            unreachable!()
-        }
     }
 
     pub struct EmptyStorageDevice;
@@ -0,0 +1,145 @@
+//! Utilities for testing keyboard layouts without a physical matrix.
+//!
+//! [`MockMatrix`] sends scripted key presses and releases to the layout task
+//! ([`crate::keyboard::layout_collect`]), in the same way that [`crate::keyboard::matrix_poll`]
+//! would. The keyboard reports produced by the layout task can then be captured with
+//! [`next_keyboard_report`] or [`expect_keyboard_report`], so that a test can check that
+//! pressing certain keys results in certain reports.
+//!
+//! ```rust,ignore
+//! use rumcake::test_utils::{
+//!     capture_keyboard_reports, expect_keyboard_report, stop_capturing_keyboard_reports,
+//!     MockMatrix, ScriptStep,
+//! };
+//! use usbd_human_interface_device::page::Keyboard;
+//!
+//! // `layout_collect` must be running, but USB and Bluetooth tasks should not be, since they
+//! // would consume the reports before the test can receive them.
+//! let previous_output = capture_keyboard_reports().await;
+//! let matrix = MockMatrix::<MyKeyboard>::new();
+//! matrix.play(&[ScriptStep::Press(0, 0)]).await;
+//! expect_keyboard_report::<MyKeyboard>(&[Keyboard::A]).await;
+//! matrix.play(&[ScriptStep::Wait(50), ScriptStep::Release(0, 0)]).await;
+//! expect_keyboard_report::<MyKeyboard>(&[]).await;
+//! stop_capturing_keyboard_reports(previous_output).await;
+//! ```
+//!
+//! See `rumcake/tests/test_utils.rs` for a complete example, which runs on an RP2040.
+
+use core::marker::PhantomData;
+
+use embassy_time::{with_timeout, Duration, Timer};
+use keyberon::layout::Event;
+use usbd_human_interface_device::device::keyboard::NKROBootKeyboardReport;
+use usbd_human_interface_device::page::Keyboard as KeyboardKeycode;
+
+use crate::hw::{HIDDevice, HIDOutput, CURRENT_OUTPUT_STATE};
+use crate::keyboard::KeyboardLayout;
+
+/// How long [`expect_keyboard_report`] waits for a report before failing, in milliseconds.
+pub const REPORT_TIMEOUT: u64 = 1000;
+
+/// A single step of a script played by [`MockMatrix::play`].
+#[derive(Debug, Clone, Copy)]
+pub enum ScriptStep {
+    /// Press the key at the given (row, column) coordinate of the layout.
+    Press(u8, u8),
+    /// Release the key at the given (row, column) coordinate of the layout.
+    Release(u8, u8),
+    /// Wait for the given number of milliseconds before continuing.
+    Wait(u64),
+}
+
+/// A fake matrix that sends scripted events to a keyboard's layout.
+///
+/// The coordinates passed to a [`MockMatrix`] are layout coordinates, so
+/// [`crate::keyboard::KeyboardMatrix::remap_to_layout`] is not used.
+pub struct MockMatrix<K>(PhantomData<K>);
+
+impl<K: KeyboardLayout> MockMatrix<K> {
+    /// Create a new mock matrix for the layout of `K`.
+    pub const fn new() -> Self {
+        Self(PhantomData)
+    }
+
+    /// Send a single [`Event`] to the layout.
+    pub async fn event(&self, event: Event) {
+        K::get_matrix_events_channel().send(event).await
+    }
+
+    /// Press the key at the given coordinate.
+    pub async fn press(&self, row: u8, col: u8) {
+        self.event(Event::Press(row, col)).await
+    }
+
+    /// Release the key at the given coordinate.
+    pub async fn release(&self, row: u8, col: u8) {
+        self.event(Event::Release(row, col)).await
+    }
+
+    /// Press and release the key at the given coordinate.
+    pub async fn tap(&self, row: u8, col: u8) {
+        self.press(row, col).await;
+        self.release(row, col).await;
+    }
+
+    /// Play each step of the script in order.
+    pub async fn play(&self, script: &[ScriptStep]) {
+        for step in script {
+            match *step {
+                ScriptStep::Press(row, col) => self.press(row, col).await,
+                ScriptStep::Release(row, col) => self.release(row, col).await,
+                ScriptStep::Wait(ms) => Timer::after(Duration::from_millis(ms)).await,
+            }
+        }
+    }
+}
+
+/// Make the layout task send keyboard reports, even if there is no USB or Bluetooth connection.
+///
+/// Normally, the layout task discards reports if there is no host to send them to. This sets
+/// [`CURRENT_OUTPUT_STATE`] to [`HIDOutput::Usb`], so that reports can be received with
+/// [`next_keyboard_report`]. Listeners of [`CURRENT_OUTPUT_STATE`] are notified like they would be
+/// for a real USB connection.
+///
+/// The previous output is returned, which can be passed to [`stop_capturing_keyboard_reports`]
+/// once the test is done.
+pub async fn capture_keyboard_reports() -> Option<HIDOutput> {
+    let previous = CURRENT_OUTPUT_STATE.get().await;
+    CURRENT_OUTPUT_STATE.set(Some(HIDOutput::Usb)).await;
+    previous
+}
+
+/// Restore the output that was returned by [`capture_keyboard_reports`], and notify the listeners
+/// of [`CURRENT_OUTPUT_STATE`].
+pub async fn stop_capturing_keyboard_reports(previous: Option<HIDOutput>) {
+    CURRENT_OUTPUT_STATE.set(previous).await;
+}
+
+/// Wait for the next keyboard report produced by the layout task.
+pub async fn next_keyboard_report<K: HIDDevice>() -> NKROBootKeyboardReport {
+    K::get_keyboard_report_send_channel().receive().await
+}
+
+/// Try to obtain a keyboard report that was produced by the layout task, without waiting. Returns
+/// [`None`] if there is no report available.
+pub fn try_next_keyboard_report<K: HIDDevice>() -> Option<NKROBootKeyboardReport> {
+    K::get_keyboard_report_send_channel().try_receive().ok()
+}
+
+/// Wait for the next keyboard report, and panic if it doesn't contain exactly the given keycodes,
+/// or if no report is produced within [`REPORT_TIMEOUT`] milliseconds.
+pub async fn expect_keyboard_report<K: HIDDevice>(keys: &[KeyboardKeycode]) {
+    let Ok(report) = with_timeout(
+        Duration::from_millis(REPORT_TIMEOUT),
+        next_keyboard_report::<K>(),
+    )
+    .await
+    else {
+        panic!("No keyboard report was received");
+    };
+
+    if report != NKROBootKeyboardReport::new(keys.iter().copied()) {
+        panic!("The keyboard report did not contain the expected keycodes");
+    }
+}
@@ -0,0 +1,357 @@
+//! Unicode input.
+//!
+//! Unicode characters are typed by sending a sequence of keystrokes that the host OS interprets
+//! as a code point. The sequence that gets used depends on the selected [`UnicodeMode`], which
+//! should match the input method configured on the host. The letters in each sequence are typed
+//! using the keys that produce them on the host's keyboard layout ([`HostLayout`]), so that
+//! Unicode input also works if the host uses a layout like Dvorak or Colemak. To set the mode and
+//! layout, implement [`UnicodeDevice`], and set
+//! [`crate::keyboard::KeyboardLayout::UnicodeDeviceType`].
+//!
+//! Characters can be sent with [`crate::keyboard::Keycode::Unicode`] in your layout.
+//! Alternatively, [`unicode_sequence`](crate::unicode_sequence) can be used to create a keyberon
+//! [`Action::Sequence`](keyberon::action::Action::Sequence) for a character at compile time, which
+//! is played back by the layout like any other sequence.
+
+use defmt::{debug, warn};
+use embassy_sync::channel::Channel;
+use heapless::Vec;
+use keyberon::key_code::KeyCode;
+use num_traits::FromPrimitive;
+use usbd_human_interface_device::device::keyboard::NKROBootKeyboardReport;
+use usbd_human_interface_device::page::Keyboard as KeyboardKeycode;
+
+use crate::hw::platform::RawMutex;
+
+/// A trait that keyboards can implement to change how Unicode characters are typed.
+pub trait UnicodeDevice {
+    /// The input method used to type Unicode characters. This should match the input method
+    /// configured on the host device.
+    const UNICODE_MODE: UnicodeMode = UnicodeMode::Linux;
+
+    /// The keyboard layout used by the host device. This determines which keys are used to type
+    /// the letters in each sequence. This is ignored for [`UnicodeMode::MacOS`], since the
+    /// "Unicode Hex Input" layout is always based on QWERTY.
+    const HOST_LAYOUT: HostLayout = HostLayout::Qwerty;
+}
+
+/// Default Unicode input settings, used if
+/// [`crate::keyboard::KeyboardLayout::UnicodeDeviceType`] is not set. This uses
+/// [`UnicodeMode::Linux`] and [`HostLayout::Qwerty`].
+pub struct DefaultUnicodeDevice;
+impl UnicodeDevice for DefaultUnicodeDevice {}
+
+/// Input method used to send Unicode characters to the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnicodeMode {
+    /// Linux (IBus) input. Taps `Ctrl+Shift+U`, then types the code point in hex, followed by
+    /// `Space`.
+    Linux,
+    /// Windows alt codes. Holds `Alt`, taps keypad `+`, then types the code point in hex. This
+    /// requires the `EnableHexNumpad` registry setting. Code points beyond the Basic Multilingual
+    /// Plane are sent as a UTF-16 surrogate pair, which is not supported by all applications.
+    Windows,
+    /// [WinCompose](https://github.com/samhocevar/wincompose) input. Taps the compose key
+    /// (`RAlt`), then `U`, then types the code point in hex, followed by `Enter`.
+    WinCompose,
+    /// macOS "Unicode Hex Input" keyboard layout. Holds `Option` while typing each UTF-16 code
+    /// unit in hex.
+    MacOS,
+}
+
+/// Keyboard layout used by the host device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostLayout {
+    /// US QWERTY, and other layouts that place the letters `A` to `F` and `U` in the same
+    /// positions.
+    Qwerty,
+    /// Dvorak.
+    Dvorak,
+    /// Colemak.
+    Colemak,
+}
+
+/// Maximum number of bytes in a sequence generated by [`unicode_sequence`]. The longest sequence
+/// is a code point beyond the Basic Multilingual Plane, sent using [`UnicodeMode::Windows`].
+pub const MAX_UNICODE_SEQUENCE_LENGTH: usize = 42;
+
+const DIGITS: [KeyCode; 10] = [
+    KeyCode::Kb0,
+    KeyCode::Kb1,
+    KeyCode::Kb2,
+    KeyCode::Kb3,
+    KeyCode::Kb4,
+    KeyCode::Kb5,
+    KeyCode::Kb6,
+    KeyCode::Kb7,
+    KeyCode::Kb8,
+    KeyCode::Kb9,
+];
+
+const KEYPAD_DIGITS: [KeyCode; 10] = [
+    KeyCode::Kp0,
+    KeyCode::Kp1,
+    KeyCode::Kp2,
+    KeyCode::Kp3,
+    KeyCode::Kp4,
+    KeyCode::Kp5,
+    KeyCode::Kp6,
+    KeyCode::Kp7,
+    KeyCode::Kp8,
+    KeyCode::Kp9,
+];
+
+/// Keys that type the letters `a` to `f`, followed by `u`, on the given host layout. Digits are
+/// in the same position on all of the supported layouts.
+const fn letter_keys(layout: HostLayout) -> [KeyCode; 7] {
+    match layout {
+        HostLayout::Qwerty => [
+            KeyCode::A,
+            KeyCode::B,
+            KeyCode::C,
+            KeyCode::D,
+            KeyCode::E,
+            KeyCode::F,
+            KeyCode::U,
+        ],
+        HostLayout::Dvorak => [
+            KeyCode::A,
+            KeyCode::N,
+            KeyCode::I,
+            KeyCode::H,
+            KeyCode::D,
+            KeyCode::Y,
+            KeyCode::F,
+        ],
+        HostLayout::Colemak => [
+            KeyCode::A,
+            KeyCode::B,
+            KeyCode::C,
+            KeyCode::G,
+            KeyCode::K,
+            KeyCode::E,
+            KeyCode::I,
+        ],
+    }
+}
+
+// Sequence events, using the same encoding as keyberon's `Action::Sequence`.
+const TAP: u8 = 1;
+const PRESS: u8 = 2;
+const RELEASE: u8 = 3;
+
+/// Writes sequence events to a buffer. Events that don't fit are not written, but are still
+/// counted, so that an empty buffer can be used to determine the length of a sequence.
+struct SequenceWriter<'a> {
+    bytes: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> SequenceWriter<'a> {
+    const fn push(&mut self, event: u8, key: KeyCode) {
+        if self.len + 3 <= self.bytes.len() {
+            self.bytes[self.len] = 1;
+            self.bytes[self.len + 1] = event;
+            self.bytes[self.len + 2] = key as u8;
+        }
+        self.len += 3;
+    }
+
+    /// Type `value` in hex, using at least `min_digits` digits.
+    const fn push_hex(
+        &mut self,
+        value: u32,
+        min_digits: u32,
+        keypad: bool,
+        letters: &[KeyCode; 7],
+    ) {
+        let digits = 8 - value.leading_zeros() / 4;
+        let mut i = if digits > min_digits {
+            digits
+        } else {
+            min_digits
+        };
+
+        while i > 0 {
+            i -= 1;
+            let digit = ((value >> (i * 4)) & 0xF) as usize;
+            let key = if digit >= 10 {
+                letters[digit - 10]
+            } else if keypad {
+                KEYPAD_DIGITS[digit]
+            } else {
+                DIGITS[digit]
+            };
+            self.push(TAP, key);
+        }
+    }
+}
+
+/// Split a code point into UTF-16 code units. The second code unit is only present for code points
+/// beyond the Basic Multilingual Plane.
+const fn to_utf16(codepoint: u32) -> (u32, Option<u32>) {
+    if codepoint > 0xFFFF {
+        let offset = codepoint - 0x10000;
+        (0xD800 | (offset >> 10), Some(0xDC00 | (offset & 0x3FF)))
+    } else {
+        (codepoint, None)
+    }
+}
+
+/// Write the sequence needed to type `codepoint` to `bytes`, returning the length of the
+/// sequence.
+const fn encode(mode: UnicodeMode, layout: HostLayout, codepoint: u32, bytes: &mut [u8]) -> usize {
+    let mut writer = SequenceWriter { bytes, len: 0 };
+
+    match mode {
+        UnicodeMode::Linux => {
+            let letters = letter_keys(layout);
+            writer.push(PRESS, KeyCode::LCtrl);
+            writer.push(PRESS, KeyCode::LShift);
+            writer.push(TAP, letters[6]);
+            writer.push(RELEASE, KeyCode::LShift);
+            writer.push(RELEASE, KeyCode::LCtrl);
+            writer.push_hex(codepoint, 4, false, &letters);
+            writer.push(TAP, KeyCode::Space);
+        }
+        UnicodeMode::Windows => {
+            let letters = letter_keys(layout);
+            let (high, low) = to_utf16(codepoint);
+            let mut unit = Some(high);
+            while let Some(value) = unit {
+                writer.push(PRESS, KeyCode::LAlt);
+                writer.push(TAP, KeyCode::KpPlus);
+                writer.push_hex(value, 4, true, &letters);
+                writer.push(RELEASE, KeyCode::LAlt);
+                unit = if value == high { low } else { None };
+            }
+        }
+        UnicodeMode::WinCompose => {
+            let letters = letter_keys(layout);
+            writer.push(TAP, KeyCode::RAlt);
+            writer.push(TAP, letters[6]);
+            writer.push_hex(codepoint, 4, false, &letters);
+            writer.push(TAP, KeyCode::Enter);
+        }
+        UnicodeMode::MacOS => {
+            let letters = letter_keys(HostLayout::Qwerty);
+            let (high, low) = to_utf16(codepoint);
+            writer.push(PRESS, KeyCode::LAlt);
+            writer.push_hex(high, 4, false, &letters);
+            if let Some(low) = low {
+                writer.push_hex(low, 4, false, &letters);
+            }
+            writer.push(RELEASE, KeyCode::LAlt);
+        }
+    }
+
+    writer.len
+}
+
+/// Get the length of the sequence that [`unicode_sequence`] creates for `character`.
+pub const fn unicode_sequence_len(mode: UnicodeMode, layout: HostLayout, character: char) -> usize {
+    encode(mode, layout, character as u32, &mut [])
+}
+
+/// Create the sequence needed to type `character` on the host, using the given input method and
+/// host layout. The result can be used in a keyberon
+/// [`Action::Sequence`](keyberon::action::Action::Sequence). `N` must be equal to
+/// [`unicode_sequence_len`]. It is recommended to use [`unicode_sequence`](crate::unicode_sequence)
+/// instead, which does this for you.
+pub const fn unicode_sequence<const N: usize>(
+    mode: UnicodeMode,
+    layout: HostLayout,
+    character: char,
+) -> [u8; N] {
+    let mut bytes = [0; N];
+    assert!(
+        encode(mode, layout, character as u32, &mut bytes) == N,
+        "The length of the sequence does not match the length of the array"
+    );
+    bytes
+}
+
+/// Create a `&'static &'static [u8]` that can be passed to keyberon's
+/// [`Action::Sequence`](keyberon::action::Action::Sequence) to type a Unicode character. The
+/// sequence is created at compile time, and is played back by the layout like any other
+/// sequence.
+///
+/// ```rust,ignore
+/// use keyberon::action::Action;
+/// use rumcake::keyboard::Keycode;
+/// use rumcake::unicode::{HostLayout, UnicodeMode};
+///
+/// const E_ACUTE: Action<Keycode> = Action::Sequence(rumcake::unicode_sequence!(
+///     UnicodeMode::Linux,
+///     HostLayout::Qwerty,
+///     'é'
+/// ));
+/// ```
+#[macro_export]
+macro_rules! unicode_sequence {
+    ($mode:expr, $layout:expr, $character:expr) => {{
+        const BYTES: [u8; $crate::unicode::unicode_sequence_len($mode, $layout, $character)] =
+            $crate::unicode::unicode_sequence($mode, $layout, $character);
+        const SEQUENCE: &[u8] = &BYTES;
+        &SEQUENCE
+    }};
+}
+
+async fn send_report(
+    channel: &Channel<RawMutex, NKROBootKeyboardReport, 1>,
+    held: &[KeyCode],
+    key: Option<KeyCode>,
+) {
+    let keys = held
+        .iter()
+        .chain(key.iter())
+        .filter_map(|k| KeyboardKeycode::try_from(*k as u8).ok());
+    channel.send(NKROBootKeyboardReport::new(keys)).await;
+}
+
+/// Send the keystroke sequence needed to type `codepoint` on the host, using the given `mode` and
+/// `layout`. Reports are sent to `channel`, which is usually obtained from
+/// [`crate::hw::HIDDevice::get_keyboard_report_send_channel`]. Invalid code points are ignored.
+pub async fn send_unicode_input(
+    mode: UnicodeMode,
+    layout: HostLayout,
+    codepoint: u32,
+    channel: &Channel<RawMutex, NKROBootKeyboardReport, 1>,
+) {
+    if char::from_u32(codepoint).is_none() {
+        warn!("[UNICODE] Ignoring invalid code point: {:X}", codepoint);
+        return;
+    }
+
+    debug!(
+        "[UNICODE] Sending code point {:X} using {}",
+        codepoint,
+        defmt::Debug2Format(&mode)
+    );
+
+    let mut bytes = [0; MAX_UNICODE_SEQUENCE_LENGTH];
+    let len = encode(mode, layout, codepoint, &mut bytes);
+    let mut held: Vec<KeyCode, 2> = Vec::new();
+
+    for event in bytes[..len].chunks_exact(3) {
+        let Some(key) = KeyCode::from_u8(event[2]) else {
+            continue;
+        };
+
+        match event[1] {
+            TAP => {
+                send_report(channel, &held, Some(key)).await;
+                send_report(channel, &held, None).await;
+            }
+            PRESS => {
+                held.push(key).ok();
+                send_report(channel, &held, None).await;
+            }
+            RELEASE => {
+                held.retain(|k| *k != key);
+                send_report(channel, &held, None).await;
+            }
+            _ => {}
+        }
+    }
+}
@@ -18,6 +18,8 @@ use usbd_human_interface_device::device::consumer::{
 use usbd_human_interface_device::device::keyboard::{
     NKROBootKeyboardReport, NKRO_BOOT_KEYBOARD_REPORT_DESCRIPTOR,
 };
+#[cfg(feature = "pointer")]
+use usbd_human_interface_device::device::mouse::{WheelMouseReport, WHEEL_MOUSE_REPORT_DESCRIPTOR};
 
 use crate::hw::platform::RawMutex;
 use crate::hw::{HIDDevice, HIDOutput, CURRENT_OUTPUT_STATE};
@@ -31,6 +33,76 @@ pub use embassy_usb::{Builder, UsbDevice};
 pub(crate) static USB_RUNNING_STATE: State<bool> =
     State::new(false, &[&crate::hw::USB_RUNNING_STATE_LISTENER]);
 
+/// State that contains whether the keyboard sends NKRO keyboard reports over USB. If this is
+/// `false`, the keyboard sends standard 6KRO boot keyboard reports instead, which only contain the
+/// first six held keys. This can be useful for BIOS/UEFI screens that don't understand NKRO
+/// reports.
+///
+/// This can be changed using [`HardwareCommand::ToggleNKRO`](crate::hw::HardwareCommand::ToggleNKRO),
+/// [`HardwareCommand::EnableNKRO`](crate::hw::HardwareCommand::EnableNKRO), or
+/// [`HardwareCommand::DisableNKRO`](crate::hw::HardwareCommand::DisableNKRO).
+pub static USB_NKRO_STATE: State<bool> = State::new(
+    true,
+    &[
+        #[cfg(feature = "storage")]
+        &NKRO_STORAGE_LISTENER,
+    ],
+);
+
+#[cfg(feature = "storage")]
+pub(crate) static NKRO_STORAGE_LISTENER: Signal<RawMutex, ()> = Signal::new();
+
+#[cfg(feature = "storage")]
+pub use storage::*;
+
+#[cfg(feature = "storage")]
+mod storage {
+    use core::any::TypeId;
+
+    use defmt::{info, warn};
+
+    use crate::storage::{FlashStorage, StorageDevice, StorageKey, StorageService};
+
+    use super::{NKRO_STORAGE_LISTENER, USB_NKRO_STATE};
+
+    /// Obtains the NKRO setting from storage, and stores it in [`USB_NKRO_STATE`]. If it fails to
+    /// get data, NKRO reports will be used.
+    pub async fn initialize_nkro_data<S: StorageDevice + 'static, F: FlashStorage>(
+        database: &StorageService<'_, F, S>,
+    ) where
+        [(); F::ERASE_SIZE]:,
+    {
+        let metadata: [u8; core::mem::size_of::<TypeId>()] =
+            unsafe { core::mem::transmute(TypeId::of::<bool>()) };
+        let _ = database
+            .check_metadata(StorageKey::UsbNKRO, &metadata)
+            .await;
+
+        if let Ok(nkro) = database.read(StorageKey::UsbNKRO).await {
+            info!("[USB] Obtained NKRO setting from storage: {}", nkro);
+            USB_NKRO_STATE.set(nkro).await;
+        } else {
+            warn!("[USB] Could not get NKRO setting from storage, using default.");
+        }
+
+        // Don't save the value that we just loaded
+        NKRO_STORAGE_LISTENER.reset();
+    }
+
+    /// Task that saves the NKRO setting to storage whenever [`USB_NKRO_STATE`] changes.
+    pub async fn nkro_storage_task<S: StorageDevice, F: FlashStorage>(
+        database: &StorageService<'_, F, S>,
+    ) where
+        [(); F::ERASE_SIZE]:,
+    {
+        loop {
+            NKRO_STORAGE_LISTENER.wait().await;
+            let nkro = USB_NKRO_STATE.get().await;
+            let _ = database.write(StorageKey::UsbNKRO, nkro).await;
+        }
+    }
+}
+
 /// A trait that keyboards must implement to communicate with host devices over USB.
 pub trait USBKeyboard: Keyboard + HIDDevice {
     /// Vendor ID for the keyboard.
@@ -38,23 +110,68 @@ pub trait USBKeyboard: Keyboard + HIDDevice {
 
     /// Product ID for the keyboard.
     const USB_PID: u16;
+
+    /// Polling interval of the keyboard HID interface, in milliseconds. This is reported to the
+    /// host as the interface's `bInterval`, and determines how often the host asks for keyboard
+    /// reports. Defaults to `1`, which gives a report rate of 1000Hz.
+    const USB_POLL_INTERVAL_MS: u8 = 1;
+
+    /// Manufacturer string reported to the host over USB. Defaults to [`Keyboard::MANUFACTURER`].
+    ///
+    /// This is called once when USB is set up, so you can override this to return a string that is
+    /// computed at runtime.
+    fn get_usb_manufacturer() -> &'static str {
+        Self::MANUFACTURER
+    }
+
+    /// Product string reported to the host over USB. Defaults to [`Keyboard::PRODUCT`].
+    ///
+    /// This is called once when USB is set up, so you can override this to return a string that is
+    /// computed at runtime.
+    fn get_usb_product() -> &'static str {
+        Self::PRODUCT
+    }
+
+    /// Serial number reported to the host over USB. Defaults to [`Keyboard::SERIAL_NUMBER`].
+    ///
+    /// This is called once when USB is set up, so you can override this to return a string that is
+    /// computed at runtime, such as a serial number derived from your MCU's unique ID. This is
+    /// useful if you connect multiple identical keyboards to the same host.
+    fn get_usb_serial_number() -> &'static str {
+        Self::SERIAL_NUMBER
+    }
 }
 
 pub type NKROBootKeyboardReportWriter<'a, D: Driver<'a>> =
     HidWriter<'a, D, { <<NKROBootKeyboardReport as PackedStruct>::ByteArray as StaticArray>::LEN }>;
 
 /// Configure the HID report writer, using boot-specification-compatible NKRO keyboard reports.
+/// The polling interval is set to 1ms. To use a different polling interval, use
+/// [`setup_usb_hid_nkro_writer_with_poll_interval`].
 ///
 /// The HID writer produced should be passed to [`usb_hid_kb_write_task`].
 pub fn setup_usb_hid_nkro_writer<'a, D: Driver<'a>>(
     b: &mut Builder<'a, D>,
     kb_state: &'a mut UsbState<'a>,
+) -> NKROBootKeyboardReportWriter<'a, D> {
+    setup_usb_hid_nkro_writer_with_poll_interval(b, kb_state, 1)
+}
+
+/// Configure the HID report writer, using boot-specification-compatible NKRO keyboard reports,
+/// and the given polling interval in milliseconds. This should usually be
+/// [`USBKeyboard::USB_POLL_INTERVAL_MS`].
+///
+/// The HID writer produced should be passed to [`usb_hid_kb_write_task`].
+pub fn setup_usb_hid_nkro_writer_with_poll_interval<'a, D: Driver<'a>>(
+    b: &mut Builder<'a, D>,
+    kb_state: &'a mut UsbState<'a>,
+    poll_ms: u8,
 ) -> NKROBootKeyboardReportWriter<'a, D> {
     // Keyboard HID setup
     let kb_hid_config = Config {
         request_handler: None,
         report_descriptor: NKRO_BOOT_KEYBOARD_REPORT_DESCRIPTOR,
-        poll_ms: 1,
+        poll_ms,
         max_packet_size: 64,
     };
     HidWriter::<_, { <<NKROBootKeyboardReport as PackedStruct>::ByteArray as StaticArray>::LEN }>::new(
@@ -88,6 +205,32 @@ pub fn setup_usb_hid_consumer_writer<'a, D: Driver<'a>>(
     )
 }
 
+#[cfg(feature = "pointer")]
+pub type WheelMouseReportWriter<'a, D: Driver<'a>> =
+    HidWriter<'a, D, { <<WheelMouseReport as PackedStruct>::ByteArray as StaticArray>::LEN }>;
+
+#[cfg(feature = "pointer")]
+/// Configure the HID report writer, for mouse reports generated by pointing devices.
+///
+/// The HID writer produced should be passed to [`usb_hid_mouse_write_task`].
+pub fn setup_usb_hid_mouse_writer<'a, D: Driver<'a>>(
+    b: &mut Builder<'a, D>,
+    mouse_state: &'a mut UsbState<'a>,
+) -> WheelMouseReportWriter<'a, D> {
+    // Mouse HID setup
+    let mouse_hid_config = Config {
+        request_handler: None,
+        report_descriptor: WHEEL_MOUSE_REPORT_DESCRIPTOR,
+        poll_ms: 1,
+        max_packet_size: 64,
+    };
+    HidWriter::<_, { <<WheelMouseReport as PackedStruct>::ByteArray as StaticArray>::LEN }>::new(
+        b,
+        mouse_state,
+        mouse_hid_config,
+    )
+}
+
 pub async fn start_usb<'a, D: Driver<'a>>(mut usb: UsbDevice<'a, D>) {
     loop {
         info!("[USB] USB started");
@@ -124,19 +267,52 @@ macro_rules! usb_task_inner {
 
 pub(crate) static KB_CURRENT_OUTPUT_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
 
+/// Length of a standard 6KRO boot keyboard report (modifiers, a reserved byte, and six keycodes).
+/// An NKRO boot keyboard report starts with a 6KRO boot keyboard report containing the first six
+/// held keys, so we can send just the beginning of the report if NKRO is disabled.
+const BOOT_KEYBOARD_REPORT_LENGTH: usize = 8;
+
 pub async fn usb_hid_kb_write_task<'a, K: HIDDevice, D: Driver<'a>>(
     _k: K,
     mut hid: NKROBootKeyboardReportWriter<'a, D>,
 ) {
     let channel = K::get_keyboard_report_send_channel();
 
-    usb_task_inner!(
-        hid,
-        KB_CURRENT_OUTPUT_STATE_LISTENER,
-        channel,
-        "[USB] Writing NKRO HID keyboard report to USB: {:?}",
-        "[USB] Couldn't write HID keyboard report: {:?}"
-    )
+    loop {
+        if matches!(CURRENT_OUTPUT_STATE.get().await, Some(HIDOutput::Usb)) {
+            match select(KB_CURRENT_OUTPUT_STATE_LISTENER.wait(), channel.receive()).await {
+                select::Either::First(()) => {}
+                select::Either::Second(report) => {
+                    let packed = report.pack().unwrap();
+                    let result = if USB_NKRO_STATE.get().await {
+                        info!(
+                            "[USB] Writing NKRO HID keyboard report to USB: {:?}",
+                            Debug2Format(&report)
+                        );
+                        hid.write(&packed).await
+                    } else {
+                        info!(
+                            "[USB] Writing 6KRO HID keyboard report to USB: {:?}",
+                            Debug2Format(&report)
+                        );
+                        hid.write(&packed[..BOOT_KEYBOARD_REPORT_LENGTH]).await
+                    };
+
+                    if let Err(err) = result {
+                        error!(
+                            "[USB] Couldn't write HID keyboard report: {:?}",
+                            Debug2Format(&err)
+                        );
+                    };
+                }
+            }
+        } else {
+            KB_CURRENT_OUTPUT_STATE_LISTENER.wait().await;
+
+            // Ignore any unprocessed reports due to lack of a connection
+            while channel.try_receive().is_ok() {}
+        }
+    }
 }
 
 pub(crate) static CONSUMER_CURRENT_OUTPUT_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
@@ -156,6 +332,25 @@ pub async fn usb_hid_consumer_write_task<'a, K: HIDDevice, D: Driver<'a>>(
     );
 }
 
+#[cfg(feature = "pointer")]
+pub(crate) static MOUSE_CURRENT_OUTPUT_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
+
+#[cfg(feature = "pointer")]
+pub async fn usb_hid_mouse_write_task<'a, K: HIDDevice, D: Driver<'a>>(
+    _k: K,
+    mut hid: WheelMouseReportWriter<'a, D>,
+) {
+    let channel = K::get_mouse_report_send_channel();
+
+    usb_task_inner!(
+        hid,
+        MOUSE_CURRENT_OUTPUT_STATE_LISTENER,
+        channel,
+        "[USB] Writing mouse HID report to USB: {:?}",
+        "[USB] Couldn't write mouse HID report: {:?}"
+    );
+}
+
 #[cfg(feature = "via")]
 struct ViaCommandHandler<T> {
     _phantom: PhantomData<T>,
@@ -243,3 +438,93 @@ pub async fn usb_hid_via_write_task<K: HIDDevice>(
         "[USB] Couldn't write HID via report: {:?}"
     )
 }
+
+#[cfg(feature = "raw-hid")]
+struct RawHidCommandHandler<T> {
+    _phantom: PhantomData<T>,
+}
+
+#[cfg(feature = "raw-hid")]
+pub type RawHidReportWriter<'a, D: Driver<'a>> = HidWriter<'a, D, 32>;
+#[cfg(feature = "raw-hid")]
+pub type RawHidReportReader<'a, D: Driver<'a>> = HidReader<'a, D, 32>;
+
+#[cfg(feature = "raw-hid")]
+/// Configure the HID report reader and writer for raw HID reports.
+///
+/// The reader should be passed to [`usb_hid_raw_hid_read_task`], and the writer should be passed
+/// to [`usb_hid_raw_hid_write_task`].
+pub fn setup_usb_raw_hid_reader_writer<'a, D: Driver<'a>>(
+    builder: &mut Builder<'a, D>,
+    raw_hid_state: &'a mut UsbState<'a>,
+) -> (RawHidReportReader<'a, D>, RawHidReportWriter<'a, D>) {
+    let raw_hid_config = Config {
+        request_handler: None,
+        report_descriptor: crate::raw_hid::RAW_HID_REPORT_DESCRIPTOR,
+        poll_ms: 1,
+        max_packet_size: 32,
+    };
+    HidReaderWriter::<_, 32, 32>::new(builder, raw_hid_state, raw_hid_config).split()
+}
+
+#[cfg(feature = "raw-hid")]
+impl<T: HIDDevice> RequestHandler for RawHidCommandHandler<T> {
+    fn get_report(&mut self, _id: ReportId, _buf: &mut [u8]) -> Option<usize> {
+        None
+    }
+
+    fn set_report(&mut self, _id: ReportId, buf: &[u8]) -> OutResponse {
+        let mut data: [u8; 32] = [0; 32];
+        data.copy_from_slice(buf);
+
+        let channel = T::get_raw_hid_receive_channel();
+
+        if let Err(err) = channel.try_send(data) {
+            error!(
+                "[RAW_HID] Could not queue the raw HID report to be processed: {:?}",
+                err
+            );
+        };
+
+        OutResponse::Accepted
+    }
+
+    fn get_idle_ms(&mut self, _id: Option<ReportId>) -> Option<u32> {
+        None
+    }
+
+    fn set_idle_ms(&mut self, _id: Option<ReportId>, _duration_ms: u32) {}
+}
+
+#[cfg(feature = "raw-hid")]
+pub async fn usb_hid_raw_hid_read_task<T: HIDDevice>(
+    _kb: T,
+    hid: HidReader<'static, impl Driver<'static>, 32>,
+) {
+    hid.run(
+        false,
+        &mut RawHidCommandHandler {
+            _phantom: PhantomData as PhantomData<T>,
+        },
+    )
+    .await;
+}
+
+#[cfg(feature = "raw-hid")]
+pub(crate) static RAW_HID_CURRENT_OUTPUT_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
+
+#[cfg(feature = "raw-hid")]
+pub async fn usb_hid_raw_hid_write_task<K: HIDDevice>(
+    _k: K,
+    mut hid: HidWriter<'static, impl Driver<'static>, 32>,
+) {
+    let channel = K::get_raw_hid_send_channel();
+
+    usb_task_inner!(
+        hid,
+        RAW_HID_CURRENT_OUTPUT_STATE_LISTENER,
+        channel,
+        "[USB] Writing raw HID report: {:?}",
+        "[USB] Couldn't write raw HID report: {:?}"
+    )
+}
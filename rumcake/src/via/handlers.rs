@@ -1,5 +1,6 @@
 use defmt::warn;
 use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Timer};
 use keyberon::action::Action;
 
 use crate::hw::platform::RawMutex;
@@ -60,33 +61,205 @@ where
     }
 }
 
-pub async fn device_indication<K: ViaKeyboard>() {
+pub(super) static DEVICE_INDICATION_SIGNAL: Signal<RawMutex, ()> = Signal::new();
+
+pub fn device_indication() {
+    DEVICE_INDICATION_SIGNAL.signal(());
+}
+
+/// Number of times the lighting is toggled to identify the keyboard. This is an even number, so
+/// that the lighting ends in the same state that it started in.
+const DEVICE_INDICATION_TOGGLES: u8 = 6;
+
+/// Time between each toggle of the lighting when identifying the keyboard.
+const DEVICE_INDICATION_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Whether each lighting system was on before the device indication started. Lighting systems
+/// that are not set up are `None`.
+#[derive(Default)]
+struct DeviceIndicationLighting {
     #[cfg(feature = "simple-backlight")]
-    if let Some(channel) = <<K::Layout as KeyboardLayout>::SimpleBacklightDeviceType as crate::lighting::simple_backlight::private::MaybeSimpleBacklightDevice>::get_command_channel() {
-        channel
-            .send(crate::lighting::simple_backlight::SimpleBacklightCommand::Toggle)
-            .await;
+    simple_backlight: Option<bool>,
+    #[cfg(feature = "simple-backlight-matrix")]
+    simple_backlight_matrix: Option<bool>,
+    #[cfg(feature = "rgb-backlight-matrix")]
+    rgb_backlight_matrix: Option<bool>,
+    #[cfg(feature = "underglow")]
+    underglow: Option<bool>,
+}
+
+impl DeviceIndicationLighting {
+    async fn save<K: ViaKeyboard>() -> Self {
+        #[allow(unused_mut)]
+        let mut saved = Self::default();
+
+        #[cfg(feature = "simple-backlight")]
+        {
+            use crate::lighting::simple_backlight::private::MaybeSimpleBacklightDevice;
+
+            if let Some(state) =
+                <K::Layout as KeyboardLayout>::SimpleBacklightDeviceType::get_state()
+            {
+                saved.simple_backlight = Some(state.get().await.enabled);
+            }
+        }
+
+        #[cfg(feature = "simple-backlight-matrix")]
+        {
+            use crate::lighting::simple_backlight_matrix::private::MaybeSimpleBacklightMatrixDevice;
+
+            if let Some(state) =
+                <K::Layout as KeyboardLayout>::SimpleBacklightMatrixDeviceType::get_state()
+            {
+                saved.simple_backlight_matrix = Some(state.get().await.enabled);
+            }
+        }
+
+        #[cfg(feature = "rgb-backlight-matrix")]
+        {
+            use crate::lighting::rgb_backlight_matrix::private::MaybeRGBBacklightMatrixDevice;
+
+            if let Some(state) =
+                <K::Layout as KeyboardLayout>::RGBBacklightMatrixDeviceType::get_state()
+            {
+                saved.rgb_backlight_matrix = Some(state.get().await.enabled);
+            }
+        }
+
+        #[cfg(feature = "underglow")]
+        {
+            use crate::lighting::underglow::private::MaybeUnderglowDevice;
+
+            if let Some(state) = <K::Layout as KeyboardLayout>::UnderglowDeviceType::get_state() {
+                saved.underglow = Some(state.get().await.enabled);
+            }
+        }
+
+        saved
     }
 
-    #[cfg(feature = "simple-backlight-matrix")]
-    if let Some(channel) = <<K::Layout as KeyboardLayout>::SimpleBacklightMatrixDeviceType as crate::lighting::simple_backlight_matrix::private::MaybeSimpleBacklightMatrixDevice>::get_command_channel() {
-        channel
-            .send(crate::lighting::simple_backlight_matrix::SimpleBacklightMatrixCommand::Toggle)
-            .await;
+    /// Check if any lighting system is set up.
+    fn is_empty(&self) -> bool {
+        #[allow(unused_mut)]
+        let mut empty = true;
+
+        #[cfg(feature = "simple-backlight")]
+        {
+            empty &= self.simple_backlight.is_none();
+        }
+
+        #[cfg(feature = "simple-backlight-matrix")]
+        {
+            empty &= self.simple_backlight_matrix.is_none();
+        }
+
+        #[cfg(feature = "rgb-backlight-matrix")]
+        {
+            empty &= self.rgb_backlight_matrix.is_none();
+        }
+
+        #[cfg(feature = "underglow")]
+        {
+            empty &= self.underglow.is_none();
+        }
+
+        empty
     }
 
-    #[cfg(feature = "rgb-backlight-matrix")]
-    if let Some(channel) = <<K::Layout as KeyboardLayout>::RGBBacklightMatrixDeviceType as crate::lighting::rgb_backlight_matrix::private::MaybeRGBBacklightMatrixDevice>::get_command_channel() {
-        channel
-            .send(crate::lighting::rgb_backlight_matrix::RGBBacklightMatrixCommand::Toggle)
-            .await;
+    /// Turn each lighting system on or off. If `invert` is `true`, the opposite of its saved state
+    /// is used.
+    async fn apply<K: ViaKeyboard>(&self, invert: bool) {
+        #[cfg(feature = "simple-backlight")]
+        if let (Some(enabled), Some(channel)) = (
+            self.simple_backlight,
+            <<K::Layout as KeyboardLayout>::SimpleBacklightDeviceType as crate::lighting::simple_backlight::private::MaybeSimpleBacklightDevice>::get_command_channel(),
+        ) {
+            use crate::lighting::simple_backlight::SimpleBacklightCommand;
+
+            channel
+                .send(if enabled != invert {
+                    SimpleBacklightCommand::TurnOn
+                } else {
+                    SimpleBacklightCommand::TurnOff
+                })
+                .await;
+        }
+
+        #[cfg(feature = "simple-backlight-matrix")]
+        if let (Some(enabled), Some(channel)) = (
+            self.simple_backlight_matrix,
+            <<K::Layout as KeyboardLayout>::SimpleBacklightMatrixDeviceType as crate::lighting::simple_backlight_matrix::private::MaybeSimpleBacklightMatrixDevice>::get_command_channel(),
+        ) {
+            use crate::lighting::simple_backlight_matrix::SimpleBacklightMatrixCommand;
+
+            channel
+                .send(if enabled != invert {
+                    SimpleBacklightMatrixCommand::TurnOn
+                } else {
+                    SimpleBacklightMatrixCommand::TurnOff
+                })
+                .await;
+        }
+
+        #[cfg(feature = "rgb-backlight-matrix")]
+        if let (Some(enabled), Some(channel)) = (
+            self.rgb_backlight_matrix,
+            <<K::Layout as KeyboardLayout>::RGBBacklightMatrixDeviceType as crate::lighting::rgb_backlight_matrix::private::MaybeRGBBacklightMatrixDevice>::get_command_channel(),
+        ) {
+            use crate::lighting::rgb_backlight_matrix::RGBBacklightMatrixCommand;
+
+            channel
+                .send(if enabled != invert {
+                    RGBBacklightMatrixCommand::TurnOn
+                } else {
+                    RGBBacklightMatrixCommand::TurnOff
+                })
+                .await;
+        }
+
+        #[cfg(feature = "underglow")]
+        if let (Some(enabled), Some(channel)) = (
+            self.underglow,
+            <<K::Layout as KeyboardLayout>::UnderglowDeviceType as crate::lighting::underglow::private::MaybeUnderglowDevice>::get_command_channel(),
+        ) {
+            use crate::lighting::underglow::UnderglowCommand;
+
+            channel
+                .send(if enabled != invert {
+                    UnderglowCommand::TurnOn
+                } else {
+                    UnderglowCommand::TurnOff
+                })
+                .await;
+        }
     }
+}
 
-    #[cfg(feature = "underglow")]
-    if let Some(channel) = <<K::Layout as KeyboardLayout>::UnderglowDeviceType as crate::lighting::underglow::private::MaybeUnderglowDevice>::get_command_channel() {
-        channel
-            .send(crate::lighting::underglow::UnderglowCommand::Toggle)
-            .await;
+/// Flash the lighting of the keyboard whenever the host requests a device indication, so that the
+/// user can identify it. After flashing, each lighting system is restored to the state it was in
+/// before. If no lighting is set up, the status LED is flashed instead.
+pub(super) async fn device_indication_task<K: ViaKeyboard>() {
+    loop {
+        DEVICE_INDICATION_SIGNAL.wait().await;
+
+        let saved = DeviceIndicationLighting::save::<K>().await;
+
+        if saved.is_empty() {
+            #[cfg(feature = "status-led")]
+            crate::status_led::indicate();
+
+            continue;
+        }
+
+        for i in 0..DEVICE_INDICATION_TOGGLES {
+            saved.apply::<K>(i % 2 == 0).await;
+            Timer::after(DEVICE_INDICATION_INTERVAL).await;
+        }
+
+        saved.apply::<K>(false).await;
+
+        // Ignore any requests that were received while flashing
+        DEVICE_INDICATION_SIGNAL.reset();
     }
 }
 
@@ -95,10 +268,10 @@ where
     [(); <<K::StorageType as StorageDevice>::FlashStorageType as FlashStorage>::ERASE_SIZE]:,
 {
     if let Some(database) = K::get_storage_service() {
-        let _ = database.delete(StorageKey::LayoutOptions).await;
-        let _ = database.delete(StorageKey::DynamicKeymap).await;
-        let _ = database.delete(StorageKey::DynamicKeymapMacro).await;
-        let _ = database.delete(StorageKey::DynamicKeymapEncoder).await;
+        let _ = database.erase_key(StorageKey::LayoutOptions).await;
+        let _ = database.erase_key(StorageKey::DynamicKeymap).await;
+        let _ = database.erase_key(StorageKey::DynamicKeymapMacro).await;
+        let _ = database.erase_key(StorageKey::DynamicKeymapEncoder).await;
     }
 }
 
@@ -118,7 +291,8 @@ where
     [(); K::DYNAMIC_KEYMAP_MACRO_COUNT as usize]:,
 {
     if let Some(macro_data) = K::get_macro_buffer() {
-        macro_data.buffer.fill(0)
+        macro_data.buffer.fill(0);
+        macro_data.converted.fill(0);
     };
 }
 
@@ -161,7 +335,11 @@ pub async fn dynamic_keymap_macro_set_buffer<K: ViaKeyboard + 'static>(
     };
 
     if let Some(macro_data) = K::get_macro_buffer() {
-        macro_data.update_buffer(offset as usize, &data[..len]);
+        macro_data.update_buffer(
+            offset as usize,
+            &data[..len],
+            K::Layout::LAYOUT_TICK_INTERVAL_US,
+        );
     }
 
     if let Some(database) = K::get_storage_service() {
@@ -297,22 +475,46 @@ pub async fn dynamic_keymap_set_keycode<K: ViaKeyboard + 'static>(
     }
 }
 
-pub async fn dynamic_keymap_get_encoder<K: ViaKeyboard>(
+/// Get the position in the layout that the given encoder rotation is mapped to, using
+/// [`KeyboardLayout::ENCODER_MAPPINGS`].
+pub(crate) fn encoder_layout_position<K: ViaKeyboard>(
+    encoder_id: u8,
+    clockwise: bool,
+) -> Option<(u8, u8)> {
+    <K::Layout as KeyboardLayout>::ENCODER_MAPPINGS
+        .get(encoder_id as usize)
+        .map(|[cw_pos, ccw_pos]| if clockwise { *cw_pos } else { *ccw_pos })
+}
+
+pub async fn dynamic_keymap_get_encoder<K: ViaKeyboard + 'static>(
     layer: u8,
     encoder_id: u8,
     clockwise: bool,
     data: &mut [u8],
-) {
+    convert_action_to_keycode: impl Fn(Action<Keycode>) -> u16,
+) where
+    [(); <K::Layout as KeyboardLayout>::LAYERS]:,
+    [(); <K::Layout as KeyboardLayout>::LAYOUT_ROWS]:,
+    [(); <K::Layout as KeyboardLayout>::LAYOUT_COLS]:,
+{
     let keycode = &mut data[0..=1];
 
-    let offset = ((layer * <K::Layout as KeyboardLayout>::NUM_ENCODERS as u8 * 2 * 2)
-        + (encoder_id * 2 * 2)
-        + if clockwise { 0 } else { 2 }) as usize;
-
     if !(layer as usize >= K::DYNAMIC_KEYMAP_LAYER_COUNT
         || encoder_id as usize >= <K::Layout as KeyboardLayout>::NUM_ENCODERS)
     {
-        //TODO: encoder support
+        let Some(position) = encoder_layout_position::<K>(encoder_id, clockwise) else {
+            warn!("[VIA] Requested a dynamic keymap encoder that has no layout mapping.");
+            return;
+        };
+
+        if let Some(action) = <K::Layout as KeyboardLayout>::get_layout()
+            .layout
+            .lock()
+            .await
+            .get_action(position, layer as usize)
+        {
+            keycode.copy_from_slice(&convert_action_to_keycode(action).to_be_bytes())
+        };
     } else {
         warn!("[VIA] Requested a dynamic keymap encoder that is out of bounds.")
     }
@@ -323,9 +525,13 @@ pub async fn dynamic_keymap_set_encoder<K: ViaKeyboard + 'static>(
     encoder_id: u8,
     clockwise: bool,
     data: &[u8],
+    convert_keycode_to_action: impl Fn(u16) -> Option<Action<Keycode>>,
 ) where
     [(); <<K::StorageType as StorageDevice>::FlashStorageType as FlashStorage>::ERASE_SIZE]:,
     [(); K::DYNAMIC_KEYMAP_LAYER_COUNT * K::Layout::NUM_ENCODERS * 2 * 2]:,
+    [(); <K::Layout as KeyboardLayout>::LAYERS]:,
+    [(); <K::Layout as KeyboardLayout>::LAYOUT_ROWS]:,
+    [(); <K::Layout as KeyboardLayout>::LAYOUT_COLS]:,
 {
     let keycode = &data[0..=1];
 
@@ -336,7 +542,30 @@ pub async fn dynamic_keymap_set_encoder<K: ViaKeyboard + 'static>(
     if !(layer as usize >= K::DYNAMIC_KEYMAP_LAYER_COUNT
         || encoder_id as usize >= <K::Layout as KeyboardLayout>::NUM_ENCODERS)
     {
-        //TODO: encoder support
+        let Some(position) = encoder_layout_position::<K>(encoder_id, clockwise) else {
+            warn!("[VIA] Attempted to set a dynamic keymap encoder that has no layout mapping.");
+            return;
+        };
+
+        // Encoders send their rotations to a position in the layout, so the encoder task will use
+        // the new action on this layer.
+        {
+            let mut layout = <K::Layout as KeyboardLayout>::get_layout()
+                .layout
+                .lock()
+                .await;
+            if let Some(action) =
+                convert_keycode_to_action(u16::from_be_bytes(keycode.try_into().unwrap()))
+            {
+                if layout
+                    .change_action(position, layer as usize, action)
+                    .is_err()
+                {
+                    warn!("[VIA] Encoder layout mapping is out of bounds.");
+                    return;
+                }
+            }
+        }
 
         if let Some(database) = K::get_storage_service() {
             let mut buf = [0; K::DYNAMIC_KEYMAP_LAYER_COUNT * K::Layout::NUM_ENCODERS * 2 * 2];
@@ -488,11 +717,16 @@ pub async fn dynamic_keymap_set_buffer<K: ViaKeyboard + 'static>(
     }
 }
 
+/// Restore the keymap to its compile-time defaults, and clear any remapped encoders and recorded
+/// macros. Unlike [`eeprom_reset`], this does not affect layout options.
 pub async fn dynamic_keymap_reset<K: ViaKeyboard + 'static>()
 where
     [(); <K::Layout as KeyboardLayout>::LAYERS]:,
     [(); <K::Layout as KeyboardLayout>::LAYOUT_ROWS]:,
     [(); <K::Layout as KeyboardLayout>::LAYOUT_COLS]:,
+    [(); <<K::StorageType as StorageDevice>::FlashStorageType as FlashStorage>::ERASE_SIZE]:,
+    [(); K::DYNAMIC_KEYMAP_MACRO_BUFFER_SIZE as usize]:,
+    [(); K::DYNAMIC_KEYMAP_MACRO_COUNT as usize]:,
 {
     let mut layout = <K::Layout as KeyboardLayout>::get_layout()
         .layout
@@ -509,14 +743,28 @@ where
             }
         }
     }
+
+    // Release the layout lock before touching storage
+    drop(layout);
+
+    dynamic_keymap_macro_reset::<K>();
+
+    // Remove the stored keymap, encoders and macros, so that the defaults get loaded on the next
+    // boot. Remapped encoders are stored in the encoders' layout positions, so restoring the
+    // layout above also resets them.
+    if let Some(database) = K::get_storage_service() {
+        let _ = database.erase_key(StorageKey::DynamicKeymap).await;
+        let _ = database.erase_key(StorageKey::DynamicKeymapEncoder).await;
+        let _ = database.erase_key(StorageKey::DynamicKeymapMacro).await;
+    }
 }
 
 #[cfg(feature = "underglow")]
 pub async fn underglow_get_enabled<K: ViaKeyboard>(data: &mut [u8]) {
     if let Some(state) =
-        <<K::Layout as KeyboardLayout>::UnderglowDeviceType as crate::lighting::underglow::private::MaybeUnderglowDevice>::get_state()
+        <<K::Layout as KeyboardLayout>::UnderglowDeviceType as crate::lighting::underglow::private::MaybeUnderglowDevice>::get_selected_zone_state()
     {
-        data[0] = state.get().await.enabled as u8
+        data[0] = state.get().await.1.enabled as u8
     }
 }
 
@@ -596,9 +844,9 @@ pub async fn rgb_backlight_matrix_set_enabled<K: ViaKeyboard>(data: &[u8]) {
 #[cfg(feature = "underglow")]
 pub async fn underglow_get_brightness<K: ViaKeyboard>(data: &mut [u8]) {
     if let Some(state) =
-        <<K::Layout as KeyboardLayout>::UnderglowDeviceType as crate::lighting::underglow::private::MaybeUnderglowDevice>::get_state()
+        <<K::Layout as KeyboardLayout>::UnderglowDeviceType as crate::lighting::underglow::private::MaybeUnderglowDevice>::get_selected_zone_state()
     {
-        data[0] = state.get().await.val;
+        data[0] = state.get().await.1.val;
     }
 }
 
@@ -673,9 +921,9 @@ pub async fn underglow_get_effect<K: ViaKeyboard>(
     convert_effect_to_qmk_id: impl Fn(crate::lighting::underglow::UnderglowConfig) -> u8,
 ) {
     if let Some(state) =
-        <<K::Layout as KeyboardLayout>::UnderglowDeviceType as crate::lighting::underglow::private::MaybeUnderglowDevice>::get_state()
+        <<K::Layout as KeyboardLayout>::UnderglowDeviceType as crate::lighting::underglow::private::MaybeUnderglowDevice>::get_selected_zone_state()
     {
-        data[0] = convert_effect_to_qmk_id(state.get().await);
+        data[0] = convert_effect_to_qmk_id(state.get().await.1);
     }
 }
 
@@ -832,9 +1080,9 @@ pub async fn rgb_backlight_matrix_set_effect<K: ViaKeyboard>(
 #[cfg(feature = "underglow")]
 pub async fn underglow_get_speed<K: ViaKeyboard>(data: &mut [u8]) {
     if let Some(state) =
-        <<K::Layout as KeyboardLayout>::UnderglowDeviceType as crate::lighting::underglow::private::MaybeUnderglowDevice>::get_state()
+        <<K::Layout as KeyboardLayout>::UnderglowDeviceType as crate::lighting::underglow::private::MaybeUnderglowDevice>::get_selected_zone_state()
     {
-        data[0] = state.get().await.speed;
+        data[0] = state.get().await.1.speed;
     }
 }
 
@@ -906,9 +1154,9 @@ pub async fn rgb_backlight_matrix_set_speed<K: ViaKeyboard>(data: &[u8]) {
 #[cfg(feature = "underglow")]
 pub async fn underglow_get_color<K: ViaKeyboard>(data: &mut [u8]) {
     if let Some(state) =
-        <<K::Layout as KeyboardLayout>::UnderglowDeviceType as crate::lighting::underglow::private::MaybeUnderglowDevice>::get_state()
+        <<K::Layout as KeyboardLayout>::UnderglowDeviceType as crate::lighting::underglow::private::MaybeUnderglowDevice>::get_selected_zone_state()
     {
-        let config = state.get().await;
+        let config = state.get().await.1;
         data[0] = config.hue;
         data[1] = config.sat;
     }
@@ -940,6 +1188,26 @@ pub async fn underglow_set_color<K: ViaKeyboard>(data: &[u8]) {
     }
 }
 
+#[cfg(feature = "underglow")]
+pub async fn underglow_get_zone<K: ViaKeyboard>(data: &mut [u8]) {
+    if let Some(state) =
+        <<K::Layout as KeyboardLayout>::UnderglowDeviceType as crate::lighting::underglow::private::MaybeUnderglowDevice>::get_selected_zone_state()
+    {
+        data[0] = state.get().await.0;
+    }
+}
+
+#[cfg(feature = "underglow")]
+pub async fn underglow_set_zone<K: ViaKeyboard>(data: &[u8]) {
+    if let Some(channel) = <<K::Layout as KeyboardLayout>::UnderglowDeviceType as crate::lighting::underglow::private::MaybeUnderglowDevice>::get_command_channel() {
+        channel
+            .send(crate::lighting::underglow::UnderglowCommand::SelectZone(
+                data[0],
+            ))
+            .await;
+    }
+}
+
 #[cfg(feature = "rgb-backlight-matrix")]
 pub async fn rgb_backlight_matrix_set_color<K: ViaKeyboard>(data: &[u8]) {
     if let Some(channel) = <<K::Layout as KeyboardLayout>::RGBBacklightMatrixDeviceType as crate::lighting::rgb_backlight_matrix::private::MaybeRGBBacklightMatrixDevice>::get_command_channel() {
@@ -994,3 +1262,220 @@ pub async fn rgb_backlight_matrix_save<K: ViaKeyboard>() {
             .await;
     }
 }
+
+#[cfg(feature = "via-key-events")]
+pub fn key_events_get_enabled(data: &mut [u8]) {
+    data[0] = super::KEY_EVENTS_ENABLED.load(core::sync::atomic::Ordering::Relaxed) as u8;
+}
+
+#[cfg(feature = "via-key-events")]
+pub fn key_events_set_enabled(data: &[u8]) {
+    super::KEY_EVENTS_ENABLED.store(data[0] == 1, core::sync::atomic::Ordering::Relaxed);
+}
+
+pub async fn analog_matrix_get_values<K: ViaKeyboard>(data: &mut [u8]) {
+    // data[0] is the number of keys returned, followed by (row, col, value) for each key
+    let mut values = [(0, 0, 0); 9];
+    let count = <K::MatrixType as crate::keyboard::private::MaybeKeyboardMatrix>::take_changed_analog_values(&mut values[..((data.len() - 1) / 3).min(9)]).await;
+    data[0] = count as u8;
+    for (chunk, (row, col, value)) in data[1..].chunks_exact_mut(3).zip(&values[..count]) {
+        chunk.copy_from_slice(&[*row, *col, *value]);
+    }
+}
+
+pub async fn analog_matrix_get_actuation_point<K: ViaKeyboard>(data: &mut [u8]) {
+    let (row, col) = (data[0], data[1]);
+    match <K::MatrixType as crate::keyboard::private::MaybeKeyboardMatrix>::get_actuation_point(
+        row, col,
+    )
+    .await
+    {
+        Some(point) => data[2] = point,
+        None => warn!(
+            "[VIA] Could not get actuation point for key at row {:?}, col {:?}",
+            row, col
+        ),
+    }
+}
+
+pub async fn analog_matrix_set_actuation_point<K: ViaKeyboard>(data: &[u8]) {
+    let (row, col, value) = (data[0], data[1], data[2]);
+    if !<K::MatrixType as crate::keyboard::private::MaybeKeyboardMatrix>::set_actuation_point(
+        row, col, value,
+    )
+    .await
+    {
+        warn!(
+            "[VIA] Could not set actuation point for key at row {:?}, col {:?}",
+            row, col
+        )
+    }
+}
+
+pub async fn analog_matrix_save<K: ViaKeyboard>()
+where
+    [(); <<K::StorageType as StorageDevice>::FlashStorageType as FlashStorage>::ERASE_SIZE]:,
+{
+    if let Some(database) = K::get_storage_service() {
+        <K::MatrixType as crate::keyboard::private::MaybeKeyboardMatrix>::save_actuation_points(
+            database,
+        )
+        .await;
+    }
+}
+
+/// Parse a version component from `CARGO_PKG_VERSION_*` at compile time.
+const fn parse_version_component(component: &str) -> u8 {
+    let bytes = component.as_bytes();
+    let mut value = 0u8;
+    let mut i = 0;
+    while i < bytes.len() {
+        value = value * 10 + (bytes[i] - b'0');
+        i += 1;
+    }
+    value
+}
+
+const RUMCAKE_VERSION: [u8; 3] = [
+    parse_version_component(env!("CARGO_PKG_VERSION_MAJOR")),
+    parse_version_component(env!("CARGO_PKG_VERSION_MINOR")),
+    parse_version_component(env!("CARGO_PKG_VERSION_PATCH")),
+];
+
+/// Bitfield of the `rumcake` features that this firmware was compiled with. The order of these
+/// bits must not change, since host tools rely on it.
+///
+/// Every new keyboard feature flag needs a bit here. New bits must be appended to the end, and
+/// the feature list in the Via docs should be updated to match. Bit 31 is reserved for
+/// [`EXTENDED_FEATURES`].
+const ENABLED_FEATURES: u32 = (cfg!(feature = "usb") as u32)
+    | (cfg!(feature = "bluetooth") as u32) << 1
+    | (cfg!(feature = "storage") as u32) << 2
+    | (cfg!(feature = "via") as u32) << 3
+    | (cfg!(feature = "vial") as u32) << 4
+    | (cfg!(feature = "split-central") as u32) << 5
+    | (cfg!(feature = "split-peripheral") as u32) << 6
+    | (cfg!(feature = "media-keycodes") as u32) << 7
+    | (cfg!(feature = "unicode") as u32) << 8
+    | (cfg!(feature = "simple-backlight") as u32) << 9
+    | (cfg!(feature = "simple-backlight-matrix") as u32) << 10
+    | (cfg!(feature = "rgb-backlight-matrix") as u32) << 11
+    | (cfg!(feature = "underglow") as u32) << 12
+    | (cfg!(feature = "display") as u32) << 13
+    | (cfg!(feature = "audio") as u32) << 14
+    | (cfg!(feature = "pointer") as u32) << 15
+    | (cfg!(feature = "via-key-events") as u32) << 16
+    | (cfg!(feature = "caps-word") as u32) << 17
+    | (cfg!(feature = "leader-key") as u32) << 18
+    | (cfg!(feature = "auto-shift") as u32) << 19
+    | (cfg!(feature = "key-lock") as u32) << 20
+    | (cfg!(feature = "dynamic-macros") as u32) << 21
+    | (cfg!(feature = "wpm") as u32) << 22
+    | (cfg!(feature = "scan-metrics") as u32) << 23
+    | (cfg!(feature = "idle") as u32) << 24
+    | (cfg!(feature = "raw-hid") as u32) << 25
+    | (cfg!(feature = "status-led") as u32) << 26
+    | (cfg!(feature = "split-crc") as u32) << 27;
+
+/// Bit of [`ENABLED_FEATURES`] that signals an extended feature word. Once bits 0 to 30 are used
+/// up, this bit gets set, and another big-endian feature word is placed directly after the build
+/// ID, using the same layout (with its own bit 31 reserved to signal another word).
+const EXTENDED_FEATURES: u32 = 1 << 31;
+
+const _: () = {
+    assert!(
+        ENABLED_FEATURES & EXTENDED_FEATURES == 0,
+        "Bit 31 of the enabled features is reserved for extended features"
+    );
+};
+
+/// Maximum number of bytes of [`ViaKeyboard::VIA_BUILD_ID`] that get reported to the host.
+const BUILD_ID_MAX_LEN: usize = 64;
+
+const CAPABILITIES_HEADER_LEN: usize = 17;
+
+/// Number of capability bytes that fit in a single packet, after the command ID, page index and
+/// page count.
+const CAPABILITIES_PAGE_SIZE: usize = 29;
+
+fn lighting_channels<K: ViaKeyboard>() -> u8 {
+    #[allow(unused_mut)]
+    let mut channels = 0;
+
+    #[cfg(feature = "simple-backlight")]
+    if <<K::Layout as KeyboardLayout>::SimpleBacklightDeviceType as crate::lighting::simple_backlight::private::MaybeSimpleBacklightDevice>::get_command_channel().is_some() {
+        channels |= 1;
+    }
+
+    #[cfg(feature = "simple-backlight-matrix")]
+    if <<K::Layout as KeyboardLayout>::SimpleBacklightMatrixDeviceType as crate::lighting::simple_backlight_matrix::private::MaybeSimpleBacklightMatrixDevice>::get_command_channel().is_some() {
+        channels |= 1 << 1;
+    }
+
+    #[cfg(feature = "rgb-backlight-matrix")]
+    if <<K::Layout as KeyboardLayout>::RGBBacklightMatrixDeviceType as crate::lighting::rgb_backlight_matrix::private::MaybeRGBBacklightMatrixDevice>::get_command_channel().is_some() {
+        channels |= 1 << 2;
+    }
+
+    #[cfg(feature = "underglow")]
+    if <<K::Layout as KeyboardLayout>::UnderglowDeviceType as crate::lighting::underglow::private::MaybeUnderglowDevice>::get_command_channel().is_some() {
+        channels |= 1 << 3;
+    }
+
+    channels
+}
+
+fn has_pointer<K: ViaKeyboard>() -> bool {
+    #[cfg(feature = "pointer")]
+    if <K::PointerDeviceType as crate::pointer::private::MaybePointerDevice>::get_mouse_report_send_channel().is_some() {
+        return true;
+    }
+
+    false
+}
+
+/// Respond with a page of the firmware capabilities. `data[1]` contains the requested page index.
+/// The response contains the page index in `data[1]`, the total number of pages in `data[2]`, and
+/// the contents of the page in the remaining bytes.
+pub fn get_capabilities<K: ViaKeyboard>(data: &mut [u8])
+where
+    [(); <<K::StorageType as StorageDevice>::FlashStorageType as FlashStorage>::ERASE_SIZE]:,
+{
+    let build_id = &K::VIA_BUILD_ID.as_bytes()[..K::VIA_BUILD_ID.len().min(BUILD_ID_MAX_LEN)];
+
+    let mut info = [0u8; CAPABILITIES_HEADER_LEN + BUILD_ID_MAX_LEN];
+    info[0] = 1; // Format version
+    info[1..=3].copy_from_slice(&RUMCAKE_VERSION);
+    info[4..=7].copy_from_slice(&ENABLED_FEATURES.to_be_bytes());
+    info[8] = K::Layout::LAYOUT_ROWS as u8;
+    info[9] = K::Layout::LAYOUT_COLS as u8;
+    info[10] = K::Layout::LAYERS as u8;
+    info[11] = K::DYNAMIC_KEYMAP_LAYER_COUNT as u8;
+    info[12] = K::Layout::NUM_ENCODERS as u8;
+    info[13] = lighting_channels::<K>();
+    info[14] = has_pointer::<K>() as u8;
+    // Set for any configured storage backend, whether it uses internal or external flash
+    info[15] = K::get_storage_service().is_some() as u8;
+    info[16] = build_id.len() as u8;
+    info[CAPABILITIES_HEADER_LEN..(CAPABILITIES_HEADER_LEN + build_id.len())]
+        .copy_from_slice(build_id);
+
+    let len = CAPABILITIES_HEADER_LEN + build_id.len();
+    let page_count = (len + CAPABILITIES_PAGE_SIZE - 1) / CAPABILITIES_PAGE_SIZE;
+    let page = data[1] as usize;
+
+    data[2] = page_count as u8;
+    data[3..].fill(0);
+
+    if page >= page_count {
+        warn!(
+            "[VIA] Requested capabilities page {} does not exist, page count: {}",
+            page, page_count
+        );
+        return;
+    }
+
+    let start = page * CAPABILITIES_PAGE_SIZE;
+    let end = (start + CAPABILITIES_PAGE_SIZE).min(len);
+    data[3..(3 + end - start)].copy_from_slice(&info[start..end]);
+}
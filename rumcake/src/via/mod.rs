@@ -3,6 +3,9 @@
 //! To use Via, you will need to implement [`ViaKeyboard`]. If you would like to save your Via
 //! changes, you will also need to enable the `storage` feature flag, and setup the appropriate
 //! storage buffers using [`crate::setup_via_storage_buffers`].
+//!
+//! If the `via-key-events` feature flag is enabled, the host can also ask the keyboard to send a
+//! report for every key press and release (see [`key_events_task`]).
 
 use defmt::assert;
 use embassy_futures::join;
@@ -26,7 +29,10 @@ pub use rumcake_macros::{connect_storage_service, setup_macro_buffer};
 /// and the number of sequences that can be created to be specified.
 #[derive(Debug)]
 pub struct MacroBuffer<'a, const N: usize, const S: usize> {
+    /// Macro data, exactly as it was sent by Via.
     buffer: [u8; N],
+    /// Macro data with delays converted to layout ticks, used by the sequences.
+    converted: [u8; N],
     sequences: [&'a [u8]; S],
 }
 
@@ -34,21 +40,120 @@ impl<'a, const N: usize, const S: usize> MacroBuffer<'a, N, S> {
     pub const fn new() -> Self {
         Self {
             buffer: [0; N],
+            converted: [0; N],
             sequences: [&[]; S],
         }
     }
 
-    pub fn update_buffer(&'a mut self, offset: usize, data: &[u8]) {
+    /// Write macro data sent by Via to the buffer, and update the sequences for each macro.
+    ///
+    /// Macros are separated by a `0` byte, and use the same encoding as keyberon's
+    /// [`keyberon::action::Action::Sequence`]. The only exception is Via's delay token
+    /// (`SS_DELAY`), which is encoded as `1, 4`, followed by the duration as ASCII digits,
+    /// terminated with `b'|'`. Via uses milliseconds, while keyberon counts layout ticks, so the
+    /// duration is converted using `tick_interval_us` (see
+    /// [`KeyboardLayout::LAYOUT_TICK_INTERVAL_US`]).
+    pub fn update_buffer(&'a mut self, offset: usize, data: &[u8], tick_interval_us: u64) {
         self.buffer[offset..(offset + data.len())].copy_from_slice(data);
 
-        // update existing actions
-        let mut chunks = self.buffer.splitn(S + 1, |byte| *byte == 0);
-        for (i, action) in self.sequences.iter_mut().enumerate() {
-            if let Some(chunk) = chunks.nth(i) {
-                *action = chunk
+        // Convert the delays of every macro. Converted delays can have more digits than the
+        // original if ticks are shorter than a millisecond, so the last macros may get cut off.
+        self.converted.fill(0);
+        let mut len = 0;
+        for chunk in self.buffer.splitn(S + 1, |byte| *byte == 0).take(S) {
+            let Some(converted_len) =
+                convert_macro_delays(chunk, &mut self.converted[len..], tick_interval_us)
+            else {
+                defmt::warn!("[VIA] Not enough space in the macro buffer to convert macro delays");
+                self.converted[len..].fill(0);
+                break;
+            };
+            len += converted_len + 1; // separating 0 byte
+            if len >= N {
+                break;
             }
         }
+
+        // update existing actions
+        let converted: &'a [u8] = &self.converted;
+        let chunks = converted.splitn(S + 1, |byte| *byte == 0);
+        for (action, chunk) in self.sequences.iter_mut().zip(chunks) {
+            *action = chunk
+        }
+    }
+}
+
+/// Copy a macro to `out`, converting the durations of delay tokens from milliseconds to layout
+/// ticks. Returns the number of bytes written, or `None` if the converted macro does not fit.
+fn convert_macro_delays(mut data: &[u8], out: &mut [u8], tick_interval_us: u64) -> Option<usize> {
+    let mut len = 0;
+    let mut write = |bytes: &[u8]| {
+        let dest = out.get_mut(len..(len + bytes.len()))?;
+        dest.copy_from_slice(bytes);
+        len += bytes.len();
+        Some(())
+    };
+
+    while !data.is_empty() {
+        let token_len = match data {
+            [1, 1 | 2 | 3, ..] => data.len().min(3),
+            [1, 4, digits @ ..] => match parse_macro_delay(digits) {
+                Some((ms, end)) => {
+                    // Round up, so that short delays don't become 0 ticks
+                    let ticks = ms.saturating_mul(1000).div_ceil(tick_interval_us.max(1));
+                    let mut digits = [0; 20];
+                    let digit_count = format_macro_delay(ticks.min(u32::MAX as u64), &mut digits);
+
+                    write(&[1, 4])?;
+                    write(&digits[..digit_count])?;
+                    write(&[b'|'])?;
+                    data = &data[(3 + end)..]; // prefix (1) + variant number (4 for delay) + '|'
+                    continue;
+                }
+                // Invalid delays are copied as-is, and end the sequence when they are reached
+                None => data.len(),
+            },
+            _ => 1,
+        };
+
+        write(&data[..token_len])?;
+        data = &data[token_len..];
     }
+
+    Some(len)
+}
+
+/// Parse the duration of a delay token, in the same way as keyberon. Returns the duration, and
+/// the position of the terminating `b'|'`.
+fn parse_macro_delay(digits: &[u8]) -> Option<(u64, usize)> {
+    let end = digits.iter().position(|d| *d == b'|')?;
+    if end == 0 || digits[0] == b'0' {
+        return None;
+    }
+
+    digits[..end]
+        .iter()
+        .try_fold(0u64, |acc, digit| {
+            digit
+                .is_ascii_digit()
+                .then(|| acc.saturating_mul(10).saturating_add((digit - b'0') as u64))
+        })
+        .map(|delay| (delay, end))
+}
+
+/// Write `value` to `buf` as ASCII digits. Returns the number of digits written.
+fn format_macro_delay(mut value: u64, buf: &mut [u8; 20]) -> usize {
+    let mut count = 0;
+    loop {
+        buf[count] = b'0' + (value % 10) as u8;
+        count += 1;
+        value /= 10;
+        if value == 0 {
+            break;
+        }
+    }
+    buf[..count].reverse();
+    count
 }
 
 /// The different types of backlighting that can be used with Via. See
@@ -59,11 +164,44 @@ pub enum BacklightType {
     RGBBacklightMatrix,
 }
 
+/// A handler for commands sent to a Via custom UI channel. See
+/// [`ViaKeyboard::CUSTOM_CHANNEL_HANDLERS`].
+///
+/// For each command, `value_id` is the value ID sent by the host (byte `2` of the packet), and
+/// `data` contains the rest of the packet, starting from byte `3`.
+pub trait ViaCustomChannelHandler {
+    /// Handle an `id_custom_get_value` command by writing the requested value to `data`. Returns
+    /// `false` if `value_id` is not recognized. By default, this returns `false`.
+    fn get_value(&self, _value_id: u8, _data: &mut [u8]) -> bool {
+        false
+    }
+
+    /// Handle an `id_custom_set_value` command. Returns `false` if `value_id` is not recognized.
+    /// By default, this returns `false`.
+    fn set_value(&self, _value_id: u8, _data: &[u8]) -> bool {
+        false
+    }
+
+    /// Handle an `id_custom_save` command. By default, this does nothing.
+    fn save(&self) {}
+}
+
 /// A trait that keyboards must implement to use the Via protocol.
 pub trait ViaKeyboard {
     /// The layout that this Via instance will control.
     type Layout: KeyboardLayout;
 
+    /// The analog matrix that this Via instance can tune. If this is set, the host can read the
+    /// current analog values of each key, and change their actuation points.
+    type MatrixType: crate::keyboard::private::MaybeKeyboardMatrix =
+        crate::keyboard::private::EmptyKeyboardMatrix;
+
+    #[cfg(feature = "pointer")]
+    /// The pointing device of this keyboard. If this is set, the capabilities command reports that
+    /// a pointing device is present.
+    type PointerDeviceType: crate::pointer::private::MaybePointerDevice =
+        crate::pointer::private::EmptyPointerDevice;
+
     /// The storage device used to store Via data.
     type StorageType: StorageDevice = EmptyStorageDevice;
     fn get_storage_service() -> Option<
@@ -84,6 +222,14 @@ pub trait ViaKeyboard {
     /// Version of your firmware.
     const VIA_FIRMWARE_VERSION: u32 = 1; // This is the default if not set in QMK.
 
+    /// Identifier for this build of your firmware, reported by the capabilities command. Defaults
+    /// to the value of the `RUMCAKE_BUILD_ID` environment variable at compile time, if it is set.
+    /// Only the first 64 bytes are reported.
+    const VIA_BUILD_ID: &'static str = match option_env!("RUMCAKE_BUILD_ID") {
+        Some(id) => id,
+        None => "",
+    };
+
     /// How many bytes are needed to represent the number of possible layout options for your
     /// keyboard.
     const VIA_EEPROM_LAYOUT_OPTIONS_SIZE: usize = 1; // This is the default if not set in QMK
@@ -136,17 +282,38 @@ pub trait ViaKeyboard {
     /// Optional handler that allows you to handle changes to the current layout options setting.
     fn handle_set_layout_options(updated_layout: u32) {}
 
+    /// Handlers for custom UI channels, and the channel IDs that they handle. See
+    /// <https://www.caniusevia.com/docs/custom_ui>. **This is currently only applicable to Via,
+    /// not Vial.**
+    ///
+    /// Commands sent to a channel in this list are passed to its handler, unless the channel is
+    /// handled by the Via protocol implementation itself (see
+    /// [`ViaKeyboard::handle_custom_value_command`]). Commands sent to any other channels are
+    /// passed to [`ViaKeyboard::handle_custom_value_command`].
+    const CUSTOM_CHANNEL_HANDLERS: &'static [(u8, &'static dyn ViaCustomChannelHandler)] = &[];
+
     /// Optional handler that you can use to handle custom UI channel commands. See
     /// <https://www.caniusevia.com/docs/custom_ui>. **This is currently only applicable to Via,
     /// not Vial.**
     ///
-    /// This is called if the Via protocol is unable to handle a custom channel command. The
-    /// current Via protocol implementation handles lighting (`rgblight`/`underglow`,
+    /// This is called if the Via protocol is unable to handle a custom channel command, and there
+    /// is no handler for the channel in [`ViaKeyboard::CUSTOM_CHANNEL_HANDLERS`]. The current Via
+    /// protocol implementation handles lighting (`rgblight`/`underglow`,
     /// `backlight`/`simple-backlight`, `led_matrix`/`simple-backlight-matrix`,
-    /// `rgb_matrix`/`rgb-backlight-matrix`) channels.
+    /// `rgb_matrix`/`rgb-backlight-matrix`) channels, the analog matrix channel (see
+    /// [`ViaKeyboard::MatrixType`]), and the key events channel.
     fn handle_custom_value_command(data: &mut [u8], _len: u8) {
         data[0] = protocol::ViaCommandId::Unhandled as u8;
     }
+
+    /// Optional handler that is called with every Via/Vial packet received from the host, before
+    /// it is processed. This can be used to log the commands sent by the host. Processing of the
+    /// packet is delayed until this returns, so it can also be used to throttle commands.
+    async fn on_via_command_received(_data: &[u8]) {}
+
+    /// Optional handler that is called with the response to every Via/Vial packet, right before it
+    /// is sent to the host.
+    fn on_via_response(_data: &[u8]) {}
 }
 
 /// Report descriptor used for Via. Pulled from QMK.
@@ -211,17 +378,67 @@ where
             let mut report = receive_channel.receive().await;
 
             if K::VIA_ENABLED {
+                K::on_via_command_received(&report).await;
+
                 {
                     let mut via_state = via_state.lock().await;
                     protocol::process_via_command::<K>(&mut report, &mut via_state).await;
                 }
 
+                K::on_via_response(&report);
                 send_channel.send(report).await;
             }
         }
     };
 
-    join::join(report_fut, protocol::background_task::<K>(&via_state)).await;
+    join::join4(
+        report_fut,
+        protocol::background_task::<K>(&via_state),
+        handlers::device_indication_task::<K>(),
+        key_events_task::<T>(),
+    )
+    .await;
+}
+
+#[cfg(feature = "via-key-events")]
+static KEY_EVENTS_ENABLED: core::sync::atomic::AtomicBool =
+    core::sync::atomic::AtomicBool::new(false);
+
+/// Send a report to the host for every event received from [`crate::keyboard::MATRIX_EVENTS`].
+///
+/// This is disabled until the host enables it, so key presses are never sent to the host unless
+/// they were asked for. Each report contains the rumcake-specific command ID `0xF1` in byte `0`,
+/// followed by the row and column of the key, and `1` if the key was pressed, or `0` if it was
+/// released.
+///
+/// If the `via-key-events` feature flag is not enabled, this does nothing.
+pub(crate) async fn key_events_task<T: HIDDevice>() {
+    #[cfg(feature = "via-key-events")]
+    {
+        let Ok(mut subscriber) = crate::keyboard::MATRIX_EVENTS.subscriber() else {
+            defmt::warn!(
+                "[VIA] Could not subscribe to matrix events. Key events will not be sent."
+            );
+            return;
+        };
+        let send_channel = T::get_via_hid_send_channel();
+
+        loop {
+            let event = subscriber.next_message_pure().await;
+
+            if !KEY_EVENTS_ENABLED.load(core::sync::atomic::Ordering::Relaxed) {
+                continue;
+            }
+
+            let (row, col) = event.coord();
+            let mut report = [0; 32];
+            report[0] = protocol::ViaCommandId::KeyEvent as u8;
+            report[1] = row;
+            report[2] = col;
+            report[3] = event.is_press() as u8;
+            send_channel.send(report).await;
+        }
+    }
 }
 
 static VIA_LAYOUT_OPTIONS: Signal<RawMutex, u32> = Signal::new();
@@ -314,6 +531,50 @@ where
                 &encoder_metadata,
             )
             .await;
+        if let Ok(stored_data) = database
+            .read_raw(crate::storage::StorageKey::DynamicKeymapEncoder)
+            .await
+        {
+            // Load encoder layout from flash
+            let mut layout = V::Layout::get_layout().layout.lock().await;
+            for byte in (0..stored_data.len()).step_by(2) {
+                let layer = byte / (V::Layout::NUM_ENCODERS * 2 * 2);
+                let encoder_id = (byte / (2 * 2)) % V::Layout::NUM_ENCODERS;
+                let clockwise = (byte / 2) % 2 == 0;
+
+                if let (Some(position), Some(action)) = (
+                    handlers::encoder_layout_position::<V>(encoder_id as u8, clockwise),
+                    protocol::keycodes::convert_keycode_to_action::<V>(u16::from_be_bytes(
+                        stored_data[byte..byte + 2].try_into().unwrap(),
+                    )),
+                ) {
+                    if layout.change_action(position, layer, action).is_err() {
+                        defmt::warn!("[VIA] Encoder layout mapping is out of bounds.");
+                    }
+                }
+            }
+        } else {
+            // Save default encoder layout to flash
+            let layout = V::Layout::get_layout().layout.lock().await;
+            let mut buf = [0; V::DYNAMIC_KEYMAP_LAYER_COUNT * V::Layout::NUM_ENCODERS * 2 * 2];
+            for byte in (0..buf.len()).step_by(2) {
+                let layer = byte / (V::Layout::NUM_ENCODERS * 2 * 2);
+                let encoder_id = (byte / (2 * 2)) % V::Layout::NUM_ENCODERS;
+                let clockwise = (byte / 2) % 2 == 0;
+
+                if let Some(action) =
+                    handlers::encoder_layout_position::<V>(encoder_id as u8, clockwise)
+                        .and_then(|position| layout.get_action(position, layer))
+                {
+                    buf[(byte)..(byte + 2)].copy_from_slice(
+                        &protocol::keycodes::convert_action_to_keycode::<V>(action).to_be_bytes(),
+                    );
+                }
+            }
+            let _ = database
+                .write_raw(StorageKey::DynamicKeymapEncoder, &buf)
+                .await;
+        };
 
         // Initialize macros
         let _ = database
@@ -327,8 +588,15 @@ where
             .await
         {
             if let Some(macro_data) = V::get_macro_buffer() {
-                macro_data.update_buffer(0, stored_data)
+                macro_data.update_buffer(
+                    0,
+                    stored_data,
+                    <V::Layout as KeyboardLayout>::LAYOUT_TICK_INTERVAL_US,
+                )
             }
         };
+
+        // Initialize analog actuation points
+        <V::MatrixType as crate::keyboard::private::MaybeKeyboardMatrix>::initialize_actuation_points(database).await;
     }
 }
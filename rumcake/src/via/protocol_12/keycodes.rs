@@ -1,8 +1,9 @@
+use defmt::{warn, Debug2Format};
 use keyberon::action::{Action, OneShotAction, OneShotEndConfig};
 use keyberon::key_code::KeyCode;
 use num_derive::FromPrimitive;
 
-use crate::keyboard::Keycode;
+use crate::keyboard::{KeyboardLayout, Keycode, TapDanceEntry};
 use crate::via::{BacklightType, ViaKeyboard};
 
 #[repr(u16)]
@@ -35,7 +36,7 @@ enum QMKKeycodeRanges {
     QK_LAYER_TAP_TOGGLE_MAX = 0x52DF,
     QK_SWAP_HANDS = 0x5600, // TODO: unhandled
     QK_SWAP_HANDS_MAX = 0x56FF,
-    QK_TAP_DANCE = 0x5700, // TODO: unhandled, switch to kanata keyberon fork
+    QK_TAP_DANCE = 0x5700,
     QK_TAP_DANCE_MAX = 0x57FF,
     QK_MAGIC = 0x7000, // TODO: unhandled
     QK_MAGIC_MAX = 0x70FF,
@@ -900,7 +901,28 @@ const UNKNOWN_KEYCODE: u16 = 0xFFFF;
 /// For QK_USER, there exists 32 QMKKeycodes enum values that fall in that range. Even though the
 /// QK_USER range spans more than 32 possible values (0x7E40 to 0x7FFF), we will only use the codes
 /// that are defined as enum values in QMKKeycodes (QK_USER_0 to QK_USER_31), and no more.
+///
+/// Every time an action is converted to the [`UNKNOWN_KEYCODE`], a warning is logged, so that
+/// actions that Via can't display can be diagnosed.
 pub(crate) fn convert_action_to_keycode<K: ViaKeyboard + 'static>(action: Action<Keycode>) -> u16
+where
+    [(); K::DYNAMIC_KEYMAP_MACRO_BUFFER_SIZE as usize]:,
+    [(); K::DYNAMIC_KEYMAP_MACRO_COUNT as usize]:,
+{
+    let keycode = action_to_keycode::<K>(action);
+
+    if keycode == UNKNOWN_KEYCODE {
+        warn!(
+            "[VIA] Action {:?} can not be converted to a QMK keycode, so it will be sent as {:#X}.",
+            Debug2Format(&action),
+            UNKNOWN_KEYCODE
+        );
+    }
+
+    keycode
+}
+
+fn action_to_keycode<K: ViaKeyboard + 'static>(action: Action<Keycode>) -> u16
 where
     [(); K::DYNAMIC_KEYMAP_MACRO_BUFFER_SIZE as usize]:,
     [(); K::DYNAMIC_KEYMAP_MACRO_COUNT as usize]:,
@@ -938,7 +960,7 @@ where
                 UNKNOWN_KEYCODE
             }
         }
-        Action::HoldTap(_) => todo!(),
+        Action::HoldTap(_) => UNKNOWN_KEYCODE,
         Action::OneShot(&OneShotAction {
             action: keyberon::action::Action::Layer(layer),
             ..
@@ -962,6 +984,16 @@ where
                     QMKKeycodeRanges::QK_MACRO as u16 + macro_number as u16
                 })
         }),
+        Action::TapDance(tap_dance) => {
+            K::Layout::get_tap_dance_buffer().map_or(UNKNOWN_KEYCODE, |tap_dances| {
+                tap_dances
+                    .iter()
+                    .position(|entry| entry.is_action(tap_dance))
+                    .map_or(UNKNOWN_KEYCODE, |tap_dance_number| {
+                        QMKKeycodeRanges::QK_TAP_DANCE as u16 + tap_dance_number as u16
+                    })
+            })
+        }
         Action::Custom(key) => match key {
             Keycode::User(id) => {
                 if id as u16 <= 31 {
@@ -971,10 +1003,14 @@ where
                 }
             }
             Keycode::Hardware(command) => match command {
+                crate::hw::HardwareCommand::OutputAuto => QMKKeycodes::QK_OUTPUT_AUTO as u16,
                 crate::hw::HardwareCommand::OutputUSB => QMKKeycodes::QK_OUTPUT_USB as u16,
                 crate::hw::HardwareCommand::OutputBluetooth => {
                     QMKKeycodes::QK_OUTPUT_BLUETOOTH as u16
                 }
+                crate::hw::HardwareCommand::ToggleNKRO => QMKKeycodes::QK_MAGIC_TOGGLE_NKRO as u16,
+                crate::hw::HardwareCommand::EnableNKRO => QMKKeycodes::QK_MAGIC_NKRO_ON as u16,
+                crate::hw::HardwareCommand::DisableNKRO => QMKKeycodes::QK_MAGIC_NKRO_OFF as u16,
                 _ => UNKNOWN_KEYCODE,
             },
             #[cfg(feature = "media-keycodes")]
@@ -1203,8 +1239,48 @@ where
 
                 UNKNOWN_KEYCODE
             }
-            #[allow(unreachable_patterns)]
-            _ => UNKNOWN_KEYCODE,
+            #[cfg(feature = "caps-word")]
+            Keycode::CapsWord(command) => match command {
+                crate::caps_word::CapsWordCommand::Toggle => {
+                    QMKKeycodes::QK_CAPS_WORD_TOGGLE as u16
+                }
+                _ => UNKNOWN_KEYCODE,
+            },
+            #[cfg(feature = "leader-key")]
+            Keycode::Leader => QMKKeycodes::QK_LEADER as u16,
+            #[cfg(feature = "key-lock")]
+            Keycode::KeyLock => QMKKeycodes::QK_LOCK as u16,
+            #[cfg(feature = "dynamic-macros")]
+            Keycode::DynamicMacro(command) => match command {
+                crate::dynamic_macro::DynamicMacroCommand::StartRecording(0) => {
+                    QMKKeycodes::QK_DYNAMIC_MACRO_RECORD_START_1 as u16
+                }
+                crate::dynamic_macro::DynamicMacroCommand::StartRecording(1) => {
+                    QMKKeycodes::QK_DYNAMIC_MACRO_RECORD_START_2 as u16
+                }
+                crate::dynamic_macro::DynamicMacroCommand::StopRecording => {
+                    QMKKeycodes::QK_DYNAMIC_MACRO_RECORD_STOP as u16
+                }
+                crate::dynamic_macro::DynamicMacroCommand::Play(0) => {
+                    QMKKeycodes::QK_DYNAMIC_MACRO_PLAY_1 as u16
+                }
+                crate::dynamic_macro::DynamicMacroCommand::Play(1) => {
+                    QMKKeycodes::QK_DYNAMIC_MACRO_PLAY_2 as u16
+                }
+                _ => UNKNOWN_KEYCODE,
+            },
+            Keycode::Bootloader => QMKKeycodes::QK_BOOTLOADER as u16,
+            // The following keycodes don't have a QMK equivalent. There is intentionally no
+            // wildcard pattern here, so that adding a new `Keycode` variant without deciding on
+            // its conversion results in a compile error.
+            #[cfg(feature = "unicode")]
+            Keycode::Unicode(_) => UNKNOWN_KEYCODE,
+            #[cfg(feature = "audio")]
+            Keycode::Audio(_) => UNKNOWN_KEYCODE,
+            #[cfg(feature = "pointer")]
+            Keycode::Mouse(_) => UNKNOWN_KEYCODE,
+            #[cfg(feature = "bluetooth")]
+            Keycode::Bluetooth(_) => UNKNOWN_KEYCODE,
         },
         _ => UNKNOWN_KEYCODE,
     }
@@ -1433,6 +1509,14 @@ where
         });
     }
 
+    if QMKKeycodeRanges::QK_TAP_DANCE as u16 <= keycode
+        && keycode <= QMKKeycodeRanges::QK_TAP_DANCE_MAX as u16
+    {
+        let tap_dance_number = (keycode - QMKKeycodeRanges::QK_TAP_DANCE as u16) as usize;
+        let tap_dances: &'static [TapDanceEntry] = K::Layout::get_tap_dance_buffer()?;
+        return tap_dances.get(tap_dance_number).map(TapDanceEntry::action);
+    }
+
     if QMKKeycodeRanges::QK_LIGHTING as u16 <= keycode
         && keycode <= QMKKeycodeRanges::QK_LIGHTING_MAX as u16
     {
@@ -1696,9 +1780,37 @@ where
         }
     }
 
+    if QMKKeycodeRanges::QK_MAGIC as u16 <= keycode
+        && keycode <= QMKKeycodeRanges::QK_MAGIC_MAX as u16
+    {
+        if keycode == QMKKeycodes::QK_MAGIC_TOGGLE_NKRO as u16 {
+            return Some(Action::Custom(Keycode::Hardware(
+                crate::hw::HardwareCommand::ToggleNKRO,
+            )));
+        }
+
+        if keycode == QMKKeycodes::QK_MAGIC_NKRO_ON as u16 {
+            return Some(Action::Custom(Keycode::Hardware(
+                crate::hw::HardwareCommand::EnableNKRO,
+            )));
+        }
+
+        if keycode == QMKKeycodes::QK_MAGIC_NKRO_OFF as u16 {
+            return Some(Action::Custom(Keycode::Hardware(
+                crate::hw::HardwareCommand::DisableNKRO,
+            )));
+        }
+    }
+
     if QMKKeycodeRanges::QK_QUANTUM as u16 <= keycode
         && keycode <= QMKKeycodeRanges::QK_QUANTUM as u16
     {
+        if keycode == QMKKeycodes::QK_OUTPUT_AUTO as u16 {
+            return Some(Action::Custom(Keycode::Hardware(
+                crate::hw::HardwareCommand::OutputAuto,
+            )));
+        }
+
         if keycode == QMKKeycodes::QK_OUTPUT_USB as u16 {
             return Some(Action::Custom(Keycode::Hardware(
                 crate::hw::HardwareCommand::OutputUSB,
@@ -1710,6 +1822,55 @@ where
                 crate::hw::HardwareCommand::OutputBluetooth,
             )));
         }
+
+        #[cfg(feature = "caps-word")]
+        if keycode == QMKKeycodes::QK_CAPS_WORD_TOGGLE as u16 {
+            return Some(Action::Custom(Keycode::CapsWord(
+                crate::caps_word::CapsWordCommand::Toggle,
+            )));
+        }
+
+        #[cfg(feature = "leader-key")]
+        if keycode == QMKKeycodes::QK_LEADER as u16 {
+            return Some(Action::Custom(Keycode::Leader));
+        }
+
+        #[cfg(feature = "key-lock")]
+        if keycode == QMKKeycodes::QK_LOCK as u16 {
+            return Some(Action::Custom(Keycode::KeyLock));
+        }
+
+        #[cfg(feature = "dynamic-macros")]
+        {
+            use crate::dynamic_macro::DynamicMacroCommand;
+
+            let command = match keycode {
+                k if k == QMKKeycodes::QK_DYNAMIC_MACRO_RECORD_START_1 as u16 => {
+                    Some(DynamicMacroCommand::StartRecording(0))
+                }
+                k if k == QMKKeycodes::QK_DYNAMIC_MACRO_RECORD_START_2 as u16 => {
+                    Some(DynamicMacroCommand::StartRecording(1))
+                }
+                k if k == QMKKeycodes::QK_DYNAMIC_MACRO_RECORD_STOP as u16 => {
+                    Some(DynamicMacroCommand::StopRecording)
+                }
+                k if k == QMKKeycodes::QK_DYNAMIC_MACRO_PLAY_1 as u16 => {
+                    Some(DynamicMacroCommand::Play(0))
+                }
+                k if k == QMKKeycodes::QK_DYNAMIC_MACRO_PLAY_2 as u16 => {
+                    Some(DynamicMacroCommand::Play(1))
+                }
+                _ => None,
+            };
+
+            if let Some(command) = command {
+                return Some(Action::Custom(Keycode::DynamicMacro(command)));
+            }
+        }
+
+        if keycode == QMKKeycodes::QK_BOOTLOADER as u16 {
+            return Some(Action::Custom(Keycode::Bootloader));
+        }
     }
 
     if QMKKeycodeRanges::QK_KB as u16 <= keycode && keycode <= QMKKeycodeRanges::QK_KB_MAX as u16 {
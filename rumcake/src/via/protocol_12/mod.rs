@@ -37,6 +37,9 @@ pub(crate) enum ViaCommandId {
     DynamicKeymapSetBuffer,
     DynamicKeymapGetEncoder,
     DynamicKeymapSetEncoder,
+    // rumcake-specific commands
+    GetCapabilities = 0xF0,
+    KeyEvent, // Only sent by the keyboard, see `super::key_events_task`
     Unhandled = 0xFF,
 }
 
@@ -61,6 +64,8 @@ enum ViaChannelId {
     RGBMatrix,
     // Audio, // unused
     LEDMatrix = 5,
+    AnalogMatrix,
+    KeyEvents,
 }
 
 #[derive(FromPrimitive, Debug)]
@@ -78,6 +83,7 @@ enum ViaRGBLightValue {
     EffectSpeed,
     Color,
     Enabled,
+    Zone,
 }
 
 #[derive(FromPrimitive, Debug)]
@@ -97,6 +103,17 @@ enum ViaLEDMatrixValue {
     Enabled,
 }
 
+#[derive(FromPrimitive, Debug)]
+enum ViaAnalogMatrixValue {
+    Values = 1,
+    ActuationPoint,
+}
+
+#[derive(FromPrimitive, Debug)]
+enum ViaKeyEventsValue {
+    Enabled = 1,
+}
+
 pub(crate) struct ViaState<K: ViaKeyboard>
 where
     [(); (K::Layout::LAYOUT_COLS + u8::BITS as usize - 1) / u8::BITS as usize
@@ -180,7 +197,7 @@ pub(crate) async fn process_via_command<K: ViaKeyboard + 'static>(
                     Some(ViaKeyboardValueId::LayoutOptions) => {
                         set_layout_options::<K>(&mut via_state.layout_options, &data[2..=5]).await
                     }
-                    Some(ViaKeyboardValueId::DeviceIndication) => device_indication::<K>().await,
+                    Some(ViaKeyboardValueId::DeviceIndication) => device_indication(),
                     Some(value) => {
                         data[0] = ViaCommandId::Unhandled as u8;
                         warn!(
@@ -249,14 +266,27 @@ pub(crate) async fn process_via_command<K: ViaKeyboard + 'static>(
                 let layer = data[1];
                 let encoder_id = data[2];
                 let clockwise = data[3] != 0;
-                dynamic_keymap_get_encoder::<K>(layer, encoder_id, clockwise, &mut data[4..=5])
-                    .await
+                dynamic_keymap_get_encoder::<K>(
+                    layer,
+                    encoder_id,
+                    clockwise,
+                    &mut data[4..=5],
+                    keycodes::convert_action_to_keycode::<K>,
+                )
+                .await
             } // only if encoder map is enabled
             ViaCommandId::DynamicKeymapSetEncoder => {
                 let layer = data[1];
                 let encoder_id = data[2];
                 let clockwise = data[3] != 0;
-                dynamic_keymap_set_encoder::<K>(layer, encoder_id, clockwise, &data[4..=5]).await
+                dynamic_keymap_set_encoder::<K>(
+                    layer,
+                    encoder_id,
+                    clockwise,
+                    &data[4..=5],
+                    keycodes::convert_keycode_to_action::<K>,
+                )
+                .await
             } // only if encoder map is enabled
             ViaCommandId::DynamicKeymapGetBuffer => {
                 let offset = u16::from_be_bytes(data[1..=2].try_into().unwrap());
@@ -281,6 +311,7 @@ pub(crate) async fn process_via_command<K: ViaKeyboard + 'static>(
                 .await
             }
             ViaCommandId::DynamicKeymapReset => dynamic_keymap_reset().await,
+            ViaCommandId::GetCapabilities => get_capabilities::<K>(data),
             command
                 if command == ViaCommandId::CustomGetValue
                     || command == ViaCommandId::CustomSetValue
@@ -514,6 +545,9 @@ pub(crate) async fn process_via_command<K: ViaKeyboard + 'static>(
                                     Some(ViaRGBLightValue::Enabled) => {
                                         underglow_get_enabled::<K>(&mut data[3..=3]).await;
                                     }
+                                    Some(ViaRGBLightValue::Zone) => {
+                                        underglow_get_zone::<K>(&mut data[3..=3]).await;
+                                    }
                                     None => {
                                         warn!(
                                             "[VIA] Unknown RGB underglow get command received from host {:?}",
@@ -547,6 +581,9 @@ pub(crate) async fn process_via_command<K: ViaKeyboard + 'static>(
                                     Some(ViaRGBLightValue::Enabled) => {
                                         underglow_set_enabled::<K>(&data[3..=3]).await;
                                     }
+                                    Some(ViaRGBLightValue::Zone) => {
+                                        underglow_set_zone::<K>(&data[3..=3]).await;
+                                    }
                                     None => {
                                         warn!(
                                             "[VIA] Unknown RGB underglow get command received from host {:?}",
@@ -559,23 +596,128 @@ pub(crate) async fn process_via_command<K: ViaKeyboard + 'static>(
                             _ => unreachable!("Should not happen"),
                         };
                     }
+                    Some(ViaChannelId::AnalogMatrix) => {
+                        match command {
+                            ViaCommandId::CustomGetValue => {
+                                match num::FromPrimitive::from_u8(data[2]) {
+                                    Some(ViaAnalogMatrixValue::Values) => {
+                                        analog_matrix_get_values::<K>(&mut data[3..]).await
+                                    }
+                                    Some(ViaAnalogMatrixValue::ActuationPoint) => {
+                                        analog_matrix_get_actuation_point::<K>(&mut data[3..=5])
+                                            .await
+                                    }
+                                    None => {
+                                        warn!(
+                                            "[VIA] Unknown analog matrix get command received from host {:?}",
+                                            data[2]
+                                        )
+                                    }
+                                };
+                            }
+                            ViaCommandId::CustomSetValue => {
+                                match num::FromPrimitive::from_u8(data[2]) {
+                                    Some(ViaAnalogMatrixValue::ActuationPoint) => {
+                                        analog_matrix_set_actuation_point::<K>(&data[3..=5]).await
+                                    }
+                                    Some(value) => {
+                                        warn!(
+                                            "[VIA] Analog matrix value {:?} can not be set",
+                                            Debug2Format(&value)
+                                        )
+                                    }
+                                    None => {
+                                        warn!(
+                                            "[VIA] Unknown analog matrix set command received from host {:?}",
+                                            data[2]
+                                        )
+                                    }
+                                };
+                            }
+                            ViaCommandId::CustomSave => analog_matrix_save::<K>().await,
+                            _ => unreachable!("Should not happen"),
+                        };
+                    }
+                    #[cfg(feature = "via-key-events")]
+                    Some(ViaChannelId::KeyEvents) => {
+                        match command {
+                            ViaCommandId::CustomGetValue => {
+                                match num::FromPrimitive::from_u8(data[2]) {
+                                    Some(ViaKeyEventsValue::Enabled) => {
+                                        key_events_get_enabled(&mut data[3..=3])
+                                    }
+                                    None => {
+                                        warn!(
+                                            "[VIA] Unknown key events get command received from host {:?}",
+                                            data[2]
+                                        )
+                                    }
+                                };
+                            }
+                            ViaCommandId::CustomSetValue => {
+                                match num::FromPrimitive::from_u8(data[2]) {
+                                    Some(ViaKeyEventsValue::Enabled) => {
+                                        key_events_set_enabled(&data[3..=3])
+                                    }
+                                    None => {
+                                        warn!(
+                                            "[VIA] Unknown key events set command received from host {:?}",
+                                            data[2]
+                                        )
+                                    }
+                                };
+                            }
+                            ViaCommandId::CustomSave => {
+                                // The toggle is intentionally not saved, so that key events are
+                                // never streamed unless the host asks for them.
+                            }
+                            _ => unreachable!("Should not happen"),
+                        };
+                    }
                     other => {
-                        match other {
-                            Some(channel) => {
+                        if let Some((_, handler)) = K::CUSTOM_CHANNEL_HANDLERS
+                            .iter()
+                            .find(|(channel_id, _)| *channel_id == data[1])
+                        {
+                            let handled = match command {
+                                ViaCommandId::CustomGetValue => {
+                                    handler.get_value(data[2], &mut data[3..])
+                                }
+                                ViaCommandId::CustomSetValue => {
+                                    handler.set_value(data[2], &data[3..])
+                                }
+                                ViaCommandId::CustomSave => {
+                                    handler.save();
+                                    true
+                                }
+                                _ => unreachable!("Should not happen"),
+                            };
+
+                            if !handled {
                                 warn!(
-                                    "[VIA] Unimplemented channel ID received from host: {:?}",
-                                    Debug2Format(&channel)
+                                    "[VIA] Unknown value ID received from host for custom channel {:?}: {:?}",
+                                    data[1], data[2]
                                 );
+                                data[0] = ViaCommandId::Unhandled as u8;
                             }
-                            None => {
-                                warn!(
+                        } else {
+                            match other {
+                                Some(channel) => {
+                                    warn!(
+                                        "[VIA] Unimplemented channel ID received from host: {:?}",
+                                        Debug2Format(&channel)
+                                    );
+                                }
+                                None => {
+                                    warn!(
                                     "[VIA] Unknown channel ID received from host, handle_custom_value_command called: {:?}",
                                     Debug2Format(&data[1])
                                 )
+                                }
                             }
-                        }
 
-                        K::handle_custom_value_command(data, 32);
+                            K::handle_custom_value_command(data, 32);
+                        }
                     }
                 };
             }
@@ -1,12 +1,17 @@
 use defmt::warn;
-use smart_leds::hsv::hsv2rgb;
+use keyberon::action::{Action, HoldTapConfig};
+use serde::{Deserialize, Serialize};
 
 use super::protocol::via::ViaState;
 use super::protocol::{VialState, VIAL_RAW_EPSIZE};
-use super::{VialKeyboard, VIAL_DIRECT_SET_CHANNEL};
-use crate::keyboard::KeyboardLayout;
+use super::{
+    VialKeyboard, VIAL_COMBO_ENTRY_SIZE, VIAL_DIRECT_SET_CHANNEL, VIAL_TAP_DANCE_ENTRY_SIZE,
+};
+use crate::color::{hsv_to_rgb, Hsv};
+use crate::keyboard::{Combo, DynamicTapDance, KeyboardLayout, MAX_COMBO_KEYS};
 use crate::lighting::BacklightMatrixDevice;
 use crate::storage::{FlashStorage, StorageDevice, StorageKey};
+use crate::via::protocol::keycodes;
 
 // Unlike the other normal Via comands, Vial overwrites the command data received from the host
 
@@ -279,27 +284,188 @@ where
             let val = data[(3 + (led - first_led) * 3 + 2) as usize];
             // TODO: use max brightness?
             VIAL_DIRECT_SET_CHANNEL
-                .send((led, hsv2rgb(smart_leds::hsv::Hsv { hue, sat, val })))
+                .send((led, hsv_to_rgb(Hsv::new(hue, sat, val))))
                 .await;
         }
     }
 }
 
+/// QMK setting ID for the one shot key timeout.
+const QMK_SETTING_ONESHOT_TIMEOUT: u16 = 6;
+
+/// QMK setting ID for the tapping term.
+const QMK_SETTING_TAPPING_TERM: u16 = 7;
+
+/// QMK setting ID for the tap-hold configuration bitfield.
+const QMK_SETTING_TAPPING: u16 = 8;
+
+/// QMK settings supported by rumcake, in ascending order.
+const SUPPORTED_QMK_SETTINGS: [u16; 3] = [
+    QMK_SETTING_ONESHOT_TIMEOUT,
+    QMK_SETTING_TAPPING_TERM,
+    QMK_SETTING_TAPPING,
+];
+
+/// Bit in [`QMK_SETTING_TAPPING`] that enables permissive hold.
+const QMK_TAPPING_PERMISSIVE_HOLD: u8 = 1 << 0;
+
+/// Bit in [`QMK_SETTING_TAPPING`] that enables hold on other key press.
+const QMK_TAPPING_HOLD_ON_OTHER_KEY_PRESS: u8 = 1 << 1;
+
+/// Bit in [`QMK_SETTING_TAPPING`] that enables retro tapping.
+const QMK_TAPPING_RETRO_TAPPING: u8 = 1 << 3;
+
+/// QMK settings that were changed using Vial. `None` (or a `hold_tap_config` of `0`) means that
+/// the layout uses the timeouts and configuration defined by each action.
+#[derive(Serialize, Deserialize, Default, Clone, Copy)]
+pub(super) struct QmkSettings {
+    oneshot_timeout: Option<u16>,
+    tapping_term: Option<u16>,
+    hold_tap_config: u8,
+}
+
+fn encode_hold_tap_config(config: Option<HoldTapConfig>) -> u8 {
+    match config {
+        Some(HoldTapConfig::PermissiveHold) => QMK_TAPPING_PERMISSIVE_HOLD,
+        Some(HoldTapConfig::HoldOnOtherKeyPress) => QMK_TAPPING_HOLD_ON_OTHER_KEY_PRESS,
+        Some(HoldTapConfig::RetroTap) => QMK_TAPPING_RETRO_TAPPING,
+        _ => 0,
+    }
+}
+
+fn decode_hold_tap_config(tapping: u8) -> Option<HoldTapConfig> {
+    if tapping & QMK_TAPPING_HOLD_ON_OTHER_KEY_PRESS != 0 {
+        Some(HoldTapConfig::HoldOnOtherKeyPress)
+    } else if tapping & QMK_TAPPING_PERMISSIVE_HOLD != 0 {
+        Some(HoldTapConfig::PermissiveHold)
+    } else if tapping & QMK_TAPPING_RETRO_TAPPING != 0 {
+        Some(HoldTapConfig::RetroTap)
+    } else {
+        None
+    }
+}
+
+/// Apply stored QMK settings to the layout.
+pub(super) async fn apply_qmk_settings<K: VialKeyboard>(settings: QmkSettings)
+where
+    [(); K::Layout::LAYERS]:,
+    [(); K::Layout::LAYOUT_ROWS]:,
+    [(); K::Layout::LAYOUT_COLS]:,
+{
+    let mut layout = <K::Layout as KeyboardLayout>::get_layout()
+        .layout
+        .lock()
+        .await;
+    layout.set_oneshot_timeout(settings.oneshot_timeout);
+    layout.set_tapping_term(settings.tapping_term);
+    layout.set_hold_tap_config(decode_hold_tap_config(settings.hold_tap_config));
+}
+
 pub fn qmk_settings_query(data: &mut [u8]) {
-    // TODO: maybe support some QMK settings
-    data.fill(0xFF); // This indicates that we don't support any QMK settings
+    let qsid_greater_than = u16::from_le_bytes(data[2..=3].try_into().unwrap());
+
+    data.fill(0xFF); // 0xFFFF terminates the list of supported settings
+    for (qsid, chunk) in SUPPORTED_QMK_SETTINGS
+        .iter()
+        .filter(|qsid| **qsid > qsid_greater_than)
+        .zip(data.chunks_exact_mut(2))
+    {
+        chunk.copy_from_slice(&qsid.to_le_bytes());
+    }
 }
 
-pub fn qmk_settings_get(data: &mut [u8]) {
-    // TODO: maybe support some QMK settings
+pub async fn qmk_settings_get<K: VialKeyboard>(data: &mut [u8])
+where
+    [(); K::Layout::LAYERS]:,
+    [(); K::Layout::LAYOUT_ROWS]:,
+    [(); K::Layout::LAYOUT_COLS]:,
+{
+    let qsid = u16::from_le_bytes(data[2..=3].try_into().unwrap());
+    let layout = <K::Layout as KeyboardLayout>::get_layout()
+        .layout
+        .lock()
+        .await;
+
+    // A timeout of 0 means that each action uses its own timeout
+    match qsid {
+        QMK_SETTING_ONESHOT_TIMEOUT => {
+            data[1..=2].copy_from_slice(&layout.oneshot_timeout().unwrap_or(0).to_le_bytes())
+        }
+        QMK_SETTING_TAPPING_TERM => {
+            data[1..=2].copy_from_slice(&layout.tapping_term().unwrap_or(0).to_le_bytes())
+        }
+        QMK_SETTING_TAPPING => data[1] = encode_hold_tap_config(layout.hold_tap_config()),
+        _ => {
+            data[0] = 0xFF;
+            return;
+        }
+    }
+
+    data[0] = 0;
 }
 
-pub fn qmk_settings_set(data: &mut [u8]) {
-    // TODO: maybe support some QMK settings
+pub async fn qmk_settings_set<K: VialKeyboard + 'static>(data: &mut [u8])
+where
+    [(); <<K::StorageType as StorageDevice>::FlashStorageType as FlashStorage>::ERASE_SIZE]:,
+    [(); K::DYNAMIC_KEYMAP_MACRO_BUFFER_SIZE as usize]:,
+    [(); K::DYNAMIC_KEYMAP_MACRO_COUNT as usize]:,
+    [(); K::Layout::LAYERS]:,
+    [(); K::Layout::LAYOUT_ROWS]:,
+    [(); K::Layout::LAYOUT_COLS]:,
+{
+    let qsid = u16::from_le_bytes(data[2..=3].try_into().unwrap());
+    let timeout = Some(u16::from_le_bytes(data[4..=5].try_into().unwrap())).filter(|t| *t > 0);
+
+    let settings = {
+        let mut layout = <K::Layout as KeyboardLayout>::get_layout()
+            .layout
+            .lock()
+            .await;
+
+        match qsid {
+            QMK_SETTING_ONESHOT_TIMEOUT => layout.set_oneshot_timeout(timeout),
+            QMK_SETTING_TAPPING_TERM => layout.set_tapping_term(timeout),
+            QMK_SETTING_TAPPING => layout.set_hold_tap_config(decode_hold_tap_config(data[4])),
+            _ => {
+                data[0] = 0xFF;
+                return;
+            }
+        }
+
+        QmkSettings {
+            oneshot_timeout: layout.oneshot_timeout(),
+            tapping_term: layout.tapping_term(),
+            hold_tap_config: encode_hold_tap_config(layout.hold_tap_config()),
+        }
+    };
+
+    if let Some(database) = K::get_storage_service() {
+        if let Err(()) = database.write(StorageKey::QmkSettings, settings).await {
+            warn!("[VIAL] Could not write QMK settings.")
+        };
+    }
+
+    data[0] = 0;
 }
 
-pub fn qmk_settings_reset(data: &mut [u8]) {
-    // TODO: maybe support some QMK settings
+pub async fn qmk_settings_reset<K: VialKeyboard + 'static>(data: &mut [u8])
+where
+    [(); <<K::StorageType as StorageDevice>::FlashStorageType as FlashStorage>::ERASE_SIZE]:,
+    [(); K::DYNAMIC_KEYMAP_MACRO_BUFFER_SIZE as usize]:,
+    [(); K::DYNAMIC_KEYMAP_MACRO_COUNT as usize]:,
+    [(); K::Layout::LAYERS]:,
+    [(); K::Layout::LAYOUT_ROWS]:,
+    [(); K::Layout::LAYOUT_COLS]:,
+{
+    apply_qmk_settings::<K>(QmkSettings::default()).await;
+
+    if let Some(database) = K::get_storage_service() {
+        if let Err(()) = database.erase_key(StorageKey::QmkSettings).await {
+            warn!("[VIAL] Could not reset QMK settings.")
+        };
+    }
+
+    data[0] = 0;
 }
 
 pub fn dynamic_keymap_get_number_of_entries<K: VialKeyboard>(data: &mut [u8]) {
@@ -309,20 +475,331 @@ pub fn dynamic_keymap_get_number_of_entries<K: VialKeyboard>(data: &mut [u8]) {
     data[2] = K::VIAL_KEY_OVERRIDE_ENTRIES;
 }
 
-pub fn dynamic_keymap_get_tap_dance(data: &mut [u8]) {
-    // TODO
+/// Tapping term used by tap dances that don't specify one. This is the same as QMK's default
+/// `TAPPING_TERM`.
+const DEFAULT_TAP_DANCE_TIMEOUT: u16 = 200;
+
+/// Convert a Vial tap dance entry into a [`DynamicTapDance`].
+pub(super) fn decode_tap_dance<K: VialKeyboard + 'static>(entry: &[u8]) -> Option<DynamicTapDance>
+where
+    [(); K::DYNAMIC_KEYMAP_MACRO_BUFFER_SIZE as usize]:,
+    [(); K::DYNAMIC_KEYMAP_MACRO_COUNT as usize]:,
+{
+    let value = |i: usize| u16::from_le_bytes([entry[i * 2], entry[i * 2 + 1]]);
+    let action =
+        |i: usize| keycodes::convert_keycode_to_action::<K>(value(i)).unwrap_or(Action::NoOp);
+
+    let tap_dance = DynamicTapDance {
+        on_tap: action(0),
+        on_hold: action(1),
+        on_double_tap: action(2),
+        on_tap_hold: action(3),
+        timeout: match value(4) {
+            0 => DEFAULT_TAP_DANCE_TIMEOUT,
+            timeout => timeout,
+        },
+    };
+
+    [
+        tap_dance.on_tap,
+        tap_dance.on_hold,
+        tap_dance.on_double_tap,
+        tap_dance.on_tap_hold,
+    ]
+    .iter()
+    .any(|action| !matches!(action, Action::NoOp))
+    .then_some(tap_dance)
 }
 
-pub fn dynamic_keymap_set_tap_dance(data: &mut [u8]) {
-    // TODO
+/// Convert a [`DynamicTapDance`] into a Vial tap dance entry.
+fn encode_tap_dance<K: VialKeyboard + 'static>(tap_dance: &DynamicTapDance, entry: &mut [u8])
+where
+    [(); K::DYNAMIC_KEYMAP_MACRO_BUFFER_SIZE as usize]:,
+    [(); K::DYNAMIC_KEYMAP_MACRO_COUNT as usize]:,
+{
+    let values = [
+        keycodes::convert_action_to_keycode::<K>(tap_dance.on_tap),
+        keycodes::convert_action_to_keycode::<K>(tap_dance.on_hold),
+        keycodes::convert_action_to_keycode::<K>(tap_dance.on_double_tap),
+        keycodes::convert_action_to_keycode::<K>(tap_dance.on_tap_hold),
+        tap_dance.timeout,
+    ];
+
+    for (i, value) in values.iter().enumerate() {
+        entry[(i * 2)..=(i * 2 + 1)].copy_from_slice(&value.to_le_bytes());
+    }
 }
 
-pub fn dynamic_keymap_get_combo(data: &mut [u8]) {
-    // TODO
+pub fn dynamic_keymap_get_tap_dance<K: VialKeyboard + 'static>(data: &mut [u8])
+where
+    [(); K::DYNAMIC_KEYMAP_MACRO_BUFFER_SIZE as usize]:,
+    [(); K::DYNAMIC_KEYMAP_MACRO_COUNT as usize]:,
+{
+    let idx = data[3] as usize;
+    let mut entry = [0; VIAL_TAP_DANCE_ENTRY_SIZE];
+
+    if idx >= K::VIAL_TAP_DANCE_ENTRIES as usize {
+        data[0] = 0xFF;
+        return;
+    }
+
+    if let Some(tap_dance) = K::Layout::get_tap_dance_buffer()
+        .and_then(|tap_dances| tap_dances.get(idx))
+        .and_then(|entry| entry.get_tap_dance())
+    {
+        encode_tap_dance::<K>(tap_dance, &mut entry);
+    }
+
+    data[0] = 0;
+    data[1..=VIAL_TAP_DANCE_ENTRY_SIZE].copy_from_slice(&entry);
 }
 
-pub fn dynamic_keymap_set_combo(data: &mut [u8]) {
-    // TODO
+pub async fn dynamic_keymap_set_tap_dance<K: VialKeyboard + 'static>(data: &mut [u8])
+where
+    [(); <<K::StorageType as StorageDevice>::FlashStorageType as FlashStorage>::ERASE_SIZE]:,
+    [(); K::DYNAMIC_KEYMAP_MACRO_BUFFER_SIZE as usize]:,
+    [(); K::DYNAMIC_KEYMAP_MACRO_COUNT as usize]:,
+    [(); K::Layout::TAP_DANCE_COUNT * VIAL_TAP_DANCE_ENTRY_SIZE]:,
+{
+    let idx = data[3] as usize;
+
+    if idx >= K::VIAL_TAP_DANCE_ENTRIES as usize {
+        data[0] = 0xFF;
+        return;
+    }
+
+    let entry = &data[4..(4 + VIAL_TAP_DANCE_ENTRY_SIZE)];
+
+    if let Some(tap_dance) =
+        K::Layout::get_tap_dance_buffer().and_then(|tap_dances| tap_dances.get_mut(idx))
+    {
+        tap_dance.set_tap_dance(decode_tap_dance::<K>(entry));
+    }
+
+    if let Some(database) = K::get_storage_service() {
+        let mut buf = [0; K::Layout::TAP_DANCE_COUNT * VIAL_TAP_DANCE_ENTRY_SIZE];
+
+        match database.read_raw(StorageKey::DynamicKeymapTapDance).await {
+            Ok(stored_data) => {
+                let len = stored_data.len().min(buf.len());
+                buf[..len].copy_from_slice(&stored_data[..len]);
+            }
+            Err(()) => {
+                warn!("[VIAL] Could not read dynamic keymap tap dances.");
+            }
+        };
+
+        let offset = idx * VIAL_TAP_DANCE_ENTRY_SIZE;
+        buf[offset..(offset + VIAL_TAP_DANCE_ENTRY_SIZE)].copy_from_slice(entry);
+
+        if let Err(()) = database
+            .write_raw(StorageKey::DynamicKeymapTapDance, &buf)
+            .await
+        {
+            warn!("[VIAL] Could not write dynamic keymap tap dances.")
+        };
+    }
+
+    data[0] = 0;
+}
+
+/// Convert a Vial combo entry into a [`Combo`]. Vial uses keycodes to describe the keys of a
+/// combo, so each input keycode is matched with the first key on the base layer that has the same
+/// keycode.
+///
+/// Because of this, if the same keycode appears more than once on the base layer, only the first
+/// key can be used in a combo. Since [`Combo`]s store key coordinates, they must be re-resolved
+/// whenever the base layer changes (see [`resolve_combos`]).
+pub(super) async fn decode_combo<K: VialKeyboard + 'static>(entry: &[u8]) -> Option<Combo>
+where
+    [(); K::DYNAMIC_KEYMAP_MACRO_BUFFER_SIZE as usize]:,
+    [(); K::DYNAMIC_KEYMAP_MACRO_COUNT as usize]:,
+    [(); K::Layout::LAYERS]:,
+    [(); K::Layout::LAYOUT_ROWS]:,
+    [(); K::Layout::LAYOUT_COLS]:,
+{
+    let keycode = |i: usize| u16::from_le_bytes([entry[i * 2], entry[i * 2 + 1]]);
+
+    let output = keycode(MAX_COMBO_KEYS);
+    let action = match keycodes::convert_keycode_to_action::<K>(output) {
+        Some(Action::NoOp) | None => return None,
+        Some(action) => action,
+    };
+
+    let mut layout = K::Layout::get_layout().layout.lock().await;
+    let mut keys = [None; MAX_COMBO_KEYS];
+
+    for (i, key) in keys.iter_mut().enumerate() {
+        let input = keycode(i);
+        if input == 0 {
+            continue;
+        }
+
+        let coord = (0..K::Layout::LAYOUT_ROWS as u8)
+            .flat_map(|row| (0..K::Layout::LAYOUT_COLS as u8).map(move |col| (row, col)))
+            .find(|coord| {
+                layout
+                    .get_action(*coord, 0)
+                    .map(keycodes::convert_action_to_keycode::<K>)
+                    == Some(input)
+            });
+
+        if coord.is_none() {
+            warn!(
+                "[VIAL] Could not find a key for keycode {:X} in the base layer. The combo will be ignored.",
+                input
+            );
+            return None;
+        }
+
+        *key = coord;
+    }
+
+    keys.iter()
+        .any(Option::is_some)
+        .then_some(Combo { keys, action })
+}
+
+/// Convert a [`Combo`] into a Vial combo entry. The keycodes of the combo's keys are obtained from
+/// the base layer.
+async fn encode_combo<K: VialKeyboard + 'static>(combo: &Combo, entry: &mut [u8])
+where
+    [(); K::DYNAMIC_KEYMAP_MACRO_BUFFER_SIZE as usize]:,
+    [(); K::DYNAMIC_KEYMAP_MACRO_COUNT as usize]:,
+    [(); K::Layout::LAYERS]:,
+    [(); K::Layout::LAYOUT_ROWS]:,
+    [(); K::Layout::LAYOUT_COLS]:,
+{
+    let mut layout = K::Layout::get_layout().layout.lock().await;
+
+    for (i, key) in combo.keys.iter().enumerate() {
+        let input = key
+            .and_then(|coord| layout.get_action(coord, 0))
+            .map_or(0, keycodes::convert_action_to_keycode::<K>);
+        entry[(i * 2)..=(i * 2 + 1)].copy_from_slice(&input.to_le_bytes());
+    }
+
+    let output = keycodes::convert_action_to_keycode::<K>(combo.action);
+    entry[(MAX_COMBO_KEYS * 2)..=(MAX_COMBO_KEYS * 2 + 1)].copy_from_slice(&output.to_le_bytes());
+}
+
+pub async fn dynamic_keymap_get_combo<K: VialKeyboard + 'static>(data: &mut [u8])
+where
+    [(); K::DYNAMIC_KEYMAP_MACRO_BUFFER_SIZE as usize]:,
+    [(); K::DYNAMIC_KEYMAP_MACRO_COUNT as usize]:,
+    [(); K::Layout::LAYERS]:,
+    [(); K::Layout::LAYOUT_ROWS]:,
+    [(); K::Layout::LAYOUT_COLS]:,
+    [(); K::Layout::COMBO_COUNT]:,
+{
+    let idx = data[3] as usize;
+    let mut entry = [0; VIAL_COMBO_ENTRY_SIZE];
+
+    if idx >= K::VIAL_COMBO_ENTRIES as usize {
+        data[0] = 0xFF;
+        return;
+    }
+
+    if let Some(combo) = K::Layout::get_combo_buffer().and_then(|combos| combos.get_combo(idx)) {
+        encode_combo::<K>(combo, &mut entry).await;
+    }
+
+    data[0] = 0;
+    data[1..=VIAL_COMBO_ENTRY_SIZE].copy_from_slice(&entry);
+}
+
+pub async fn dynamic_keymap_set_combo<K: VialKeyboard + 'static>(data: &mut [u8])
+where
+    [(); <<K::StorageType as StorageDevice>::FlashStorageType as FlashStorage>::ERASE_SIZE]:,
+    [(); K::DYNAMIC_KEYMAP_MACRO_BUFFER_SIZE as usize]:,
+    [(); K::DYNAMIC_KEYMAP_MACRO_COUNT as usize]:,
+    [(); K::Layout::LAYERS]:,
+    [(); K::Layout::LAYOUT_ROWS]:,
+    [(); K::Layout::LAYOUT_COLS]:,
+    [(); K::Layout::COMBO_COUNT]:,
+    [(); K::Layout::COMBO_COUNT * VIAL_COMBO_ENTRY_SIZE]:,
+{
+    let idx = data[3] as usize;
+
+    if idx >= K::VIAL_COMBO_ENTRIES as usize {
+        data[0] = 0xFF;
+        return;
+    }
+
+    let entry = &data[4..(4 + VIAL_COMBO_ENTRY_SIZE)];
+
+    if let Some(combos) = K::Layout::get_combo_buffer() {
+        combos.set_combo(idx, decode_combo::<K>(entry).await);
+    }
+
+    if let Some(database) = K::get_storage_service() {
+        let mut buf = [0; K::Layout::COMBO_COUNT * VIAL_COMBO_ENTRY_SIZE];
+
+        match database.read_raw(StorageKey::DynamicKeymapCombo).await {
+            Ok(stored_data) => {
+                let len = stored_data.len().min(buf.len());
+                buf[..len].copy_from_slice(&stored_data[..len]);
+            }
+            Err(()) => {
+                warn!("[VIAL] Could not read dynamic keymap combos.");
+            }
+        };
+
+        let offset = idx * VIAL_COMBO_ENTRY_SIZE;
+        buf[offset..(offset + VIAL_COMBO_ENTRY_SIZE)].copy_from_slice(entry);
+
+        if let Err(()) = database
+            .write_raw(StorageKey::DynamicKeymapCombo, &buf)
+            .await
+        {
+            warn!("[VIAL] Could not write dynamic keymap combos.")
+        };
+    }
+
+    data[0] = 0;
+}
+
+/// Convert all combos in the combo buffer into Vial combo entries, using the current base layer.
+pub(super) async fn encode_combos<K: VialKeyboard + 'static>(entries: &mut [u8])
+where
+    [(); K::DYNAMIC_KEYMAP_MACRO_BUFFER_SIZE as usize]:,
+    [(); K::DYNAMIC_KEYMAP_MACRO_COUNT as usize]:,
+    [(); K::Layout::LAYERS]:,
+    [(); K::Layout::LAYOUT_ROWS]:,
+    [(); K::Layout::LAYOUT_COLS]:,
+    [(); K::Layout::COMBO_COUNT]:,
+{
+    let Some(combos) = K::Layout::get_combo_buffer() else {
+        return;
+    };
+
+    for (idx, entry) in entries.chunks_exact_mut(VIAL_COMBO_ENTRY_SIZE).enumerate() {
+        if let Some(combo) = combos.get_combo(idx) {
+            encode_combo::<K>(combo, entry).await;
+        }
+    }
+}
+
+/// Re-resolve the key coordinates of all combos from Vial combo entries, which should have been
+/// obtained from [`encode_combos`] before the base layer was changed. This keeps the combos
+/// triggering on the same keycodes that were configured in Vial. Combos with keycodes that are no
+/// longer on the base layer are disabled.
+pub(super) async fn resolve_combos<K: VialKeyboard + 'static>(entries: &[u8])
+where
+    [(); K::DYNAMIC_KEYMAP_MACRO_BUFFER_SIZE as usize]:,
+    [(); K::DYNAMIC_KEYMAP_MACRO_COUNT as usize]:,
+    [(); K::Layout::LAYERS]:,
+    [(); K::Layout::LAYOUT_ROWS]:,
+    [(); K::Layout::LAYOUT_COLS]:,
+    [(); K::Layout::COMBO_COUNT]:,
+{
+    let Some(combos) = K::Layout::get_combo_buffer() else {
+        return;
+    };
+
+    for (idx, entry) in entries.chunks_exact(VIAL_COMBO_ENTRY_SIZE).enumerate() {
+        combos.set_combo(idx, decode_combo::<K>(entry).await);
+    }
 }
 
 pub fn dynamic_keymap_get_key_override(data: &mut [u8]) {
@@ -338,8 +815,10 @@ where
     [(); <<K::StorageType as StorageDevice>::FlashStorageType as FlashStorage>::ERASE_SIZE]:,
 {
     if let Some(database) = K::get_storage_service() {
-        let _ = database.delete(StorageKey::DynamicKeymapTapDance).await;
-        let _ = database.delete(StorageKey::DynamicKeymapCombo).await;
-        let _ = database.delete(StorageKey::DynamicKeymapKeyOverride).await;
+        let _ = database.erase_key(StorageKey::DynamicKeymapTapDance).await;
+        let _ = database.erase_key(StorageKey::DynamicKeymapCombo).await;
+        let _ = database
+            .erase_key(StorageKey::DynamicKeymapKeyOverride)
+            .await;
     }
 }
@@ -10,7 +10,7 @@ use smart_leds::RGB8;
 
 use crate::hw::platform::RawMutex;
 use crate::hw::HIDDevice;
-use crate::keyboard::KeyboardLayout;
+use crate::keyboard::{KeyboardLayout, MAX_COMBO_KEYS};
 use crate::lighting::BacklightMatrixDevice;
 use crate::storage::{FlashStorage, StorageDevice, StorageKey};
 use crate::via::ViaKeyboard;
@@ -42,11 +42,31 @@ pub trait VialKeyboard: ViaKeyboard {
     /// Whether RGB lighting features should be used. Usage of VialRGB assumes you have the
     /// [`rgb-backlight-matrix`] feature flag enabled.
     const VIALRGB_ENABLE: bool = false;
-    const VIAL_TAP_DANCE_ENTRIES: u8 = 0; // TODO: Change when tap dance is implemented
-    const VIAL_COMBO_ENTRIES: u8 = 0; // TODO: Change when combo is implemented
+
+    /// The number of tap dances that can be configured using Vial. This defaults to
+    /// [`KeyboardLayout::TAP_DANCE_COUNT`], which can be set using
+    /// [`setup_tap_dance_buffer`](crate::keyboard::setup_tap_dance_buffer). This must not be
+    /// greater than [`KeyboardLayout::TAP_DANCE_COUNT`].
+    const VIAL_TAP_DANCE_ENTRIES: u8 = <Self::Layout as KeyboardLayout>::TAP_DANCE_COUNT as u8;
+
+    /// The number of combos that can be configured using Vial. This defaults to
+    /// [`KeyboardLayout::COMBO_COUNT`], which can be set using
+    /// [`setup_combo_buffer`](crate::keyboard::setup_combo_buffer). This must not be greater than
+    /// [`KeyboardLayout::COMBO_COUNT`].
+    const VIAL_COMBO_ENTRIES: u8 = <Self::Layout as KeyboardLayout>::COMBO_COUNT as u8;
     const VIAL_KEY_OVERRIDE_ENTRIES: u8 = 0; // TODO: Change when key override is implemented
 }
 
+/// Size of a single combo entry used by the Vial protocol. Each entry consists of
+/// [`MAX_COMBO_KEYS`] input keycodes, followed by the output keycode, all stored as 16-bit
+/// integers.
+pub const VIAL_COMBO_ENTRY_SIZE: usize = (MAX_COMBO_KEYS + 1) * 2;
+
+/// Size of a single tap dance entry used by the Vial protocol. Each entry consists of the on tap,
+/// on hold, on double tap and on tap + hold keycodes, followed by the tapping term, all stored as
+/// 16-bit integers.
+pub const VIAL_TAP_DANCE_ENTRY_SIZE: usize = 5 * 2;
+
 /// Channel used to update the frame buffer for the
 /// [`crate::lighting::rgb_backlight_matrix::RGBBacklightMatrixEffect::DirectSet`] effect.
 pub(crate) static VIAL_DIRECT_SET_CHANNEL: Channel<RawMutex, (u8, RGB8), 4> = Channel::new();
@@ -63,7 +83,16 @@ where
     [(); K::Layout::LAYERS]:,
     [(); K::DYNAMIC_KEYMAP_MACRO_BUFFER_SIZE as usize]:,
     [(); K::DYNAMIC_KEYMAP_MACRO_COUNT as usize]:,
+    [(); K::Layout::COMBO_COUNT]:,
+    [(); K::Layout::COMBO_COUNT * VIAL_COMBO_ENTRY_SIZE]:,
+    [(); K::Layout::TAP_DANCE_COUNT * VIAL_TAP_DANCE_ENTRY_SIZE]:,
 {
+    const {
+        assert!(
+            K::Layout::COMBO_COUNT <= u8::MAX as usize,
+            "Vial can not configure more than 255 combos."
+        )
+    };
     assert!(K::DYNAMIC_KEYMAP_LAYER_COUNT <= K::Layout::LAYERS);
     assert!(K::DYNAMIC_KEYMAP_LAYER_COUNT <= 16);
     assert!(K::VIAL_UNLOCK_COMBO.len() < 15);
@@ -82,6 +111,14 @@ where
             "Macro count should be 0 if you are not using Via macros."
         );
     }
+    assert!(
+        K::VIAL_COMBO_ENTRIES as usize <= K::Layout::COMBO_COUNT,
+        "Vial combo entries must not be greater than the number of combos in your combo buffer."
+    );
+    assert!(
+        K::VIAL_TAP_DANCE_ENTRIES as usize <= K::Layout::TAP_DANCE_COUNT,
+        "Vial tap dance entries must not be greater than the number of tap dances in your tap dance buffer."
+    );
 
     let vial_state: Mutex<RawMutex, protocol::VialState> = Mutex::new(Default::default());
     let via_state: Mutex<RawMutex, protocol::via::ViaState<K>> = Mutex::new(Default::default());
@@ -97,6 +134,8 @@ where
             let mut report = receive_channel.receive().await;
 
             if K::VIAL_ENABLED && K::VIA_ENABLED {
+                K::on_via_command_received(&report).await;
+
                 {
                     let mut vial_state = vial_state.lock().await;
                     let mut via_state = via_state.lock().await;
@@ -108,6 +147,7 @@ where
                     .await;
                 }
 
+                K::on_via_response(&report);
                 send_channel.send(report).await;
             }
         }
@@ -119,19 +159,51 @@ where
 pub async fn initialize_vial_data<V: VialKeyboard + 'static>(_v: V)
 where
     [(); <<V::StorageType as StorageDevice>::FlashStorageType as FlashStorage>::ERASE_SIZE]:,
+    [(); V::DYNAMIC_KEYMAP_MACRO_BUFFER_SIZE as usize]:,
+    [(); V::DYNAMIC_KEYMAP_MACRO_COUNT as usize]:,
+    [(); V::Layout::LAYERS]:,
+    [(); V::Layout::LAYOUT_ROWS]:,
+    [(); V::Layout::LAYOUT_COLS]:,
+    [(); V::Layout::COMBO_COUNT]:,
 {
     if let Some(database) = V::get_storage_service() {
-        // let tap_dance_metadata: [u8; core::mem::size_of::<TypeId>()] = unsafe {core::mem::transmute(TypeId::of::<>())};
-        let tap_dance_metadata = [1];
+        let tap_dance_metadata = [
+            VIAL_TAP_DANCE_ENTRY_SIZE as u8,
+            V::Layout::TAP_DANCE_COUNT as u8,
+        ];
         let _ = database
             .check_metadata(StorageKey::DynamicKeymapTapDance, &tap_dance_metadata)
             .await;
+        if let Ok(stored_data) = database.read_raw(StorageKey::DynamicKeymapTapDance).await {
+            if let Some(tap_dances) = V::Layout::get_tap_dance_buffer() {
+                for (tap_dance, entry) in tap_dances
+                    .iter_mut()
+                    .zip(stored_data.chunks_exact(VIAL_TAP_DANCE_ENTRY_SIZE))
+                {
+                    tap_dance.set_tap_dance(handlers::decode_tap_dance::<V>(entry));
+                }
+            }
+        };
 
-        // let combo_metadata: [u8; core::mem::size_of::<TypeId>()] = unsafe {core::mem::transmute(TypeId::of::<>())};
-        let combo_metadata = [1];
+        let combo_metadata = [MAX_COMBO_KEYS as u8, V::Layout::COMBO_COUNT as u8];
         let _ = database
             .check_metadata(StorageKey::DynamicKeymapCombo, &combo_metadata)
             .await;
+        if let Ok(stored_data) = database.read_raw(StorageKey::DynamicKeymapCombo).await {
+            if let Some(combos) = V::Layout::get_combo_buffer() {
+                for (idx, entry) in stored_data.chunks_exact(VIAL_COMBO_ENTRY_SIZE).enumerate() {
+                    combos.set_combo(idx, handlers::decode_combo::<V>(entry).await);
+                }
+            }
+        };
+
+        let qmk_settings_metadata = [1];
+        let _ = database
+            .check_metadata(StorageKey::QmkSettings, &qmk_settings_metadata)
+            .await;
+        if let Ok(settings) = database.read(StorageKey::QmkSettings).await {
+            handlers::apply_qmk_settings::<V>(settings).await;
+        };
 
         // let key_override_metadata: [u8; core::mem::size_of::<TypeId>()] = unsafe {core::mem::transmute(TypeId::of::<>())};
         let key_override_metadata = [1];
@@ -1,8 +1,9 @@
-use super::VialKeyboard;
+use super::{VialKeyboard, VIAL_COMBO_ENTRY_SIZE, VIAL_TAP_DANCE_ENTRY_SIZE};
 use crate::keyboard::KeyboardLayout;
 use crate::lighting::BacklightMatrixDevice;
 use crate::storage::{FlashStorage, StorageDevice};
 use crate::via::handlers::{dynamic_keymap_get_encoder, dynamic_keymap_set_encoder};
+use crate::via::protocol::keycodes;
 use crate::vial::handlers::*;
 use defmt::{info, warn, Debug2Format};
 use num_derive::FromPrimitive;
@@ -74,6 +75,9 @@ pub(crate) async fn process_vial_command<K: VialKeyboard + 'static>(
     [(); K::Layout::LAYOUT_COLS]:,
     [(); K::DYNAMIC_KEYMAP_MACRO_BUFFER_SIZE as usize]:,
     [(); K::DYNAMIC_KEYMAP_MACRO_COUNT as usize]:,
+    [(); K::Layout::COMBO_COUNT]:,
+    [(); K::Layout::COMBO_COUNT * VIAL_COMBO_ENTRY_SIZE]:,
+    [(); K::Layout::TAP_DANCE_COUNT * VIAL_TAP_DANCE_ENTRY_SIZE]:,
 {
     if K::handle_via_command(data) {
         return;
@@ -120,6 +124,7 @@ pub(crate) async fn process_vial_command<K: VialKeyboard + 'static>(
                                     encoder_id,
                                     false,
                                     &mut data[0..=1],
+                                    keycodes::convert_action_to_keycode::<K>,
                                 )
                                 .await;
                                 dynamic_keymap_get_encoder::<K>(
@@ -127,6 +132,7 @@ pub(crate) async fn process_vial_command<K: VialKeyboard + 'static>(
                                     encoder_id,
                                     true,
                                     &mut data[2..=3],
+                                    keycodes::convert_action_to_keycode::<K>,
                                 )
                                 .await;
                             }
@@ -139,6 +145,7 @@ pub(crate) async fn process_vial_command<K: VialKeyboard + 'static>(
                                     encoder_id,
                                     clockwise,
                                     &mut data[5..=6],
+                                    keycodes::convert_keycode_to_action::<K>,
                                 )
                                 .await;
                             }
@@ -151,9 +158,9 @@ pub(crate) async fn process_vial_command<K: VialKeyboard + 'static>(
                             }
                             VialCommandId::Lock => lock(vial_state),
                             VialCommandId::QmkSettingsQuery => qmk_settings_query(data),
-                            VialCommandId::QmkSettingsGet => qmk_settings_get(data),
-                            VialCommandId::QmkSettingsSet => qmk_settings_set(data),
-                            VialCommandId::QmkSettingsReset => qmk_settings_reset(data),
+                            VialCommandId::QmkSettingsGet => qmk_settings_get::<K>(data).await,
+                            VialCommandId::QmkSettingsSet => qmk_settings_set::<K>(data).await,
+                            VialCommandId::QmkSettingsReset => qmk_settings_reset::<K>(data).await,
                             VialCommandId::DynamicEntryOp => {
                                 if let Some(cmd) = num::FromPrimitive::from_u8(data[2]) {
                                     match cmd {
@@ -161,16 +168,16 @@ pub(crate) async fn process_vial_command<K: VialKeyboard + 'static>(
                                             dynamic_keymap_get_number_of_entries::<K>(data)
                                         }
                                         VialDynamicValue::TapDanceGet => {
-                                            dynamic_keymap_get_tap_dance(data)
+                                            dynamic_keymap_get_tap_dance::<K>(data)
                                         }
                                         VialDynamicValue::TapDanceSet => {
-                                            dynamic_keymap_set_tap_dance(data)
+                                            dynamic_keymap_set_tap_dance::<K>(data).await
                                         }
                                         VialDynamicValue::ComboGet => {
-                                            dynamic_keymap_get_combo(data)
+                                            dynamic_keymap_get_combo::<K>(data).await
                                         }
                                         VialDynamicValue::ComboSet => {
-                                            dynamic_keymap_set_combo(data)
+                                            dynamic_keymap_set_combo::<K>(data).await
                                         }
                                         VialDynamicValue::KeyOverrideGet => {
                                             dynamic_keymap_get_key_override(data)
@@ -186,7 +193,26 @@ pub(crate) async fn process_vial_command<K: VialKeyboard + 'static>(
                 }
             }
             _ => {
+                // Combos store the coordinates of their keys, but Vial configures them using
+                // keycodes. If the keymap changes, the coordinates are resolved again, so that
+                // combos still use the keycodes that were configured.
+                let keymap_changed = matches!(
+                    command,
+                    ViaCommandId::DynamicKeymapSetKeycode
+                        | ViaCommandId::DynamicKeymapSetBuffer
+                        | ViaCommandId::DynamicKeymapReset
+                );
+                let mut combo_entries = [0; K::Layout::COMBO_COUNT * VIAL_COMBO_ENTRY_SIZE];
+
+                if keymap_changed {
+                    encode_combos::<K>(&mut combo_entries).await;
+                }
+
                 process_via_command::<K>(data, via_state, vial_state).await;
+
+                if keymap_changed {
+                    resolve_combos::<K>(&combo_entries).await;
+                }
             }
         }
     } else {
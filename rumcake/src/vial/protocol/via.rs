@@ -399,8 +399,8 @@ pub(crate) async fn process_via_command<K: VialKeyboard + 'static>(
                     }
                     #[cfg(feature = "underglow")]
                     Some(ViaLightingValue::RGBLightEffect) => {
-                        if let Some(state) = <<K::Layout as KeyboardLayout>::UnderglowDeviceType as crate::lighting::underglow::private::MaybeUnderglowDevice>::get_state() {
-                            if !state.get().await.enabled {
+                        if let Some(state) = <<K::Layout as KeyboardLayout>::UnderglowDeviceType as crate::lighting::underglow::private::MaybeUnderglowDevice>::get_selected_zone_state() {
+                            if !state.get().await.1.enabled {
                                 data[2] = 0
                             } else {
                                 underglow_get_effect::<K>(&mut data[2..=2], |config| {
@@ -0,0 +1,102 @@
+//! Words per minute (WPM) tracking.
+//!
+//! [`wpm_task`] counts the keys that are pressed over a sliding window of [`WPM_WINDOW`] seconds,
+//! and stores the resulting typing speed in [`WPM_STATE`]. Every 5 key presses are counted as one
+//! word. Keys that only send modifiers, and keys that don't send any keycodes (like layer keys),
+//! are ignored.
+//!
+//! When you stop typing, old key presses leave the window, so the WPM decays towards zero.
+
+use defmt::warn;
+use embassy_futures::select::{select, Either};
+use embassy_time::{Duration, Ticker};
+use keyberon::action::Action;
+use keyberon::layout::Event;
+
+use crate::keyboard::{KeyboardLayout, Keycode, MATRIX_EVENTS};
+use crate::State;
+
+/// Length of the sliding window, in seconds, that key presses are counted in.
+pub const WPM_WINDOW: usize = 5;
+
+/// Number of key presses that are counted as one word.
+const CHARACTERS_PER_WORD: u32 = 5;
+
+/// State that contains the current typing speed, in words per minute. This is updated by
+/// [`wpm_task`] once every second while you are typing.
+pub static WPM_STATE: State<u16> = State::new(
+    0,
+    &[
+        #[cfg(feature = "display")]
+        &crate::display::WPM_LISTENER,
+        #[cfg(all(feature = "split-central", feature = "display"))]
+        &crate::split::central::DISPLAY_STATE_LISTENER,
+    ],
+);
+
+/// Determine whether pressing a key with the given action should count towards the WPM.
+fn counts_towards_wpm(action: &Action<Keycode>) -> bool {
+    match action {
+        Action::KeyCode(key) => !key.is_modifier(),
+        Action::MultipleKeyCodes(keys) => keys.iter().any(|key| !key.is_modifier()),
+        Action::MultipleActions(actions) => actions.iter().any(counts_towards_wpm),
+        Action::HoldTap(hold_tap) => counts_towards_wpm(&hold_tap.tap),
+        Action::TapDance(tap_dance) => tap_dance.actions.iter().any(counts_towards_wpm),
+        _ => false,
+    }
+}
+
+pub async fn wpm_task<K: KeyboardLayout>(_k: K)
+where
+    [(); K::LAYERS]:,
+    [(); K::LAYOUT_ROWS]:,
+    [(); K::LAYOUT_COLS]:,
+{
+    let Ok(mut subscriber) = MATRIX_EVENTS.subscriber() else {
+        warn!("[WPM] Could not subscribe to matrix events. WPM will not be tracked.");
+        return;
+    };
+
+    let mut ticker = Ticker::every(Duration::from_secs(1));
+    let mut presses = [0u16; WPM_WINDOW];
+    let mut current = 0;
+
+    loop {
+        let event = if presses.iter().all(|count| *count == 0) {
+            // Nothing left to decay, so we can wait for the next key press without waking up
+            // every second.
+            let event = subscriber.next_message_pure().await;
+            ticker.reset();
+            Some(event)
+        } else {
+            match select(subscriber.next_message_pure(), ticker.next()).await {
+                Either::First(event) => Some(event),
+                Either::Second(()) => None,
+            }
+        };
+
+        match event {
+            Some(Event::Press(row, col)) => {
+                let mut layout = K::get_layout().layout.lock().await;
+                let layer = layout.current_layer();
+                if layout
+                    .get_action((row, col), layer)
+                    .is_some_and(|action| counts_towards_wpm(&action))
+                {
+                    presses[current] = presses[current].saturating_add(1);
+                }
+            }
+            Some(Event::Release(_, _)) => {}
+            None => {
+                // Drop the oldest second from the window before calculating the WPM, so that the
+                // WPM reaches zero once the window is empty.
+                current = (current + 1) % WPM_WINDOW;
+                presses[current] = 0;
+
+                let total: u32 = presses.iter().map(|count| *count as u32).sum();
+                let wpm = total * 60 / WPM_WINDOW as u32 / CHARACTERS_PER_WORD;
+                WPM_STATE.set(wpm.min(u16::MAX as u32) as u16).await;
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Checks that key presses sent with [`MockMatrix`] are turned into keyboard reports by the layout
+//! task.
+//!
+//! These tests run on an RP2040, using [`embedded-test`](https://github.com/probe-rs/embedded-test)
+//! with `probe-rs` as the test runner. A `memory.x` for the RP2040 must be in the linker search
+//! path, and the tests must be linked with `-Tlink.x -Tlink-rp.x -Tdefmt.x -Tembedded-test.x`:
+//!
+//! ```sh
+//! cargo test --target thumbv6m-none-eabi --features rp2040,test-utils --test test_utils
+//! ```
+//!
+//! [`MockMatrix`]: rumcake::test_utils::MockMatrix
+
+#![no_std]
+#![no_main]
+#![feature(generic_const_exprs)]
+#![allow(incomplete_features)]
+
+use defmt_rtt as _;
+use panic_probe as _;
+
+use rumcake::hw::HIDDevice;
+use rumcake::keyboard::{build_layout, Keyboard, KeyboardLayout};
+
+struct TestKeyboard;
+
+impl Keyboard for TestKeyboard {
+    const MANUFACTURER: &'static str = "rumcake";
+    const PRODUCT: &'static str = "Test keyboard";
+}
+
+impl KeyboardLayout for TestKeyboard {
+    build_layout! {
+        {
+            [ A (1) ]
+        }
+        {
+            [ B t ]
+        }
+    }
+}
+
+impl HIDDevice for TestKeyboard {}
+
+#[embedded_test::tests]
+mod tests {
+    use core::future::Future;
+
+    use embassy_futures::select::{select, Either};
+    use rumcake::test_utils::{
+        capture_keyboard_reports, expect_keyboard_report, stop_capturing_keyboard_reports,
+        try_next_keyboard_report, MockMatrix, ScriptStep,
+    };
+    use usbd_human_interface_device::page::Keyboard;
+
+    use super::TestKeyboard;
+
+    /// Run the layout task until `test` completes.
+    async fn with_layout(test: impl Future<Output = ()>) {
+        let _ = rumcake::hw::platform::embassy_rp::init(Default::default());
+
+        let previous_output = capture_keyboard_reports().await;
+
+        match select(rumcake::tasks::layout_collect(TestKeyboard), test).await {
+            Either::First(()) => panic!("The layout task stopped"),
+            Either::Second(()) => {}
+        }
+
+        stop_capturing_keyboard_reports(previous_output).await;
+    }
+
+    #[test]
+    async fn tap_produces_press_and_release_reports() {
+        with_layout(async {
+            let matrix = MockMatrix::<TestKeyboard>::new();
+
+            matrix.play(&[ScriptStep::Press(0, 0)]).await;
+            expect_keyboard_report::<TestKeyboard>(&[Keyboard::A]).await;
+
+            matrix
+                .play(&[ScriptStep::Wait(50), ScriptStep::Release(0, 0)])
+                .await;
+            expect_keyboard_report::<TestKeyboard>(&[]).await;
+
+            assert!(try_next_keyboard_report::<TestKeyboard>().is_none());
+        })
+        .await
+    }
+
+    #[test]
+    async fn held_layer_changes_reported_keycode() {
+        with_layout(async {
+            let matrix = MockMatrix::<TestKeyboard>::new();
+
+            matrix
+                .play(&[
+                    ScriptStep::Press(0, 1),
+                    ScriptStep::Wait(10),
+                    ScriptStep::Press(0, 0),
+                ])
+                .await;
+            expect_keyboard_report::<TestKeyboard>(&[Keyboard::B]).await;
+
+            matrix
+                .play(&[ScriptStep::Release(0, 0), ScriptStep::Release(0, 1)])
+                .await;
+            expect_keyboard_report::<TestKeyboard>(&[]).await;
+        })
+        .await
+    }
+}